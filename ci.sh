@@ -45,9 +45,12 @@ cargo batch  \
     --- build --release --manifest-path embassy-stm32/Cargo.toml --target thumbv7em-none-eabi --features stm32f411ce,defmt \
     --- build --release --manifest-path embassy-stm32/Cargo.toml --target thumbv7em-none-eabi --features stm32f429zi,log \
     --- build --release --manifest-path embassy-stm32/Cargo.toml --target thumbv7em-none-eabi --features stm32h755zi-cm7,defmt \
+    --- build --release --manifest-path embassy-stm32/Cargo.toml --target thumbv7em-none-eabi --features stm32h755zi-cm7,defmt,usb-otg-fs \
     --- build --release --manifest-path embassy-stm32/Cargo.toml --target thumbv7em-none-eabi --features stm32l476vg,defmt \
     --- build --release --manifest-path embassy-stm32/Cargo.toml --target thumbv6m-none-eabi --features stm32l072cz,defmt \
     --- build --release --manifest-path embassy-stm32/Cargo.toml --target thumbv7m-none-eabi --features stm32l151cb-a,defmt \
+    --- build --release --manifest-path embassy-fat/Cargo.toml --target thumbv7em-none-eabi \
+    --- build --release --manifest-path embassy-fat/Cargo.toml --target thumbv7em-none-eabi --features defmt \
     --- build --release --manifest-path docs/modules/ROOT/examples/basic/Cargo.toml --target thumbv7em-none-eabi \
     --- build --release --manifest-path examples/std/Cargo.toml --target x86_64-unknown-linux-gnu --out-dir out/examples/std \
     --- build --release --manifest-path examples/nrf/Cargo.toml --target thumbv7em-none-eabi --out-dir out/examples/nrf \
@@ -73,6 +76,45 @@ cargo batch  \
     --- build --release --manifest-path tests/stm32/Cargo.toml --target thumbv7em-none-eabi --features stm32h755zi --out-dir out/tests/nucleo-stm32h755zi \
     --- build --release --manifest-path tests/stm32/Cargo.toml --target thumbv7em-none-eabi --features stm32wb55rg --out-dir out/tests/nucleo-stm32wb55rg \
 
+# The SFDP parser of embassy-stm32 has no dependency, run its unit tests on the host.
+mkdir -p $CARGO_TARGET_DIR
+rustc --edition 2018 --test embassy-stm32/src/qspi/sfdp.rs -o $CARGO_TARGET_DIR/sfdp-tests
+$CARGO_TARGET_DIR/sfdp-tests
+
+# The FDCAN bit timing calculation has no dependency, run its unit tests on the host.
+rustc --edition 2018 --test embassy-stm32/src/fdcan/timing.rs -o $CARGO_TARGET_DIR/fdcan-timing-tests
+$CARGO_TARGET_DIR/fdcan-timing-tests
+
+# The flash sector layouts have no dependency, run their unit tests on the host.
+rustc --edition 2018 --test embassy-stm32/src/flash/layout.rs -o $CARGO_TARGET_DIR/flash-layout-tests
+$CARGO_TARGET_DIR/flash-layout-tests
+
+# The RTC date and time conversions have no dependency, run their unit tests on the host.
+rustc --edition 2018 --test embassy-stm32/src/rtc/datetime.rs -o $CARGO_TARGET_DIR/rtc-datetime-tests
+$CARGO_TARGET_DIR/rtc-datetime-tests
+
+# The IWDG timeout calculation has no dependency, run its unit tests on the host.
+rustc --edition 2018 --test embassy-stm32/src/iwdg/timeout.rs -o $CARGO_TARGET_DIR/iwdg-timeout-tests
+$CARGO_TARGET_DIR/iwdg-timeout-tests
+
+# The DMA ring buffer has no dependency, run its unit tests on the host.
+rustc --edition 2018 --test embassy-stm32/src/dma/ringbuffer.rs -o $CARGO_TARGET_DIR/dma-ringbuffer-tests
+$CARGO_TARGET_DIR/dma-ringbuffer-tests
+
+# The dead time calculation of the timers has no dependency, run its unit tests on the host.
+rustc --edition 2018 --test embassy-stm32/src/pwm/dead_time.rs -o $CARGO_TARGET_DIR/pwm-dead-time-tests
+$CARGO_TARGET_DIR/pwm-dead-time-tests
+
+# The Ethernet v1 descriptor ring has no dependency, run its unit tests on the host.
+rustc --edition 2018 --test embassy-stm32/src/eth/v1/ring.rs -o $CARGO_TARGET_DIR/eth-ring-tests
+$CARGO_TARGET_DIR/eth-ring-tests
+
+# The Ethernet PHY interface and the generic PHY have no dependency, run their unit tests on the host.
+rustc --edition 2018 --test embassy-stm32/src/eth/phy/mod.rs -o $CARGO_TARGET_DIR/eth-phy-tests
+$CARGO_TARGET_DIR/eth-phy-tests
+
+# The tests of embassy-fat use disk image files, run them on the host with std.
+cargo test --release --manifest-path embassy-fat/Cargo.toml --target x86_64-unknown-linux-gnu --features std
 
 function run_elf {
     echo Running target=$1 elf=$2
@@ -0,0 +1,273 @@
+//! Boot sector and partition table parsing
+
+use crate::SECTOR_SIZE;
+
+/// FAT variant of a volume, determined by its number of clusters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// Value marking the last cluster of a chain.
+    pub(crate) fn end_of_chain(self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    /// Returns true if `entry` marks the last cluster of a chain.
+    pub(crate) fn is_end_of_chain(self, entry: u32) -> bool {
+        entry >= self.end_of_chain() - 7
+    }
+}
+
+/// Layout of a FAT volume, as described by its BIOS Parameter Block.
+///
+/// All the sectors are absolute, i.e. relative to the start of the device.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Layout {
+    pub fat_type: FatType,
+    pub sectors_per_cluster: u32,
+    pub num_fats: u32,
+    pub fat_start: u32,
+    pub fat_size: u32,
+    /// First sector of the fixed root directory (FAT12/16 only)
+    pub root_dir_start: u32,
+    pub root_dir_sectors: u32,
+    pub data_start: u32,
+    /// Number of data clusters, which are numbered from 2
+    pub cluster_count: u32,
+    /// First cluster of the root directory (FAT32 only)
+    pub root_cluster: u32,
+    /// Sector of the FSInfo structure (FAT32 only)
+    pub fs_info: Option<u32>,
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+fn has_signature(sector: &[u8]) -> bool {
+    sector[510] == 0x55 && sector[511] == 0xAA
+}
+
+impl Layout {
+    /// Parses the boot sector of a volume starting at sector `start`.
+    ///
+    /// Returns `None` if it isn't a FAT boot sector with 512-byte sectors.
+    pub fn parse(start: u32, sector: &[u8]) -> Option<Self> {
+        if !has_signature(sector) || !(sector[0] == 0xEB || sector[0] == 0xE9) {
+            return None;
+        }
+
+        let bytes_per_sector = u16_at(sector, 11) as usize;
+        let sectors_per_cluster = sector[13] as u32;
+        let reserved_sectors = u16_at(sector, 14) as u32;
+        let num_fats = sector[16] as u32;
+        let root_entries = u16_at(sector, 17) as u32;
+        let total_sectors = match u16_at(sector, 19) {
+            0 => u32_at(sector, 32),
+            n => n as u32,
+        };
+        let fat_size = match u16_at(sector, 22) {
+            0 => u32_at(sector, 36),
+            n => n as u32,
+        };
+
+        if bytes_per_sector != SECTOR_SIZE
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || num_fats == 0
+            || fat_size == 0
+        {
+            return None;
+        }
+
+        let fat_start = start + reserved_sectors;
+        let root_dir_start = fat_start + num_fats * fat_size;
+        let root_dir_sectors = (root_entries * 32 + SECTOR_SIZE as u32 - 1) / SECTOR_SIZE as u32;
+        let data_start = root_dir_start + root_dir_sectors;
+        let data_sectors = total_sectors.checked_sub(data_start - start)?;
+        let cluster_count = data_sectors / sectors_per_cluster;
+
+        let fat_type = if cluster_count < 4085 {
+            FatType::Fat12
+        } else if cluster_count < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        let (root_cluster, fs_info) = match fat_type {
+            FatType::Fat32 => {
+                if root_entries != 0 {
+                    return None;
+                }
+                let fs_info = match u16_at(sector, 48) {
+                    0 | 0xFFFF => None,
+                    n => Some(start + n as u32),
+                };
+                (u32_at(sector, 44), fs_info)
+            }
+            _ => (0, None),
+        };
+
+        Some(Self {
+            fat_type,
+            sectors_per_cluster,
+            num_fats,
+            fat_start,
+            fat_size,
+            root_dir_start,
+            root_dir_sectors,
+            data_start,
+            cluster_count,
+            root_cluster,
+            fs_info,
+        })
+    }
+
+    /// Returns the first sector of the data cluster `cluster`.
+    pub fn cluster_start(&self, cluster: u32) -> u32 {
+        self.data_start + (cluster - 2) * self.sectors_per_cluster
+    }
+
+    pub fn cluster_size(&self) -> u32 {
+        self.sectors_per_cluster * SECTOR_SIZE as u32
+    }
+
+    /// Returns true if `cluster` is a data cluster of the volume.
+    pub fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.cluster_count + 2
+    }
+}
+
+/// Returns the start sector of the first FAT partition of the Master Boot Record in `sector`.
+pub(crate) fn first_partition(sector: &[u8]) -> Option<u32> {
+    if !has_signature(sector) {
+        return None;
+    }
+
+    (0..4).find_map(|i| {
+        let entry = &sector[446 + i * 16..][..16];
+        match entry[4] {
+            // FAT12, FAT16 and FAT32, with CHS or LBA addressing
+            0x01 | 0x04 | 0x06 | 0x0B | 0x0C | 0x0E => Some(u32_at(entry, 8)),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boot_sector(
+        total_sectors: u32,
+        reserved: u16,
+        root_entries: u16,
+        fat_size_16: u16,
+        fat_size_32: u32,
+    ) -> [u8; 512] {
+        let mut sector = [0; 512];
+        sector[0] = 0xEB;
+        sector[11..13].copy_from_slice(&512u16.to_le_bytes());
+        sector[13] = 1;
+        sector[14..16].copy_from_slice(&reserved.to_le_bytes());
+        sector[16] = 2;
+        sector[17..19].copy_from_slice(&root_entries.to_le_bytes());
+        sector[32..36].copy_from_slice(&total_sectors.to_le_bytes());
+        sector[22..24].copy_from_slice(&fat_size_16.to_le_bytes());
+        sector[36..40].copy_from_slice(&fat_size_32.to_le_bytes());
+        sector[44..48].copy_from_slice(&2u32.to_le_bytes());
+        sector[48..50].copy_from_slice(&1u16.to_le_bytes());
+        sector[510] = 0x55;
+        sector[511] = 0xAA;
+        sector
+    }
+
+    #[test]
+    fn fat_type_from_cluster_count() {
+        let fat12 = Layout::parse(0, &boot_sector(4096, 1, 512, 12, 0)).unwrap();
+        assert_eq!(fat12.fat_type, FatType::Fat12);
+        assert_eq!(fat12.fat_start, 1);
+        assert_eq!(fat12.root_dir_start, 25);
+        assert_eq!(fat12.root_dir_sectors, 32);
+        assert_eq!(fat12.data_start, 57);
+        assert_eq!(fat12.cluster_count, 4096 - 57);
+        assert_eq!(fat12.fs_info, None);
+
+        let fat16 = Layout::parse(0, &boot_sector(32768, 1, 512, 128, 0)).unwrap();
+        assert_eq!(fat16.fat_type, FatType::Fat16);
+
+        let fat32 = Layout::parse(0, &boot_sector(70000, 32, 0, 0, 547)).unwrap();
+        assert_eq!(fat32.fat_type, FatType::Fat32);
+        assert_eq!(fat32.root_dir_sectors, 0);
+        assert_eq!(fat32.data_start, 32 + 2 * 547);
+        assert_eq!(fat32.root_cluster, 2);
+        assert_eq!(fat32.fs_info, Some(1));
+    }
+
+    #[test]
+    fn volume_offset() {
+        let layout = Layout::parse(63, &boot_sector(4096, 1, 512, 12, 0)).unwrap();
+        assert_eq!(layout.fat_start, 64);
+        assert_eq!(layout.data_start, 63 + 57);
+        assert_eq!(layout.cluster_start(2), 63 + 57);
+        assert_eq!(layout.cluster_start(3), 63 + 58);
+    }
+
+    #[test]
+    fn rejects_invalid_boot_sectors() {
+        let mut sector = boot_sector(4096, 1, 512, 12, 0);
+        sector[510] = 0;
+        assert_eq!(Layout::parse(0, &sector), None);
+
+        let mut sector = boot_sector(4096, 1, 512, 12, 0);
+        sector[11..13].copy_from_slice(&4096u16.to_le_bytes());
+        assert_eq!(Layout::parse(0, &sector), None);
+
+        // FAT32 volumes have no fixed root directory
+        assert_eq!(Layout::parse(0, &boot_sector(70000, 32, 512, 0, 547)), None);
+    }
+
+    #[test]
+    fn partition_table() {
+        let mut sector = [0; 512];
+        sector[510] = 0x55;
+        sector[511] = 0xAA;
+        assert_eq!(first_partition(&sector), None);
+
+        // Linux partition, then FAT32 LBA
+        sector[446 + 4] = 0x83;
+        sector[446 + 8..446 + 12].copy_from_slice(&63u32.to_le_bytes());
+        sector[462 + 4] = 0x0C;
+        sector[462 + 8..462 + 12].copy_from_slice(&2048u32.to_le_bytes());
+        assert_eq!(first_partition(&sector), Some(2048));
+    }
+
+    #[test]
+    fn end_of_chain() {
+        assert!(FatType::Fat12.is_end_of_chain(0xFF8));
+        assert!(!FatType::Fat12.is_end_of_chain(0xFF7));
+        assert!(FatType::Fat16.is_end_of_chain(0xFFFF));
+        assert!(!FatType::Fat16.is_end_of_chain(0xFFF0));
+        assert!(FatType::Fat32.is_end_of_chain(0x0FFF_FFF8));
+        assert!(!FatType::Fat32.is_end_of_chain(0x0FFF_FFF7));
+    }
+}
@@ -0,0 +1,296 @@
+//! Directory entries
+
+use core::fmt;
+
+pub(crate) const ENTRY_SIZE: usize = 32;
+
+pub(crate) const ATTR_READ_ONLY: u8 = 0x01;
+pub(crate) const ATTR_HIDDEN: u8 = 0x02;
+pub(crate) const ATTR_SYSTEM: u8 = 0x04;
+pub(crate) const ATTR_VOLUME_ID: u8 = 0x08;
+pub(crate) const ATTR_DIRECTORY: u8 = 0x10;
+pub(crate) const ATTR_ARCHIVE: u8 = 0x20;
+pub(crate) const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+/// First name byte of the free entry ending a directory
+pub(crate) const END_OF_DIR: u8 = 0x00;
+/// First name byte of a deleted entry
+pub(crate) const DELETED: u8 = 0xE5;
+
+/// 1980-01-01, the FAT epoch, used for all the timestamps.
+const DATE: u16 = (1 << 5) | 1;
+
+/// A 8.3 file name, as stored in directory entries.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct ShortName([u8; 11]);
+
+impl ShortName {
+    pub(crate) const DOT: ShortName = ShortName(*b".          ");
+    pub(crate) const DOT_DOT: ShortName = ShortName(*b"..         ");
+
+    /// Converts `name` to a short name, in upper case.
+    ///
+    /// Returns `None` if `name` doesn't fit in 8.3 characters, or contains characters that are not
+    /// allowed in short names. Long file names are not supported.
+    pub fn new(name: &str) -> Option<Self> {
+        match name {
+            "." => return Some(Self::DOT),
+            ".." => return Some(Self::DOT_DOT),
+            _ => {}
+        }
+
+        let (base, ext) = match name.find('.') {
+            Some(i) => (&name[..i], &name[i + 1..]),
+            None => (name, ""),
+        };
+        if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+            return None;
+        }
+
+        let mut bytes = [b' '; 11];
+        for (dst, &c) in bytes[..8].iter_mut().zip(base.as_bytes()) {
+            *dst = Self::convert(c)?;
+        }
+        for (dst, &c) in bytes[8..].iter_mut().zip(ext.as_bytes()) {
+            *dst = Self::convert(c)?;
+        }
+        Some(Self(bytes))
+    }
+
+    fn convert(c: u8) -> Option<u8> {
+        match c {
+            b'A'..=b'Z' | b'0'..=b'9' => Some(c),
+            b'a'..=b'z' => Some(c.to_ascii_uppercase()),
+            b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'(' | b')' | b'-' | b'@' | b'^' | b'_'
+            | b'`' | b'{' | b'}' | b'~' => Some(c),
+            _ => None,
+        }
+    }
+
+    /// Returns true for the `.` and `..` entries of subdirectories.
+    pub(crate) fn is_dot(&self) -> bool {
+        *self == Self::DOT || *self == Self::DOT_DOT
+    }
+
+    fn base(&self) -> &[u8] {
+        trim(&self.0[..8])
+    }
+
+    fn ext(&self) -> &[u8] {
+        trim(&self.0[8..])
+    }
+}
+
+fn trim(bytes: &[u8]) -> &[u8] {
+    let len = bytes.iter().rposition(|&c| c != b' ').map_or(0, |i| i + 1);
+    &bytes[..len]
+}
+
+impl fmt::Display for ShortName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &c in self.base() {
+            write!(f, "{}", c as char)?;
+        }
+        if !self.ext().is_empty() {
+            write!(f, ".")?;
+            for &c in self.ext() {
+                write!(f, "{}", c as char)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ShortName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ShortName {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "{=[u8]:a}.{=[u8]:a}", self.base(), self.ext())
+    }
+}
+
+/// Position of an entry on the device
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct EntryLocation {
+    pub sector: u32,
+    /// Byte offset in the sector
+    pub offset: usize,
+}
+
+/// A file or directory entry
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub(crate) name: ShortName,
+    pub(crate) attributes: u8,
+    pub(crate) cluster: u32,
+    pub(crate) size: u32,
+    pub(crate) location: EntryLocation,
+}
+
+impl DirEntry {
+    pub(crate) fn new(name: ShortName, attributes: u8, cluster: u32) -> Self {
+        Self {
+            name,
+            attributes,
+            cluster,
+            size: 0,
+            location: EntryLocation {
+                sector: 0,
+                offset: 0,
+            },
+        }
+    }
+
+    /// Decodes a raw entry, unless it is free, deleted or part of a long file name.
+    pub(crate) fn parse(raw: &[u8], location: EntryLocation) -> Option<Self> {
+        let attributes = raw[11];
+        if raw[0] == END_OF_DIR
+            || raw[0] == DELETED
+            || attributes & ATTR_LONG_NAME == ATTR_LONG_NAME
+            || attributes & ATTR_VOLUME_ID != 0
+        {
+            return None;
+        }
+
+        let mut name = [0; 11];
+        name.copy_from_slice(&raw[..11]);
+        // 0xE5 is a valid first character, stored as 0x05
+        if name[0] == 0x05 {
+            name[0] = DELETED;
+        }
+
+        let cluster_hi = u16::from_le_bytes([raw[20], raw[21]]) as u32;
+        let cluster_lo = u16::from_le_bytes([raw[26], raw[27]]) as u32;
+
+        Some(Self {
+            name: ShortName(name),
+            attributes,
+            cluster: cluster_hi << 16 | cluster_lo,
+            size: u32::from_le_bytes([raw[28], raw[29], raw[30], raw[31]]),
+            location,
+        })
+    }
+
+    /// Encodes this entry into `raw`.
+    pub(crate) fn serialize(&self, raw: &mut [u8]) {
+        raw[..ENTRY_SIZE].fill(0);
+        raw[..11].copy_from_slice(&self.name.0);
+        raw[11] = self.attributes;
+        // Creation, last access and last write dates
+        raw[16..18].copy_from_slice(&DATE.to_le_bytes());
+        raw[18..20].copy_from_slice(&DATE.to_le_bytes());
+        raw[24..26].copy_from_slice(&DATE.to_le_bytes());
+        self.serialize_data(raw);
+    }
+
+    /// Updates the start cluster and size of the encoded entry in `raw`.
+    pub(crate) fn serialize_data(&self, raw: &mut [u8]) {
+        raw[20..22].copy_from_slice(&((self.cluster >> 16) as u16).to_le_bytes());
+        raw[26..28].copy_from_slice(&(self.cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&self.size.to_le_bytes());
+    }
+
+    pub fn name(&self) -> &ShortName {
+        &self.name
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attributes & ATTR_DIRECTORY != 0
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.attributes & ATTR_READ_ONLY != 0
+    }
+
+    /// Size of the file, in bytes. Always 0 for directories.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn short_names() {
+        assert_eq!(ShortName::new("readme.txt").unwrap().0, *b"README  TXT");
+        assert_eq!(ShortName::new("KERNEL").unwrap().0, *b"KERNEL     ");
+        assert_eq!(ShortName::new("a.b").unwrap().0, *b"A       B  ");
+        assert_eq!(ShortName::new("..").unwrap(), ShortName::DOT_DOT);
+
+        assert_eq!(ShortName::new(""), None);
+        assert_eq!(ShortName::new(".txt"), None);
+        assert_eq!(ShortName::new("toolongname.txt"), None);
+        assert_eq!(ShortName::new("file.text"), None);
+        assert_eq!(ShortName::new("two.dots.txt"), None);
+        assert_eq!(ShortName::new("sp ace"), None);
+        assert_eq!(ShortName::new("dir/file"), None);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            ShortName::new("readme.txt").unwrap().to_string(),
+            "README.TXT"
+        );
+        assert_eq!(ShortName::new("kernel").unwrap().to_string(), "KERNEL");
+        assert_eq!(ShortName::DOT_DOT.to_string(), "..");
+    }
+
+    #[test]
+    fn roundtrip() {
+        let location = EntryLocation {
+            sector: 12,
+            offset: 64,
+        };
+        let mut entry = DirEntry::new(ShortName::new("log.bin").unwrap(), ATTR_ARCHIVE, 0x12_3456);
+        entry.size = 100_000;
+
+        let mut raw = [0xAA; ENTRY_SIZE];
+        entry.serialize(&mut raw);
+        let parsed = DirEntry::parse(&raw, location).unwrap();
+        assert_eq!(parsed.name, entry.name);
+        assert_eq!(parsed.cluster, 0x12_3456);
+        assert_eq!(parsed.size, 100_000);
+        assert_eq!(parsed.location, location);
+        assert!(!parsed.is_dir());
+        assert_eq!(&raw[16..18], &[0x21, 0x00]);
+    }
+
+    #[test]
+    fn skips_unused_entries() {
+        let location = EntryLocation {
+            sector: 0,
+            offset: 0,
+        };
+        let mut raw = [0; ENTRY_SIZE];
+        assert!(DirEntry::parse(&raw, location).is_none());
+
+        DirEntry::new(ShortName::new("a").unwrap(), ATTR_DIRECTORY, 3).serialize(&mut raw);
+        assert!(DirEntry::parse(&raw, location).unwrap().is_dir());
+
+        raw[0] = DELETED;
+        assert!(DirEntry::parse(&raw, location).is_none());
+
+        raw[0] = b'A';
+        raw[11] = ATTR_LONG_NAME;
+        assert!(DirEntry::parse(&raw, location).is_none());
+
+        raw[11] = ATTR_VOLUME_ID | ATTR_ARCHIVE;
+        assert!(DirEntry::parse(&raw, location).is_none());
+
+        // Kanji lead byte 0xE5
+        raw[0] = 0x05;
+        raw[11] = ATTR_ARCHIVE;
+        assert_eq!(DirEntry::parse(&raw, location).unwrap().name.0[0], 0xE5);
+    }
+}
@@ -0,0 +1,101 @@
+//! Disk image files, to use volumes on a host
+
+use core::future::{ready, Ready};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use embassy_traits::block_device::BlockDevice;
+
+use crate::SECTOR_SIZE;
+
+/// A [`BlockDevice`] backed by a disk image file, with 512-byte blocks
+pub struct FileBlockDevice {
+    file: File,
+    block_count: u32,
+}
+
+impl FileBlockDevice {
+    /// Opens the disk image at `path` for reading and writing.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Self::new(file)
+    }
+
+    pub fn new(file: File) -> io::Result<Self> {
+        let len = file.metadata()?.len();
+        Ok(Self {
+            file,
+            block_count: (len / SECTOR_SIZE as u64) as u32,
+        })
+    }
+
+    fn seek(&mut self, block_idx: u32, len: usize) -> io::Result<()> {
+        assert_eq!(len % SECTOR_SIZE, 0);
+        if block_idx as u64 + (len / SECTOR_SIZE) as u64 > self.block_count as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "access past the end of the disk image",
+            ));
+        }
+        self.file
+            .seek(SeekFrom::Start(block_idx as u64 * SECTOR_SIZE as u64))?;
+        Ok(())
+    }
+}
+
+impl BlockDevice for FileBlockDevice {
+    type Error = io::Error;
+
+    type ReadFuture<'a> = Ready<io::Result<()>>;
+    type WriteFuture<'a> = Ready<io::Result<()>>;
+
+    fn read<'a>(&'a mut self, block_idx: u32, buf: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        ready(
+            self.seek(block_idx, buf.len())
+                .and_then(|_| self.file.read_exact(buf)),
+        )
+    }
+
+    fn write<'a>(&'a mut self, block_idx: u32, buf: &'a [u8]) -> Self::WriteFuture<'a> {
+        ready(
+            self.seek(block_idx, buf.len())
+                .and_then(|_| self.file.write_all(buf)),
+        )
+    }
+
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u32 {
+        self.block_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_on;
+
+    #[test]
+    fn read_write_blocks() {
+        let path = std::env::temp_dir().join("embassy-fat-image-test.img");
+        std::fs::write(&path, vec![0; 4 * SECTOR_SIZE]).unwrap();
+        let mut device = FileBlockDevice::open(&path).unwrap();
+        assert_eq!(device.block_count(), 4);
+
+        let data: Vec<u8> = (0..2 * SECTOR_SIZE).map(|i| i as u8).collect();
+        block_on(device.write(2, &data)).unwrap();
+        let mut buf = vec![0; 3 * SECTOR_SIZE];
+        block_on(device.read(1, &mut buf)).unwrap();
+        assert_eq!(&buf[..SECTOR_SIZE], &[0; SECTOR_SIZE][..]);
+        assert_eq!(&buf[SECTOR_SIZE..], &data[..]);
+
+        // The image is not extended
+        assert!(block_on(device.write(3, &data)).is_err());
+        assert!(block_on(device.read(4, &mut buf[..SECTOR_SIZE])).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![feature(generic_associated_types)]
+
+//! Async FAT12/16/32 file system, on top of a [`BlockDevice`](embassy_traits::block_device::BlockDevice).
+
+mod bpb;
+mod dir;
+#[cfg(feature = "std")]
+mod image;
+mod volume;
+
+pub use bpb::FatType;
+pub use dir::{DirEntry, ShortName};
+#[cfg(feature = "std")]
+pub use image::FileBlockDevice;
+pub use volume::{Dir, File, Mode, Volume};
+
+/// Size of the sectors of the supported volumes, and of the blocks of their devices.
+pub const SECTOR_SIZE: usize = 512;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error<E> {
+    /// The block device failed.
+    Device(E),
+    /// The block size of the device isn't 512 bytes.
+    UnsupportedBlockSize,
+    /// No FAT volume was found on the device.
+    NoVolume,
+    /// The file system structures are inconsistent.
+    Corrupted,
+    NotFound,
+    AlreadyExists,
+    NotADirectory,
+    IsADirectory,
+    /// The name isn't a valid 8.3 short name.
+    InvalidName,
+    /// The file is read-only, or was opened for reading only.
+    ReadOnly,
+    /// The fixed size root directory of the FAT12/16 volume is full.
+    DirectoryFull,
+    /// There is no free cluster left.
+    DiskFull,
+    /// The file would exceed the maximum size of 4 GiB.
+    FileTooLarge,
+    /// The position is past the end of the file.
+    InvalidSeek,
+}
+
+/// Runs `future` to completion, for tests with devices that never block.
+#[cfg(test)]
+pub(crate) fn block_on<F: core::future::Future>(mut future: F) -> F::Output {
+    use core::pin::Pin;
+    use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    fn raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(core::ptr::null(), &VTABLE)
+    }
+
+    let waker = unsafe { Waker::from_raw(raw_waker()) };
+    let mut cx = Context::from_waker(&waker);
+    // NOTE(unsafe) `future` is never moved
+    let mut future = unsafe { Pin::new_unchecked(&mut future) };
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
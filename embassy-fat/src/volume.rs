@@ -0,0 +1,1063 @@
+//! FAT volumes, files and directories
+
+use embassy_traits::block_device::BlockDevice;
+
+use crate::bpb::{first_partition, FatType, Layout};
+use crate::dir::*;
+use crate::{Error, SECTOR_SIZE};
+
+/// A directory of a [`Volume`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Dir {
+    /// First cluster, or 0 for the fixed root directory of FAT12/16 volumes
+    cluster: u32,
+}
+
+/// How to open a file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mode {
+    /// Opens an existing file for reading.
+    Read,
+    /// Opens an existing file for reading and writing, at its start.
+    ReadWrite,
+    /// Opens a file for writing at its end, creating it if it doesn't exist.
+    Append,
+    /// Creates a file, or empties it if it already exists.
+    Truncate,
+}
+
+/// A file opened on a [`Volume`]
+///
+/// Its new size is only saved by [`Volume::flush_file`] or [`Volume::close_file`].
+#[derive(Debug)]
+pub struct File {
+    entry: DirEntry,
+    mode: Mode,
+    position: u32,
+    /// Cluster containing `position`, or 0 if not looked up yet
+    cluster: u32,
+    /// Index of `cluster` in the chain of the file
+    cluster_index: u32,
+    /// The size or first cluster of the file changed
+    dirty: bool,
+}
+
+impl File {
+    pub fn entry(&self) -> &DirEntry {
+        &self.entry
+    }
+
+    pub fn size(&self) -> u32 {
+        self.entry.size
+    }
+
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+}
+
+struct Cache {
+    buf: [u8; SECTOR_SIZE],
+    sector: Option<u32>,
+    dirty: bool,
+}
+
+/// Position in the sectors of a directory
+struct DirCursor {
+    /// Current cluster, or 0 in the fixed root directory
+    cluster: u32,
+    /// Index of the current sector in the cluster, or in the fixed root directory
+    index: u32,
+}
+
+/// A FAT12, FAT16 or FAT32 volume on a block device
+///
+/// The volume is either the whole device, or its first FAT partition. Only 8.3 short names are
+/// supported: long file names are ignored.
+///
+/// Sectors are read and written through a single sector cache, which is only written back when
+/// another sector is needed, or by [`Self::flush`].
+pub struct Volume<D: BlockDevice> {
+    device: D,
+    layout: Layout,
+    cache: Cache,
+    /// Cluster from which to look for a free cluster
+    next_free: u32,
+    /// The free cluster count of the FSInfo sector was marked as unknown
+    fs_info_invalidated: bool,
+}
+
+impl<D: BlockDevice> Volume<D> {
+    /// Opens the FAT volume on `device`, which must have 512-byte blocks.
+    pub async fn open(mut device: D) -> Result<Self, Error<D::Error>> {
+        if device.block_size() != SECTOR_SIZE {
+            return Err(Error::UnsupportedBlockSize);
+        }
+
+        let mut buf = [0; SECTOR_SIZE];
+        device.read(0, &mut buf).await.map_err(Error::Device)?;
+        let layout = match Layout::parse(0, &buf) {
+            Some(layout) => layout,
+            None => {
+                let start = first_partition(&buf).ok_or(Error::NoVolume)?;
+                device.read(start, &mut buf).await.map_err(Error::Device)?;
+                Layout::parse(start, &buf).ok_or(Error::NoVolume)?
+            }
+        };
+
+        Ok(Self {
+            device,
+            layout,
+            cache: Cache {
+                buf,
+                sector: None,
+                dirty: false,
+            },
+            next_free: 2,
+            fs_info_invalidated: false,
+        })
+    }
+
+    /// Writes back the pending changes, and returns the device.
+    pub async fn close(mut self) -> Result<D, Error<D::Error>> {
+        self.flush().await?;
+        Ok(self.device)
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        self.layout.fat_type
+    }
+
+    pub fn root_dir(&self) -> Dir {
+        match self.layout.fat_type {
+            FatType::Fat32 => Dir {
+                cluster: self.layout.root_cluster,
+            },
+            _ => Dir { cluster: 0 },
+        }
+    }
+
+    /// Writes back the cached sector, if it was modified.
+    pub async fn flush(&mut self) -> Result<(), Error<D::Error>> {
+        let sector = match (self.cache.sector, self.cache.dirty) {
+            (Some(sector), true) => sector,
+            _ => return Ok(()),
+        };
+
+        self.device
+            .write(sector, &self.cache.buf)
+            .await
+            .map_err(Error::Device)?;
+
+        // Keep the copies of the FAT in sync
+        let layout = self.layout;
+        if sector >= layout.fat_start && sector < layout.fat_start + layout.fat_size {
+            for i in 1..layout.num_fats {
+                self.device
+                    .write(sector + i * layout.fat_size, &self.cache.buf)
+                    .await
+                    .map_err(Error::Device)?;
+            }
+        }
+
+        self.cache.dirty = false;
+        Ok(())
+    }
+
+    /// Looks up the entry named `name` in `dir`.
+    pub async fn find(&mut self, dir: &Dir, name: &str) -> Result<DirEntry, Error<D::Error>> {
+        let name = ShortName::new(name).ok_or(Error::InvalidName)?;
+        self.find_entry(dir, &name).await?.ok_or(Error::NotFound)
+    }
+
+    /// Calls `f` with each entry of `dir`, except `.` and `..`.
+    pub async fn read_dir(
+        &mut self,
+        dir: &Dir,
+        mut f: impl FnMut(&DirEntry),
+    ) -> Result<(), Error<D::Error>> {
+        self.walk_dir(dir, |raw, location| {
+            if let Some(entry) = DirEntry::parse(raw, location) {
+                if !entry.name.is_dot() {
+                    f(&entry);
+                }
+            }
+            false
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Opens the subdirectory `name` of `dir`.
+    pub async fn open_dir(&mut self, dir: &Dir, name: &str) -> Result<Dir, Error<D::Error>> {
+        let entry = self.find(dir, name).await?;
+        if !entry.is_dir() {
+            return Err(Error::NotADirectory);
+        }
+        match entry.cluster {
+            // `..` of the subdirectories of the root directory
+            0 => Ok(self.root_dir()),
+            cluster => Ok(Dir { cluster }),
+        }
+    }
+
+    /// Creates the subdirectory `name` in `dir`.
+    pub async fn create_dir(&mut self, dir: &Dir, name: &str) -> Result<Dir, Error<D::Error>> {
+        let name = self.new_name(dir, name).await?;
+
+        let cluster = self.alloc_cluster(None).await?;
+        self.zero_cluster(cluster).await?;
+
+        let parent = if *dir == self.root_dir() {
+            0
+        } else {
+            dir.cluster
+        };
+        let buf = self.load_mut(self.layout.cluster_start(cluster)).await?;
+        DirEntry::new(ShortName::DOT, ATTR_DIRECTORY, cluster).serialize(&mut buf[..ENTRY_SIZE]);
+        DirEntry::new(ShortName::DOT_DOT, ATTR_DIRECTORY, parent)
+            .serialize(&mut buf[ENTRY_SIZE..2 * ENTRY_SIZE]);
+
+        self.add_entry(dir, DirEntry::new(name, ATTR_DIRECTORY, cluster))
+            .await?;
+        Ok(Dir { cluster })
+    }
+
+    /// Opens the file `name` of `dir`.
+    pub async fn open_file(
+        &mut self,
+        dir: &Dir,
+        name: &str,
+        mode: Mode,
+    ) -> Result<File, Error<D::Error>> {
+        let short_name = ShortName::new(name)
+            .filter(|name| !name.is_dot())
+            .ok_or(Error::InvalidName)?;
+
+        let entry = match self.find_entry(dir, &short_name).await? {
+            Some(entry) if entry.is_dir() => return Err(Error::IsADirectory),
+            Some(entry) if mode != Mode::Read && entry.is_read_only() => {
+                return Err(Error::ReadOnly)
+            }
+            Some(entry) => entry,
+            None if mode == Mode::Append || mode == Mode::Truncate => {
+                self.add_entry(dir, DirEntry::new(short_name, ATTR_ARCHIVE, 0))
+                    .await?
+            }
+            None => return Err(Error::NotFound),
+        };
+
+        let mut file = File {
+            entry,
+            mode,
+            position: 0,
+            cluster: 0,
+            cluster_index: 0,
+            dirty: false,
+        };
+        match mode {
+            Mode::Append => file.position = file.entry.size,
+            Mode::Truncate if file.entry.cluster != 0 => {
+                self.free_chain(file.entry.cluster).await?;
+                file.entry.cluster = 0;
+                file.entry.size = 0;
+                file.dirty = true;
+            }
+            _ => {}
+        }
+        Ok(file)
+    }
+
+    /// Reads from the current position of `file` into `buf`.
+    ///
+    /// Returns the number of bytes read, which is only less than `buf.len()` at the end of the
+    /// file.
+    pub async fn read(
+        &mut self,
+        file: &mut File,
+        buf: &mut [u8],
+    ) -> Result<usize, Error<D::Error>> {
+        let len = buf.len().min((file.entry.size - file.position) as usize);
+        let sectors_per_cluster = self.layout.sectors_per_cluster;
+
+        let mut done = 0;
+        while done < len {
+            let cluster = self.file_cluster(file, false).await?;
+            let cluster_offset = file.position % self.layout.cluster_size();
+            let sector_index = cluster_offset / SECTOR_SIZE as u32;
+            let sector = self.layout.cluster_start(cluster) + sector_index;
+            let offset = cluster_offset as usize % SECTOR_SIZE;
+            let remaining = len - done;
+
+            let n = if offset == 0 && remaining >= SECTOR_SIZE {
+                // Read the whole sectors directly, up to the end of the cluster
+                let count =
+                    ((remaining / SECTOR_SIZE) as u32).min(sectors_per_cluster - sector_index);
+                let n = count as usize * SECTOR_SIZE;
+                self.bypass_cache(sector, count, false).await?;
+                self.device
+                    .read(sector, &mut buf[done..done + n])
+                    .await
+                    .map_err(Error::Device)?;
+                n
+            } else {
+                self.load(sector).await?;
+                let n = remaining.min(SECTOR_SIZE - offset);
+                buf[done..done + n].copy_from_slice(&self.cache.buf[offset..offset + n]);
+                n
+            };
+
+            done += n;
+            file.position += n as u32;
+        }
+        Ok(done)
+    }
+
+    /// Writes `data` at the current position of `file`, or at its end in [`Mode::Append`].
+    pub async fn write(&mut self, file: &mut File, data: &[u8]) -> Result<(), Error<D::Error>> {
+        match file.mode {
+            Mode::Read => return Err(Error::ReadOnly),
+            Mode::Append => file.position = file.entry.size,
+            _ => {}
+        }
+        if file.position as u64 + data.len() as u64 > u32::MAX as u64 {
+            return Err(Error::FileTooLarge);
+        }
+        let sectors_per_cluster = self.layout.sectors_per_cluster;
+
+        let mut done = 0;
+        while done < data.len() {
+            let cluster = self.file_cluster(file, true).await?;
+            let cluster_offset = file.position % self.layout.cluster_size();
+            let sector_index = cluster_offset / SECTOR_SIZE as u32;
+            let sector = self.layout.cluster_start(cluster) + sector_index;
+            let offset = cluster_offset as usize % SECTOR_SIZE;
+            let remaining = data.len() - done;
+
+            let n = if offset == 0 && remaining >= SECTOR_SIZE {
+                // Write the whole sectors directly, up to the end of the cluster
+                let count =
+                    ((remaining / SECTOR_SIZE) as u32).min(sectors_per_cluster - sector_index);
+                let n = count as usize * SECTOR_SIZE;
+                self.bypass_cache(sector, count, true).await?;
+                self.device
+                    .write(sector, &data[done..done + n])
+                    .await
+                    .map_err(Error::Device)?;
+                n
+            } else {
+                let buf = self.load_mut(sector).await?;
+                let n = remaining.min(SECTOR_SIZE - offset);
+                buf[offset..offset + n].copy_from_slice(&data[done..done + n]);
+                n
+            };
+
+            done += n;
+            file.position += n as u32;
+            if file.position > file.entry.size {
+                file.entry.size = file.position;
+                file.dirty = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the position of `file` to `position`, which must not be past its end.
+    pub fn seek(&mut self, file: &mut File, position: u32) -> Result<(), Error<D::Error>> {
+        if position > file.entry.size {
+            return Err(Error::InvalidSeek);
+        }
+        file.position = position;
+        Ok(())
+    }
+
+    /// Saves the size and first cluster of `file` in its directory entry, and writes back the
+    /// pending changes.
+    pub async fn flush_file(&mut self, file: &mut File) -> Result<(), Error<D::Error>> {
+        if file.dirty {
+            let location = file.entry.location;
+            let buf = self.load_mut(location.sector).await?;
+            file.entry
+                .serialize_data(&mut buf[location.offset..location.offset + ENTRY_SIZE]);
+            file.dirty = false;
+        }
+        self.flush().await
+    }
+
+    pub async fn close_file(&mut self, mut file: File) -> Result<(), Error<D::Error>> {
+        self.flush_file(&mut file).await
+    }
+
+    async fn load(&mut self, sector: u32) -> Result<(), Error<D::Error>> {
+        if self.cache.sector != Some(sector) {
+            self.flush().await?;
+            self.cache.sector = None;
+            self.device
+                .read(sector, &mut self.cache.buf)
+                .await
+                .map_err(Error::Device)?;
+            self.cache.sector = Some(sector);
+        }
+        Ok(())
+    }
+
+    /// Loads `sector` in the cache, to modify it.
+    async fn load_mut(&mut self, sector: u32) -> Result<&mut [u8; SECTOR_SIZE], Error<D::Error>> {
+        self.load(sector).await?;
+        self.cache.dirty = true;
+        Ok(&mut self.cache.buf)
+    }
+
+    /// Prepares direct accesses to `count` sectors from `start`, which bypass the cache.
+    ///
+    /// Before reads, the cached sector is written back. Before writes, it is dropped since it
+    /// would be outdated.
+    async fn bypass_cache(
+        &mut self,
+        start: u32,
+        count: u32,
+        write: bool,
+    ) -> Result<(), Error<D::Error>> {
+        match self.cache.sector {
+            Some(sector) if sector >= start && sector < start + count => {
+                if write {
+                    self.cache.sector = None;
+                    self.cache.dirty = false;
+                    Ok(())
+                } else {
+                    self.flush().await
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn fat_byte(&mut self, offset: u32) -> Result<u8, Error<D::Error>> {
+        self.load(self.layout.fat_start + offset / SECTOR_SIZE as u32)
+            .await?;
+        Ok(self.cache.buf[offset as usize % SECTOR_SIZE])
+    }
+
+    async fn set_fat_byte(&mut self, offset: u32, value: u8) -> Result<(), Error<D::Error>> {
+        let buf = self
+            .load_mut(self.layout.fat_start + offset / SECTOR_SIZE as u32)
+            .await?;
+        buf[offset as usize % SECTOR_SIZE] = value;
+        Ok(())
+    }
+
+    async fn fat_entry(&mut self, cluster: u32) -> Result<u32, Error<D::Error>> {
+        match self.layout.fat_type {
+            FatType::Fat12 => {
+                // Entries are packed on 12 bits, and may straddle two sectors
+                let offset = cluster + cluster / 2;
+                let value = self.fat_byte(offset).await? as u32
+                    | (self.fat_byte(offset + 1).await? as u32) << 8;
+                if cluster & 1 == 1 {
+                    Ok(value >> 4)
+                } else {
+                    Ok(value & 0xFFF)
+                }
+            }
+            FatType::Fat16 => {
+                let offset = cluster * 2;
+                Ok(self.fat_byte(offset).await? as u32
+                    | (self.fat_byte(offset + 1).await? as u32) << 8)
+            }
+            FatType::Fat32 => {
+                let offset = cluster * 4;
+                let mut value = 0;
+                for i in 0..4 {
+                    value |= (self.fat_byte(offset + i).await? as u32) << (8 * i);
+                }
+                // The upper 4 bits are reserved
+                Ok(value & 0x0FFF_FFFF)
+            }
+        }
+    }
+
+    async fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), Error<D::Error>> {
+        match self.layout.fat_type {
+            FatType::Fat12 => {
+                let offset = cluster + cluster / 2;
+                if cluster & 1 == 1 {
+                    let low = self.fat_byte(offset).await? & 0x0F;
+                    self.set_fat_byte(offset, low | (value << 4) as u8).await?;
+                    self.set_fat_byte(offset + 1, (value >> 4) as u8).await
+                } else {
+                    let high = self.fat_byte(offset + 1).await? & 0xF0;
+                    self.set_fat_byte(offset, value as u8).await?;
+                    self.set_fat_byte(offset + 1, high | (value >> 8) as u8 & 0x0F)
+                        .await
+                }
+            }
+            FatType::Fat16 => {
+                let offset = cluster * 2;
+                self.set_fat_byte(offset, value as u8).await?;
+                self.set_fat_byte(offset + 1, (value >> 8) as u8).await
+            }
+            FatType::Fat32 => {
+                let offset = cluster * 4;
+                let reserved = self.fat_byte(offset + 3).await? & 0xF0;
+                let value = (value & 0x0FFF_FFFF) | (reserved as u32) << 24;
+                for i in 0..4 {
+                    self.set_fat_byte(offset + i, (value >> (8 * i)) as u8)
+                        .await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the cluster following `cluster` in its chain, or `None` if it is the last one.
+    async fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, Error<D::Error>> {
+        let entry = self.fat_entry(cluster).await?;
+        if self.layout.fat_type.is_end_of_chain(entry) {
+            Ok(None)
+        } else if self.layout.is_valid_cluster(entry) {
+            Ok(Some(entry))
+        } else {
+            Err(Error::Corrupted)
+        }
+    }
+
+    /// Allocates a free cluster, and appends it to the chain ending with `previous`.
+    async fn alloc_cluster(&mut self, previous: Option<u32>) -> Result<u32, Error<D::Error>> {
+        let mut cluster = self.next_free;
+        for _ in 0..self.layout.cluster_count {
+            if !self.layout.is_valid_cluster(cluster) {
+                cluster = 2;
+            }
+            if self.fat_entry(cluster).await? == 0 {
+                let end_of_chain = self.layout.fat_type.end_of_chain();
+                self.set_fat_entry(cluster, end_of_chain).await?;
+                if let Some(previous) = previous {
+                    self.set_fat_entry(previous, cluster).await?;
+                }
+                self.next_free = cluster + 1;
+                self.invalidate_fs_info().await?;
+                return Ok(cluster);
+            }
+            cluster += 1;
+        }
+        Err(Error::DiskFull)
+    }
+
+    /// Frees the chain of clusters starting at `cluster`.
+    async fn free_chain(&mut self, cluster: u32) -> Result<(), Error<D::Error>> {
+        let mut cluster = Some(cluster);
+        while let Some(current) = cluster {
+            cluster = self.next_cluster(current).await?;
+            self.set_fat_entry(current, 0).await?;
+        }
+        self.invalidate_fs_info().await
+    }
+
+    /// Marks the free cluster count and next free cluster of the FSInfo sector as unknown, since
+    /// they are not kept up to date.
+    async fn invalidate_fs_info(&mut self) -> Result<(), Error<D::Error>> {
+        let sector = match (self.layout.fs_info, self.fs_info_invalidated) {
+            (Some(sector), false) => sector,
+            _ => return Ok(()),
+        };
+        self.fs_info_invalidated = true;
+
+        self.load(sector).await?;
+        if self.cache.buf[..4] == 0x4161_5252u32.to_le_bytes() {
+            self.cache.buf[488..496].fill(0xFF);
+            self.cache.dirty = true;
+        }
+        Ok(())
+    }
+
+    async fn zero_cluster(&mut self, cluster: u32) -> Result<(), Error<D::Error>> {
+        let start = self.layout.cluster_start(cluster);
+        let count = self.layout.sectors_per_cluster;
+        self.bypass_cache(start, count, true).await?;
+
+        let zeros = [0; SECTOR_SIZE];
+        for sector in start..start + count {
+            self.device
+                .write(sector, &zeros)
+                .await
+                .map_err(Error::Device)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the cluster containing the position of `file`, which is allocated if `allocate` is
+    /// true and the position is at the end of the file.
+    async fn file_cluster(
+        &mut self,
+        file: &mut File,
+        allocate: bool,
+    ) -> Result<u32, Error<D::Error>> {
+        let index = file.position / self.layout.cluster_size();
+
+        if file.cluster == 0 || index < file.cluster_index {
+            if file.entry.cluster == 0 {
+                if !allocate {
+                    return Err(Error::Corrupted);
+                }
+                file.entry.cluster = self.alloc_cluster(None).await?;
+                file.dirty = true;
+            }
+            file.cluster = file.entry.cluster;
+            file.cluster_index = 0;
+        }
+
+        while file.cluster_index < index {
+            file.cluster = match self.next_cluster(file.cluster).await? {
+                Some(next) => next,
+                None if allocate => self.alloc_cluster(Some(file.cluster)).await?,
+                None => return Err(Error::Corrupted),
+            };
+            file.cluster_index += 1;
+        }
+        Ok(file.cluster)
+    }
+
+    fn cursor_sector(&self, cursor: &DirCursor) -> u32 {
+        match cursor.cluster {
+            0 => self.layout.root_dir_start + cursor.index,
+            cluster => self.layout.cluster_start(cluster) + cursor.index,
+        }
+    }
+
+    /// Moves `cursor` to the next sector of its directory. Returns false at the end of the
+    /// directory.
+    async fn advance(&mut self, cursor: &mut DirCursor) -> Result<bool, Error<D::Error>> {
+        let sectors = match cursor.cluster {
+            0 => self.layout.root_dir_sectors,
+            _ => self.layout.sectors_per_cluster,
+        };
+        if cursor.index + 1 < sectors {
+            cursor.index += 1;
+            return Ok(true);
+        }
+        if cursor.cluster == 0 {
+            return Ok(false);
+        }
+
+        match self.next_cluster(cursor.cluster).await? {
+            Some(next) => {
+                cursor.cluster = next;
+                cursor.index = 0;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Calls `f` with the raw entries of `dir`, until it returns true or the free entry ending the
+    /// directory is reached.
+    ///
+    /// Returns the location of the entry for which `f` returned true.
+    async fn walk_dir(
+        &mut self,
+        dir: &Dir,
+        mut f: impl FnMut(&[u8], EntryLocation) -> bool,
+    ) -> Result<Option<EntryLocation>, Error<D::Error>> {
+        let mut cursor = DirCursor {
+            cluster: dir.cluster,
+            index: 0,
+        };
+        loop {
+            let sector = self.cursor_sector(&cursor);
+            self.load(sector).await?;
+            for offset in (0..SECTOR_SIZE).step_by(ENTRY_SIZE) {
+                let raw = &self.cache.buf[offset..offset + ENTRY_SIZE];
+                let location = EntryLocation { sector, offset };
+                if f(raw, location) {
+                    return Ok(Some(location));
+                }
+                if raw[0] == END_OF_DIR {
+                    return Ok(None);
+                }
+            }
+            if !self.advance(&mut cursor).await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    async fn find_entry(
+        &mut self,
+        dir: &Dir,
+        name: &ShortName,
+    ) -> Result<Option<DirEntry>, Error<D::Error>> {
+        let mut found = None;
+        self.walk_dir(dir, |raw, location| match DirEntry::parse(raw, location) {
+            Some(entry) if entry.name == *name => {
+                found = Some(entry);
+                true
+            }
+            _ => false,
+        })
+        .await?;
+        Ok(found)
+    }
+
+    /// Checks that `name` is valid for a new entry of `dir`.
+    async fn new_name(&mut self, dir: &Dir, name: &str) -> Result<ShortName, Error<D::Error>> {
+        let name = ShortName::new(name)
+            .filter(|name| !name.is_dot())
+            .ok_or(Error::InvalidName)?;
+        if self.find_entry(dir, &name).await?.is_some() {
+            return Err(Error::AlreadyExists);
+        }
+        Ok(name)
+    }
+
+    /// Writes `entry` in a free entry of `dir`, which is extended if it is full.
+    async fn add_entry(
+        &mut self,
+        dir: &Dir,
+        mut entry: DirEntry,
+    ) -> Result<DirEntry, Error<D::Error>> {
+        let mut cursor = DirCursor {
+            cluster: dir.cluster,
+            index: 0,
+        };
+        let location = loop {
+            let sector = self.cursor_sector(&cursor);
+            self.load(sector).await?;
+            let buf = &self.cache.buf;
+            let free = (0..SECTOR_SIZE)
+                .step_by(ENTRY_SIZE)
+                .find(|&offset| buf[offset] == END_OF_DIR || buf[offset] == DELETED);
+            if let Some(offset) = free {
+                break EntryLocation { sector, offset };
+            }
+
+            if !self.advance(&mut cursor).await? {
+                if cursor.cluster == 0 {
+                    return Err(Error::DirectoryFull);
+                }
+                let cluster = self.alloc_cluster(Some(cursor.cluster)).await?;
+                self.zero_cluster(cluster).await?;
+                break EntryLocation {
+                    sector: self.layout.cluster_start(cluster),
+                    offset: 0,
+                };
+            }
+        };
+
+        let buf = self.load_mut(location.sector).await?;
+        entry.serialize(&mut buf[location.offset..location.offset + ENTRY_SIZE]);
+        entry.location = location;
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::block_on;
+    use core::future::{ready, Ready};
+    use std::string::{String, ToString};
+    use std::vec;
+    use std::vec::Vec;
+
+    struct RamDisk(Vec<u8>);
+
+    impl BlockDevice for RamDisk {
+        type Error = ();
+        type ReadFuture<'a> = Ready<Result<(), ()>>;
+        type WriteFuture<'a> = Ready<Result<(), ()>>;
+
+        fn read<'a>(&'a mut self, block_idx: u32, buf: &'a mut [u8]) -> Self::ReadFuture<'a> {
+            let start = block_idx as usize * SECTOR_SIZE;
+            buf.copy_from_slice(&self.0[start..start + buf.len()]);
+            ready(Ok(()))
+        }
+
+        fn write<'a>(&'a mut self, block_idx: u32, buf: &'a [u8]) -> Self::WriteFuture<'a> {
+            let start = block_idx as usize * SECTOR_SIZE;
+            self.0[start..start + buf.len()].copy_from_slice(buf);
+            ready(Ok(()))
+        }
+
+        fn block_size(&self) -> usize {
+            SECTOR_SIZE
+        }
+
+        fn block_count(&self) -> u32 {
+            (self.0.len() / SECTOR_SIZE) as u32
+        }
+    }
+
+    impl RamDisk {
+        fn sector(&mut self, sector: u32) -> &mut [u8] {
+            let start = sector as usize * SECTOR_SIZE;
+            &mut self.0[start..start + SECTOR_SIZE]
+        }
+    }
+
+    /// Formats an empty volume of `total_sectors` at sector `start` of a new disk.
+    fn format(
+        fat_type: FatType,
+        start: u32,
+        total_sectors: u32,
+        sectors_per_cluster: u8,
+    ) -> RamDisk {
+        let (reserved, root_entries, fat_size): (u16, u16, u32) = match fat_type {
+            FatType::Fat12 => (1, 512, 12),
+            FatType::Fat16 => (1, 512, 128),
+            FatType::Fat32 => (32, 0, (total_sectors * 4 + 511) / 512),
+        };
+
+        let mut disk = RamDisk(vec![0; (start + total_sectors) as usize * SECTOR_SIZE]);
+        let boot = disk.sector(start);
+        boot[0] = 0xEB;
+        boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+        boot[13] = sectors_per_cluster;
+        boot[14..16].copy_from_slice(&reserved.to_le_bytes());
+        boot[16] = 2;
+        boot[17..19].copy_from_slice(&root_entries.to_le_bytes());
+        boot[21] = 0xF8;
+        boot[32..36].copy_from_slice(&total_sectors.to_le_bytes());
+        match fat_type {
+            FatType::Fat32 => {
+                boot[36..40].copy_from_slice(&fat_size.to_le_bytes());
+                boot[44..48].copy_from_slice(&2u32.to_le_bytes());
+                boot[48..50].copy_from_slice(&1u16.to_le_bytes());
+            }
+            _ => boot[22..24].copy_from_slice(&(fat_size as u16).to_le_bytes()),
+        }
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+
+        if fat_type == FatType::Fat32 {
+            let fs_info = disk.sector(start + 1);
+            fs_info[..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+            fs_info[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+            fs_info[488..496].fill(0);
+            fs_info[508..512].copy_from_slice(&0xAA55_0000u32.to_le_bytes());
+        }
+
+        // Media descriptor, end of chain marker, and the root directory cluster of FAT32
+        let first_entries: &[u8] = match fat_type {
+            FatType::Fat12 => &[0xF8, 0xFF, 0xFF],
+            FatType::Fat16 => &[0xF8, 0xFF, 0xFF, 0xFF],
+            FatType::Fat32 => &[
+                0xF8, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F,
+            ],
+        };
+        for i in 0..2 {
+            let fat = disk.sector(start + reserved as u32 + i * fat_size);
+            fat[..first_entries.len()].copy_from_slice(first_entries);
+        }
+
+        disk
+    }
+
+    fn names<D: BlockDevice>(volume: &mut Volume<D>, dir: &Dir) -> Vec<String>
+    where
+        D::Error: core::fmt::Debug,
+    {
+        let mut names = Vec::new();
+        block_on(volume.read_dir(dir, |entry| names.push(entry.name().to_string()))).unwrap();
+        names
+    }
+
+    fn files_and_directories(fat_type: FatType, total_sectors: u32) {
+        let disk = format(fat_type, 0, total_sectors, 1);
+        let mut volume = block_on(Volume::open(disk)).unwrap();
+        assert_eq!(volume.fat_type(), fat_type);
+        let root = volume.root_dir();
+
+        // A file spanning several clusters, written with unaligned chunks
+        let data: Vec<u8> = (0..3000u32).map(|i| (i * 7) as u8).collect();
+        let mut file = block_on(volume.open_file(&root, "data.bin", Mode::Truncate)).unwrap();
+        for chunk in data.chunks(700) {
+            block_on(volume.write(&mut file, chunk)).unwrap();
+        }
+        block_on(volume.close_file(file)).unwrap();
+
+        let logs = block_on(volume.create_dir(&root, "logs")).unwrap();
+        let mut file = block_on(volume.open_file(&logs, "boot.log", Mode::Append)).unwrap();
+        block_on(volume.write(&mut file, b"hello ")).unwrap();
+        block_on(volume.close_file(file)).unwrap();
+        let mut file = block_on(volume.open_file(&logs, "boot.log", Mode::Append)).unwrap();
+        block_on(volume.write(&mut file, b"world")).unwrap();
+        block_on(volume.close_file(file)).unwrap();
+
+        // Reopen the volume from the disk
+        let disk = block_on(volume.close()).unwrap();
+        let mut volume = block_on(Volume::open(disk)).unwrap();
+        let root = volume.root_dir();
+        assert_eq!(names(&mut volume, &root), ["DATA.BIN", "LOGS"]);
+
+        let entry = block_on(volume.find(&root, "DATA.BIN")).unwrap();
+        assert_eq!(entry.size(), 3000);
+        assert!(!entry.is_dir());
+
+        let mut file = block_on(volume.open_file(&root, "data.bin", Mode::Read)).unwrap();
+        let mut read = vec![0; 4000];
+        assert_eq!(block_on(volume.read(&mut file, &mut read[..1])).unwrap(), 1);
+        assert_eq!(
+            block_on(volume.read(&mut file, &mut read[1..])).unwrap(),
+            2999
+        );
+        assert_eq!(&read[..3000], &data[..]);
+        assert_eq!(block_on(volume.read(&mut file, &mut read)).unwrap(), 0);
+
+        block_on(async {
+            volume.seek(&mut file, 1500)?;
+            volume.read(&mut file, &mut read[..10]).await
+        })
+        .unwrap();
+        assert_eq!(&read[..10], &data[1500..1510]);
+        assert_eq!(volume.seek(&mut file, 3001), Err(Error::InvalidSeek));
+        assert_eq!(
+            block_on(volume.write(&mut file, b"x")),
+            Err(Error::ReadOnly)
+        );
+
+        let logs = block_on(volume.open_dir(&root, "LOGS")).unwrap();
+        assert_eq!(names(&mut volume, &logs), ["BOOT.LOG"]);
+        let mut file = block_on(volume.open_file(&logs, "boot.log", Mode::Read)).unwrap();
+        let n = block_on(volume.read(&mut file, &mut read)).unwrap();
+        assert_eq!(&read[..n], b"hello world");
+        assert_eq!(block_on(volume.open_dir(&logs, "..")).unwrap(), root);
+    }
+
+    #[test]
+    fn fat12() {
+        files_and_directories(FatType::Fat12, 4096);
+    }
+
+    #[test]
+    fn fat16() {
+        files_and_directories(FatType::Fat16, 32768);
+    }
+
+    #[test]
+    fn fat32() {
+        files_and_directories(FatType::Fat32, 70000);
+    }
+
+    #[test]
+    fn partitioned_disk() {
+        let mut disk = format(FatType::Fat16, 2048, 32768, 4);
+        let mbr = disk.sector(0);
+        mbr[446 + 4] = 0x06;
+        mbr[446 + 8..446 + 12].copy_from_slice(&2048u32.to_le_bytes());
+        mbr[510] = 0x55;
+        mbr[511] = 0xAA;
+
+        let mut volume = block_on(Volume::open(disk)).unwrap();
+        let root = volume.root_dir();
+        let mut file = block_on(volume.open_file(&root, "a.txt", Mode::Truncate)).unwrap();
+        block_on(volume.write(&mut file, b"partition")).unwrap();
+        block_on(volume.close_file(file)).unwrap();
+
+        let mut disk = block_on(volume.close()).unwrap();
+        // The data starts after the 512 entries of the root directory
+        let data_start = 2048 + 1 + 2 * 128 + 32;
+        assert_eq!(&disk.sector(data_start)[..9], b"partition");
+        assert!(block_on(Volume::open(RamDisk(vec![0; 4096]))).is_err());
+    }
+
+    #[test]
+    fn fat_copies_and_fs_info() {
+        let disk = format(FatType::Fat32, 0, 70000, 1);
+        let mut volume = block_on(Volume::open(disk)).unwrap();
+        let root = volume.root_dir();
+        block_on(volume.create_dir(&root, "dir")).unwrap();
+        let mut disk = block_on(volume.close()).unwrap();
+
+        let fat_size = (70000 * 4 + 511) / 512;
+        let first = disk.sector(32).to_vec();
+        assert_eq!(&first[12..16], &[0xFF, 0xFF, 0xFF, 0x0F]);
+        assert_eq!(disk.sector(32 + fat_size), &first[..]);
+        assert_eq!(&disk.sector(1)[488..496], &[0xFF; 8]);
+    }
+
+    #[test]
+    fn truncate_frees_clusters() {
+        let disk = format(FatType::Fat12, 0, 4096, 1);
+        let mut volume = block_on(Volume::open(disk)).unwrap();
+        let root = volume.root_dir();
+
+        let mut file = block_on(volume.open_file(&root, "big", Mode::Truncate)).unwrap();
+        block_on(volume.write(&mut file, &[1; 2048])).unwrap();
+        block_on(volume.close_file(file)).unwrap();
+        // Clusters 2 to 5 are chained
+        assert_eq!(block_on(volume.fat_entry(2)).unwrap(), 3);
+        assert_eq!(block_on(volume.fat_entry(3)).unwrap(), 4);
+        assert_eq!(block_on(volume.fat_entry(5)).unwrap(), 0xFFF);
+
+        let file = block_on(volume.open_file(&root, "big", Mode::Truncate)).unwrap();
+        assert_eq!(file.size(), 0);
+        block_on(volume.close_file(file)).unwrap();
+        for cluster in 2..6 {
+            assert_eq!(block_on(volume.fat_entry(cluster)).unwrap(), 0);
+        }
+        assert_eq!(block_on(volume.find(&root, "big")).unwrap().size(), 0);
+    }
+
+    #[test]
+    fn directories_grow() {
+        let disk = format(FatType::Fat16, 0, 32768, 1);
+        let mut volume = block_on(Volume::open(disk)).unwrap();
+        let root = volume.root_dir();
+        let dir = block_on(volume.create_dir(&root, "many")).unwrap();
+
+        // 16 entries per cluster, including `.` and `..`
+        for i in 0..40 {
+            let name = std::format!("f{}", i);
+            let file = block_on(volume.open_file(&dir, &name, Mode::Truncate)).unwrap();
+            block_on(volume.close_file(file)).unwrap();
+        }
+        assert_eq!(names(&mut volume, &dir).len(), 40);
+        assert!(block_on(volume.find(&dir, "F39")).is_ok());
+    }
+
+    #[test]
+    fn errors() {
+        let disk = format(FatType::Fat12, 0, 4096, 1);
+        let mut volume = block_on(Volume::open(disk)).unwrap();
+        let root = volume.root_dir();
+        let dir = block_on(volume.create_dir(&root, "dir")).unwrap();
+
+        assert_eq!(
+            block_on(volume.create_dir(&root, "DIR")),
+            Err(Error::AlreadyExists)
+        );
+        assert_eq!(
+            block_on(volume.open_file(&root, "dir", Mode::Read)).unwrap_err(),
+            Error::IsADirectory
+        );
+        assert_eq!(
+            block_on(volume.open_file(&root, "none", Mode::ReadWrite)).unwrap_err(),
+            Error::NotFound
+        );
+        assert_eq!(
+            block_on(volume.open_file(&dir, "long_name.txt", Mode::Truncate)).unwrap_err(),
+            Error::InvalidName
+        );
+
+        let file = block_on(volume.open_file(&root, "file", Mode::Truncate)).unwrap();
+        block_on(volume.close_file(file)).unwrap();
+        assert_eq!(
+            block_on(volume.open_dir(&root, "file")),
+            Err(Error::NotADirectory)
+        );
+
+        // The fixed root directory holds 512 entries
+        for i in 2..512 {
+            let name = std::format!("f{}", i);
+            block_on(volume.create_dir(&root, &name)).unwrap();
+        }
+        assert_eq!(
+            block_on(volume.create_dir(&root, "full")),
+            Err(Error::DirectoryFull)
+        );
+    }
+}
@@ -122,6 +122,12 @@ embassy_hal_common::peripherals! {
 
     // TEMP
     TEMP,
+
+    // QDEC
+    QDEC,
+
+    // RADIO
+    RADIO,
 }
 
 impl_uarte!(UARTE0, UARTE0, UARTE0_UART0);
@@ -134,6 +140,10 @@ impl_timer!(TIMER0, TIMER0, TIMER0);
 impl_timer!(TIMER1, TIMER1, TIMER1);
 impl_timer!(TIMER2, TIMER2, TIMER2);
 
+impl_rtc!(RTC0, RTC0, RTC0, 3);
+#[cfg(not(feature = "_time-driver"))]
+impl_rtc!(RTC1, RTC1, RTC1, 4);
+
 impl_pin!(P0_00, 0, 0);
 impl_pin!(P0_01, 0, 1);
 impl_pin!(P0_02, 0, 2);
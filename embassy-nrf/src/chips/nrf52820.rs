@@ -123,6 +123,15 @@ embassy_hal_common::peripherals! {
 
     // TEMP
     TEMP,
+
+    // QDEC
+    QDEC,
+
+    // RADIO
+    RADIO,
+
+    // COMP
+    COMP,
 }
 
 impl_usb!(USBD, USBD, USBD);
@@ -140,6 +149,10 @@ impl_timer!(TIMER1, TIMER1, TIMER1);
 impl_timer!(TIMER2, TIMER2, TIMER2);
 impl_timer!(TIMER3, TIMER3, TIMER3, extended);
 
+impl_rtc!(RTC0, RTC0, RTC0, 3);
+#[cfg(not(feature = "_time-driver"))]
+impl_rtc!(RTC1, RTC1, RTC1, 4);
+
 impl_pin!(P0_00, 0, 0);
 impl_pin!(P0_01, 0, 1);
 impl_pin!(P0_02, 0, 2);
@@ -206,6 +219,15 @@ impl_ppi_channel!(PPI_CH29, 29 => static);
 impl_ppi_channel!(PPI_CH30, 30 => static);
 impl_ppi_channel!(PPI_CH31, 31 => static);
 
+impl_comp_input!(P0_02, 0);
+impl_comp_input!(P0_03, 1);
+impl_comp_input!(P0_04, 2);
+impl_comp_input!(P0_05, 3);
+impl_comp_input!(P0_28, 4);
+impl_comp_input!(P0_29, 5);
+impl_comp_input!(P0_30, 6);
+impl_comp_input!(P0_31, 7);
+
 pub mod irqs {
     use crate::pac::Interrupt as InterruptEnum;
     use embassy_macros::interrupt_declare as declare;
@@ -218,6 +218,9 @@ embassy_hal_common::peripherals! {
     RTC0,
     RTC1,
 
+    // IPC
+    IPC,
+
     // WDT
     WDT,
 
@@ -343,6 +346,12 @@ embassy_hal_common::peripherals! {
     P1_13,
     P1_14,
     P1_15,
+
+    // COMP
+    COMP,
+
+    // LPCOMP
+    LPCOMP,
 }
 
 impl_usb!(USBD, USBD, USBD);
@@ -371,6 +380,10 @@ impl_timer!(TIMER0, TIMER0, TIMER0);
 impl_timer!(TIMER1, TIMER1, TIMER1);
 impl_timer!(TIMER2, TIMER2, TIMER2);
 
+impl_rtc!(RTC0, RTC0, RTC0, 4);
+#[cfg(not(feature = "_time-driver"))]
+impl_rtc!(RTC1, RTC1, RTC1, 4);
+
 impl_pin!(P0_00, 0, 0);
 impl_pin!(P0_01, 0, 1);
 impl_pin!(P0_02, 0, 2);
@@ -463,6 +476,15 @@ impl_saadc_input!(P0_18, ANALOGINPUT5);
 impl_saadc_input!(P0_19, ANALOGINPUT6);
 impl_saadc_input!(P0_20, ANALOGINPUT7);
 
+impl_comp_input!(P0_04, 0);
+impl_comp_input!(P0_05, 1);
+impl_comp_input!(P0_06, 2);
+impl_comp_input!(P0_07, 3);
+impl_comp_input!(P0_25, 4);
+impl_comp_input!(P0_26, 5);
+impl_comp_input!(P0_27, 6);
+impl_comp_input!(P0_28, 7);
+
 pub mod irqs {
     use crate::pac::Interrupt as InterruptEnum;
     use embassy_macros::interrupt_declare as declare;
@@ -0,0 +1,355 @@
+//! General purpose comparator (COMP) driver.
+//!
+//! COMP compares an analog input against a reference, either a fraction of one of the internal
+//! or external references with configurable thresholds (single-ended mode), or another analog
+//! input (differential mode).
+//!
+//! On chips that also have LPCOMP, both share the same peripheral instance and interrupt: only
+//! one of [`Comp`] and [`crate::lpcomp::Lpcomp`] can be used at a time.
+
+#![macro_use]
+
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::interrupt;
+use crate::pac;
+use crate::peripherals::COMP;
+use crate::ppi::{Event, Task};
+
+#[cfg(any(feature = "nrf52810", feature = "nrf52811", feature = "nrf52820"))]
+type Irq = interrupt::COMP;
+#[cfg(not(any(feature = "nrf52810", feature = "nrf52811", feature = "nrf52820")))]
+type Irq = interrupt::COMP_LPCOMP;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Internal reference used in single-ended mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Reference {
+    /// 1.2 V internal reference.
+    Int1V2,
+    /// 1.8 V internal reference, VDD must be at least 2.7 V.
+    Int1V8,
+    /// 2.4 V internal reference, VDD must be at least 2.7 V.
+    Int2V4,
+    /// VDD.
+    Vdd,
+}
+
+/// Speed and power mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Speed {
+    Low,
+    Normal,
+    High,
+}
+
+/// COMP configuration.
+///
+/// See the `Default` impl for suitable default values.
+#[non_exhaustive]
+pub struct Config {
+    /// Reference used in single-ended mode, ignored in differential mode and with an external
+    /// reference.
+    pub reference: Reference,
+    /// Speed and power mode.
+    pub speed: Speed,
+    /// Single-ended mode lower threshold (0..=63): the output goes low when the input falls
+    /// below `(threshold_down + 1) / 64 * VREF`.
+    pub threshold_down: u8,
+    /// Single-ended mode upper threshold (0..=63): the output goes high when the input rises
+    /// above `(threshold_up + 1) / 64 * VREF`.
+    pub threshold_up: u8,
+    /// Differential mode 50 mV hysteresis.
+    pub hysteresis: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            reference: Reference::Int1V2,
+            speed: Speed::Normal,
+            threshold_down: 30,
+            threshold_up: 32,
+            hysteresis: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Edge {
+    Up,
+    Down,
+    Cross,
+}
+
+impl Edge {
+    fn is_set(self, r: &pac::comp::RegisterBlock) -> bool {
+        match self {
+            Edge::Up => r.events_up.read().bits() != 0,
+            Edge::Down => r.events_down.read().bits() != 0,
+            Edge::Cross => r.events_cross.read().bits() != 0,
+        }
+    }
+
+    fn reset(self, r: &pac::comp::RegisterBlock) {
+        match self {
+            Edge::Up => r.events_up.reset(),
+            Edge::Down => r.events_down.reset(),
+            Edge::Cross => r.events_cross.reset(),
+        }
+    }
+
+    fn enable_irq(self, r: &pac::comp::RegisterBlock) {
+        match self {
+            Edge::Up => r.intenset.write(|w| w.up().set()),
+            Edge::Down => r.intenset.write(|w| w.down().set()),
+            Edge::Cross => r.intenset.write(|w| w.cross().set()),
+        }
+    }
+
+    fn disable_irq(self, r: &pac::comp::RegisterBlock) {
+        match self {
+            Edge::Up => r.intenclr.write(|w| w.up().clear()),
+            Edge::Down => r.intenclr.write(|w| w.down().clear()),
+            Edge::Cross => r.intenclr.write(|w| w.cross().clear()),
+        }
+    }
+}
+
+/// Comparator driver.
+pub struct Comp<'d> {
+    phantom: PhantomData<&'d mut COMP>,
+    _irq: Irq,
+}
+
+impl<'d> Comp<'d> {
+    /// Creates a single-ended comparator, comparing `input` against a fraction of
+    /// `config.reference`.
+    pub fn new(
+        comp: impl Unborrow<Target = COMP> + 'd,
+        irq: impl Unborrow<Target = Irq> + 'd,
+        input: impl Unborrow<Target = impl Input> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(input);
+        let r = Self::regs();
+        r.refsel.write(|w| match config.reference {
+            Reference::Int1V2 => w.refsel().int1v2(),
+            Reference::Int1V8 => w.refsel().int1v8(),
+            Reference::Int2V4 => w.refsel().int2v4(),
+            Reference::Vdd => w.refsel().vdd(),
+        });
+        Self::new_inner(comp, irq, input.ain(), None, false, config)
+    }
+
+    /// Creates a single-ended comparator, comparing `input` against a fraction of the voltage
+    /// on the `reference` analog input.
+    pub fn new_with_external_reference(
+        comp: impl Unborrow<Target = COMP> + 'd,
+        irq: impl Unborrow<Target = Irq> + 'd,
+        input: impl Unborrow<Target = impl Input> + 'd,
+        reference: impl Unborrow<Target = impl Input> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(input, reference);
+        Self::regs().refsel.write(|w| w.refsel().aref());
+        Self::new_inner(comp, irq, input.ain(), Some(reference.ain()), false, config)
+    }
+
+    /// Creates a differential comparator, comparing `p_input` against `n_input`.
+    pub fn new_differential(
+        comp: impl Unborrow<Target = COMP> + 'd,
+        irq: impl Unborrow<Target = Irq> + 'd,
+        p_input: impl Unborrow<Target = impl Input> + 'd,
+        n_input: impl Unborrow<Target = impl Input> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(p_input, n_input);
+        Self::new_inner(comp, irq, p_input.ain(), Some(n_input.ain()), true, config)
+    }
+
+    fn new_inner(
+        _comp: impl Unborrow<Target = COMP> + 'd,
+        irq: impl Unborrow<Target = Irq> + 'd,
+        psel: u8,
+        extrefsel: Option<u8>,
+        differential: bool,
+        config: Config,
+    ) -> Self {
+        unborrow!(irq);
+
+        let r = Self::regs();
+
+        r.psel.write(|w| unsafe { w.bits(psel as u32) });
+        if let Some(extrefsel) = extrefsel {
+            r.extrefsel.write(|w| unsafe { w.bits(extrefsel as u32) });
+        }
+
+        r.mode.write(|w| {
+            match config.speed {
+                Speed::Low => w.sp().low(),
+                Speed::Normal => w.sp().normal(),
+                Speed::High => w.sp().high(),
+            };
+            if differential {
+                w.main().diff()
+            } else {
+                w.main().se()
+            }
+        });
+        r.th.write(|w| unsafe {
+            w.thdown().bits(config.threshold_down.min(63));
+            w.thup().bits(config.threshold_up.min(63));
+            w
+        });
+        r.hyst.write(|w| w.hyst().bit(config.hysteresis));
+
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        r.shorts.reset();
+
+        irq.disable();
+        irq.set_handler(|_| {
+            let r = Self::regs();
+            if r.events_up.read().bits() != 0 {
+                r.intenclr.write(|w| w.up().clear());
+            }
+            if r.events_down.read().bits() != 0 {
+                r.intenclr.write(|w| w.down().clear());
+            }
+            if r.events_cross.read().bits() != 0 {
+                r.intenclr.write(|w| w.cross().clear());
+            }
+            WAKER.wake();
+        });
+        irq.unpend();
+        irq.enable();
+
+        r.enable.write(|w| w.enable().enabled());
+
+        // Startup takes a few microseconds.
+        r.events_ready.reset();
+        r.tasks_start.write(|w| unsafe { w.bits(1) });
+        while r.events_ready.read().bits() == 0 {}
+        r.events_ready.reset();
+
+        Self {
+            phantom: PhantomData,
+            _irq: irq,
+        }
+    }
+
+    fn regs() -> &'static pac::comp::RegisterBlock {
+        unsafe { &*pac::COMP::ptr() }
+    }
+
+    /// Samples the comparator output, returning `true` if the input is above the reference.
+    pub fn sample(&mut self) -> bool {
+        let r = Self::regs();
+        r.tasks_sample.write(|w| unsafe { w.bits(1) });
+        r.result.read().result().is_above()
+    }
+
+    /// Waits for the input to rise above the reference.
+    pub async fn wait_up(&mut self) {
+        Self::wait(Edge::Up).await
+    }
+
+    /// Waits for the input to fall below the reference.
+    pub async fn wait_down(&mut self) {
+        Self::wait(Edge::Down).await
+    }
+
+    /// Waits for the input to cross the reference in either direction.
+    pub async fn wait_cross(&mut self) {
+        Self::wait(Edge::Cross).await
+    }
+
+    async fn wait(edge: Edge) {
+        let r = Self::regs();
+
+        // In case the future is dropped, don't leave the interrupt enabled.
+        let on_drop = OnDrop::new(|| edge.disable_irq(Self::regs()));
+
+        edge.reset(r);
+        edge.enable_irq(r);
+
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if edge.is_set(r) {
+                edge.reset(r);
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        drop(on_drop);
+    }
+
+    /// Returns the SAMPLE task, for use with PPI.
+    pub fn task_sample(&self) -> Task {
+        Task::from_reg(&Self::regs().tasks_sample)
+    }
+
+    /// Returns the STOP task, for use with PPI.
+    pub fn task_stop(&self) -> Task {
+        Task::from_reg(&Self::regs().tasks_stop)
+    }
+
+    /// Returns the UP event, for use with PPI.
+    pub fn event_up(&self) -> Event {
+        Event::from_reg(&Self::regs().events_up)
+    }
+
+    /// Returns the DOWN event, for use with PPI.
+    pub fn event_down(&self) -> Event {
+        Event::from_reg(&Self::regs().events_down)
+    }
+
+    /// Returns the CROSS event, for use with PPI.
+    pub fn event_cross(&self) -> Event {
+        Event::from_reg(&Self::regs().events_cross)
+    }
+}
+
+impl<'d> Drop for Comp<'d> {
+    fn drop(&mut self) {
+        let r = Self::regs();
+        r.tasks_stop.write(|w| unsafe { w.bits(1) });
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        r.enable.write(|w| w.enable().disabled());
+    }
+}
+
+pub(crate) mod sealed {
+    pub trait Input {
+        /// Index of the analog input (AINn).
+        fn ain(&self) -> u8;
+    }
+}
+
+/// An analog input of the comparators.
+pub trait Input: sealed::Input + Unborrow<Target = Self> {}
+
+macro_rules! impl_comp_input {
+    ($pin:ident, $ain:expr) => {
+        impl crate::comp::sealed::Input for crate::peripherals::$pin {
+            fn ain(&self) -> u8 {
+                $ain
+            }
+        }
+        impl crate::comp::Input for crate::peripherals::$pin {}
+    };
+}
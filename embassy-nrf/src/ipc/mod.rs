@@ -0,0 +1,257 @@
+//! Interprocessor communication (IPC) driver.
+//!
+//! The IPC peripheral lets the cores of a multi-core chip signal each other. Each core has a
+//! set of SEND tasks and RECEIVE events, which are connected through IPC channels: triggering
+//! SEND task `n` signals all the channels enabled in `SEND_CNF[n]`, and RECEIVE event `m` fires
+//! when any of the channels enabled in `RECEIVE_CNF[m]` is signalled.
+//!
+//! IPC only carries notifications, data is exchanged through shared RAM. [`Mailbox`] combines
+//! IPC events with a pair of [`ring::Ring`]s to exchange framed messages.
+
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::interrupt;
+use crate::pac;
+use crate::peripherals::IPC;
+use crate::ppi::{Event, Task};
+
+pub mod ring;
+
+use ring::Ring;
+
+/// Number of SEND tasks, RECEIVE events and IPC channels.
+#[cfg(feature = "_nrf5340")]
+pub const EVENT_COUNT: usize = 16;
+/// Number of SEND tasks, RECEIVE events and IPC channels.
+#[cfg(feature = "_nrf9160")]
+pub const EVENT_COUNT: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// There's not enough free space in the ring right now.
+    Full,
+    /// The message is larger than the ring can ever hold.
+    MessageTooLarge,
+    /// The destination buffer is too small for the next message.
+    BufferTooShort,
+    /// The ring contents are inconsistent, the other side is misbehaving.
+    Corrupted,
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NEW_AW: AtomicWaker = AtomicWaker::new();
+static WAKERS: [AtomicWaker; EVENT_COUNT] = [NEW_AW; EVENT_COUNT];
+
+fn regs() -> &'static pac::ipc::RegisterBlock {
+    unsafe { &*pac::IPC::ptr() }
+}
+
+/// IPC driver.
+pub struct Ipc<'d> {
+    phantom: PhantomData<&'d mut IPC>,
+    _irq: interrupt::IPC,
+}
+
+impl<'d> Ipc<'d> {
+    pub fn new(
+        _ipc: impl Unborrow<Target = IPC> + 'd,
+        irq: impl Unborrow<Target = interrupt::IPC> + 'd,
+    ) -> Self {
+        unborrow!(irq);
+
+        let r = regs();
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+
+        irq.disable();
+        irq.set_handler(|_| {
+            let r = regs();
+            let pending = r.intpend.read().bits();
+            for n in 0..EVENT_COUNT {
+                if pending & (1 << n) != 0 {
+                    // Leave the event set, it's consumed by the future.
+                    r.intenclr.write(|w| unsafe { w.bits(1 << n) });
+                    WAKERS[n].wake();
+                }
+            }
+        });
+        irq.unpend();
+        irq.enable();
+
+        Self {
+            phantom: PhantomData,
+            _irq: irq,
+        }
+    }
+
+    /// Sets the IPC channels signalled by SEND task `event`, as a bitmask.
+    ///
+    /// # Panics
+    /// Panics if `event` >= [`EVENT_COUNT`].
+    pub fn configure_send(&mut self, event: usize, channels: u32) {
+        assert!(event < EVENT_COUNT);
+        regs().send_cnf[event].write(|w| unsafe { w.bits(channels) });
+    }
+
+    /// Sets the IPC channels that trigger RECEIVE event `event`, as a bitmask.
+    ///
+    /// # Panics
+    /// Panics if `event` >= [`EVENT_COUNT`].
+    pub fn configure_receive(&mut self, event: usize, channels: u32) {
+        assert!(event < EVENT_COUNT);
+        regs().receive_cnf[event].write(|w| unsafe { w.bits(channels) });
+    }
+
+    /// Triggers SEND task `event`, signalling the channels configured for it.
+    pub fn send(&self, event: usize) {
+        assert!(event < EVENT_COUNT);
+        regs().tasks_send[event].write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Waits for RECEIVE event `event`.
+    ///
+    /// If the event already happened since the last call, this returns immediately. The event
+    /// is cleared before returning.
+    pub async fn wait(&self, event: usize) {
+        assert!(event < EVENT_COUNT);
+        let r = regs();
+
+        r.intenset.write(|w| unsafe { w.bits(1 << event) });
+
+        poll_fn(|cx| {
+            WAKERS[event].register(cx.waker());
+
+            if r.events_receive[event].read().bits() != 0 {
+                r.events_receive[event].reset();
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+    }
+
+    /// Clears RECEIVE event `event`, discarding notifications received so far.
+    pub fn clear(&self, event: usize) {
+        assert!(event < EVENT_COUNT);
+        regs().events_receive[event].reset();
+    }
+
+    /// Returns SEND task `event`, for use with PPI.
+    pub fn task_send(&self, event: usize) -> Task {
+        assert!(event < EVENT_COUNT);
+        Task::from_reg(&regs().tasks_send[event])
+    }
+
+    /// Returns RECEIVE event `event`, for use with PPI.
+    pub fn event_receive(&self, event: usize) -> Event {
+        assert!(event < EVENT_COUNT);
+        Event::from_reg(&regs().events_receive[event])
+    }
+}
+
+impl<'d> Drop for Ipc<'d> {
+    fn drop(&mut self) {
+        regs()
+            .intenclr
+            .write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+    }
+}
+
+/// Message exchange with the other core over shared memory rings.
+///
+/// Each side owns the ring it sends on and rings a doorbell (an IPC channel) after every push
+/// and pop, so the other side can wait for data or for free space.
+///
+/// Both sides must use the same two memory regions, with `tx` and `rx` swapped, and opposite
+/// doorbell channels. Exactly one side should call [`Mailbox::reset`] before the other starts.
+pub struct Mailbox<'a, 'd> {
+    ipc: &'a mut Ipc<'d>,
+    tx: Ring,
+    rx: Ring,
+    tx_event: usize,
+    rx_event: usize,
+}
+
+impl<'a, 'd> Mailbox<'a, 'd> {
+    /// Creates a mailbox using IPC channel `tx_channel` to notify the other core, and
+    /// `rx_channel` to be notified by it.
+    ///
+    /// SEND task and RECEIVE event numbers are the same as the channel numbers.
+    pub fn new(
+        ipc: &'a mut Ipc<'d>,
+        tx: Ring,
+        rx: Ring,
+        tx_channel: usize,
+        rx_channel: usize,
+    ) -> Self {
+        ipc.configure_send(tx_channel, 1 << tx_channel);
+        ipc.configure_receive(rx_channel, 1 << rx_channel);
+        Self {
+            ipc,
+            tx,
+            rx,
+            tx_event: tx_channel,
+            rx_event: rx_channel,
+        }
+    }
+
+    /// Empties both rings.
+    pub fn reset(&mut self) {
+        self.tx.reset();
+        self.rx.reset();
+    }
+
+    /// Sends a message, waiting for free space in the ring if needed.
+    pub async fn send(&mut self, msg: &[u8]) -> Result<(), Error> {
+        loop {
+            // Clear first, so a doorbell rung between `push` and `wait` isn't lost.
+            self.ipc.clear(self.rx_event);
+            match self.tx.push(msg) {
+                Ok(()) => {
+                    self.ipc.send(self.tx_event);
+                    return Ok(());
+                }
+                Err(Error::Full) => self.ipc.wait(self.rx_event).await,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Sends a message if there's space in the ring, without waiting.
+    pub fn try_send(&mut self, msg: &[u8]) -> Result<(), Error> {
+        self.tx.push(msg)?;
+        self.ipc.send(self.tx_event);
+        Ok(())
+    }
+
+    /// Receives a message into `buf`, waiting until one is available. Returns its length.
+    pub async fn receive(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        loop {
+            self.ipc.clear(self.rx_event);
+            if let Some(len) = self.rx.pop(buf)? {
+                // Tell the sender there's free space.
+                self.ipc.send(self.tx_event);
+                return Ok(len);
+            }
+            self.ipc.wait(self.rx_event).await;
+        }
+    }
+
+    /// Receives a message into `buf` if one is available, without waiting.
+    pub fn try_receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Error> {
+        let res = self.rx.pop(buf)?;
+        if res.is_some() {
+            self.ipc.send(self.tx_event);
+        }
+        Ok(res)
+    }
+}
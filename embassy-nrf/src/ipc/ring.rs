@@ -0,0 +1,333 @@
+//! Single-producer single-consumer message ring in shared memory.
+//!
+//! The ring lives in a RAM region visible to both cores. It doesn't depend on the IPC peripheral,
+//! [`Mailbox`](super::Mailbox) combines it with IPC events for notifications.
+//!
+//! Layout, all fields little-endian and 4-byte aligned:
+//!
+//! | offset | content                                  |
+//! |--------|------------------------------------------|
+//! | 0      | write offset into the data area (`u32`)  |
+//! | 4      | read offset into the data area (`u32`)   |
+//! | 8      | data area                                |
+//!
+//! Each message is stored as a `u32` length word followed by the payload, padded to a multiple
+//! of 4 bytes. The payload wraps around the end of the data area, so that the free space is
+//! usable whatever the offsets are. One word is always kept free, so that a full ring can be
+//! told apart from an empty one.
+
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use super::Error;
+
+/// Size of the ring header (write and read offsets).
+pub const HEADER_LEN: usize = 8;
+
+const WORD: usize = 4;
+
+fn padded(len: usize) -> usize {
+    (len + WORD - 1) & !(WORD - 1)
+}
+
+/// One direction of a shared memory channel.
+///
+/// Only one core may push and only one core may pop.
+pub struct Ring {
+    base: *mut u8,
+    capacity: usize,
+}
+
+unsafe impl Send for Ring {}
+
+impl Ring {
+    /// Creates a ring over the `len` bytes of memory at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// The memory must stay valid for the lifetime of the `Ring`, be 4-byte aligned, and only be
+    /// accessed through `Ring`s agreeing on the same region, by at most one producer and one consumer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ptr` isn't 4-byte aligned, `len` isn't a multiple of 4 or the region is too small
+    /// to hold any message.
+    pub unsafe fn new(ptr: *mut u8, len: usize) -> Self {
+        assert!(ptr as usize % WORD == 0);
+        assert!(len % WORD == 0);
+        assert!(len >= HEADER_LEN + 3 * WORD);
+        Self {
+            base: ptr,
+            capacity: len - HEADER_LEN,
+        }
+    }
+
+    /// Resets the ring to empty.
+    ///
+    /// Must be called by exactly one side, before the other side starts using the ring.
+    pub fn reset(&self) {
+        self.write_idx().store(0, Ordering::Relaxed);
+        self.read_idx().store(0, Ordering::Release);
+    }
+
+    /// Size of the data area, in bytes.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Largest message that can be pushed into an empty ring.
+    pub fn max_message_len(&self) -> usize {
+        // Length word, plus the free word separating the write and read offsets.
+        self.capacity - 2 * WORD
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.write_idx().load(Ordering::Acquire) == self.read_idx().load(Ordering::Acquire)
+    }
+
+    /// Pushes a message. Producer side only.
+    ///
+    /// Returns [`Error::Full`] if there's not enough free space right now, in which case the
+    /// ring is left unchanged.
+    pub fn push(&self, msg: &[u8]) -> Result<(), Error> {
+        if msg.len() > self.max_message_len() {
+            return Err(Error::MessageTooLarge);
+        }
+        let need = WORD + padded(msg.len());
+
+        let write = self.write_idx().load(Ordering::Relaxed) as usize;
+        let read = self.read_idx().load(Ordering::Acquire) as usize;
+        if write >= self.capacity || read >= self.capacity {
+            return Err(Error::Corrupted);
+        }
+
+        let used = (write + self.capacity - read) % self.capacity;
+        if need + WORD > self.capacity - used {
+            return Err(Error::Full);
+        }
+
+        self.write_word(write, msg.len() as u32);
+        self.copy_in((write + WORD) % self.capacity, msg);
+
+        let end = (write + need) % self.capacity;
+        self.write_idx().store(end as u32, Ordering::Release);
+        Ok(())
+    }
+
+    /// Pops a message into `buf`, returning its length. Consumer side only.
+    ///
+    /// Returns `Ok(None)` if the ring is empty. If `buf` is too small, [`Error::BufferTooShort`]
+    /// is returned and the message is left in the ring.
+    pub fn pop(&self, buf: &mut [u8]) -> Result<Option<usize>, Error> {
+        let write = self.write_idx().load(Ordering::Acquire) as usize;
+        let read = self.read_idx().load(Ordering::Relaxed) as usize;
+        if write >= self.capacity || read >= self.capacity {
+            return Err(Error::Corrupted);
+        }
+        if read == write {
+            return Ok(None);
+        }
+
+        let len = self.read_word(read) as usize;
+        let used = (write + self.capacity - read) % self.capacity;
+        if len > self.max_message_len() || WORD + padded(len) > used {
+            return Err(Error::Corrupted);
+        }
+        if buf.len() < len {
+            return Err(Error::BufferTooShort);
+        }
+        self.copy_out((read + WORD) % self.capacity, &mut buf[..len]);
+
+        let end = (read + WORD + padded(len)) % self.capacity;
+        self.read_idx().store(end as u32, Ordering::Release);
+        Ok(Some(len))
+    }
+
+    fn write_idx(&self) -> &AtomicU32 {
+        unsafe { &*(self.base as *const AtomicU32) }
+    }
+
+    fn read_idx(&self) -> &AtomicU32 {
+        unsafe { &*(self.base.add(WORD) as *const AtomicU32) }
+    }
+
+    fn data(&self) -> *mut u8 {
+        unsafe { self.base.add(HEADER_LEN) }
+    }
+
+    /// Copies `data` into the data area at `offset`, wrapping around its end.
+    fn copy_in(&self, offset: usize, data: &[u8]) {
+        let first = data.len().min(self.capacity - offset);
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), self.data().add(offset), first);
+            ptr::copy_nonoverlapping(data[first..].as_ptr(), self.data(), data.len() - first);
+        }
+    }
+
+    /// Copies the data area at `offset` into `buf`, wrapping around its end.
+    fn copy_out(&self, offset: usize, buf: &mut [u8]) {
+        let first = buf.len().min(self.capacity - offset);
+        let len = buf.len();
+        unsafe {
+            ptr::copy_nonoverlapping(self.data().add(offset), buf.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(self.data(), buf[first..].as_mut_ptr(), len - first);
+        }
+    }
+
+    fn write_word(&self, offset: usize, value: u32) {
+        unsafe { ptr::write_volatile(self.data().add(offset) as *mut u32, value.to_le()) }
+    }
+
+    fn read_word(&self, offset: usize) -> u32 {
+        u32::from_le(unsafe { ptr::read_volatile(self.data().add(offset) as *const u32) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(mem: &mut [u32]) -> Ring {
+        let ring = unsafe { Ring::new(mem.as_mut_ptr() as *mut u8, mem.len() * 4) };
+        ring.reset();
+        ring
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut mem = [0u32; 16];
+        let r = ring(&mut mem);
+        assert_eq!(r.capacity(), 56);
+        assert!(r.is_empty());
+
+        let mut buf = [0; 64];
+        assert_eq!(r.pop(&mut buf), Ok(None));
+
+        r.push(b"hello").unwrap();
+        r.push(b"").unwrap();
+        r.push(b"world!!!").unwrap();
+        assert!(!r.is_empty());
+
+        assert_eq!(r.pop(&mut buf), Ok(Some(5)));
+        assert_eq!(&buf[..5], b"hello");
+        assert_eq!(r.pop(&mut buf), Ok(Some(0)));
+        assert_eq!(r.pop(&mut buf), Ok(Some(8)));
+        assert_eq!(&buf[..8], b"world!!!");
+        assert_eq!(r.pop(&mut buf), Ok(None));
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_full() {
+        let mut mem = [0u32; 8];
+        let r = ring(&mut mem);
+        // 24 bytes of data: two 8-byte frames fit, a third would leave no free word.
+        r.push(b"abcd").unwrap();
+        r.push(b"efgh").unwrap();
+        assert_eq!(r.push(b"ijkl"), Err(Error::Full));
+
+        let mut buf = [0; 4];
+        assert_eq!(r.pop(&mut buf), Ok(Some(4)));
+        assert_eq!(&buf, b"abcd");
+        assert_eq!(r.pop(&mut buf), Ok(Some(4)));
+        assert_eq!(&buf, b"efgh");
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_too_large() {
+        let mut mem = [0u32; 8];
+        let r = ring(&mut mem);
+        assert_eq!(r.max_message_len(), 16);
+        assert_eq!(r.push(&[0; 17]), Err(Error::MessageTooLarge));
+        r.push(&[0xAA; 16]).unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(r.pop(&mut buf), Ok(Some(16)));
+        assert_eq!(buf, [0xAA; 16]);
+    }
+
+    #[test]
+    fn test_buffer_too_short_keeps_message() {
+        let mut mem = [0u32; 8];
+        let r = ring(&mut mem);
+        r.push(b"12345").unwrap();
+        let mut small = [0; 4];
+        assert_eq!(r.pop(&mut small), Err(Error::BufferTooShort));
+        let mut buf = [0; 8];
+        assert_eq!(r.pop(&mut buf), Ok(Some(5)));
+        assert_eq!(&buf[..5], b"12345");
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut mem = [0u32; 10];
+        let r = ring(&mut mem);
+        assert_eq!(r.capacity(), 32);
+        let mut buf = [0; 32];
+
+        // Walk the offsets around the ring many times with messages of varying sizes.
+        let mut sent = 0u8;
+        let mut received = 0u8;
+        for round in 0..200usize {
+            let len = round % 13;
+            let msg = [sent; 12];
+            match r.push(&msg[..len]) {
+                Ok(()) => sent = sent.wrapping_add(1),
+                Err(Error::Full) => {}
+                Err(e) => panic!("{:?}", e),
+            }
+            if round % 3 != 0 {
+                if let Some(n) = r.pop(&mut buf).unwrap() {
+                    assert!(buf[..n].iter().all(|&b| b == received));
+                    received = received.wrapping_add(1);
+                }
+            }
+        }
+        while let Some(n) = r.pop(&mut buf).unwrap() {
+            assert!(buf[..n].iter().all(|&b| b == received));
+            received = received.wrapping_add(1);
+        }
+        assert_eq!(sent, received);
+        assert!(sent > 100);
+    }
+
+    #[test]
+    fn test_max_message_with_offsets_mid_buffer() {
+        let mut mem = [0u32; 18];
+        let r = ring(&mut mem);
+        assert_eq!(r.capacity(), 64);
+        assert_eq!(r.max_message_len(), 56);
+        let mut buf = [0; 64];
+
+        r.push(&[1; 28]).unwrap();
+        assert_eq!(r.pop(&mut buf), Ok(Some(28)));
+        assert!(r.is_empty());
+
+        // The ring is empty, but the offsets are in the middle of the data area.
+        let mut msg = [0; 40];
+        for (i, b) in msg.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        r.push(&msg).unwrap();
+        assert_eq!(r.pop(&mut buf), Ok(Some(40)));
+        assert_eq!(&buf[..40], &msg[..]);
+
+        r.push(&[2; 56]).unwrap();
+        assert_eq!(r.pop(&mut buf), Ok(Some(56)));
+        assert_eq!(&buf[..56], &[2; 56][..]);
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let mut mem = [0u32; 8];
+        let r = ring(&mut mem);
+        r.push(b"abcd").unwrap();
+        r.write_word(0, 1000);
+        let mut buf = [0; 32];
+        assert_eq!(r.pop(&mut buf), Err(Error::Corrupted));
+
+        r.write_idx().store(100, Ordering::Relaxed);
+        assert_eq!(r.push(b"x"), Err(Error::Corrupted));
+    }
+}
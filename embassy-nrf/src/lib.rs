@@ -27,18 +27,34 @@ pub(crate) mod util;
 mod time_driver;
 
 pub mod buffered_uarte;
+#[cfg(not(any(feature = "nrf52805", feature = "_nrf5340-net", feature = "_nrf9160")))]
+pub mod comp;
 pub mod gpio;
 #[cfg(feature = "gpiote")]
 pub mod gpiote;
+#[cfg(any(feature = "_nrf5340", feature = "_nrf9160"))]
+pub mod ipc;
+#[cfg(any(
+    feature = "nrf52832",
+    feature = "nrf52833",
+    feature = "nrf52840",
+    feature = "_nrf5340-app"
+))]
+pub mod lpcomp;
 #[cfg(not(any(feature = "_nrf5340", feature = "_nrf9160")))]
 pub mod nvmc;
 pub mod ppi;
 #[cfg(not(any(feature = "nrf52805", feature = "nrf52820", feature = "_nrf5340-net")))]
 pub mod pwm;
+#[cfg(not(any(feature = "_nrf5340", feature = "_nrf9160")))]
+pub mod qdec;
 #[cfg(feature = "nrf52840")]
 pub mod qspi;
 #[cfg(not(any(feature = "_nrf5340", feature = "_nrf9160")))]
+pub mod radio;
+#[cfg(not(any(feature = "_nrf5340", feature = "_nrf9160")))]
 pub mod rng;
+pub mod rtc;
 #[cfg(not(any(feature = "nrf52820", feature = "_nrf5340-net")))]
 pub mod saadc;
 pub mod spim;
@@ -0,0 +1,304 @@
+//! Low power comparator (LPCOMP) driver.
+//!
+//! LPCOMP compares an analog input against a fraction of VDD or of an external reference. It
+//! keeps running in System ON sleep, and can wake the chip up from System OFF (see
+//! [`Config::detect`]).
+//!
+//! LPCOMP shares its peripheral instance and interrupt with COMP: only one of [`Lpcomp`] and
+//! [`crate::comp::Comp`] can be used at a time.
+
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::comp::Input;
+use crate::interrupt;
+use crate::pac;
+use crate::peripherals::LPCOMP;
+use crate::ppi::{Event, Task};
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Reference voltage, as a fraction of VDD or of the external reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Reference {
+    _1_8 = 0,
+    _2_8 = 1,
+    _3_8 = 2,
+    _4_8 = 3,
+    _5_8 = 4,
+    _6_8 = 5,
+    _7_8 = 6,
+    _1_16 = 8,
+    _3_16 = 9,
+    _5_16 = 10,
+    _7_16 = 11,
+    _9_16 = 12,
+    _11_16 = 13,
+    _13_16 = 14,
+    _15_16 = 15,
+}
+
+/// REFSEL value selecting the external reference.
+const REFSEL_AREF: u32 = 7;
+
+/// Crossing that wakes the chip up from System OFF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Detect {
+    Cross,
+    Up,
+    Down,
+}
+
+/// LPCOMP configuration.
+///
+/// See the `Default` impl for suitable default values.
+#[non_exhaustive]
+pub struct Config {
+    /// Fraction of VDD (or of the external reference) the input is compared against.
+    pub reference: Reference,
+    /// Crossing that wakes the chip up from System OFF.
+    pub detect: Detect,
+    /// Enable the 50 mV hysteresis.
+    pub hysteresis: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            reference: Reference::_4_8,
+            detect: Detect::Cross,
+            hysteresis: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Edge {
+    Up,
+    Down,
+    Cross,
+}
+
+impl Edge {
+    fn is_set(self, r: &pac::lpcomp::RegisterBlock) -> bool {
+        match self {
+            Edge::Up => r.events_up.read().bits() != 0,
+            Edge::Down => r.events_down.read().bits() != 0,
+            Edge::Cross => r.events_cross.read().bits() != 0,
+        }
+    }
+
+    fn reset(self, r: &pac::lpcomp::RegisterBlock) {
+        match self {
+            Edge::Up => r.events_up.reset(),
+            Edge::Down => r.events_down.reset(),
+            Edge::Cross => r.events_cross.reset(),
+        }
+    }
+
+    fn enable_irq(self, r: &pac::lpcomp::RegisterBlock) {
+        match self {
+            Edge::Up => r.intenset.write(|w| w.up().set()),
+            Edge::Down => r.intenset.write(|w| w.down().set()),
+            Edge::Cross => r.intenset.write(|w| w.cross().set()),
+        }
+    }
+
+    fn disable_irq(self, r: &pac::lpcomp::RegisterBlock) {
+        match self {
+            Edge::Up => r.intenclr.write(|w| w.up().clear()),
+            Edge::Down => r.intenclr.write(|w| w.down().clear()),
+            Edge::Cross => r.intenclr.write(|w| w.cross().clear()),
+        }
+    }
+}
+
+/// Low power comparator driver.
+pub struct Lpcomp<'d> {
+    phantom: PhantomData<&'d mut LPCOMP>,
+    _irq: interrupt::COMP_LPCOMP,
+}
+
+impl<'d> Lpcomp<'d> {
+    /// Creates a comparator, comparing `input` against a fraction of VDD.
+    pub fn new(
+        lpcomp: impl Unborrow<Target = LPCOMP> + 'd,
+        irq: impl Unborrow<Target = interrupt::COMP_LPCOMP> + 'd,
+        input: impl Unborrow<Target = impl Input> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(input);
+        Self::regs()
+            .refsel
+            .write(|w| unsafe { w.bits(config.reference as u32) });
+        Self::new_inner(lpcomp, irq, input.ain(), config)
+    }
+
+    /// Creates a comparator, comparing `input` against the voltage on the `reference` analog
+    /// input, which must be AIN0 or AIN1.
+    ///
+    /// `config.reference` must be one of the eighths, sixteenths can't be used with an
+    /// external reference.
+    ///
+    /// # Panics
+    /// Panics if `reference` isn't AIN0 or AIN1.
+    pub fn new_with_external_reference(
+        lpcomp: impl Unborrow<Target = LPCOMP> + 'd,
+        irq: impl Unborrow<Target = interrupt::COMP_LPCOMP> + 'd,
+        input: impl Unborrow<Target = impl Input> + 'd,
+        reference: impl Unborrow<Target = impl Input> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(input, reference);
+        assert!(reference.ain() <= 1);
+        let r = Self::regs();
+        r.refsel.write(|w| unsafe { w.bits(REFSEL_AREF) });
+        r.extrefsel
+            .write(|w| unsafe { w.bits(reference.ain() as u32) });
+        Self::new_inner(lpcomp, irq, input.ain(), config)
+    }
+
+    fn new_inner(
+        _lpcomp: impl Unborrow<Target = LPCOMP> + 'd,
+        irq: impl Unborrow<Target = interrupt::COMP_LPCOMP> + 'd,
+        psel: u8,
+        config: Config,
+    ) -> Self {
+        unborrow!(irq);
+
+        let r = Self::regs();
+
+        r.psel.write(|w| unsafe { w.bits(psel as u32) });
+        r.anadetect.write(|w| match config.detect {
+            Detect::Cross => w.anadetect().cross(),
+            Detect::Up => w.anadetect().up(),
+            Detect::Down => w.anadetect().down(),
+        });
+        r.hyst.write(|w| w.hyst().bit(config.hysteresis));
+
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        r.shorts.reset();
+
+        irq.disable();
+        irq.set_handler(|_| {
+            let r = Self::regs();
+            if r.events_up.read().bits() != 0 {
+                r.intenclr.write(|w| w.up().clear());
+            }
+            if r.events_down.read().bits() != 0 {
+                r.intenclr.write(|w| w.down().clear());
+            }
+            if r.events_cross.read().bits() != 0 {
+                r.intenclr.write(|w| w.cross().clear());
+            }
+            WAKER.wake();
+        });
+        irq.unpend();
+        irq.enable();
+
+        r.enable.write(|w| w.enable().enabled());
+
+        // Startup takes up to 140 us.
+        r.events_ready.reset();
+        r.tasks_start.write(|w| unsafe { w.bits(1) });
+        while r.events_ready.read().bits() == 0 {}
+        r.events_ready.reset();
+
+        Self {
+            phantom: PhantomData,
+            _irq: irq,
+        }
+    }
+
+    fn regs() -> &'static pac::lpcomp::RegisterBlock {
+        unsafe { &*pac::LPCOMP::ptr() }
+    }
+
+    /// Samples the comparator output, returning `true` if the input is above the reference.
+    pub fn sample(&mut self) -> bool {
+        let r = Self::regs();
+        r.tasks_sample.write(|w| unsafe { w.bits(1) });
+        r.result.read().result().is_above()
+    }
+
+    /// Waits for the input to rise above the reference.
+    pub async fn wait_up(&mut self) {
+        Self::wait(Edge::Up).await
+    }
+
+    /// Waits for the input to fall below the reference.
+    pub async fn wait_down(&mut self) {
+        Self::wait(Edge::Down).await
+    }
+
+    /// Waits for the input to cross the reference in either direction.
+    pub async fn wait_cross(&mut self) {
+        Self::wait(Edge::Cross).await
+    }
+
+    async fn wait(edge: Edge) {
+        let r = Self::regs();
+
+        // In case the future is dropped, don't leave the interrupt enabled.
+        let on_drop = OnDrop::new(|| edge.disable_irq(Self::regs()));
+
+        edge.reset(r);
+        edge.enable_irq(r);
+
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if edge.is_set(r) {
+                edge.reset(r);
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        drop(on_drop);
+    }
+
+    /// Returns the SAMPLE task, for use with PPI.
+    pub fn task_sample(&self) -> Task {
+        Task::from_reg(&Self::regs().tasks_sample)
+    }
+
+    /// Returns the STOP task, for use with PPI.
+    pub fn task_stop(&self) -> Task {
+        Task::from_reg(&Self::regs().tasks_stop)
+    }
+
+    /// Returns the UP event, for use with PPI.
+    pub fn event_up(&self) -> Event {
+        Event::from_reg(&Self::regs().events_up)
+    }
+
+    /// Returns the DOWN event, for use with PPI.
+    pub fn event_down(&self) -> Event {
+        Event::from_reg(&Self::regs().events_down)
+    }
+
+    /// Returns the CROSS event, for use with PPI.
+    pub fn event_cross(&self) -> Event {
+        Event::from_reg(&Self::regs().events_cross)
+    }
+}
+
+impl<'d> Drop for Lpcomp<'d> {
+    fn drop(&mut self) {
+        let r = Self::regs();
+        r.tasks_stop.write(|w| unsafe { w.bits(1) });
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        r.enable.write(|w| w.enable().disabled());
+    }
+}
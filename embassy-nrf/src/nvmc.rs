@@ -1,14 +1,24 @@
-//! Nvmcerature sensor interface.
+//! Non-Volatile Memory Controller (NVMC) interface.
+//!
+//! The internal flash is available through the blocking `embedded-storage` traits, and through
+//! the async `embassy::traits::flash::Flash` and `embedded-storage-async` traits so code can be
+//! generic over internal and external (QSPI) flash. The CPU is halted while the NVMC is writing
+//! or erasing, so the async versions complete without yielding.
 
 use crate::pac;
 use crate::peripherals::NVMC;
 
+use core::future::Future;
 use core::marker::PhantomData;
 use core::ptr;
 use core::slice;
+use embassy::traits::flash::{Error as FlashError, Flash};
 use embassy::util::Unborrow;
 use embassy_hal_common::unborrow;
-use embedded_storage::nor_flash::{MultiwriteNorFlash, NorFlash, ReadNorFlash};
+use embedded_storage::nor_flash::{
+    ErrorType, MultiwriteNorFlash, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use embedded_storage_async::nor_flash::{AsyncNorFlash, AsyncReadNorFlash};
 
 pub const PAGE_SIZE: usize = 4096;
 pub const FLASH_SIZE: usize = crate::chip::FLASH_SIZE;
@@ -20,6 +30,24 @@ pub enum Error {
     Unaligned,
 }
 
+impl NorFlashError for Error {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Self::Unaligned => NorFlashErrorKind::NotAligned,
+        }
+    }
+}
+
+impl From<Error> for FlashError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::OutOfBounds => FlashError::Failed,
+            Error::Unaligned => FlashError::AddressMisaligned,
+        }
+    }
+}
+
 pub struct Nvmc<'d> {
     _p: PhantomData<&'d NVMC>,
 }
@@ -41,11 +69,13 @@ impl<'d> Nvmc<'d> {
     }
 }
 
+impl<'d> ErrorType for Nvmc<'d> {
+    type Error = Error;
+}
+
 impl<'d> MultiwriteNorFlash for Nvmc<'d> {}
 
 impl<'d> ReadNorFlash for Nvmc<'d> {
-    type Error = Error;
-
     const READ_SIZE: usize = 1;
 
     fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
@@ -121,3 +151,89 @@ impl<'d> NorFlash for Nvmc<'d> {
         Ok(())
     }
 }
+
+impl<'d> AsyncReadNorFlash for Nvmc<'d> {
+    const READ_SIZE: usize = <Self as ReadNorFlash>::READ_SIZE;
+
+    type ReadFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), Error>> + 'a;
+
+    fn read<'a>(&'a mut self, offset: u32, bytes: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move { ReadNorFlash::read(self, offset, bytes) }
+    }
+
+    fn capacity(&self) -> usize {
+        FLASH_SIZE
+    }
+}
+
+impl<'d> AsyncNorFlash for Nvmc<'d> {
+    const WRITE_SIZE: usize = <Self as NorFlash>::WRITE_SIZE;
+    const ERASE_SIZE: usize = <Self as NorFlash>::ERASE_SIZE;
+
+    type WriteFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), Error>> + 'a;
+
+    fn write<'a>(&'a mut self, offset: u32, bytes: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move { NorFlash::write(self, offset, bytes) }
+    }
+
+    type EraseFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), Error>> + 'a;
+
+    fn erase<'a>(&'a mut self, from: u32, to: u32) -> Self::EraseFuture<'a> {
+        async move { NorFlash::erase(self, from, to) }
+    }
+}
+
+impl<'d> Flash for Nvmc<'d> {
+    type ReadFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
+    type WriteFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
+    type ErasePageFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
+
+    fn read<'a>(&'a mut self, address: usize, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move { Ok(ReadNorFlash::read(self, address as u32, data)?) }
+    }
+
+    fn write<'a>(&'a mut self, address: usize, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move { Ok(NorFlash::write(self, address as u32, data)?) }
+    }
+
+    fn erase<'a>(&'a mut self, address: usize) -> Self::ErasePageFuture<'a> {
+        async move {
+            let address = address as u32;
+            Ok(NorFlash::erase(self, address, address + PAGE_SIZE as u32)?)
+        }
+    }
+
+    fn size(&self) -> usize {
+        FLASH_SIZE
+    }
+
+    fn read_size(&self) -> usize {
+        <Self as ReadNorFlash>::READ_SIZE
+    }
+
+    fn write_size(&self) -> usize {
+        <Self as NorFlash>::WRITE_SIZE
+    }
+
+    fn erase_size(&self) -> usize {
+        PAGE_SIZE
+    }
+}
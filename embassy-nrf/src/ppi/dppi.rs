@@ -5,12 +5,12 @@ use embassy_hal_common::unborrow;
 
 use crate::pac;
 
-use super::{Channel, ConfigurableChannel, Event, Ppi, Task};
+use super::{Channel, ConfigurableChannel, Event, PoolChannel, Ppi, Task};
 
 const DPPI_ENABLE_BIT: u32 = 0x8000_0000;
 const DPPI_CHANNEL_MASK: u32 = 0x0000_00FF;
 
-fn regs() -> &'static pac::dppic::RegisterBlock {
+pub(super) fn regs() -> &'static pac::dppic::RegisterBlock {
     unsafe { &*pac::DPPIC::ptr() }
 }
 
@@ -64,22 +64,6 @@ impl<'d, C: ConfigurableChannel, const EVENT_COUNT: usize, const TASK_COUNT: usi
     }
 }
 
-impl<'d, C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize>
-    Ppi<'d, C, EVENT_COUNT, TASK_COUNT>
-{
-    /// Enables the channel.
-    pub fn enable(&mut self) {
-        let n = self.ch.number();
-        regs().chenset.write(|w| unsafe { w.bits(1 << n) });
-    }
-
-    /// Disables the channel.
-    pub fn disable(&mut self) {
-        let n = self.ch.number();
-        regs().chenclr.write(|w| unsafe { w.bits(1 << n) });
-    }
-}
-
 impl<'d, C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize> Drop
     for Ppi<'d, C, EVENT_COUNT, TASK_COUNT>
 {
@@ -94,3 +78,40 @@ impl<'d, C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize> Drop
         }
     }
 }
+
+impl<'p> PoolChannel<'p> {
+    /// Sets the event triggering the channel, in place of the previous one.
+    pub fn set_event(&mut self, event: Event) {
+        if let Some(old) = self.event.take() {
+            unsafe { old.publish_reg().write_volatile(0) }
+        }
+        if unsafe { event.publish_reg().read_volatile() } != 0 {
+            panic!("Event is already in use");
+        }
+        let val = DPPI_ENABLE_BIT | (self.ch.number() as u32 & DPPI_CHANNEL_MASK);
+        unsafe { event.publish_reg().write_volatile(val) }
+        self.event = Some(event);
+    }
+
+    /// Sets the task triggered by the channel, in place of the previous one.
+    pub fn set_task(&mut self, task: Task) {
+        if let Some(old) = self.task.take() {
+            unsafe { old.subscribe_reg().write_volatile(0) }
+        }
+        if unsafe { task.subscribe_reg().read_volatile() } != 0 {
+            panic!("Task is already in use");
+        }
+        let val = DPPI_ENABLE_BIT | (self.ch.number() as u32 & DPPI_CHANNEL_MASK);
+        unsafe { task.subscribe_reg().write_volatile(val) }
+        self.task = Some(task);
+    }
+
+    pub(super) fn disconnect(&mut self) {
+        if let Some(task) = self.task.take() {
+            unsafe { task.subscribe_reg().write_volatile(0) }
+        }
+        if let Some(event) = self.event.take() {
+            unsafe { event.publish_reg().write_volatile(0) }
+        }
+    }
+}
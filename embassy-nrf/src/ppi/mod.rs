@@ -14,18 +14,30 @@
 //! The DPPI for nRF53 and nRF91 devices works in a different way. Every channel can support infinitely
 //! many tasks and events, but any single task or event can only be coupled with one channel.
 //!
+//! Both have channel groups, see [`PpiGroup`], which allow enabling and disabling several channels
+//! at once, from software or from other PPI channels.
+//!
+//! Instead of statically assigning channels, configurable channels can be handed over to a
+//! [`ChannelPool`], which hands them out at runtime.
+//!
 
 use crate::peripherals;
 use core::marker::PhantomData;
 use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU32, Ordering};
 use embassy::util::Unborrow;
-use embassy_hal_common::unsafe_impl_unborrow;
+use embassy_hal_common::{unborrow, unsafe_impl_unborrow};
 
 #[cfg(feature = "_dppi")]
 mod dppi;
 #[cfg(feature = "_ppi")]
 mod ppi;
 
+#[cfg(feature = "_dppi")]
+use dppi::regs;
+#[cfg(feature = "_ppi")]
+use ppi::regs;
+
 pub struct Ppi<'d, C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize> {
     ch: C,
     #[cfg(feature = "_dppi")]
@@ -35,6 +47,28 @@ pub struct Ppi<'d, C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize
     phantom: PhantomData<&'d mut C>,
 }
 
+impl<'d, C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize>
+    Ppi<'d, C, EVENT_COUNT, TASK_COUNT>
+{
+    /// Enables the channel.
+    pub fn enable(&mut self) {
+        let n = self.ch.number();
+        regs().chenset.write(|w| unsafe { w.bits(1 << n) });
+    }
+
+    /// Disables the channel.
+    pub fn disable(&mut self) {
+        let n = self.ch.number();
+        regs().chenclr.write(|w| unsafe { w.bits(1 << n) });
+    }
+
+    /// Returns whether the channel is enabled, for example by a [`PpiGroup`].
+    pub fn is_enabled(&self) -> bool {
+        let n = self.ch.number();
+        regs().chen.read().bits() & (1 << n) != 0
+    }
+}
+
 const REGISTER_DPPI_CONFIG_OFFSET: usize = 0x80 / core::mem::size_of::<u32>();
 
 /// Represents a task that a peripheral can do.
@@ -185,6 +219,204 @@ macro_rules! impl_ppi_channel {
 // ======================
 //       groups
 
+/// A group of channels, which can be enabled or disabled at once.
+///
+/// The group's enable and disable tasks can be triggered from software, or by other PPI
+/// channels, for example to arm a chain of channels when an event happens.
+pub struct PpiGroup<'d, G: Group> {
+    g: G,
+    phantom: PhantomData<&'d mut G>,
+}
+
+impl<'d, G: Group> PpiGroup<'d, G> {
+    /// Creates an empty group.
+    pub fn new(g: impl Unborrow<Target = G> + 'd) -> Self {
+        unborrow!(g);
+
+        let n = g.number();
+        regs().chg[n].write(|w| unsafe { w.bits(0) });
+
+        Self {
+            g,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Adds a channel to the group.
+    pub fn add_channel<C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize>(
+        &mut self,
+        ch: &Ppi<'_, C, EVENT_COUNT, TASK_COUNT>,
+    ) {
+        let n = self.g.number();
+        regs().chg[n].modify(|r, w| unsafe { w.bits(r.bits() | 1 << ch.ch.number()) });
+    }
+
+    /// Removes a channel from the group.
+    pub fn remove_channel<C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize>(
+        &mut self,
+        ch: &Ppi<'_, C, EVENT_COUNT, TASK_COUNT>,
+    ) {
+        let n = self.g.number();
+        regs().chg[n].modify(|r, w| unsafe { w.bits(r.bits() & !(1 << ch.ch.number())) });
+    }
+
+    /// Enables all the channels in the group.
+    pub fn enable_all(&mut self) {
+        let n = self.g.number();
+        regs().tasks_chg[n].en.write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Disables all the channels in the group.
+    pub fn disable_all(&mut self) {
+        let n = self.g.number();
+        regs().tasks_chg[n].dis.write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Returns the task enabling all the channels in the group, for use with PPI.
+    pub fn task_enable_all(&self) -> Task {
+        let n = self.g.number();
+        Task::from_reg(&regs().tasks_chg[n].en)
+    }
+
+    /// Returns the task disabling all the channels in the group, for use with PPI.
+    pub fn task_disable_all(&self) -> Task {
+        let n = self.g.number();
+        Task::from_reg(&regs().tasks_chg[n].dis)
+    }
+}
+
+impl<'d, G: Group> Drop for PpiGroup<'d, G> {
+    fn drop(&mut self) {
+        let n = self.g.number();
+        regs().chg[n].write(|w| unsafe { w.bits(0) });
+    }
+}
+
+// ======================
+//       allocation
+
+/// A pool of configurable channels, handed out at runtime.
+///
+/// Channels are given to the pool with [`ChannelPool::add`], which takes ownership of the
+/// channel singletons, so they can't be used directly anymore. [`ChannelPool::alloc`] returns a
+/// [`PoolChannel`], which is configured through its own methods, and gives the channel back to
+/// the pool when dropped.
+///
+/// ```no_run
+/// static POOL: ChannelPool = ChannelPool::new();
+///
+/// POOL.add(p.PPI_CH0);
+/// POOL.add(p.PPI_CH1);
+///
+/// let mut ch = POOL.alloc().unwrap();
+/// ch.set_event(event);
+/// ch.set_task(task);
+/// ch.enable();
+/// // ...
+/// // Disables the channel and returns it to the pool.
+/// drop(ch);
+/// ```
+pub struct ChannelPool {
+    free: AtomicU32,
+}
+
+impl ChannelPool {
+    /// Creates an empty pool.
+    pub const fn new() -> Self {
+        Self {
+            free: AtomicU32::new(0),
+        }
+    }
+
+    /// Gives a channel to the pool.
+    pub fn add(&self, ch: impl ConfigurableChannel) {
+        self.free(ch.degrade())
+    }
+
+    /// Takes a channel from the pool, or returns `None` if all channels are in use.
+    pub fn alloc(&self) -> Option<PoolChannel<'_>> {
+        critical_section::with(|_| {
+            let free = self.free.load(Ordering::Relaxed);
+            if free == 0 {
+                return None;
+            }
+            let number = free.trailing_zeros();
+            self.free.store(free & !(1 << number), Ordering::Relaxed);
+            Some(PoolChannel {
+                pool: self,
+                ch: AnyConfigurableChannel {
+                    number: number as u8,
+                },
+                #[cfg(feature = "_dppi")]
+                event: None,
+                #[cfg(feature = "_dppi")]
+                task: None,
+            })
+        })
+    }
+
+    fn free(&self, ch: AnyConfigurableChannel) {
+        critical_section::with(|_| {
+            let free = self.free.load(Ordering::Relaxed);
+            self.free.store(free | 1 << ch.number, Ordering::Relaxed);
+        })
+    }
+
+    /// Returns the number of channels available.
+    pub fn available(&self) -> usize {
+        self.free.load(Ordering::Relaxed).count_ones() as usize
+    }
+}
+
+impl Default for ChannelPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A channel taken from a [`ChannelPool`], disabled and returned to it when dropped.
+///
+/// The channel itself isn't handed out, it would let the pool free a channel it doesn't own:
+/// the event and the task are set with [`PoolChannel::set_event`] and
+/// [`PoolChannel::set_task`].
+pub struct PoolChannel<'p> {
+    pool: &'p ChannelPool,
+    ch: AnyConfigurableChannel,
+    #[cfg(feature = "_dppi")]
+    event: Option<Event>,
+    #[cfg(feature = "_dppi")]
+    task: Option<Task>,
+}
+
+impl<'p> PoolChannel<'p> {
+    /// Returns the number of the channel.
+    pub fn number(&self) -> usize {
+        self.ch.number()
+    }
+
+    /// Enables the channel.
+    pub fn enable(&mut self) {
+        let n = self.ch.number();
+        regs().chenset.write(|w| unsafe { w.bits(1 << n) });
+    }
+
+    /// Disables the channel.
+    pub fn disable(&mut self) {
+        let n = self.ch.number();
+        regs().chenclr.write(|w| unsafe { w.bits(1 << n) });
+    }
+}
+
+impl<'p> Drop for PoolChannel<'p> {
+    fn drop(&mut self) {
+        self.disable();
+        self.disconnect();
+        self.pool.free(AnyConfigurableChannel {
+            number: self.ch.number,
+        })
+    }
+}
+
 pub struct AnyGroup {
     number: u8,
 }
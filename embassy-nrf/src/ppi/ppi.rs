@@ -3,7 +3,7 @@ use core::marker::PhantomData;
 use embassy::util::Unborrow;
 use embassy_hal_common::unborrow;
 
-use super::{Channel, ConfigurableChannel, Event, Ppi, StaticChannel, Task};
+use super::{Channel, ConfigurableChannel, Event, PoolChannel, Ppi, StaticChannel, Task};
 use crate::pac;
 
 impl Task {
@@ -17,7 +17,7 @@ impl Event {
     }
 }
 
-fn regs() -> &'static pac::ppi::RegisterBlock {
+pub(super) fn regs() -> &'static pac::ppi::RegisterBlock {
     unsafe { &*pac::PPI::ptr() }
 }
 
@@ -76,22 +76,6 @@ impl<'d, C: ConfigurableChannel> Ppi<'d, C, 1, 2> {
     }
 }
 
-impl<'d, C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize>
-    Ppi<'d, C, EVENT_COUNT, TASK_COUNT>
-{
-    /// Enables the channel.
-    pub fn enable(&mut self) {
-        let n = self.ch.number();
-        regs().chenset.write(|w| unsafe { w.bits(1 << n) });
-    }
-
-    /// Disables the channel.
-    pub fn disable(&mut self) {
-        let n = self.ch.number();
-        regs().chenclr.write(|w| unsafe { w.bits(1 << n) });
-    }
-}
-
 impl<'d, C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize> Drop
     for Ppi<'d, C, EVENT_COUNT, TASK_COUNT>
 {
@@ -105,3 +89,27 @@ impl<'d, C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize> Drop
         r.fork[n].tep.write(|w| unsafe { w.bits(0) });
     }
 }
+
+impl<'p> PoolChannel<'p> {
+    /// Sets the event triggering the channel.
+    pub fn set_event(&mut self, event: Event) {
+        let n = self.ch.number();
+        regs().ch[n]
+            .eep
+            .write(|w| unsafe { w.bits(event.reg_val()) });
+    }
+
+    /// Sets the task triggered by the channel.
+    pub fn set_task(&mut self, task: Task) {
+        let n = self.ch.number();
+        regs().ch[n]
+            .tep
+            .write(|w| unsafe { w.bits(task.reg_val()) });
+    }
+
+    pub(super) fn disconnect(&mut self) {
+        let n = self.ch.number();
+        regs().ch[n].eep.write(|w| unsafe { w.bits(0) });
+        regs().ch[n].tep.write(|w| unsafe { w.bits(0) });
+    }
+}
@@ -0,0 +1,310 @@
+//! Quadrature decoder (QDEC) driver.
+
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::gpio::sealed::Pin as _;
+use crate::gpio::{AnyPin, OptionalPin as GpioOptionalPin, Pin as GpioPin};
+use crate::interrupt;
+use crate::pac;
+use crate::peripherals::QDEC;
+use crate::ppi::{Event, Task};
+
+/// Quadrature decoder driver.
+pub struct Qdec<'d> {
+    phantom: PhantomData<&'d QDEC>,
+    irq: interrupt::QDEC,
+    a: AnyPin,
+    b: AnyPin,
+    led: Option<AnyPin>,
+}
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Time between two samples of the A and B inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SamplePeriod {
+    _128us,
+    _256us,
+    _512us,
+    _1024us,
+    _2048us,
+    _4096us,
+    _8192us,
+    _16384us,
+    _32ms,
+    _65ms,
+    _131ms,
+}
+
+/// Number of samples accumulated in ACC before a REPORTRDY event is generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NumSamples {
+    _10smpl,
+    _40smpl,
+    _80smpl,
+    _120smpl,
+    _160smpl,
+    _200smpl,
+    _240smpl,
+    _280smpl,
+    _1smpl,
+}
+
+/// Polarity of the LED output while it's active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LedPolarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// QDEC configuration.
+///
+/// See the `Default` impl for suitable default values.
+#[non_exhaustive]
+pub struct Config {
+    /// Time between two samples.
+    pub period: SamplePeriod,
+    /// Number of samples per report.
+    pub num_samples: NumSamples,
+    /// Polarity of the LED output, if an LED pin is used.
+    pub led_polarity: LedPolarity,
+    /// Time the LED is switched on before a sample is taken, in microseconds (0..=511).
+    pub led_pre_usecs: u16,
+    /// Enable the input debounce filters.
+    pub debounce: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            period: SamplePeriod::_256us,
+            num_samples: NumSamples::_1smpl,
+            led_polarity: LedPolarity::ActiveHigh,
+            led_pre_usecs: 16,
+            debounce: true,
+        }
+    }
+}
+
+impl<'d> Qdec<'d> {
+    /// Creates a QDEC driver without an LED output.
+    pub fn new(
+        qdec: impl Unborrow<Target = QDEC> + 'd,
+        irq: impl Unborrow<Target = interrupt::QDEC> + 'd,
+        a: impl Unborrow<Target = impl GpioPin> + 'd,
+        b: impl Unborrow<Target = impl GpioPin> + 'd,
+        config: Config,
+    ) -> Self {
+        Self::new_with_led(qdec, irq, a, b, crate::gpio::NoPin, config)
+    }
+
+    /// Creates a QDEC driver driving an LED output, which is switched on while sampling.
+    ///
+    /// Use `NoPin` for `led` if no LED output is needed.
+    pub fn new_with_led(
+        _qdec: impl Unborrow<Target = QDEC> + 'd,
+        irq: impl Unborrow<Target = interrupt::QDEC> + 'd,
+        a: impl Unborrow<Target = impl GpioPin> + 'd,
+        b: impl Unborrow<Target = impl GpioPin> + 'd,
+        led: impl Unborrow<Target = impl GpioOptionalPin> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(irq, a, b, led);
+
+        let r = Self::regs();
+
+        // Make sure the peripheral is stopped before reconfiguring it.
+        r.tasks_stop.write(|w| unsafe { w.bits(1) });
+        r.enable.write(|w| w.enable().disabled());
+
+        a.conf().write(|w| w.input().connect().pull().pullup());
+        b.conf().write(|w| w.input().connect().pull().pullup());
+        r.psel.a.write(|w| unsafe { w.bits(a.psel_bits()) });
+        r.psel.b.write(|w| unsafe { w.bits(b.psel_bits()) });
+
+        if let Some(pin) = led.pin_mut() {
+            match config.led_polarity {
+                LedPolarity::ActiveHigh => pin.set_low(),
+                LedPolarity::ActiveLow => pin.set_high(),
+            }
+            pin.conf().write(|w| w.dir().output());
+        }
+        r.psel.led.write(|w| unsafe { w.bits(led.psel_bits()) });
+
+        r.dbfen.write(|w| w.dbfen().bit(config.debounce));
+
+        r.ledpol.write(|w| match config.led_polarity {
+            LedPolarity::ActiveHigh => w.ledpol().active_high(),
+            LedPolarity::ActiveLow => w.ledpol().active_low(),
+        });
+        r.ledpre
+            .write(|w| unsafe { w.ledpre().bits(config.led_pre_usecs.min(511)) });
+
+        r.sampleper.write(|w| match config.period {
+            SamplePeriod::_128us => w.sampleper()._128us(),
+            SamplePeriod::_256us => w.sampleper()._256us(),
+            SamplePeriod::_512us => w.sampleper()._512us(),
+            SamplePeriod::_1024us => w.sampleper()._1024us(),
+            SamplePeriod::_2048us => w.sampleper()._2048us(),
+            SamplePeriod::_4096us => w.sampleper()._4096us(),
+            SamplePeriod::_8192us => w.sampleper()._8192us(),
+            SamplePeriod::_16384us => w.sampleper()._16384us(),
+            SamplePeriod::_32ms => w.sampleper()._32ms(),
+            SamplePeriod::_65ms => w.sampleper()._65ms(),
+            SamplePeriod::_131ms => w.sampleper()._131ms(),
+        });
+
+        r.reportper.write(|w| match config.num_samples {
+            NumSamples::_10smpl => w.reportper()._10smpl(),
+            NumSamples::_40smpl => w.reportper()._40smpl(),
+            NumSamples::_80smpl => w.reportper()._80smpl(),
+            NumSamples::_120smpl => w.reportper()._120smpl(),
+            NumSamples::_160smpl => w.reportper()._160smpl(),
+            NumSamples::_200smpl => w.reportper()._200smpl(),
+            NumSamples::_240smpl => w.reportper()._240smpl(),
+            NumSamples::_280smpl => w.reportper()._280smpl(),
+            NumSamples::_1smpl => w.reportper()._1smpl(),
+        });
+
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        r.shorts.reset();
+        r.events_reportrdy.reset();
+        r.events_accof.reset();
+        r.events_samplerdy.reset();
+
+        irq.disable();
+        irq.set_handler(|_| {
+            let r = Self::regs();
+            r.intenclr.write(|w| w.reportrdy().clear());
+            WAKER.wake();
+        });
+        irq.unpend();
+        irq.enable();
+
+        r.enable.write(|w| w.enable().enabled());
+        r.tasks_start.write(|w| unsafe { w.bits(1) });
+
+        Self {
+            phantom: PhantomData,
+            irq,
+            a: a.degrade(),
+            b: b.degrade(),
+            led: led.degrade_optional(),
+        }
+    }
+
+    /// Waits for the next report and returns the accumulated count since the previous read.
+    ///
+    /// The accumulator is cleared on each read. Positive values mean the A input leads B.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() {
+    /// use embassy_nrf::interrupt;
+    /// use embassy_nrf::qdec::{self, Qdec};
+    ///
+    /// let p = embassy_nrf::init(Default::default());
+    /// let irq = interrupt::take!(QDEC);
+    /// let mut q = Qdec::new(p.QDEC, irq, p.P0_31, p.P0_30, qdec::Config::default());
+    /// let delta = q.read().await;
+    /// # }
+    /// ```
+    pub async fn read(&mut self) -> i16 {
+        let r = Self::regs();
+
+        // In case the future is dropped, don't leave the interrupt enabled.
+        let on_drop = OnDrop::new(|| {
+            Self::regs().intenclr.write(|w| w.reportrdy().clear());
+        });
+
+        r.events_reportrdy.reset();
+        r.intenset.write(|w| w.reportrdy().set());
+
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if r.events_reportrdy.read().bits() == 0 {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await;
+        on_drop.defuse();
+
+        r.events_reportrdy.reset();
+        self.read_clear()
+    }
+
+    /// Reads and clears the accumulated count without waiting for a report.
+    pub fn read_clear(&mut self) -> i16 {
+        let r = Self::regs();
+        r.tasks_readclracc.write(|w| unsafe { w.bits(1) });
+        r.accread.read().bits() as i32 as i16
+    }
+
+    /// Reads and clears the number of double transitions (invalid samples) detected since the previous read.
+    pub fn read_double_transitions(&mut self) -> u8 {
+        let r = Self::regs();
+        r.tasks_rdclrdbl.write(|w| unsafe { w.bits(1) });
+        r.accdblread.read().bits() as u8
+    }
+
+    /// Returns whether the accumulator has overflowed since the last call, and clears the flag.
+    pub fn take_overflow(&mut self) -> bool {
+        let r = Self::regs();
+        let overflowed = r.events_accof.read().bits() != 0;
+        r.events_accof.reset();
+        overflowed
+    }
+
+    /// Returns the READCLRACC task, for use with PPI.
+    pub fn task_read_clear(&self) -> Task {
+        Task::from_reg(&Self::regs().tasks_readclracc)
+    }
+
+    /// Returns the SAMPLERDY event, for use with PPI.
+    pub fn event_sample_ready(&self) -> Event {
+        Event::from_reg(&Self::regs().events_samplerdy)
+    }
+
+    /// Returns the REPORTRDY event, for use with PPI.
+    pub fn event_report_ready(&self) -> Event {
+        Event::from_reg(&Self::regs().events_reportrdy)
+    }
+
+    fn regs() -> &'static pac::qdec::RegisterBlock {
+        unsafe { &*pac::QDEC::ptr() }
+    }
+}
+
+impl<'d> Drop for Qdec<'d> {
+    fn drop(&mut self) {
+        let r = Self::regs();
+        r.tasks_stop.write(|w| unsafe { w.bits(1) });
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        r.enable.write(|w| w.enable().disabled());
+        self.irq.disable();
+        self.irq.remove_handler();
+
+        self.a.conf().reset();
+        self.b.conf().reset();
+        r.psel.a.reset();
+        r.psel.b.reset();
+        if let Some(pin) = &self.led {
+            pin.conf().reset();
+        }
+        r.psel.led.reset();
+    }
+}
@@ -5,15 +5,18 @@ use core::marker::PhantomData;
 use core::ptr;
 use core::task::Poll;
 use embassy::interrupt::{Interrupt, InterruptExt};
-use embassy::traits::flash::{Error, Flash};
+use embassy::traits::flash::{Error as FlashError, Flash};
 use embassy::util::Unborrow;
 use embassy_hal_common::drop::DropBomb;
 use embassy_hal_common::unborrow;
+use embedded_storage::nor_flash::{ErrorType, NorFlashError, NorFlashErrorKind};
+use embedded_storage_async::nor_flash::{AsyncNorFlash, AsyncReadNorFlash};
 use futures::future::poll_fn;
 
 use crate::gpio::sealed::Pin as _;
 use crate::gpio::{self, Pin as GpioPin};
 use crate::pac;
+use crate::util::slice_in_ram_or;
 
 pub use crate::pac::qspi::ifconfig0::ADDRMODE_A as AddressMode;
 pub use crate::pac::qspi::ifconfig0::PPSIZE_A as WritePageSize;
@@ -30,6 +33,42 @@ pub use crate::pac::qspi::ifconfig0::WRITEOC_A as WriteOpcode;
 // - activate/deactivate
 // - set gpio in high drive
 
+/// Size of the sectors erased by [`Qspi::erase`].
+pub const ERASE_SIZE: usize = 4096;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    OutOfBounds,
+    /// The flash address or length isn't a multiple of 4, or of the erase size for erase.
+    Unaligned,
+    /// The buffer address isn't a multiple of 4.
+    BufferMisaligned,
+    /// EasyDMA can only read from data memory, read only buffers in flash will fail.
+    BufferNotInRAM,
+}
+
+impl NorFlashError for Error {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Self::Unaligned => NorFlashErrorKind::NotAligned,
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+impl From<Error> for FlashError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Unaligned => FlashError::AddressMisaligned,
+            Error::BufferMisaligned | Error::BufferNotInRAM => FlashError::BufferMisaligned,
+            _ => FlashError::Failed,
+        }
+    }
+}
+
 pub struct DeepPowerDownConfig {
     /// Time required for entering DPM, in units of 16us
     pub enter_time: u16,
@@ -39,6 +78,8 @@ pub struct DeepPowerDownConfig {
 
 #[non_exhaustive]
 pub struct Config {
+    /// Size of the external flash, in bytes.
+    pub capacity: u32,
     pub xip_offset: u32,
     pub read_opcode: ReadOpcode,
     pub write_opcode: WriteOpcode,
@@ -54,12 +95,15 @@ impl Default for Config {
             xip_offset: 0,
             write_page_size: WritePageSize::_256BYTES,
             deep_power_down: None,
+            // MX25R64 on the nRF52840 DK
+            capacity: 8 * 1024 * 1024,
         }
     }
 }
 
 pub struct Qspi<'d, T: Instance> {
     dpm_enabled: bool,
+    capacity: u32,
     phantom: PhantomData<&'d mut T>,
 }
 
@@ -137,6 +181,7 @@ impl<'d, T: Instance> Qspi<'d, T> {
 
         let mut res = Self {
             dpm_enabled: config.deep_power_down.is_some(),
+            capacity: config.capacity,
             phantom: PhantomData,
         };
 
@@ -228,6 +273,113 @@ impl<'d, T: Instance> Qspi<'d, T> {
         Ok(())
     }
 
+    /// Reads data from the flash, using EasyDMA.
+    ///
+    /// `address`, the buffer address and its length must be multiples of 4.
+    pub async fn read(&mut self, address: usize, data: &mut [u8]) -> Result<(), Error> {
+        self.check_range(address, data.len())?;
+        if data.as_ptr() as usize % 4 != 0 {
+            return Err(Error::BufferMisaligned);
+        }
+
+        let bomb = DropBomb::new();
+
+        let r = T::regs();
+
+        r.read
+            .src
+            .write(|w| unsafe { w.src().bits(address as u32) });
+        r.read
+            .dst
+            .write(|w| unsafe { w.dst().bits(data.as_ptr() as u32) });
+        r.read
+            .cnt
+            .write(|w| unsafe { w.cnt().bits(data.len() as u32) });
+
+        r.events_ready.reset();
+        r.intenset.write(|w| w.ready().set());
+        r.tasks_readstart.write(|w| w.tasks_readstart().bit(true));
+
+        self.wait_ready().await;
+
+        bomb.defuse();
+
+        Ok(())
+    }
+
+    /// Writes data to the flash, using EasyDMA.
+    ///
+    /// `address`, the buffer address and its length must be multiples of 4, and the buffer must
+    /// be in RAM.
+    pub async fn write(&mut self, address: usize, data: &[u8]) -> Result<(), Error> {
+        self.check_range(address, data.len())?;
+        if data.as_ptr() as usize % 4 != 0 {
+            return Err(Error::BufferMisaligned);
+        }
+        slice_in_ram_or(data, Error::BufferNotInRAM)?;
+
+        let bomb = DropBomb::new();
+
+        let r = T::regs();
+        r.write
+            .src
+            .write(|w| unsafe { w.src().bits(data.as_ptr() as u32) });
+        r.write
+            .dst
+            .write(|w| unsafe { w.dst().bits(address as u32) });
+        r.write
+            .cnt
+            .write(|w| unsafe { w.cnt().bits(data.len() as u32) });
+
+        r.events_ready.reset();
+        r.intenset.write(|w| w.ready().set());
+        r.tasks_writestart.write(|w| w.tasks_writestart().bit(true));
+
+        self.wait_ready().await;
+
+        bomb.defuse();
+
+        Ok(())
+    }
+
+    /// Erases the 4 KiB sector at `address`.
+    pub async fn erase(&mut self, address: usize) -> Result<(), Error> {
+        if address % ERASE_SIZE != 0 {
+            return Err(Error::Unaligned);
+        }
+        if address >= self.capacity as usize {
+            return Err(Error::OutOfBounds);
+        }
+
+        let bomb = DropBomb::new();
+
+        let r = T::regs();
+        r.erase
+            .ptr
+            .write(|w| unsafe { w.ptr().bits(address as u32) });
+        r.erase.len.write(|w| w.len()._4kb());
+
+        r.events_ready.reset();
+        r.intenset.write(|w| w.ready().set());
+        r.tasks_erasestart.write(|w| w.tasks_erasestart().bit(true));
+
+        self.wait_ready().await;
+
+        bomb.defuse();
+
+        Ok(())
+    }
+
+    fn check_range(&self, address: usize, len: usize) -> Result<(), Error> {
+        if address % 4 != 0 || len % 4 != 0 {
+            return Err(Error::Unaligned);
+        }
+        if address > self.capacity as usize || len > self.capacity as usize - address {
+            return Err(Error::OutOfBounds);
+        }
+        Ok(())
+    }
+
     async fn wait_ready(&mut self) {
         poll_fn(move |cx| {
             let r = T::regs();
@@ -285,121 +437,101 @@ impl<'d, T: Instance> Drop for Qspi<'d, T> {
     }
 }
 
-impl<'d, T: Instance> Flash for Qspi<'d, T> {
+impl<'d, T: Instance> ErrorType for Qspi<'d, T> {
+    type Error = Error;
+}
+
+impl<'d, T: Instance> AsyncReadNorFlash for Qspi<'d, T> {
+    const READ_SIZE: usize = 4;
+
     type ReadFuture<'a>
     where
         Self: 'a,
     = impl Future<Output = Result<(), Error>> + 'a;
+
+    fn read<'a>(&'a mut self, offset: u32, bytes: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        self.read(offset as usize, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+}
+
+impl<'d, T: Instance> AsyncNorFlash for Qspi<'d, T> {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
     type WriteFuture<'a>
     where
         Self: 'a,
     = impl Future<Output = Result<(), Error>> + 'a;
-    type ErasePageFuture<'a>
+
+    fn write<'a>(&'a mut self, offset: u32, bytes: &'a [u8]) -> Self::WriteFuture<'a> {
+        self.write(offset as usize, bytes)
+    }
+
+    type EraseFuture<'a>
     where
         Self: 'a,
     = impl Future<Output = Result<(), Error>> + 'a;
 
-    fn read<'a>(&'a mut self, address: usize, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+    fn erase<'a>(&'a mut self, from: u32, to: u32) -> Self::EraseFuture<'a> {
         async move {
-            let bomb = DropBomb::new();
-
-            assert_eq!(data.as_ptr() as u32 % 4, 0);
-            assert_eq!(data.len() as u32 % 4, 0);
-            assert_eq!(address as u32 % 4, 0);
-
-            let r = T::regs();
-
-            r.read
-                .src
-                .write(|w| unsafe { w.src().bits(address as u32) });
-            r.read
-                .dst
-                .write(|w| unsafe { w.dst().bits(data.as_ptr() as u32) });
-            r.read
-                .cnt
-                .write(|w| unsafe { w.cnt().bits(data.len() as u32) });
-
-            r.events_ready.reset();
-            r.intenset.write(|w| w.ready().set());
-            r.tasks_readstart.write(|w| w.tasks_readstart().bit(true));
-
-            self.wait_ready().await;
-
-            bomb.defuse();
-
+            if to < from || to > self.capacity {
+                return Err(Error::OutOfBounds);
+            }
+            if from as usize % ERASE_SIZE != 0 || to as usize % ERASE_SIZE != 0 {
+                return Err(Error::Unaligned);
+            }
+            for address in (from..to).step_by(ERASE_SIZE) {
+                self.erase(address as usize).await?;
+            }
             Ok(())
         }
     }
+}
 
-    fn write<'a>(&'a mut self, address: usize, data: &'a [u8]) -> Self::WriteFuture<'a> {
-        async move {
-            let bomb = DropBomb::new();
-
-            assert_eq!(data.as_ptr() as u32 % 4, 0);
-            assert_eq!(data.len() as u32 % 4, 0);
-            assert_eq!(address as u32 % 4, 0);
-
-            let r = T::regs();
-            r.write
-                .src
-                .write(|w| unsafe { w.src().bits(data.as_ptr() as u32) });
-            r.write
-                .dst
-                .write(|w| unsafe { w.dst().bits(address as u32) });
-            r.write
-                .cnt
-                .write(|w| unsafe { w.cnt().bits(data.len() as u32) });
-
-            r.events_ready.reset();
-            r.intenset.write(|w| w.ready().set());
-            r.tasks_writestart.write(|w| w.tasks_writestart().bit(true));
-
-            self.wait_ready().await;
+impl<'d, T: Instance> Flash for Qspi<'d, T> {
+    type ReadFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
+    type WriteFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
+    type ErasePageFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
 
-            bomb.defuse();
+    fn read<'a>(&'a mut self, address: usize, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move { Ok(self.read(address, data).await?) }
+    }
 
-            Ok(())
-        }
+    fn write<'a>(&'a mut self, address: usize, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move { Ok(self.write(address, data).await?) }
     }
 
     fn erase<'a>(&'a mut self, address: usize) -> Self::ErasePageFuture<'a> {
-        async move {
-            let bomb = DropBomb::new();
-
-            assert_eq!(address as u32 % 4096, 0);
-
-            let r = T::regs();
-            r.erase
-                .ptr
-                .write(|w| unsafe { w.ptr().bits(address as u32) });
-            r.erase.len.write(|w| w.len()._4kb());
-
-            r.events_ready.reset();
-            r.intenset.write(|w| w.ready().set());
-            r.tasks_erasestart.write(|w| w.tasks_erasestart().bit(true));
-
-            self.wait_ready().await;
-
-            bomb.defuse();
-
-            Ok(())
-        }
+        async move { Ok(self.erase(address).await?) }
     }
 
     fn size(&self) -> usize {
-        256 * 4096 // TODO
+        self.capacity as usize
     }
 
     fn read_size(&self) -> usize {
-        4 // TODO
+        4
     }
 
     fn write_size(&self) -> usize {
-        4 // TODO
+        4
     }
 
     fn erase_size(&self) -> usize {
-        4096 // TODO
+        ERASE_SIZE
     }
 }
 
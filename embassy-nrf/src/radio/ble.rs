@@ -0,0 +1,213 @@
+//! Raw Bluetooth Low Energy (1 Mbit) radio driver.
+
+use core::marker::PhantomData;
+
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+
+use super::pdu::{self, ADV_ACCESS_ADDRESS, ADV_CRC_INIT};
+use super::{regs, Error};
+use crate::interrupt;
+use crate::peripherals::RADIO;
+use crate::ppi::Task;
+
+/// Size of the PDU buffer: 2-byte header and up to 255 bytes of payload.
+const BUFFER_LEN: usize = 2 + 255;
+
+/// BLE radio configuration.
+///
+/// See the `Default` impl for suitable default values, which match the advertising channels.
+#[non_exhaustive]
+pub struct Config {
+    /// Channel index (0..=39).
+    pub channel: u8,
+    /// Transmit power in dBm.
+    pub tx_power: i8,
+    /// Access address of the link.
+    pub access_address: u32,
+    /// CRC initial value of the link (24 bits).
+    pub crc_init: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            channel: 37,
+            tx_power: 0,
+            access_address: ADV_ACCESS_ADDRESS,
+            crc_init: ADV_CRC_INIT,
+        }
+    }
+}
+
+/// Information about a received PDU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Received {
+    /// Length of the PDU, including its 2-byte header.
+    pub len: usize,
+    /// Received signal strength in dBm.
+    pub rssi: i8,
+}
+
+/// BLE radio driver.
+///
+/// PDUs are passed as the 2-byte header followed by the payload. The preamble, access address,
+/// CRC and whitening are handled by the hardware.
+pub struct Radio<'d> {
+    phantom: PhantomData<&'d mut RADIO>,
+    _irq: interrupt::RADIO,
+    buffer: [u8; BUFFER_LEN],
+}
+
+impl<'d> Radio<'d> {
+    pub fn new(
+        _radio: impl Unborrow<Target = RADIO> + 'd,
+        irq: impl Unborrow<Target = interrupt::RADIO> + 'd,
+        config: Config,
+    ) -> Result<Self, Error> {
+        unborrow!(irq);
+
+        super::disable();
+
+        let r = regs();
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+
+        r.mode.write(|w| w.mode().ble_1mbit());
+        r.pcnf0.write(|w| unsafe {
+            // S0 holds the first header byte, LENGTH the second.
+            w.lflen().bits(8);
+            w.s0len().set_bit();
+            w.s1len().bits(0);
+            w
+        });
+        r.pcnf1.write(|w| unsafe {
+            w.maxlen().bits(255);
+            w.statlen().bits(0);
+            // 3-byte base address + 1-byte prefix = 4-byte access address.
+            w.balen().bits(3);
+            w.endian().little();
+            w.whiteen().enabled();
+            w
+        });
+        r.crccnf.write(|w| {
+            w.len().three();
+            w.skipaddr().skip();
+            w
+        });
+        r.crcpoly.write(|w| unsafe { w.crcpoly().bits(0x00_065B) });
+        r.tifs.write(|w| unsafe { w.tifs().bits(150) });
+        r.txaddress.write(|w| unsafe { w.txaddress().bits(0) });
+        r.rxaddresses.write(|w| w.addr0().enabled());
+
+        irq.disable();
+        irq.set_handler(super::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        let mut this = Self {
+            phantom: PhantomData,
+            _irq: irq,
+            buffer: [0; BUFFER_LEN],
+        };
+        this.set_access_address(config.access_address, config.crc_init);
+        this.set_tx_power(config.tx_power);
+        this.set_channel(config.channel)?;
+        Ok(this)
+    }
+
+    /// Sets the channel index (0..=39), which selects the frequency and the whitening sequence.
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), Error> {
+        let mhz = pdu::channel_frequency(channel).ok_or(Error::InvalidChannel)?;
+        super::set_frequency(mhz);
+        regs()
+            .datawhiteiv
+            .write(|w| unsafe { w.datawhiteiv().bits(channel) });
+        Ok(())
+    }
+
+    /// Sets the transmit power in dBm.
+    ///
+    /// Must be one of the levels supported by the chip.
+    pub fn set_tx_power(&mut self, dbm: i8) {
+        super::set_tx_power(dbm)
+    }
+
+    /// Sets the access address and CRC initial value of the link.
+    pub fn set_access_address(&mut self, access_address: u32, crc_init: u32) {
+        let r = regs();
+        r.base0.write(|w| unsafe { w.bits(access_address << 8) });
+        r.prefix0
+            .write(|w| unsafe { w.ap0().bits((access_address >> 24) as u8) });
+        r.crcinit
+            .write(|w| unsafe { w.crcinit().bits(crc_init & 0xFF_FFFF) });
+    }
+
+    /// Transmits a PDU.
+    ///
+    /// Returns [`Error::InvalidFrame`] if the length field of the header doesn't match the
+    /// length of the payload.
+    pub async fn transmit(&mut self, pdu: &[u8]) -> Result<(), Error> {
+        if pdu.len() < 2 {
+            return Err(Error::BufferTooShort);
+        }
+        if pdu.len() > BUFFER_LEN {
+            return Err(Error::BufferTooLong);
+        }
+        // The radio sends as many payload bytes as the length field says.
+        if pdu[1] as usize != pdu.len() - 2 {
+            return Err(Error::InvalidFrame);
+        }
+        self.buffer[..pdu.len()].copy_from_slice(pdu);
+
+        let r = regs();
+        r.packetptr
+            .write(|w| unsafe { w.bits(self.buffer.as_ptr() as u32) });
+        r.shorts
+            .write(|w| w.ready_start().enabled().end_disable().enabled());
+
+        super::run_until_disabled(Task::from_reg(&r.tasks_txen)).await;
+        Ok(())
+    }
+
+    /// Receives a single PDU into `buf`.
+    ///
+    /// Returns [`Error::Crc`] if the PDU was corrupted.
+    pub async fn receive(&mut self, buf: &mut [u8]) -> Result<Received, Error> {
+        let r = regs();
+        r.packetptr
+            .write(|w| unsafe { w.bits(self.buffer.as_mut_ptr() as u32) });
+        r.shorts.write(|w| {
+            w.ready_start().enabled();
+            w.end_disable().enabled();
+            w.address_rssistart().enabled();
+            w.disabled_rssistop().enabled();
+            w
+        });
+
+        super::run_until_disabled(Task::from_reg(&r.tasks_rxen)).await;
+
+        if r.crcstatus.read().crcstatus().is_crcerror() {
+            return Err(Error::Crc);
+        }
+
+        let len = 2 + self.buffer[1] as usize;
+        if buf.len() < len {
+            return Err(Error::BufferTooShort);
+        }
+        buf[..len].copy_from_slice(&self.buffer[..len]);
+
+        Ok(Received {
+            len,
+            rssi: super::last_rssi(),
+        })
+    }
+}
+
+impl<'d> Drop for Radio<'d> {
+    fn drop(&mut self) {
+        super::disable();
+        regs().intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+    }
+}
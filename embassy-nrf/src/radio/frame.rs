@@ -0,0 +1,345 @@
+//! IEEE 802.15.4 MAC frame encoding helpers.
+//!
+//! These are pure functions operating on byte buffers, independent of the RADIO peripheral.
+//! The buffers hold the PSDU without the FCS, which is computed and checked by the hardware.
+
+use super::Error;
+
+/// Largest PSDU, including the 2-byte FCS.
+pub const MAX_PSDU_LEN: usize = 127;
+/// Length of the frame check sequence (CRC) appended to every frame.
+pub const FCS_LEN: usize = 2;
+/// Broadcast PAN ID and short address.
+pub const BROADCAST: u16 = 0xFFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameType {
+    Beacon = 0,
+    Data = 1,
+    Ack = 2,
+    MacCommand = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Address {
+    None,
+    Short(u16),
+    Extended(u64),
+}
+
+impl Address {
+    fn mode(&self) -> u16 {
+        match self {
+            Address::None => 0,
+            Address::Short(_) => 2,
+            Address::Extended(_) => 3,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Address::None => 0,
+            Address::Short(_) => 2,
+            Address::Extended(_) => 8,
+        }
+    }
+
+    /// Is this the broadcast short address?
+    pub fn is_broadcast(&self) -> bool {
+        *self == Address::Short(BROADCAST)
+    }
+}
+
+/// MAC header (MHR) of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Header {
+    pub frame_type: FrameType,
+    pub security_enabled: bool,
+    pub frame_pending: bool,
+    pub ack_request: bool,
+    pub seq: u8,
+    /// Destination PAN ID. Only present if `dst` is not `Address::None`.
+    pub dst_pan_id: Option<u16>,
+    pub dst: Address,
+    /// Source PAN ID. Omitted on the air (PAN ID compression) if equal to `dst_pan_id`.
+    pub src_pan_id: Option<u16>,
+    pub src: Address,
+}
+
+impl Header {
+    /// Header of a data frame.
+    pub fn data(seq: u8, pan_id: u16, dst: Address, src: Address) -> Self {
+        Self {
+            frame_type: FrameType::Data,
+            security_enabled: false,
+            frame_pending: false,
+            ack_request: !matches!(dst, Address::None) && !dst.is_broadcast(),
+            seq,
+            dst_pan_id: Some(pan_id),
+            dst,
+            src_pan_id: Some(pan_id),
+            src,
+        }
+    }
+
+    fn pan_id_compression(&self) -> bool {
+        self.dst != Address::None
+            && self.src != Address::None
+            && (self.src_pan_id.is_none() || self.src_pan_id == self.dst_pan_id)
+    }
+
+    /// Length of the encoded header, in bytes.
+    pub fn len(&self) -> usize {
+        let mut len = 3 + self.dst.len() + self.src.len();
+        if self.dst != Address::None {
+            len += 2;
+        }
+        if self.src != Address::None && !self.pan_id_compression() {
+            len += 2;
+        }
+        len
+    }
+
+    /// Encode the header into `buf`, returning the number of bytes written.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let len = self.len();
+        if buf.len() < len {
+            return Err(Error::BufferTooShort);
+        }
+
+        let fc = (self.frame_type as u16)
+            | (self.security_enabled as u16) << 3
+            | (self.frame_pending as u16) << 4
+            | (self.ack_request as u16) << 5
+            | (self.pan_id_compression() as u16) << 6
+            | self.dst.mode() << 10
+            | self.src.mode() << 14;
+        buf[0..2].copy_from_slice(&fc.to_le_bytes());
+        buf[2] = self.seq;
+
+        let mut pos = 3;
+        if self.dst != Address::None {
+            let pan = self.dst_pan_id.unwrap_or(BROADCAST);
+            buf[pos..pos + 2].copy_from_slice(&pan.to_le_bytes());
+            pos += 2;
+            pos += write_address(&mut buf[pos..], &self.dst);
+        }
+        if self.src != Address::None {
+            if !self.pan_id_compression() {
+                let pan = self.src_pan_id.unwrap_or(BROADCAST);
+                buf[pos..pos + 2].copy_from_slice(&pan.to_le_bytes());
+                pos += 2;
+            }
+            pos += write_address(&mut buf[pos..], &self.src);
+        }
+
+        Ok(pos)
+    }
+
+    /// Decode a header from the start of `buf`, returning it and its length in bytes.
+    pub fn decode(buf: &[u8]) -> Result<(Self, usize), Error> {
+        if buf.len() < 3 {
+            return Err(Error::BufferTooShort);
+        }
+        let fc = u16::from_le_bytes([buf[0], buf[1]]);
+        let frame_type = match fc & 0b111 {
+            0 => FrameType::Beacon,
+            1 => FrameType::Data,
+            2 => FrameType::Ack,
+            3 => FrameType::MacCommand,
+            _ => return Err(Error::InvalidFrame),
+        };
+        let pan_id_compression = fc & (1 << 6) != 0;
+
+        let mut pos = 3;
+        let mut dst_pan_id = None;
+        let dst = match (fc >> 10) & 0b11 {
+            0 => Address::None,
+            1 => return Err(Error::InvalidFrame),
+            mode => {
+                dst_pan_id = Some(read_u16(buf, pos)?);
+                pos += 2;
+                let addr = read_address(buf, pos, mode)?;
+                pos += addr.len();
+                addr
+            }
+        };
+        let mut src_pan_id = None;
+        let src = match (fc >> 14) & 0b11 {
+            0 => Address::None,
+            1 => return Err(Error::InvalidFrame),
+            mode => {
+                if pan_id_compression {
+                    src_pan_id = dst_pan_id;
+                } else {
+                    src_pan_id = Some(read_u16(buf, pos)?);
+                    pos += 2;
+                }
+                let addr = read_address(buf, pos, mode)?;
+                pos += addr.len();
+                addr
+            }
+        };
+
+        let header = Self {
+            frame_type,
+            security_enabled: fc & (1 << 3) != 0,
+            frame_pending: fc & (1 << 4) != 0,
+            ack_request: fc & (1 << 5) != 0,
+            seq: buf[2],
+            dst_pan_id,
+            dst,
+            src_pan_id,
+            src,
+        };
+        Ok((header, pos))
+    }
+}
+
+fn write_address(buf: &mut [u8], addr: &Address) -> usize {
+    match *addr {
+        Address::None => 0,
+        Address::Short(a) => {
+            buf[..2].copy_from_slice(&a.to_le_bytes());
+            2
+        }
+        Address::Extended(a) => {
+            buf[..8].copy_from_slice(&a.to_le_bytes());
+            8
+        }
+    }
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> Result<u16, Error> {
+    match buf.get(pos..pos + 2) {
+        Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+        None => Err(Error::BufferTooShort),
+    }
+}
+
+fn read_address(buf: &[u8], pos: usize, mode: u16) -> Result<Address, Error> {
+    if mode == 2 {
+        return Ok(Address::Short(read_u16(buf, pos)?));
+    }
+    match buf.get(pos..pos + 8) {
+        Some(b) => {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(b);
+            Ok(Address::Extended(u64::from_le_bytes(bytes)))
+        }
+        None => Err(Error::BufferTooShort),
+    }
+}
+
+/// Build an immediate acknowledgment frame (without FCS) for the frame with sequence number `seq`.
+pub fn ack(seq: u8, frame_pending: bool) -> [u8; 3] {
+    let fc = FrameType::Ack as u8 | (frame_pending as u8) << 4;
+    [fc, 0, seq]
+}
+
+/// Compute the frame check sequence of a PSDU (ITU-T CRC-16, as used by IEEE 802.15.4).
+///
+/// The FCS is transmitted little-endian after the frame.
+pub fn fcs(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            if crc & 1 != 0 {
+                crc = (crc >> 1) ^ 0x8408;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    crc
+}
+
+/// Returns the center frequency in MHz of the 2.4 GHz O-QPSK channel `channel` (11..=26).
+pub fn channel_frequency(channel: u8) -> Option<u16> {
+    match channel {
+        11..=26 => Some(2405 + 5 * (channel as u16 - 11)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fcs() {
+        assert_eq!(fcs(b"123456789"), 0x2189);
+        // Running the CRC over a frame followed by its FCS leaves a zero residue.
+        let mut frame = [0x02, 0x00, 0x56, 0, 0];
+        let crc = fcs(&frame[..3]).to_le_bytes();
+        frame[3..].copy_from_slice(&crc);
+        assert_eq!(fcs(&frame), 0);
+        assert_eq!(fcs(&[]), 0);
+    }
+
+    #[test]
+    fn test_ack() {
+        assert_eq!(ack(0x56, false), [0x02, 0x00, 0x56]);
+        assert_eq!(ack(0x01, true), [0x12, 0x00, 0x01]);
+        let (h, len) = Header::decode(&ack(7, false)).unwrap();
+        assert_eq!(len, 3);
+        assert_eq!(h.frame_type, FrameType::Ack);
+        assert_eq!(h.seq, 7);
+    }
+
+    #[test]
+    fn test_header_short_compressed() {
+        let h = Header::data(0x2a, 0xabcd, Address::Short(0x1234), Address::Short(0x5678));
+        let mut buf = [0; 32];
+        let len = h.encode(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            &[0x61, 0x88, 0x2a, 0xcd, 0xab, 0x34, 0x12, 0x78, 0x56]
+        );
+        assert_eq!(h.len(), len);
+        assert_eq!(Header::decode(&buf[..len]).unwrap(), (h, len));
+    }
+
+    #[test]
+    fn test_header_extended_two_pans() {
+        let h = Header {
+            frame_type: FrameType::MacCommand,
+            security_enabled: false,
+            frame_pending: true,
+            ack_request: false,
+            seq: 1,
+            dst_pan_id: Some(BROADCAST),
+            dst: Address::Short(BROADCAST),
+            src_pan_id: Some(0x0001),
+            src: Address::Extended(0x0011_2233_4455_6677),
+        };
+        let mut buf = [0; 32];
+        let len = h.encode(&mut buf).unwrap();
+        assert_eq!(len, 3 + 2 + 2 + 2 + 8);
+        assert_eq!(buf[0] & 0b0100_0000, 0);
+        assert_eq!(Header::decode(&buf[..len]).unwrap(), (h, len));
+
+        assert_eq!(h.encode(&mut buf[..10]), Err(Error::BufferTooShort));
+        assert_eq!(Header::decode(&buf[..10]), Err(Error::BufferTooShort));
+    }
+
+    #[test]
+    fn test_header_invalid() {
+        // Reserved frame type
+        assert_eq!(Header::decode(&[0x07, 0x00, 0x00]), Err(Error::InvalidFrame));
+        // Reserved destination addressing mode
+        assert_eq!(Header::decode(&[0x01, 0x04, 0x00]), Err(Error::InvalidFrame));
+    }
+
+    #[test]
+    fn test_channel_frequency() {
+        assert_eq!(channel_frequency(10), None);
+        assert_eq!(channel_frequency(11), Some(2405));
+        assert_eq!(channel_frequency(26), Some(2480));
+        assert_eq!(channel_frequency(27), None);
+    }
+}
@@ -0,0 +1,379 @@
+//! IEEE 802.15.4 (2.4 GHz O-QPSK, 250 kbit/s) radio driver.
+
+use core::marker::PhantomData;
+
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+
+use super::frame::{self, Address, FrameType, Header, BROADCAST, FCS_LEN, MAX_PSDU_LEN};
+use super::{regs, Error};
+use crate::interrupt;
+use crate::peripherals::RADIO;
+use crate::ppi::Task;
+
+/// Clear channel assessment mode, used before every transmission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Cca {
+    /// Transmit without checking the channel.
+    Disabled,
+    /// The channel is busy if the measured energy is above `threshold`.
+    EnergyDetection { threshold: u8 },
+    /// The channel is busy if an IEEE 802.15.4 signal is detected.
+    CarrierSense,
+    /// The channel is busy if a signal is detected and the energy is above `threshold`.
+    CarrierAndEnergy { threshold: u8 },
+    /// The channel is busy if a signal is detected or the energy is above `threshold`.
+    CarrierOrEnergy { threshold: u8 },
+}
+
+impl Cca {
+    /// Value of the CCACTRL register.
+    fn ccactrl(&self) -> Option<u32> {
+        // CCACORRTHRES = 2 and CCACORRCNT = 2 are the values recommended by the product specification.
+        const CORR: u32 = (2 << 16) | (2 << 24);
+        match *self {
+            Cca::Disabled => None,
+            Cca::EnergyDetection { threshold } => Some((threshold as u32) << 8),
+            Cca::CarrierSense => Some(1 | CORR),
+            Cca::CarrierAndEnergy { threshold } => Some(2 | (threshold as u32) << 8 | CORR),
+            Cca::CarrierOrEnergy { threshold } => Some(3 | (threshold as u32) << 8 | CORR),
+        }
+    }
+}
+
+/// IEEE 802.15.4 radio configuration.
+///
+/// See the `Default` impl for suitable default values.
+#[non_exhaustive]
+pub struct Config {
+    /// Channel (11..=26).
+    pub channel: u8,
+    /// Transmit power in dBm.
+    pub tx_power: i8,
+    /// Clear channel assessment mode used by [`Radio::transmit`].
+    pub cca: Cca,
+    /// Acknowledge received frames with the ack request bit set, that are addressed to us.
+    ///
+    /// The acknowledgment is sent in software once [`Radio::receive`] is polled again after
+    /// the reception, so it's best-effort: if the executor is late, it misses the 192 us
+    /// turnaround time the sender waits for, and the sender retransmits the frame.
+    pub auto_ack: bool,
+    /// Our PAN ID. Frames for other PANs are dropped, unless all of the addresses are `None`.
+    pub pan_id: Option<u16>,
+    /// Our short address.
+    pub short_address: Option<u16>,
+    /// Our extended address.
+    pub extended_address: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            channel: 11,
+            tx_power: 0,
+            cca: Cca::CarrierSense,
+            auto_ack: true,
+            pan_id: None,
+            short_address: None,
+            extended_address: None,
+        }
+    }
+}
+
+/// Information about a received frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Received {
+    /// Length of the PSDU, without the FCS.
+    pub len: usize,
+    /// Received signal strength in dBm.
+    pub rssi: i8,
+    /// An acknowledgment was sent for this frame.
+    pub acked: bool,
+}
+
+/// IEEE 802.15.4 radio driver.
+///
+/// Frames are passed as the PSDU without the FCS, which the hardware appends and checks.
+pub struct Radio<'d> {
+    phantom: PhantomData<&'d mut RADIO>,
+    _irq: interrupt::RADIO,
+    cca: Option<u32>,
+    auto_ack: bool,
+    pan_id: Option<u16>,
+    short_address: Option<u16>,
+    extended_address: Option<u64>,
+    /// PHR (frame length) followed by the PSDU.
+    buffer: [u8; 1 + MAX_PSDU_LEN],
+    ack: [u8; 1 + 3 + FCS_LEN],
+}
+
+impl<'d> Radio<'d> {
+    pub fn new(
+        _radio: impl Unborrow<Target = RADIO> + 'd,
+        irq: impl Unborrow<Target = interrupt::RADIO> + 'd,
+        config: Config,
+    ) -> Result<Self, Error> {
+        unborrow!(irq);
+
+        super::disable();
+
+        let r = regs();
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+
+        r.mode.write(|w| w.mode().ieee802154_250kbit());
+        r.pcnf0.write(|w| unsafe {
+            w.lflen().bits(8);
+            w.s0len().clear_bit();
+            w.s1len().bits(0);
+            w.plen()._32bit_zero();
+            w.crcinc().include();
+            w
+        });
+        r.pcnf1.write(|w| unsafe {
+            w.maxlen().bits(MAX_PSDU_LEN as u8);
+            w.statlen().bits(0);
+            w.balen().bits(0);
+            w.endian().little();
+            w.whiteen().disabled();
+            w
+        });
+        r.crccnf.write(|w| {
+            w.len().two();
+            w.skipaddr().ieee802154();
+            w
+        });
+        r.crcpoly.write(|w| unsafe { w.crcpoly().bits(0x01_1021) });
+        r.crcinit.write(|w| unsafe { w.crcinit().bits(0) });
+        r.sfd.write(|w| unsafe { w.sfd().bits(0xA7) });
+
+        irq.disable();
+        irq.set_handler(super::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        let mut this = Self {
+            phantom: PhantomData,
+            _irq: irq,
+            cca: config.cca.ccactrl(),
+            auto_ack: config.auto_ack,
+            pan_id: config.pan_id,
+            short_address: config.short_address,
+            extended_address: config.extended_address,
+            buffer: [0; 1 + MAX_PSDU_LEN],
+            ack: [0; 1 + 3 + FCS_LEN],
+        };
+        this.set_cca(config.cca);
+        this.set_tx_power(config.tx_power);
+        this.set_channel(config.channel)?;
+        Ok(this)
+    }
+
+    /// Sets the channel (11..=26).
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), Error> {
+        let mhz = frame::channel_frequency(channel).ok_or(Error::InvalidChannel)?;
+        super::set_frequency(mhz);
+        Ok(())
+    }
+
+    /// Sets the transmit power in dBm.
+    ///
+    /// Must be one of the levels supported by the chip.
+    pub fn set_tx_power(&mut self, dbm: i8) {
+        super::set_tx_power(dbm)
+    }
+
+    /// Sets the clear channel assessment mode.
+    pub fn set_cca(&mut self, cca: Cca) {
+        self.cca = cca.ccactrl();
+        if let Some(ccactrl) = self.cca {
+            regs().ccactrl.write(|w| unsafe { w.bits(ccactrl) });
+        }
+    }
+
+    /// Sets the addresses used to filter received frames and decide whether to acknowledge them.
+    pub fn set_addresses(
+        &mut self,
+        pan_id: Option<u16>,
+        short_address: Option<u16>,
+        extended_address: Option<u64>,
+    ) {
+        self.pan_id = pan_id;
+        self.short_address = short_address;
+        self.extended_address = extended_address;
+    }
+
+    /// Transmits a frame, after a clear channel assessment unless it's disabled.
+    ///
+    /// Returns [`Error::ChannelBusy`] if the channel was busy, in which case nothing was sent.
+    /// Waiting for the acknowledgment, if requested, is left to the caller.
+    pub async fn transmit(&mut self, psdu: &[u8]) -> Result<(), Error> {
+        if psdu.len() + FCS_LEN > MAX_PSDU_LEN {
+            return Err(Error::BufferTooLong);
+        }
+        self.buffer[0] = (psdu.len() + FCS_LEN) as u8;
+        self.buffer[1..1 + psdu.len()].copy_from_slice(psdu);
+
+        let r = regs();
+        r.packetptr
+            .write(|w| unsafe { w.bits(self.buffer.as_ptr() as u32) });
+        r.events_ccabusy.reset();
+
+        match self.cca {
+            Some(_) => {
+                // Ramp up in RX, run the CCA, then turn around to TX only if the channel is idle.
+                r.shorts.write(|w| {
+                    w.rxready_ccastart().enabled();
+                    w.ccaidle_txen().enabled();
+                    w.txready_start().enabled();
+                    w.ccabusy_disable().enabled();
+                    w.phyend_disable().enabled();
+                    w
+                });
+                super::run_until_disabled(Task::from_reg(&r.tasks_rxen)).await;
+            }
+            None => {
+                r.shorts.write(|w| {
+                    w.txready_start().enabled();
+                    w.phyend_disable().enabled();
+                    w
+                });
+                super::run_until_disabled(Task::from_reg(&r.tasks_txen)).await;
+            }
+        }
+
+        if r.events_ccabusy.read().bits() != 0 {
+            r.events_ccabusy.reset();
+            return Err(Error::ChannelBusy);
+        }
+        Ok(())
+    }
+
+    /// Receives a frame into `buf`, returning its PSDU length without the FCS.
+    ///
+    /// If addresses are configured, frames not addressed to us are silently dropped. If
+    /// `auto_ack` is enabled, an acknowledgment is sent for frames that request it before
+    /// this returns, on a best-effort basis (see [`Config::auto_ack`]).
+    pub async fn receive(&mut self, buf: &mut [u8]) -> Result<Received, Error> {
+        let r = regs();
+
+        loop {
+            r.packetptr
+                .write(|w| unsafe { w.bits(self.buffer.as_mut_ptr() as u32) });
+            r.shorts.write(|w| {
+                w.rxready_start().enabled();
+                w.phyend_disable().enabled();
+                w.address_rssistart().enabled();
+                w.disabled_rssistop().enabled();
+                w
+            });
+
+            super::run_until_disabled(Task::from_reg(&r.tasks_rxen)).await;
+
+            if r.crcstatus.read().crcstatus().is_crcerror() {
+                return Err(Error::Crc);
+            }
+            let rssi = super::last_rssi();
+
+            let len = (self.buffer[0] as usize).saturating_sub(FCS_LEN);
+            let psdu = &self.buffer[1..1 + len];
+            let header = match Header::decode(psdu) {
+                Ok((header, _)) => Some(header),
+                Err(_) => None,
+            };
+
+            let for_us = match &header {
+                Some(header) => self.accepts(header),
+                None => self.is_promiscuous(),
+            };
+            if !for_us {
+                continue;
+            }
+
+            if buf.len() < len {
+                return Err(Error::BufferTooShort);
+            }
+            buf[..len].copy_from_slice(psdu);
+
+            let mut acked = false;
+            if let Some(header) = header {
+                if self.auto_ack
+                    && header.ack_request
+                    && header.frame_type != FrameType::Ack
+                    && !self.is_promiscuous()
+                    && !header.dst.is_broadcast()
+                {
+                    self.send_ack(header.seq).await;
+                    acked = true;
+                }
+            }
+
+            return Ok(Received { len, rssi, acked });
+        }
+    }
+
+    /// Measures the peak energy on the channel, over 8 symbol periods (128 us).
+    ///
+    /// Returns the raw ED level (0..=255).
+    pub async fn energy_detection(&mut self) -> u8 {
+        let r = regs();
+        r.edcnt.write(|w| unsafe { w.edcnt().bits(0) });
+        r.shorts.write(|w| {
+            w.rxready_edstart().enabled();
+            w.edend_disable().enabled();
+            w
+        });
+
+        super::run_until_disabled(Task::from_reg(&r.tasks_rxen)).await;
+
+        r.edsample.read().edlvl().bits()
+    }
+
+    async fn send_ack(&mut self, seq: u8) {
+        let ack = frame::ack(seq, false);
+        self.ack[0] = (ack.len() + FCS_LEN) as u8;
+        self.ack[1..1 + ack.len()].copy_from_slice(&ack);
+
+        let r = regs();
+        r.packetptr
+            .write(|w| unsafe { w.bits(self.ack.as_ptr() as u32) });
+        r.shorts.write(|w| {
+            w.txready_start().enabled();
+            w.phyend_disable().enabled();
+            w
+        });
+        super::run_until_disabled(Task::from_reg(&r.tasks_txen)).await;
+    }
+
+    fn is_promiscuous(&self) -> bool {
+        self.pan_id.is_none() && self.short_address.is_none() && self.extended_address.is_none()
+    }
+
+    fn accepts(&self, header: &Header) -> bool {
+        if self.is_promiscuous() {
+            return true;
+        }
+        let pan_ok = match (header.dst_pan_id, self.pan_id) {
+            (Some(BROADCAST), _) | (_, None) => true,
+            (Some(dst), Some(ours)) => dst == ours,
+            // Frames without destination are for the PAN coordinator, check the source PAN.
+            (None, Some(ours)) => header.src_pan_id == Some(ours),
+        };
+        let addr_ok = match header.dst {
+            Address::None => true,
+            Address::Short(BROADCAST) => true,
+            Address::Short(a) => self.short_address == Some(a),
+            Address::Extended(a) => self.extended_address == Some(a),
+        };
+        pan_ok && addr_ok
+    }
+}
+
+impl<'d> Drop for Radio<'d> {
+    fn drop(&mut self) {
+        super::disable();
+        regs().intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+    }
+}
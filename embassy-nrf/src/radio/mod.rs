@@ -0,0 +1,124 @@
+//! Low-level RADIO peripheral drivers.
+//!
+//! - [`ble`]: raw Bluetooth Low Energy 1 Mbit PDUs, e.g. advertising and scanning.
+//! - [`ieee802154`]: IEEE 802.15.4 frames, with clear channel assessment and automatic acknowledgments.
+//!
+//! These drivers only handle transmission and reception of single packets. Link layers and
+//! MAC state machines are left to higher layers. Frame encoding helpers live in [`pdu`] and
+//! [`frame`], and don't depend on the hardware.
+
+use core::task::Poll;
+
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use futures::future::poll_fn;
+
+use crate::pac;
+use crate::ppi::Task;
+
+pub mod ble;
+pub mod frame;
+#[cfg(any(
+    feature = "nrf52811",
+    feature = "nrf52820",
+    feature = "nrf52833",
+    feature = "nrf52840"
+))]
+pub mod ieee802154;
+pub mod pdu;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The packet doesn't fit in the radio buffer.
+    BufferTooLong,
+    /// The destination buffer is too small for the received packet.
+    BufferTooShort,
+    /// The received packet failed the CRC check.
+    Crc,
+    /// Clear channel assessment found the channel busy, nothing was transmitted.
+    ChannelBusy,
+    /// The frame is malformed.
+    InvalidFrame,
+    /// The requested channel doesn't exist.
+    InvalidChannel,
+}
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+pub(crate) fn regs() -> &'static pac::radio::RegisterBlock {
+    unsafe { &*pac::RADIO::ptr() }
+}
+
+pub(crate) fn on_interrupt(_: *mut ()) {
+    let r = regs();
+    if r.events_disabled.read().bits() != 0 {
+        // Leave the event set, it's used by the future to check whether the operation is done.
+        r.intenclr.write(|w| w.disabled().clear());
+        WAKER.wake();
+    }
+}
+
+/// Forces the radio into the DISABLED state, aborting any ongoing operation.
+pub(crate) fn disable() {
+    let r = regs();
+    r.shorts.reset();
+    r.intenclr.write(|w| w.disabled().clear());
+    r.tasks_disable.write(|w| unsafe { w.bits(1) });
+    // STATE is 0 (Disabled) once the radio has ramped down.
+    while r.state.read().bits() != 0 {}
+    r.events_disabled.reset();
+}
+
+/// Triggers `task` and waits until the shortcuts configured by the caller bring the radio back
+/// to the DISABLED state.
+///
+/// If the future is dropped, the radio is disabled.
+pub(crate) async fn run_until_disabled(task: Task) {
+    let r = regs();
+
+    r.events_disabled.reset();
+    r.intenset.write(|w| w.disabled().set());
+
+    let on_drop = OnDrop::new(disable);
+
+    // The radio must not see the buffer before it's fully written.
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    unsafe { task.0.as_ptr().write_volatile(1) };
+
+    poll_fn(|cx| {
+        WAKER.register(cx.waker());
+        if r.events_disabled.read().bits() != 0 {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+
+    on_drop.defuse();
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    r.events_disabled.reset();
+    r.shorts.reset();
+}
+
+/// Sets the carrier frequency, in MHz.
+pub(crate) fn set_frequency(mhz: u16) {
+    regs()
+        .frequency
+        .write(|w| unsafe { w.frequency().bits((mhz - 2400) as u8).map().default() });
+}
+
+/// Sets the transmit power in dBm.
+///
+/// The value is written as-is, it must be one of the levels supported by the chip
+/// (e.g. +8..=+2, 0, -4, -8, -12, -16, -20 or -40 on nRF52840).
+pub(crate) fn set_tx_power(dbm: i8) {
+    regs().txpower.write(|w| unsafe { w.bits(dbm as u8 as u32) });
+}
+
+/// Returns the RSSI measured during the last reception, in dBm.
+pub(crate) fn last_rssi() -> i8 {
+    -(regs().rssisample.read().rssisample().bits() as i8)
+}
@@ -0,0 +1,365 @@
+//! Bluetooth Low Energy advertising PDU encoding helpers.
+//!
+//! These are pure functions operating on byte buffers, independent of the RADIO peripheral.
+//! A PDU buffer holds the 2-byte header followed by the payload, which is the in-memory layout
+//! used by the RADIO in BLE mode. The CRC and whitening are normally handled by the hardware,
+//! software implementations are provided for testing and sniffing.
+
+use super::Error;
+
+/// Access address used on the advertising channels.
+pub const ADV_ACCESS_ADDRESS: u32 = 0x8E89_BED6;
+/// CRC initial value used on the advertising channels.
+pub const ADV_CRC_INIT: u32 = 0x55_5555;
+/// Largest legacy advertising payload (AdvA + AdvData).
+pub const MAX_ADV_PAYLOAD_LEN: usize = 37;
+/// Largest AdvData in a legacy advertising PDU.
+pub const MAX_ADV_DATA_LEN: usize = 31;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PduType {
+    AdvInd = 0,
+    AdvDirectInd = 1,
+    AdvNonconnInd = 2,
+    ScanReq = 3,
+    ScanRsp = 4,
+    ConnectInd = 5,
+    AdvScanInd = 6,
+}
+
+impl PduType {
+    fn from_bits(bits: u8) -> Result<Self, Error> {
+        Ok(match bits {
+            0 => PduType::AdvInd,
+            1 => PduType::AdvDirectInd,
+            2 => PduType::AdvNonconnInd,
+            3 => PduType::ScanReq,
+            4 => PduType::ScanRsp,
+            5 => PduType::ConnectInd,
+            6 => PduType::AdvScanInd,
+            _ => return Err(Error::InvalidFrame),
+        })
+    }
+}
+
+/// Advertising PDU header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Header {
+    pub pdu_type: PduType,
+    /// The transmitter address (AdvA, ScanA or InitA) is random.
+    pub tx_random: bool,
+    /// The receiver address (TargetA or AdvA) is random.
+    pub rx_random: bool,
+    /// Payload length in bytes.
+    pub len: u8,
+}
+
+impl Header {
+    pub fn encode(&self) -> [u8; 2] {
+        [
+            self.pdu_type as u8 | (self.tx_random as u8) << 6 | (self.rx_random as u8) << 7,
+            self.len,
+        ]
+    }
+
+    pub fn decode(bytes: [u8; 2]) -> Result<Self, Error> {
+        Ok(Self {
+            pdu_type: PduType::from_bits(bytes[0] & 0x0F)?,
+            tx_random: bytes[0] & (1 << 6) != 0,
+            rx_random: bytes[0] & (1 << 7) != 0,
+            len: bytes[1],
+        })
+    }
+}
+
+/// Writes an advertising PDU (`ADV_IND`, `ADV_NONCONN_IND`, `ADV_SCAN_IND` or `SCAN_RSP`) into a buffer.
+///
+/// # Example
+///
+/// ```no_run
+/// let mut buf = [0; 39];
+/// let len = AdvPduWriter::new(&mut buf, PduType::AdvNonconnInd, addr, true)?
+///     .ad(0x01, &[0x06])?
+///     .ad(0x09, b"embassy")?
+///     .finish();
+/// ```
+pub struct AdvPduWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> AdvPduWriter<'a> {
+    /// Starts a PDU with advertiser address `adv_a` (little-endian, as sent on the air).
+    pub fn new(
+        buf: &'a mut [u8],
+        pdu_type: PduType,
+        adv_a: [u8; 6],
+        random: bool,
+    ) -> Result<Self, Error> {
+        if buf.len() < 8 {
+            return Err(Error::BufferTooShort);
+        }
+        let header = Header {
+            pdu_type,
+            tx_random: random,
+            rx_random: false,
+            len: 6,
+        };
+        buf[..2].copy_from_slice(&header.encode());
+        buf[2..8].copy_from_slice(&adv_a);
+        Ok(Self { buf, pos: 8 })
+    }
+
+    /// Appends an AD structure of type `ad_type`.
+    pub fn ad(self, ad_type: u8, data: &[u8]) -> Result<Self, Error> {
+        let end = self.pos + 2 + data.len();
+        if end - 2 > MAX_ADV_PAYLOAD_LEN {
+            return Err(Error::BufferTooLong);
+        }
+        if end > self.buf.len() {
+            return Err(Error::BufferTooShort);
+        }
+        self.buf[self.pos] = data.len() as u8 + 1;
+        self.buf[self.pos + 1] = ad_type;
+        self.buf[self.pos + 2..end].copy_from_slice(data);
+        Ok(Self {
+            buf: self.buf,
+            pos: end,
+        })
+    }
+
+    /// Finishes the PDU, returning its total length including the header.
+    pub fn finish(self) -> usize {
+        self.buf[1] = (self.pos - 2) as u8;
+        self.pos
+    }
+}
+
+/// A parsed advertising PDU carrying an advertiser address and AD structures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdvPdu<'a> {
+    pub header: Header,
+    pub adv_a: [u8; 6],
+    pub data: &'a [u8],
+}
+
+impl<'a> AdvPdu<'a> {
+    pub fn parse(pdu: &'a [u8]) -> Result<Self, Error> {
+        if pdu.len() < 2 {
+            return Err(Error::BufferTooShort);
+        }
+        let header = Header::decode([pdu[0], pdu[1]])?;
+        match header.pdu_type {
+            PduType::AdvInd | PduType::AdvNonconnInd | PduType::AdvScanInd | PduType::ScanRsp => {}
+            _ => return Err(Error::InvalidFrame),
+        }
+        let end = 2 + header.len as usize;
+        if header.len < 6 || pdu.len() < end {
+            return Err(Error::BufferTooShort);
+        }
+        let mut adv_a = [0; 6];
+        adv_a.copy_from_slice(&pdu[2..8]);
+        Ok(Self {
+            header,
+            adv_a,
+            data: &pdu[8..end],
+        })
+    }
+
+    /// Returns an iterator over the `(type, data)` AD structures in the payload.
+    pub fn ad_structures(&self) -> AdStructures<'a> {
+        AdStructures(self.data)
+    }
+}
+
+/// Iterator over AD structures, as `(type, data)` pairs.
+///
+/// Iteration stops at the first zero-length or truncated structure.
+pub struct AdStructures<'a>(&'a [u8]);
+
+impl<'a> Iterator for AdStructures<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = *self.0.first()? as usize;
+        if len == 0 || self.0.len() < len + 1 {
+            self.0 = &[];
+            return None;
+        }
+        let item = (self.0[1], &self.0[2..len + 1]);
+        self.0 = &self.0[len + 1..];
+        Some(item)
+    }
+}
+
+/// Returns the center frequency in MHz of the BLE channel with index `channel` (0..=39).
+pub fn channel_frequency(channel: u8) -> Option<u16> {
+    match channel {
+        37 => Some(2402),
+        38 => Some(2426),
+        39 => Some(2480),
+        0..=10 => Some(2404 + 2 * channel as u16),
+        11..=36 => Some(2428 + 2 * (channel as u16 - 11)),
+        _ => None,
+    }
+}
+
+/// Computes the 24-bit BLE CRC of a PDU, starting from `init`.
+///
+/// The bytes are processed least significant bit first, like on the air.
+pub fn crc24(init: u32, data: &[u8]) -> u32 {
+    let mut crc = init & 0xFF_FFFF;
+    for &byte in data {
+        for bit in 0..8 {
+            let feedback = ((crc >> 23) ^ (byte as u32 >> bit)) & 1;
+            crc = (crc << 1) & 0xFF_FFFF;
+            if feedback != 0 {
+                crc ^= 0x00_065B;
+            }
+        }
+    }
+    crc
+}
+
+/// Returns the CRC as the three bytes transmitted after the PDU.
+///
+/// The CRC is sent most significant bit first, unlike the rest of the packet.
+pub fn crc24_bytes(crc: u32) -> [u8; 3] {
+    [
+        ((crc >> 16) as u8).reverse_bits(),
+        ((crc >> 8) as u8).reverse_bits(),
+        (crc as u8).reverse_bits(),
+    ]
+}
+
+/// Applies (or removes) data whitening for channel index `channel` in place.
+pub fn whiten(channel: u8, data: &mut [u8]) {
+    // 7-bit LFSR, x^7 + x^4 + 1. Position 0 is set to 1, positions 1..=6 to the channel index
+    // with its most significant bit in position 1.
+    let mut lfsr: u8 = 1;
+    for i in 0..6 {
+        if channel & (1 << (5 - i)) != 0 {
+            lfsr |= 1 << (i + 1);
+        }
+    }
+    for byte in data.iter_mut() {
+        let mut out = 0;
+        for bit in 0..8 {
+            let msb = (lfsr >> 6) & 1;
+            out |= msb << bit;
+            lfsr = (lfsr << 1) & 0x7F;
+            if msb != 0 {
+                lfsr ^= 0b001_0001;
+            }
+        }
+        *byte ^= out;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: [u8; 6] = [0x11, 0x22, 0x33, 0x44, 0x55, 0xC6];
+
+    #[test]
+    fn test_header() {
+        let h = Header {
+            pdu_type: PduType::AdvNonconnInd,
+            tx_random: true,
+            rx_random: false,
+            len: 12,
+        };
+        assert_eq!(h.encode(), [0x42, 12]);
+        assert_eq!(Header::decode([0x42, 12]), Ok(h));
+        assert_eq!(Header::decode([0x0F, 0]), Err(Error::InvalidFrame));
+    }
+
+    #[test]
+    fn test_adv_pdu_roundtrip() {
+        let mut buf = [0; 39];
+        let len = AdvPduWriter::new(&mut buf, PduType::AdvInd, ADDR, true)
+            .unwrap()
+            .ad(0x01, &[0x06])
+            .unwrap()
+            .ad(0x09, b"embassy")
+            .unwrap()
+            .finish();
+        assert_eq!(len, 2 + 6 + 3 + 9);
+        assert_eq!(&buf[..2], &[0x40, 18]);
+        assert_eq!(&buf[8..11], &[0x02, 0x01, 0x06]);
+
+        let pdu = AdvPdu::parse(&buf[..len]).unwrap();
+        assert_eq!(pdu.adv_a, ADDR);
+        let mut ads = pdu.ad_structures();
+        assert_eq!(ads.next(), Some((0x01, &[0x06][..])));
+        assert_eq!(ads.next(), Some((0x09, &b"embassy"[..])));
+        assert_eq!(ads.next(), None);
+    }
+
+    #[test]
+    fn test_adv_pdu_too_long() {
+        let mut buf = [0; 64];
+        let w = AdvPduWriter::new(&mut buf, PduType::AdvInd, ADDR, true).unwrap();
+        let w = w.ad(0xFF, &[0; 29]).unwrap();
+        assert_eq!(w.ad(0xFF, &[]).err(), Some(Error::BufferTooLong));
+
+        let mut small = [0; 10];
+        let w = AdvPduWriter::new(&mut small, PduType::AdvInd, ADDR, true).unwrap();
+        assert_eq!(w.ad(0x01, &[0x06]).err(), Some(Error::BufferTooShort));
+    }
+
+    #[test]
+    fn test_ad_structures_truncated() {
+        let mut ads = AdStructures(&[0x02, 0x01, 0x06, 0x05, 0x09, b'a']);
+        assert_eq!(ads.next(), Some((0x01, &[0x06][..])));
+        assert_eq!(ads.next(), None);
+        assert_eq!(AdStructures(&[0x00, 0x01]).next(), None);
+    }
+
+    #[test]
+    fn test_channel_frequency() {
+        assert_eq!(channel_frequency(37), Some(2402));
+        assert_eq!(channel_frequency(0), Some(2404));
+        assert_eq!(channel_frequency(10), Some(2424));
+        assert_eq!(channel_frequency(38), Some(2426));
+        assert_eq!(channel_frequency(11), Some(2428));
+        assert_eq!(channel_frequency(36), Some(2478));
+        assert_eq!(channel_frequency(39), Some(2480));
+        assert_eq!(channel_frequency(40), None);
+    }
+
+    #[test]
+    fn test_crc24() {
+        assert_eq!(crc24(ADV_CRC_INIT, &[]), ADV_CRC_INIT);
+
+        // Running the CRC over a PDU followed by its CRC leaves a zero residue.
+        let mut buf = [0; 42];
+        let len = AdvPduWriter::new(&mut buf, PduType::AdvNonconnInd, ADDR, true)
+            .unwrap()
+            .ad(0x09, b"crc")
+            .unwrap()
+            .finish();
+        let crc = crc24(ADV_CRC_INIT, &buf[..len]);
+        buf[len..len + 3].copy_from_slice(&crc24_bytes(crc));
+        assert_eq!(crc24(ADV_CRC_INIT, &buf[..len + 3]), 0);
+    }
+
+    #[test]
+    fn test_whiten() {
+        let original = [0x42, 0x0C, 0x11, 0x22, 0x33, 0x44, 0x55, 0xC6];
+        let mut data = original;
+        whiten(37, &mut data);
+        assert_ne!(data, original);
+        whiten(37, &mut data);
+        assert_eq!(data, original);
+
+        // The whitening sequence for channel 0 starts from LFSR state 0000001,
+        // so its first 6 bits are zero and the 7th is one.
+        let mut zeros = [0u8; 1];
+        whiten(0, &mut zeros);
+        assert_eq!(zeros[0], 0x40);
+    }
+}
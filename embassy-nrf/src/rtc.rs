@@ -0,0 +1,402 @@
+#![macro_use]
+
+//! Real Time Counter (RTC) driver.
+//!
+//! The RTC is a 24-bit low-frequency counter clocked from LFCLK (32.768 kHz) through a 12-bit
+//! prescaler. Each instance has 3 or 4 Compare registers, whose COMPARE events can be awaited or
+//! routed to other peripherals through PPI.
+//!
+//! RTC1 is used by the embassy time driver when the `time-driver-rtc1` feature is enabled, so it
+//! is only available to this driver when that feature is disabled.
+
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::task::Poll;
+
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::pac;
+use crate::ppi::{Event, Task};
+
+/// Maximum value of the RTC counter, after which it overflows back to 0.
+pub const COUNTER_MAX: u32 = 0xFF_FFFF;
+
+/// Maximum value of the 12-bit prescaler.
+pub const PRESCALER_MAX: u16 = 0xFFF;
+
+/// Frequency of the RTC input clock (LFCLK), in Hz.
+pub const LFCLK_FREQ: u32 = 32_768;
+
+const EVT_TICK: u32 = 1 << 0;
+const EVT_OVRFLW: u32 = 1 << 1;
+
+fn evt_compare(n: usize) -> u32 {
+    1 << (16 + n)
+}
+
+pub(crate) mod sealed {
+    use super::*;
+
+    pub struct State {
+        pub compare_wakers: [AtomicWaker; 4],
+        pub overflow_waker: AtomicWaker,
+        /// Number of counter overflows seen by the interrupt handler.
+        pub overflows: AtomicU32,
+    }
+
+    impl State {
+        pub const fn new() -> Self {
+            const NEW_AW: AtomicWaker = AtomicWaker::new();
+            Self {
+                compare_wakers: [NEW_AW; 4],
+                overflow_waker: AtomicWaker::new(),
+                overflows: AtomicU32::new(0),
+            }
+        }
+    }
+
+    pub trait Instance {
+        /// The number of CC registers this instance has.
+        const CCS: usize;
+        fn regs() -> &'static pac::rtc0::RegisterBlock;
+        fn state() -> &'static State;
+    }
+}
+
+pub trait Instance: Unborrow<Target = Self> + sealed::Instance + 'static + Send {
+    type Interrupt: Interrupt;
+}
+
+macro_rules! impl_rtc {
+    ($type:ident, $pac_type:ident, $irq:ident, $ccs:literal) => {
+        impl crate::rtc::sealed::Instance for peripherals::$type {
+            const CCS: usize = $ccs;
+            fn regs() -> &'static pac::rtc0::RegisterBlock {
+                unsafe { &*(pac::$pac_type::ptr() as *const pac::rtc0::RegisterBlock) }
+            }
+            fn state() -> &'static crate::rtc::sealed::State {
+                static STATE: crate::rtc::sealed::State = crate::rtc::sealed::State::new();
+                &STATE
+            }
+        }
+        impl crate::rtc::Instance for peripherals::$type {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+}
+
+/// RTC configuration.
+#[non_exhaustive]
+pub struct Config {
+    /// Prescaler applied to LFCLK. The counter frequency is `32768 / (prescaler + 1)` Hz.
+    ///
+    /// Values above [`PRESCALER_MAX`] are clamped.
+    pub prescaler: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { prescaler: 0 }
+    }
+}
+
+impl Config {
+    /// Configuration with the prescaler closest to the requested counter frequency, in Hz.
+    pub fn with_frequency(hz: u32) -> Self {
+        let hz = hz.max(1).min(LFCLK_FREQ);
+        let prescaler = (LFCLK_FREQ + hz / 2) / hz - 1;
+        Self {
+            prescaler: (prescaler as u16).min(PRESCALER_MAX),
+        }
+    }
+}
+
+/// nRF RTC driver.
+///
+/// The counter is 24 bits wide. Overflows are counted in the interrupt handler, so [`Rtc::now`]
+/// provides a 64-bit tick count that doesn't wrap.
+pub struct Rtc<'d, T: Instance> {
+    irq: T::Interrupt,
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> Rtc<'d, T> {
+    pub fn new(
+        _rtc: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(irq);
+
+        let r = T::regs();
+        let s = T::state();
+
+        let this = Self {
+            irq,
+            phantom: PhantomData,
+        };
+
+        // The prescaler can only be written while the RTC is stopped.
+        this.stop();
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        r.evtenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        r.prescaler
+            .write(|w| unsafe { w.prescaler().bits(config.prescaler.min(PRESCALER_MAX)) });
+        this.clear();
+
+        r.events_tick.reset();
+        r.events_ovrflw.reset();
+        for n in 0..T::CCS {
+            r.events_compare[n].reset();
+            r.cc[n].write(|w| unsafe { w.bits(0) });
+        }
+        s.overflows.store(0, Ordering::Relaxed);
+
+        this.irq.set_handler(Self::on_interrupt);
+        this.irq.unpend();
+        this.irq.enable();
+
+        // Overflows are always tracked, so that `now` keeps counting past the 24-bit range.
+        r.intenset.write(|w| unsafe { w.bits(EVT_OVRFLW) });
+
+        this
+    }
+
+    fn on_interrupt(_: *mut ()) {
+        let r = T::regs();
+        let s = T::state();
+
+        if r.events_ovrflw.read().bits() != 0 {
+            r.events_ovrflw.reset();
+            s.overflows.fetch_add(1, Ordering::Release);
+            s.overflow_waker.wake();
+        }
+
+        for n in 0..T::CCS {
+            if r.events_compare[n].read().bits() != 0 {
+                // Disable the interrupt but leave the event set, it's used by the future to
+                // check whether the compare has fired.
+                r.intenclr.write(|w| unsafe { w.bits(evt_compare(n)) });
+                s.compare_wakers[n].wake();
+            }
+        }
+    }
+
+    /// Starts the counter.
+    pub fn start(&self) {
+        T::regs().tasks_start.write(|w| unsafe { w.bits(1) })
+    }
+
+    /// Stops the counter.
+    pub fn stop(&self) {
+        T::regs().tasks_stop.write(|w| unsafe { w.bits(1) })
+    }
+
+    /// Resets the counter to 0.
+    ///
+    /// This doesn't reset the overflow count used by [`Rtc::now`].
+    pub fn clear(&self) {
+        T::regs().tasks_clear.write(|w| unsafe { w.bits(1) })
+    }
+
+    /// Changes the prescaler.
+    ///
+    /// This stops the counter, since the prescaler can't be changed while it's running.
+    pub fn set_prescaler(&self, prescaler: u16) {
+        self.stop();
+        T::regs()
+            .prescaler
+            .write(|w| unsafe { w.prescaler().bits(prescaler.min(PRESCALER_MAX)) })
+    }
+
+    /// Returns the current value of the 24-bit counter.
+    pub fn counter(&self) -> u32 {
+        T::regs().counter.read().bits() & COUNTER_MAX
+    }
+
+    /// Returns the number of ticks elapsed, including counter overflows.
+    pub fn now(&self) -> u64 {
+        let r = T::regs();
+        let s = T::state();
+        critical_section::with(|_| {
+            let mut overflows = s.overflows.load(Ordering::Acquire);
+            let counter = r.counter.read().bits() & COUNTER_MAX;
+            // An overflow may have happened that the interrupt handler hasn't counted yet.
+            // If the counter is in its lower half, the overflow happened before it was read.
+            if r.events_ovrflw.read().bits() != 0 && counter < (COUNTER_MAX + 1) / 2 {
+                overflows = overflows.wrapping_add(1);
+            }
+            ((overflows as u64) << 24) | counter as u64
+        })
+    }
+
+    /// Waits until the next counter overflow.
+    pub async fn wait_overflow(&mut self) {
+        let s = T::state();
+        let start = s.overflows.load(Ordering::Acquire);
+
+        poll_fn(|cx| {
+            s.overflow_waker.register(cx.waker());
+
+            if s.overflows.load(Ordering::Acquire) != start {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+    }
+
+    /// Returns the START task, for use with PPI.
+    pub fn task_start(&self) -> Task {
+        Task::from_reg(&T::regs().tasks_start)
+    }
+
+    /// Returns the STOP task, for use with PPI.
+    pub fn task_stop(&self) -> Task {
+        Task::from_reg(&T::regs().tasks_stop)
+    }
+
+    /// Returns the CLEAR task, for use with PPI.
+    pub fn task_clear(&self) -> Task {
+        Task::from_reg(&T::regs().tasks_clear)
+    }
+
+    /// Returns the TRIGOVRFLW task, for use with PPI.
+    ///
+    /// When triggered, this task sets the counter to 0xFFFFF0, which is useful to test overflow handling.
+    pub fn task_trigger_overflow(&self) -> Task {
+        Task::from_reg(&T::regs().tasks_trigovrflw)
+    }
+
+    /// Returns the TICK event, for use with PPI.
+    ///
+    /// This enables routing of the event to PPI. The TICK event fires on every counter increment,
+    /// which increases power consumption, so disable it when no longer needed.
+    pub fn event_tick(&self) -> Event {
+        T::regs().evtenset.write(|w| unsafe { w.bits(EVT_TICK) });
+        Event::from_reg(&T::regs().events_tick)
+    }
+
+    /// Returns the OVRFLW event, for use with PPI.
+    ///
+    /// This enables routing of the event to PPI.
+    pub fn event_overflow(&self) -> Event {
+        T::regs().evtenset.write(|w| unsafe { w.bits(EVT_OVRFLW) });
+        Event::from_reg(&T::regs().events_ovrflw)
+    }
+
+    /// Stops routing the TICK event to PPI.
+    pub fn disable_event_tick(&self) {
+        T::regs().evtenclr.write(|w| unsafe { w.bits(EVT_TICK) });
+    }
+
+    /// Stops routing the OVRFLW event to PPI.
+    pub fn disable_event_overflow(&self) {
+        T::regs().evtenclr.write(|w| unsafe { w.bits(EVT_OVRFLW) });
+    }
+
+    /// Returns this RTC's `n`th CC register.
+    ///
+    /// # Panics
+    /// Panics if `n` >= the number of CC registers this RTC has (3 for RTC0 on nRF52, 4 otherwise).
+    pub fn cc(&mut self, n: usize) -> Cc<T> {
+        if n >= T::CCS {
+            panic!(
+                "Cannot get CC register {} of RTC with {} CC registers.",
+                n,
+                T::CCS
+            );
+        }
+        Cc {
+            n,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'d, T: Instance> Drop for Rtc<'d, T> {
+    fn drop(&mut self) {
+        let r = T::regs();
+        self.stop();
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        r.evtenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        self.irq.disable();
+        self.irq.remove_handler();
+    }
+}
+
+/// A representation of an RTC's Compare (CC) register.
+///
+/// The RTC fires the register's COMPARE event when its counter reaches the value stored in the register.
+pub struct Cc<'a, T: Instance> {
+    n: usize,
+    phantom: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: Instance> Cc<'a, T> {
+    /// Get the current value stored in the register.
+    pub fn read(&self) -> u32 {
+        T::regs().cc[self.n].read().bits() & COUNTER_MAX
+    }
+
+    /// Set the value stored in the register.
+    ///
+    /// Only the lower 24 bits are used. Note that the RTC won't fire a COMPARE event if the
+    /// value is written less than 2 ticks ahead of the current counter value.
+    pub fn write(&self, value: u32) {
+        T::regs().cc[self.n].write(|w| unsafe { w.bits(value & COUNTER_MAX) })
+    }
+
+    /// Wait until the counter reaches the value stored in this register.
+    ///
+    /// This requires a mutable reference so that this task's waker cannot be overwritten by a second call to `wait`.
+    pub async fn wait(&mut self) {
+        let r = T::regs();
+        let n = self.n;
+
+        r.events_compare[n].reset();
+        r.intenset.write(|w| unsafe { w.bits(evt_compare(n)) });
+
+        // Disable the interrupt if the future is dropped.
+        let on_drop = OnDrop::new(|| {
+            r.intenclr.write(|w| unsafe { w.bits(evt_compare(n)) });
+        });
+
+        poll_fn(|cx| {
+            T::state().compare_wakers[n].register(cx.waker());
+
+            if r.events_compare[n].read().bits() != 0 {
+                r.events_compare[n].reset();
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        // The interrupt was already disabled in the interrupt handler.
+        on_drop.defuse();
+    }
+
+    /// Returns this CC register's COMPARE event, for use with PPI.
+    ///
+    /// This enables routing of the event to PPI.
+    pub fn event_compare(&self) -> Event {
+        let r = T::regs();
+        r.evtenset.write(|w| unsafe { w.bits(evt_compare(self.n)) });
+        Event::from_reg(&r.events_compare[self.n])
+    }
+
+    /// Stops routing this CC register's COMPARE event to PPI.
+    pub fn disable_event_compare(&self) {
+        T::regs()
+            .evtenclr
+            .write(|w| unsafe { w.bits(evt_compare(self.n)) });
+    }
+}
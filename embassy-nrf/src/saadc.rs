@@ -6,11 +6,12 @@ use core::task::Poll;
 use embassy::interrupt::InterruptExt;
 use embassy::util::Unborrow;
 use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
 use embassy_hal_common::unborrow;
 use futures::future::poll_fn;
 
 use crate::interrupt;
-use crate::ppi::Task;
+use crate::ppi::{Event, Task};
 use crate::{pac, peripherals};
 
 use pac::{saadc, SAADC};
@@ -44,6 +45,10 @@ pub struct Config {
     /// Output resolution in bits.
     pub resolution: Resolution,
     /// Average 2^`oversample` input samples before transferring the result into memory.
+    ///
+    /// When oversampling, burst mode is enabled on all channels, so a single SAMPLE task
+    /// takes all the samples averaged into one result. This is required when scanning more
+    /// than one channel.
     pub oversample: Oversample,
 }
 
@@ -70,6 +75,10 @@ pub struct ChannelConfig<'d> {
     pub resistor: Resistor,
     /// Acquisition time in microseconds.
     pub time: Time,
+    /// Low and high limits, raising the LIMITL and LIMITH events when a result is out of them.
+    ///
+    /// See [`Saadc::wait_limit`].
+    pub limits: Option<Limits>,
     /// Positive channel to sample
     p_channel: InputChannel,
     /// An optional negative channel to sample
@@ -110,6 +119,7 @@ impl<'d> ChannelConfig<'d> {
             gain: Gain::GAIN1_6,
             resistor: Resistor::BYPASS,
             time: Time::_10US,
+            limits: None,
             p_channel: input.channel(),
             n_channel: None,
             phantom: PhantomData,
@@ -126,6 +136,7 @@ impl<'d> ChannelConfig<'d> {
             gain: Gain::GAIN1_6,
             resistor: Resistor::BYPASS,
             time: Time::_10US,
+            limits: None,
             p_channel: p_input.channel(),
             n_channel: Some(n_input.channel()),
             phantom: PhantomData,
@@ -133,6 +144,24 @@ impl<'d> ChannelConfig<'d> {
     }
 }
 
+/// Limits of a channel, in the same units as the samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Limits {
+    pub low: i16,
+    pub high: i16,
+}
+
+/// Which limit of a channel was crossed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Limit {
+    /// A result was below the low limit.
+    Low,
+    /// A result was above the high limit.
+    High,
+}
+
 /// The state of a continuously running sampler. While it reflects
 /// the progress of a sampler, it also signals what should be done
 /// next. For example, if the sampler has stopped then the Saadc implementation
@@ -190,6 +219,7 @@ impl<'d, const N: usize> Saadc<'d, N> {
                 }
                 w
             });
+            Self::write_limits(i, cc.limits);
         }
 
         // Disable all events interrupts
@@ -216,12 +246,157 @@ impl<'d, const N: usize> Saadc<'d, N> {
             r.intenclr.write(|w| w.started().clear());
             WAKER.wake();
         }
+
+        if r.events_calibratedone.read().bits() != 0 {
+            r.intenclr.write(|w| w.calibratedone().clear());
+            WAKER.wake();
+        }
+
+        for (i, ch) in r.events_ch.iter().enumerate() {
+            if ch.limith.read().bits() != 0 || ch.limitl.read().bits() != 0 {
+                r.intenclr.write(|w| unsafe { w.bits(limit_irq_mask(i)) });
+                WAKER.wake();
+            }
+        }
+    }
+
+    fn write_limits(channel: usize, limits: Option<Limits>) {
+        let Limits { low, high } = limits.unwrap_or(Limits {
+            low: i16::MIN,
+            high: i16::MAX,
+        });
+        Self::regs().ch[channel].limit.write(|w| unsafe {
+            w.low().bits(low as u16);
+            w.high().bits(high as u16);
+            w
+        });
     }
 
     fn regs() -> &'static saadc::RegisterBlock {
         unsafe { &*SAADC::ptr() }
     }
 
+    /// Calibrates the offset of the SAADC.
+    ///
+    /// This should be done once after startup, and again whenever the temperature changes by
+    /// more than 10 degrees Celsius.
+    pub async fn calibrate(&mut self) {
+        let r = Self::regs();
+
+        // In case the future is dropped, don't leave the interrupt enabled.
+        let on_drop = OnDrop::new(|| {
+            Self::regs().intenclr.write(|w| w.calibratedone().clear());
+        });
+
+        r.events_calibratedone.reset();
+        r.intenset.write(|w| w.calibratedone().set());
+
+        r.tasks_calibrateoffset.write(|w| unsafe { w.bits(1) });
+
+        poll_fn(|cx| {
+            let r = Self::regs();
+
+            WAKER.register(cx.waker());
+
+            if r.events_calibratedone.read().bits() != 0 {
+                r.events_calibratedone.reset();
+                return Poll::Ready(());
+            }
+
+            Poll::Pending
+        })
+        .await;
+        on_drop.defuse();
+    }
+
+    /// Sets the limits of `channel`, or removes them if `limits` is `None`.
+    ///
+    /// # Panics
+    /// Panics if `channel` >= `N`.
+    pub fn set_limits(&mut self, channel: usize, limits: Option<Limits>) {
+        assert!(channel < N);
+        Self::write_limits(channel, limits);
+    }
+
+    /// Samples continuously until a result of `channel` is out of its limits.
+    ///
+    /// The SAMPLE task must be triggered externally, typically from a TIMER or RTC event
+    /// through PPI, see [`Saadc::task_sample`]. Every time all channels have been sampled, the
+    /// results are discarded and the SAADC is restarted.
+    ///
+    /// # Panics
+    /// Panics if `channel` >= `N`.
+    pub async fn wait_limit(&mut self, channel: usize) -> Limit {
+        assert!(channel < N);
+        let r = Self::regs();
+        let mut buf = [0i16; N];
+
+        // In case the future is dropped, stop sampling and don't leave the interrupts enabled.
+        let on_drop = OnDrop::new(|| {
+            let r = Self::regs();
+            r.intenclr
+                .write(|w| unsafe { w.bits(limit_irq_mask(channel)) });
+            r.intenclr.write(|w| w.end().clear());
+            // Wait for the stop, so the SAADC no longer writes to `buf`.
+            r.events_stopped.reset();
+            r.tasks_stop.write(|w| unsafe { w.bits(1) });
+            while r.events_stopped.read().bits() == 0 {}
+            r.events_stopped.reset();
+        });
+
+        r.samplerate.write(|w| unsafe {
+            w.cc().bits(0);
+            w.mode().task();
+            w
+        });
+        r.result
+            .ptr
+            .write(|w| unsafe { w.ptr().bits(buf.as_mut_ptr() as u32) });
+        r.result
+            .maxcnt
+            .write(|w| unsafe { w.maxcnt().bits(N as _) });
+
+        r.events_ch[channel].limitl.reset();
+        r.events_ch[channel].limith.reset();
+        r.events_end.reset();
+        r.intenset.write(|w| unsafe { w.bits(limit_irq_mask(channel)) });
+        r.intenset.write(|w| w.end().set());
+
+        compiler_fence(Ordering::SeqCst);
+
+        r.tasks_start.write(|w| unsafe { w.bits(1) });
+
+        let limit = poll_fn(|cx| {
+            let r = Self::regs();
+
+            WAKER.register(cx.waker());
+
+            if r.events_ch[channel].limith.read().bits() != 0 {
+                r.events_ch[channel].limith.reset();
+                return Poll::Ready(Limit::High);
+            }
+            if r.events_ch[channel].limitl.read().bits() != 0 {
+                r.events_ch[channel].limitl.reset();
+                return Poll::Ready(Limit::Low);
+            }
+
+            if r.events_end.read().bits() != 0 {
+                r.events_end.reset();
+                r.intenset.write(|w| w.end().set());
+                r.tasks_start.write(|w| unsafe { w.bits(1) });
+            }
+
+            Poll::Pending
+        })
+        .await;
+
+        // Stops sampling and disables the interrupts.
+        drop(on_drop);
+        compiler_fence(Ordering::SeqCst);
+
+        limit
+    }
+
     /// One shot sampling. The buffer must be the same size as the number of channels configured.
     pub async fn sample(&mut self, buf: &mut [i16; N]) {
         let r = Self::regs();
@@ -373,6 +548,61 @@ impl<'d, const N: usize> Saadc<'d, N> {
         let r = Self::regs();
         Task::from_reg(&r.tasks_sample)
     }
+
+    /// Return the start task for use with PPI
+    pub fn task_start(&self) -> Task {
+        let r = Self::regs();
+        Task::from_reg(&r.tasks_start)
+    }
+
+    /// Return the stop task for use with PPI
+    pub fn task_stop(&self) -> Task {
+        let r = Self::regs();
+        Task::from_reg(&r.tasks_stop)
+    }
+
+    /// Return the started event for use with PPI
+    pub fn event_started(&self) -> Event {
+        let r = Self::regs();
+        Event::from_reg(&r.events_started)
+    }
+
+    /// Return the end event for use with PPI, raised when the result buffer is full
+    pub fn event_end(&self) -> Event {
+        let r = Self::regs();
+        Event::from_reg(&r.events_end)
+    }
+
+    /// Return the done event for use with PPI, raised for every conversion
+    pub fn event_done(&self) -> Event {
+        let r = Self::regs();
+        Event::from_reg(&r.events_done)
+    }
+
+    /// Return the result done event for use with PPI, raised for every result written to RAM
+    pub fn event_result_done(&self) -> Event {
+        let r = Self::regs();
+        Event::from_reg(&r.events_resultdone)
+    }
+
+    /// Return the low limit event of `channel` for use with PPI
+    pub fn event_limit_low(&self, channel: usize) -> Event {
+        assert!(channel < N);
+        let r = Self::regs();
+        Event::from_reg(&r.events_ch[channel].limitl)
+    }
+
+    /// Return the high limit event of `channel` for use with PPI
+    pub fn event_limit_high(&self, channel: usize) -> Event {
+        assert!(channel < N);
+        let r = Self::regs();
+        Event::from_reg(&r.events_ch[channel].limith)
+    }
+}
+
+/// INTEN bits of the LIMITH and LIMITL events of `channel`.
+fn limit_irq_mask(channel: usize) -> u32 {
+    0b11 << (6 + 2 * channel)
 }
 
 impl<'d> Saadc<'d, 1> {
@@ -85,13 +85,25 @@ fn main() {
         };
     }
 
+    // Flash banks, as (offset from the start of the flash, size) pairs.
+    let mut flash_banks: Vec<String> = Vec::new();
+    stm32_metapac::flash_regions!(
+        ($name:ident, $base:expr, $bytes:expr) => {
+            if stringify!($name).starts_with("BANK_") {
+                flash_banks.push(format!("({}, {})", $base as usize - stm32_metapac::FLASH_BASE, $bytes));
+            }
+        };
+    );
+
     let out_dir = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
     let out_file = out_dir.join("generated.rs").to_string_lossy().to_string();
     fs::write(
         out_file,
         format!(
-            "embassy_hal_common::peripherals!({});",
-            singletons.join(",")
+            "embassy_hal_common::peripherals!({});
+pub(crate) const FLASH_BANKS: &[(u32, u32)] = &[{}];",
+            singletons.join(","),
+            flash_banks.join(",")
         ),
     )
     .unwrap();
@@ -9,13 +9,19 @@ mod _version;
 #[allow(unused)]
 pub use _version::*;
 
+use embassy::interrupt::Interrupt;
+
+use crate::dma;
 use crate::peripherals;
 
 pub(crate) mod sealed {
+    use embassy::waitqueue::AtomicWaker;
+
     pub trait Instance {
         fn regs() -> &'static crate::pac::adc::Adc;
         #[cfg(not(adc_f1))]
         fn common_regs() -> &'static crate::pac::adccommon::AdcCommon;
+        fn waker() -> &'static AtomicWaker;
     }
 
     #[cfg(not(adc_f1))]
@@ -26,15 +32,24 @@ pub(crate) mod sealed {
     pub trait AdcPin<T: Instance> {
         fn channel(&self) -> u8;
     }
+
+    pub trait RxDma<T: Instance> {
+        fn request(&self) -> super::dma::Request;
+    }
 }
 
-#[cfg(not(adc_f1))]
-pub trait Instance: sealed::Instance + 'static {}
-#[cfg(adc_f1)]
-pub trait Instance: sealed::Instance + crate::rcc::RccPeripheral + 'static {}
+#[cfg(not(any(adc_f1, adc_v2)))]
+pub trait Instance: sealed::Instance + 'static {
+    type Interrupt: Interrupt;
+}
+#[cfg(any(adc_f1, adc_v2))]
+pub trait Instance: sealed::Instance + crate::rcc::RccPeripheral + 'static {
+    type Interrupt: Interrupt;
+}
 #[cfg(not(adc_f1))]
 pub trait Common: sealed::Common + 'static {}
 pub trait AdcPin<T: Instance>: sealed::AdcPin<T> {}
+pub trait RxDma<T: Instance>: sealed::RxDma<T> + dma::Channel {}
 
 crate::pac::peripherals!(
     (adc, $inst:ident) => {
@@ -50,12 +65,53 @@ crate::pac::peripherals!(
                     };
                 }
             }
+            fn waker() -> &'static embassy::waitqueue::AtomicWaker {
+                static WAKER: embassy::waitqueue::AtomicWaker = embassy::waitqueue::AtomicWaker::new();
+                &WAKER
+            }
         }
+    };
+);
 
-        impl crate::adc::Instance for peripherals::$inst {}
+crate::pac::interrupts!(
+    ($inst:ident, adc, $block:ident, GLOBAL, $irq:ident) => {
+        impl crate::adc::Instance for peripherals::$inst {
+            type Interrupt = crate::interrupt::$irq;
+        }
     };
 );
 
+/// Handles the interrupts of all the ADCs, as some of them share their interrupt.
+#[cfg(not(adc_f1))]
+fn on_interrupt(_: *mut ()) {
+    crate::pac::interrupts!(
+        ($inst:ident, adc, $block:ident, GLOBAL, $irq:ident) => {
+            _version::on_interrupt::<peripherals::$inst>();
+        };
+    );
+}
+
+/// Active edge of an external trigger
+#[cfg(not(adc_f1))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TriggerEdge {
+    Rising = 0b01,
+    Falling = 0b10,
+    Both = 0b11,
+}
+
+/// External event starting conversions, such as the TRGO or a compare event of a timer
+#[cfg(not(adc_f1))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Trigger {
+    /// Event number, as listed in the EXTSEL (or JEXTSEL for injected conversions) field
+    /// description of the reference manual.
+    pub source: u8,
+    pub edge: TriggerEdge,
+}
+
 #[cfg(not(adc_f1))]
 crate::pac::peripherals!(
     (adccommon, $inst:ident) => {
@@ -134,3 +190,38 @@ crate::pac::peripheral_pins!(
         impl_pin!($inst, $pin, 16);
     };
 );
+
+#[allow(unused)]
+macro_rules! impl_dma {
+    ($inst:ident, {dmamux: $dmamux:ident}, $signal:ident, $request:expr) => {
+        impl<T> sealed::$signal<peripherals::$inst> for T
+        where
+            T: crate::dma::MuxChannel<Mux = crate::dma::$dmamux>,
+        {
+            fn request(&self) -> dma::Request {
+                $request
+            }
+        }
+
+        impl<T> $signal<peripherals::$inst> for T where
+            T: crate::dma::MuxChannel<Mux = crate::dma::$dmamux>
+        {
+        }
+    };
+    ($inst:ident, {channel: $channel:ident}, $signal:ident, $request:expr) => {
+        impl sealed::$signal<peripherals::$inst> for peripherals::$channel {
+            fn request(&self) -> dma::Request {
+                $request
+            }
+        }
+
+        impl $signal<peripherals::$inst> for peripherals::$channel {}
+    };
+}
+
+// The ADC has a single DMA request, whatever its name.
+crate::pac::peripheral_dma_channels! {
+    ($peri:ident, adc, $kind:ident, $request_name:ident, $channel:tt, $request:expr) => {
+        impl_dma!($peri, $channel, RxDma, $request);
+    };
+}
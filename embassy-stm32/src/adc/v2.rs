@@ -1 +1,582 @@
+use crate::adc::{AdcPin, Instance, RxDma, Trigger};
+use crate::dma::{DmaRingBuffer, OverrunError};
+use crate::pac::adc::vals::{Exten, Jexten, Res, Smp};
+use crate::pac::adccommon::vals::Adcpre;
+use crate::time::Hertz;
+use core::marker::PhantomData;
+use core::task::Poll;
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+use embedded_hal::blocking::delay::DelayUs;
+use futures::future::poll_fn;
 
+pub const VDDA_CALIB_MV: u32 = 3300;
+
+/// Maximum ADC clock, for VDDA >= 2.4 V
+const MAX_ADC_CLK: u32 = 36_000_000;
+
+pub enum Resolution {
+    TwelveBit,
+    TenBit,
+    EightBit,
+    SixBit,
+}
+
+impl Default for Resolution {
+    fn default() -> Self {
+        Self::TwelveBit
+    }
+}
+
+impl Resolution {
+    fn res(&self) -> Res {
+        match self {
+            Resolution::TwelveBit => Res::TWELVEBIT,
+            Resolution::TenBit => Res::TENBIT,
+            Resolution::EightBit => Res::EIGHTBIT,
+            Resolution::SixBit => Res::SIXBIT,
+        }
+    }
+
+    fn to_max_count(&self) -> u32 {
+        match self {
+            Resolution::TwelveBit => (1 << 12) - 1,
+            Resolution::TenBit => (1 << 10) - 1,
+            Resolution::EightBit => (1 << 8) - 1,
+            Resolution::SixBit => (1 << 6) - 1,
+        }
+    }
+}
+
+pub struct Vref;
+impl<T: Instance> AdcPin<T> for Vref {}
+impl<T: Instance> super::sealed::AdcPin<T> for Vref {
+    fn channel(&self) -> u8 {
+        17
+    }
+}
+
+pub struct Temperature;
+impl<T: Instance> AdcPin<T> for Temperature {}
+impl<T: Instance> super::sealed::AdcPin<T> for Temperature {
+    fn channel(&self) -> u8 {
+        #[cfg(any(
+            stm32f205, stm32f207, stm32f215, stm32f217, stm32f405, stm32f407, stm32f415, stm32f417
+        ))]
+        let val = 16;
+        #[cfg(not(any(
+            stm32f205, stm32f207, stm32f215, stm32f217, stm32f405, stm32f407, stm32f415, stm32f417
+        )))]
+        let val = 18;
+        val
+    }
+}
+
+pub struct Vbat;
+impl<T: Instance> AdcPin<T> for Vbat {}
+impl<T: Instance> super::sealed::AdcPin<T> for Vbat {
+    fn channel(&self) -> u8 {
+        18
+    }
+}
+
+/// ADC sample time
+///
+/// The default setting is 3 ADC clock cycles.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum SampleTime {
+    /// 3 ADC clock cycles
+    Cycles3 = 0b000,
+
+    /// 15 ADC clock cycles
+    Cycles15 = 0b001,
+
+    /// 28 ADC clock cycles
+    Cycles28 = 0b010,
+
+    /// 56 ADC clock cycles
+    Cycles56 = 0b011,
+
+    /// 84 ADC clock cycles
+    Cycles84 = 0b100,
+
+    /// 112 ADC clock cycles
+    Cycles112 = 0b101,
+
+    /// 144 ADC clock cycles
+    Cycles144 = 0b110,
+
+    /// 480 ADC clock cycles
+    Cycles480 = 0b111,
+}
+
+impl Default for SampleTime {
+    fn default() -> Self {
+        Self::Cycles3
+    }
+}
+
+/// Returns the ADCPRE value dividing `pclk2` down to the maximum ADC clock.
+fn prescaler(pclk2: Hertz) -> Adcpre {
+    match (pclk2.0 + MAX_ADC_CLK - 1) / MAX_ADC_CLK {
+        0..=2 => Adcpre::DIV2,
+        3..=4 => Adcpre::DIV4,
+        5..=6 => Adcpre::DIV6,
+        7..=8 => Adcpre::DIV8,
+        _ => panic!("PCLK2 is too fast for the ADC"),
+    }
+}
+
+pub struct Adc<'d, T: Instance> {
+    sample_time: SampleTime,
+    resolution: Resolution,
+    trigger: Option<Trigger>,
+    injected_trigger: Option<Trigger>,
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> Adc<'d, T> {
+    /// Creates the driver, and powers the ADC up.
+    ///
+    /// The conversions waiting for the interrupt, [`read_async`](Self::read_async) and
+    /// [`read_injected`](Self::read_injected), need the driver created with
+    /// [`new_with_irq`](Self::new_with_irq).
+    pub fn new(_peri: impl Unborrow<Target = T> + 'd, delay: &mut impl DelayUs<u32>) -> Self {
+        unborrow!(_peri);
+        // The ADCs share their reset, which can't be used without disturbing the other ones.
+        T::enable();
+
+        let adcpre = prescaler(T::frequency());
+        unsafe {
+            T::common_regs().ccr().modify(|reg| reg.set_adcpre(adcpre));
+
+            // A single channel is converted when the sequence length is 1, so the scan mode can
+            // be left on for the sequences.
+            T::regs().cr1().modify(|reg| reg.set_scan(true));
+            T::regs().cr2().modify(|reg| reg.set_adon(true));
+        }
+
+        // tSTAB, the power-up time of the ADC, is 3 us at most.
+        delay.delay_us(3);
+
+        Self {
+            sample_time: Default::default(),
+            resolution: Resolution::default(),
+            trigger: None,
+            injected_trigger: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Same as [`new`](Self::new), with the interrupt used by the async conversions.
+    ///
+    /// The ADCs of a chip may share their interrupt, which can then be passed by reference to
+    /// each of them.
+    pub fn new_with_irq(
+        peri: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        delay: &mut impl DelayUs<u32>,
+    ) -> Self {
+        unborrow!(irq);
+        let this = Self::new(peri, delay);
+
+        irq.set_handler(super::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        this
+    }
+
+    pub fn enable_vref(&self, delay: &mut impl DelayUs<u32>) -> Vref {
+        unsafe {
+            T::common_regs().ccr().modify(|reg| reg.set_tsvrefe(true));
+        }
+
+        // tSTART of the temperature sensor and internal reference is 10 us at most.
+        delay.delay_us(10);
+
+        Vref {}
+    }
+
+    /// Enables the temperature sensor.
+    ///
+    /// On most chips, it shares its channel with Vbat, which must then be disabled.
+    pub fn enable_temperature(&self, delay: &mut impl DelayUs<u32>) -> Temperature {
+        unsafe {
+            T::common_regs().ccr().modify(|reg| reg.set_tsvrefe(true));
+        }
+
+        delay.delay_us(10);
+
+        Temperature {}
+    }
+
+    pub fn enable_vbat(&self) -> Vbat {
+        unsafe {
+            T::common_regs().ccr().modify(|reg| reg.set_vbate(true));
+        }
+
+        Vbat {}
+    }
+
+    /// Disables Vbat, which has priority over the temperature sensor on a shared channel.
+    pub fn disable_vbat(&self, _vbat: Vbat) {
+        unsafe {
+            T::common_regs().ccr().modify(|reg| reg.set_vbate(false));
+        }
+    }
+
+    pub fn set_sample_time(&mut self, sample_time: SampleTime) {
+        self.sample_time = sample_time;
+    }
+
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+    }
+
+    /// Sets the event starting the regular conversions of [`read`](Self::read),
+    /// [`read_async`](Self::read_async), [`read_sequence`](Self::read_sequence) and
+    /// [`start_continuous`](Self::start_continuous).
+    ///
+    /// Each event converts the whole sequence once. With `None`, the conversions are started by
+    /// software.
+    pub fn set_trigger(&mut self, trigger: Option<Trigger>) {
+        self.trigger = trigger;
+    }
+
+    /// Sets the event starting the injected conversions of [`read_injected`](Self::read_injected).
+    pub fn set_injected_trigger(&mut self, trigger: Option<Trigger>) {
+        self.injected_trigger = trigger;
+    }
+
+    /// Convert a measurement to millivolts
+    pub fn to_millivolts(&self, sample: u16) -> u16 {
+        ((u32::from(sample) * VDDA_CALIB_MV) / self.resolution.to_max_count()) as u16
+    }
+
+    /// Converts `pin`, waiting for the end of the conversion with the interrupt.
+    ///
+    /// The driver must have been created with [`new_with_irq`](Self::new_with_irq).
+    pub async fn read_async(&mut self, pin: &mut impl AdcPin<T>) -> u16 {
+        unsafe {
+            self.set_sequence(core::iter::once(pin.channel()));
+            self.configure_regular(false, false, false);
+            T::regs().cr1().modify(|reg| reg.set_eocie(true));
+            self.start_regular();
+        }
+        let _on_drop = OnDrop::new(|| unsafe { Self::stop_regular() });
+
+        poll_fn(|cx| {
+            T::waker().register(cx.waker());
+
+            if unsafe { T::regs().sr().read().eoc() } {
+                Poll::Ready(unsafe { T::regs().dr().read().0 as u16 })
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Converts `pin`, polling for the end of the conversion.
+    pub fn read(&mut self, pin: &mut impl AdcPin<T>) -> u16 {
+        unsafe {
+            self.set_sequence(core::iter::once(pin.channel()));
+            self.configure_regular(false, false, false);
+            self.start_regular();
+
+            while !T::regs().sr().read().eoc() {
+                // spin
+            }
+
+            let val = T::regs().dr().read().0 as u16;
+            Self::stop_regular();
+            val
+        }
+    }
+
+    /// Converts the sequence of up to 16 `pins` until `buf` is full, with a DMA channel.
+    ///
+    /// `buf.len()` must be a multiple of `pins.len()`, the samples of each pass being stored in
+    /// the order of `pins`. Without a trigger, the passes run back to back.
+    pub async fn read_sequence<D: RxDma<T>>(
+        &mut self,
+        pins: &mut [&mut dyn AdcPin<T>],
+        dma: impl Unborrow<Target = D>,
+        buf: &mut [u16],
+    ) {
+        assert!(!pins.is_empty() && buf.len() % pins.len() == 0);
+        unborrow!(dma);
+
+        let dr = unsafe {
+            self.set_sequence(pins.iter().map(|pin| pin.channel()));
+            let cont = self.trigger.is_none() && buf.len() > pins.len();
+            self.configure_regular(cont, true, false);
+            T::regs().dr().ptr() as *mut u16
+        };
+        let _on_drop = OnDrop::new(|| unsafe { Self::stop_regular() });
+
+        let request = dma.request();
+        let transfer = crate::dma::read(&mut dma, request, dr, buf);
+        unsafe { self.start_regular() };
+        transfer.await;
+    }
+
+    /// Converts the injected sequence of up to 4 `pins`, into the start of `buf`.
+    ///
+    /// The conversion starts right away, or on the next injected trigger event if any. The driver
+    /// must have been created with [`new_with_irq`](Self::new_with_irq).
+    pub async fn read_injected(&mut self, pins: &mut [&mut dyn AdcPin<T>], buf: &mut [u16]) {
+        assert!(!pins.is_empty() && pins.len() <= 4 && buf.len() >= pins.len());
+        let len = pins.len();
+
+        unsafe {
+            // With less than 4 conversions, the sequence is made of the last JSQx fields.
+            for pin in pins.iter() {
+                self.set_channel_sample_time(pin.channel());
+            }
+            T::regs().jsqr().write(|reg| {
+                reg.set_jl(len as u8 - 1);
+                for (i, pin) in pins.iter().enumerate() {
+                    reg.set_jsq(4 - len + i, pin.channel());
+                }
+            });
+
+            // The SR flags are cleared by writing 0, and left unchanged by writing 1.
+            T::regs().sr().write(|reg| {
+                reg.set_awd(true);
+                reg.set_eoc(true);
+                reg.set_jstrt(true);
+                reg.set_strt(true);
+                reg.set_ovr(true);
+            });
+            T::regs().cr1().modify(|reg| reg.set_jeocie(true));
+            match self.injected_trigger {
+                Some(trigger) => T::regs().cr2().modify(|reg| {
+                    reg.set_jextsel(trigger.source);
+                    reg.set_jexten(Jexten(trigger.edge as u8));
+                }),
+                None => T::regs().cr2().modify(|reg| reg.set_jswstart(true)),
+            }
+        }
+        let _on_drop = OnDrop::new(|| unsafe {
+            T::regs()
+                .cr2()
+                .modify(|reg| reg.set_jexten(Jexten::DISABLED));
+            T::regs().cr1().modify(|reg| reg.set_jeocie(false));
+        });
+
+        poll_fn(|cx| {
+            T::waker().register(cx.waker());
+
+            if unsafe { T::regs().sr().read().jeoc() } {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        for (i, sample) in buf[..len].iter_mut().enumerate() {
+            *sample = unsafe { T::regs().jdr(i).read().0 as u16 };
+        }
+    }
+
+    /// Starts converting `pin` continuously, storing the samples into `dma_buf` with a DMA
+    /// channel in circular mode.
+    ///
+    /// Without a trigger, the conversions run back to back, at a rate set by the ADC clock, the
+    /// sample time and the resolution. They stop when the returned [`ContinuousAdc`] is dropped.
+    pub fn start_continuous<'a, D: RxDma<T>>(
+        &'a mut self,
+        pin: &'a mut impl AdcPin<T>,
+        dma: impl Unborrow<Target = D> + 'a,
+        dma_buf: &'a mut [u16],
+    ) -> ContinuousAdc<'a, T, D> {
+        unborrow!(dma);
+        assert!(dma_buf.len() <= 0xFFFF);
+
+        let mut ring_buf = DmaRingBuffer::new(dma_buf);
+        unsafe {
+            self.set_sequence(core::iter::once(pin.channel()));
+            // The DMA requests go on after the end of the buffer.
+            self.configure_regular(self.trigger.is_none(), true, true);
+
+            let request = dma.request();
+            dma.start_circular_read(
+                request,
+                T::regs().dr().ptr() as *mut u16,
+                ring_buf.dma_buf(),
+            );
+
+            self.start_regular();
+        }
+
+        ContinuousAdc {
+            dma,
+            ring_buf,
+            #[cfg(feature = "low-power")]
+            _stop_veto: crate::low_power::StopVeto::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Selects `channels` as the regular sequence, converted in order.
+    unsafe fn set_sequence(&mut self, channels: impl ExactSizeIterator<Item = u8> + Clone) {
+        let len = channels.len();
+        assert!(len <= 16);
+
+        for ch in channels.clone() {
+            self.set_channel_sample_time(ch);
+        }
+
+        // SQR3, SQR2 and SQR1, holding 6, 6 and 4 channels
+        let mut channels = channels;
+        T::regs().sqr3().write(|reg| {
+            for (i, ch) in channels.by_ref().take(6).enumerate() {
+                reg.set_sq(i, ch);
+            }
+        });
+        T::regs().sqr2().write(|reg| {
+            for (i, ch) in channels.by_ref().take(6).enumerate() {
+                reg.set_sq(i, ch);
+            }
+        });
+        T::regs().sqr1().write(|reg| {
+            reg.set_l(len as u8 - 1);
+            for (i, ch) in channels.enumerate() {
+                reg.set_sq(i, ch);
+            }
+        });
+    }
+
+    /// Sets the resolution, and the continuous and DMA modes of the regular conversions.
+    unsafe fn configure_regular(&mut self, cont: bool, dma: bool, dds: bool) {
+        let res = self.resolution.res();
+        T::regs().cr1().modify(|reg| reg.set_res(res));
+        T::regs().cr2().modify(|reg| {
+            reg.set_cont(cont);
+            reg.set_dma(dma);
+            reg.set_dds(dds);
+        });
+        Self::clear_regular_flags();
+    }
+
+    unsafe fn start_regular(&mut self) {
+        match self.trigger {
+            Some(trigger) => T::regs().cr2().modify(|reg| {
+                reg.set_extsel(trigger.source);
+                reg.set_exten(Exten(trigger.edge as u8));
+            }),
+            None => T::regs().cr2().modify(|reg| reg.set_swstart(true)),
+        }
+    }
+
+    /// Stops the regular conversions after the current one.
+    unsafe fn stop_regular() {
+        T::regs().cr1().modify(|reg| reg.set_eocie(false));
+        T::regs().cr2().modify(|reg| {
+            reg.set_cont(false);
+            reg.set_dma(false);
+            reg.set_dds(false);
+            reg.set_exten(Exten::DISABLED);
+        });
+        Self::clear_regular_flags();
+    }
+
+    /// Clears EOC and OVR. The SR flags are cleared by writing 0, and left unchanged by writing 1.
+    unsafe fn clear_regular_flags() {
+        T::regs().sr().write(|reg| {
+            reg.set_awd(true);
+            reg.set_jeoc(true);
+            reg.set_jstrt(true);
+            reg.set_strt(true);
+        });
+    }
+
+    unsafe fn set_channel_sample_time(&mut self, ch: u8) {
+        let smp = Smp(self.sample_time as u8);
+        if ch <= 9 {
+            T::regs()
+                .smpr2()
+                .modify(|reg| reg.set_smp(ch as usize, smp));
+        } else {
+            T::regs()
+                .smpr1()
+                .modify(|reg| reg.set_smp(ch as usize - 10, smp));
+        }
+    }
+}
+
+/// Masks the pending interrupts of `T`, and wakes its task.
+pub(crate) fn on_interrupt<T: Instance>() {
+    unsafe {
+        let sr = T::regs().sr().read();
+        let cr1 = T::regs().cr1().read();
+
+        if (sr.eoc() && cr1.eocie()) || (sr.jeoc() && cr1.jeocie()) {
+            T::regs().cr1().modify(|reg| {
+                reg.set_eocie(reg.eocie() && !sr.eoc());
+                reg.set_jeocie(reg.jeocie() && !sr.jeoc());
+            });
+            T::waker().wake();
+        }
+    }
+}
+
+/// Continuous conversions started by [`Adc::start_continuous`].
+pub struct ContinuousAdc<'a, T: Instance, D: RxDma<T>> {
+    dma: D,
+    ring_buf: DmaRingBuffer<'a, u16>,
+    /// The DMA stops in STOP mode.
+    #[cfg(feature = "low-power")]
+    _stop_veto: crate::low_power::StopVeto,
+    phantom: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: Instance, D: RxDma<T>> ContinuousAdc<'a, T, D> {
+    /// Reads the samples converted since the last read into `buf`, waiting until at least one
+    /// is available.
+    ///
+    /// Returns an error if the samples weren't read fast enough and were overwritten. The unread
+    /// samples are then dropped.
+    pub async fn read(&mut self, buf: &mut [u16]) -> Result<usize, OverrunError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        poll_fn(|cx| {
+            self.dma.set_waker(cx.waker());
+
+            match self.ring_buf.read(&mut self.dma, buf) {
+                Ok(0) => Poll::Pending,
+                Ok(n) => Poll::Ready(Ok(n)),
+                Err(e) => {
+                    self.ring_buf.clear(&mut self.dma);
+                    Poll::Ready(Err(e))
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl<'a, T: Instance, D: RxDma<T>> Drop for ContinuousAdc<'a, T, D> {
+    fn drop(&mut self) {
+        unsafe {
+            T::regs().cr2().modify(|reg| {
+                reg.set_cont(false);
+                reg.set_dma(false);
+                reg.set_dds(false);
+                reg.set_exten(Exten::DISABLED);
+            });
+        }
+
+        self.dma.request_stop();
+        while self.dma.is_running() {}
+
+        unsafe { Adc::<T>::clear_regular_flags() };
+    }
+}
@@ -1,11 +1,30 @@
-use crate::adc::{AdcPin, Instance};
+use crate::adc::{AdcPin, Instance, RxDma, Trigger};
+use crate::dma::{DmaRingBuffer, OverrunError};
+use crate::pac::adc::vals::Exten;
+#[cfg(not(rcc_g0))]
+use crate::pac::adc::vals::Jexten;
 use core::marker::PhantomData;
+use core::task::Poll;
+use embassy::interrupt::InterruptExt;
 use embassy::util::Unborrow;
+use embassy_hal_common::drop::OnDrop;
 use embassy_hal_common::unborrow;
 use embedded_hal::blocking::delay::DelayUs;
+use futures::future::poll_fn;
 
 pub const VDDA_CALIB_MV: u32 = 3000;
 
+/// How the samples of the regular conversions are read.
+#[derive(Clone, Copy, PartialEq)]
+enum RegularDma {
+    /// Read by the CPU.
+    None,
+    /// One shot DMA mode, the DMA requests stopping at the end of the transfer.
+    OneShot,
+    /// Circular DMA mode, a sample not read in time being overwritten.
+    Circular,
+}
+
 /// Sadly we cannot use `RccPeripheral::enable` since devices are quite inconsistent ADC clock
 /// configuration.
 unsafe fn enable() {
@@ -200,10 +219,18 @@ pub struct Adc<'d, T: Instance> {
     sample_time: SampleTime,
     calibrated_vdda: u32,
     resolution: Resolution,
+    trigger: Option<Trigger>,
+    #[cfg(not(rcc_g0))]
+    injected_trigger: Option<Trigger>,
     phantom: PhantomData<&'d mut T>,
 }
 
 impl<'d, T: Instance> Adc<'d, T> {
+    /// Creates the driver, and calibrates the ADC.
+    ///
+    /// The conversions waiting for the interrupt, [`read_async`](Self::read_async) and
+    /// [`read_injected`](Self::read_injected), need the driver created with
+    /// [`new_with_irq`](Self::new_with_irq).
     pub fn new(_peri: impl Unborrow<Target = T> + 'd, delay: &mut impl DelayUs<u32>) -> Self {
         unborrow!(_peri);
         unsafe {
@@ -238,10 +265,32 @@ impl<'d, T: Instance> Adc<'d, T> {
             sample_time: Default::default(),
             resolution: Resolution::default(),
             calibrated_vdda: VDDA_CALIB_MV,
+            trigger: None,
+            #[cfg(not(rcc_g0))]
+            injected_trigger: None,
             phantom: PhantomData,
         }
     }
 
+    /// Same as [`new`](Self::new), with the interrupt used by the async conversions.
+    ///
+    /// The ADCs of a chip may share their interrupt, which can then be passed by reference to
+    /// each of them.
+    pub fn new_with_irq(
+        peri: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        delay: &mut impl DelayUs<u32>,
+    ) -> Self {
+        unborrow!(irq);
+        let this = Self::new(peri, delay);
+
+        irq.set_handler(super::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        this
+    }
+
     pub fn enable_vref(&self, delay: &mut impl DelayUs<u32>) -> Vref {
         unsafe {
             T::common_regs().ccr().modify(|reg| {
@@ -308,6 +357,22 @@ impl<'d, T: Instance> Adc<'d, T> {
         self.resolution = resolution;
     }
 
+    /// Sets the event starting the regular conversions of [`read`](Self::read),
+    /// [`read_async`](Self::read_async), [`read_sequence`](Self::read_sequence) and
+    /// [`start_continuous`](Self::start_continuous).
+    ///
+    /// Each event converts the whole sequence once. With `None`, the conversions are started by
+    /// software.
+    pub fn set_trigger(&mut self, trigger: Option<Trigger>) {
+        self.trigger = trigger;
+    }
+
+    /// Sets the event starting the injected conversions of [`read_injected`](Self::read_injected).
+    #[cfg(not(rcc_g0))]
+    pub fn set_injected_trigger(&mut self, trigger: Option<Trigger>) {
+        self.injected_trigger = trigger;
+    }
+
     /// Convert a measurement to millivolts
     pub fn to_millivolts(&self, sample: u16) -> u16 {
         ((u32::from(sample) * self.calibrated_vdda) / self.resolution.to_max_count()) as u16
@@ -343,45 +408,212 @@ impl<'d, T: Instance> Adc<'d, T> {
         }
     }
 
-    pub fn read(&mut self, pin: &mut impl AdcPin<T>) -> u16 {
+    /// Performs a single conversion, waiting for its end with the interrupt.
+    async fn convert_async(&mut self) -> u16 {
         unsafe {
-            // Make sure bits are off
-            while T::regs().cr().read().addis() {
-                // spin
+            T::regs().isr().write(|reg| {
+                reg.set_eoc(true);
+                reg.set_eos(true);
+            });
+            T::regs().ier().modify(|reg| reg.set_eosie(true));
+            T::regs().cr().modify(|reg| reg.set_adstart(true));
+        }
+
+        poll_fn(|cx| {
+            T::waker().register(cx.waker());
+
+            if unsafe { T::regs().isr().read().eos() } {
+                Poll::Ready(unsafe { T::regs().dr().read().0 as u16 })
+            } else {
+                Poll::Pending
             }
+        })
+        .await
+    }
 
-            // Enable ADC
-            T::regs().isr().modify(|reg| {
-                reg.set_adrdy(true);
-            });
-            T::regs().cr().modify(|reg| {
-                reg.set_aden(true);
-            });
+    /// Enables the ADC, and sets the resolution.
+    unsafe fn enable(&mut self) {
+        // Make sure bits are off
+        while T::regs().cr().read().addis() {
+            // spin
+        }
+
+        // Enable ADC
+        T::regs().isr().modify(|reg| {
+            reg.set_adrdy(true);
+        });
+        T::regs().cr().modify(|reg| {
+            reg.set_aden(true);
+        });
+
+        while !T::regs().isr().read().adrdy() {
+            // spin
+        }
+
+        // Configure ADC
+        #[cfg(not(rcc_g0))]
+        T::regs()
+            .cfgr()
+            .modify(|reg| reg.set_res(self.resolution.res()));
+        #[cfg(rcc_g0)]
+        T::regs()
+            .cfgr1()
+            .modify(|reg| reg.set_res(self.resolution.res()));
+    }
 
-            while !T::regs().isr().read().adrdy() {
+    /// Selects `channels` as the regular sequence, converted in order.
+    #[cfg(not(rcc_g0))]
+    unsafe fn set_sequence(&mut self, channels: impl ExactSizeIterator<Item = u8> + Clone) {
+        let len = channels.len();
+        assert!(len <= 16);
+
+        for ch in channels.clone() {
+            Self::set_channel_sample_time(ch, self.sample_time);
+        }
+
+        // SQR1 to SQR4, holding 4, 5, 5 and 2 channels, SQR1 starting with the sequence length
+        let mut channels = channels;
+        T::regs().sqr1().write(|reg| {
+            reg.set_l(len as u8 - 1);
+            for (i, ch) in channels.by_ref().take(4).enumerate() {
+                reg.set_sq(i, ch);
+            }
+        });
+        T::regs().sqr2().write(|reg| {
+            for (i, ch) in channels.by_ref().take(5).enumerate() {
+                reg.set_sq(i, ch);
+            }
+        });
+        T::regs().sqr3().write(|reg| {
+            for (i, ch) in channels.by_ref().take(5).enumerate() {
+                reg.set_sq(i, ch);
+            }
+        });
+        T::regs().sqr4().write(|reg| {
+            for (i, ch) in channels.enumerate() {
+                reg.set_sq(i, ch);
+            }
+        });
+    }
+
+    /// Selects `channels` as the regular sequence, converted in order.
+    #[cfg(rcc_g0)]
+    unsafe fn set_sequence(&mut self, channels: impl ExactSizeIterator<Item = u8> + Clone) {
+        assert!(channels.len() <= 8);
+
+        for ch in channels.clone() {
+            Self::set_channel_sample_time(ch, self.sample_time);
+        }
+
+        // In the fully configurable mode (CHSELRMOD), CHSELR holds up to 8 channels, ended by
+        // 0b1111.
+        T::regs().isr().write(|reg| reg.set_ccrdy(true));
+        T::regs().chselr_sq().write(|reg| {
+            for i in 0..8 {
+                reg.set_sq(i, 0b1111);
+            }
+            for (i, ch) in channels.enumerate() {
+                reg.set_sq(i, ch);
+            }
+        });
+        while !T::regs().isr().read().ccrdy() {
+            // spin
+        }
+    }
+
+    /// Sets the DMA and continuous modes, and the trigger of the regular conversions.
+    unsafe fn configure_regular(&mut self, dma: RegularDma, cont: bool) {
+        let trigger = self.trigger;
+
+        #[cfg(not(rcc_g0))]
+        T::regs().cfgr().modify(|reg| {
+            reg.set_dmaen(dma != RegularDma::None);
+            reg.set_dmacfg(dma == RegularDma::Circular);
+            reg.set_ovrmod(dma == RegularDma::Circular);
+            reg.set_cont(cont);
+            match trigger {
+                Some(trigger) => {
+                    reg.set_extsel(trigger.source);
+                    reg.set_exten(Exten(trigger.edge as u8));
+                }
+                None => reg.set_exten(Exten::DISABLED),
+            }
+        });
+        #[cfg(rcc_g0)]
+        T::regs().cfgr1().modify(|reg| {
+            reg.set_dmaen(dma != RegularDma::None);
+            reg.set_dmacfg(dma == RegularDma::Circular);
+            reg.set_ovrmod(dma == RegularDma::Circular);
+            reg.set_cont(cont);
+            match trigger {
+                Some(trigger) => {
+                    reg.set_extsel(trigger.source);
+                    reg.set_exten(Exten(trigger.edge as u8));
+                }
+                None => reg.set_exten(Exten::DISABLED),
+            }
+        });
+    }
+
+    /// Stops the regular conversions, and disables the ADC.
+    unsafe fn stop_regular() {
+        if T::regs().cr().read().adstart() {
+            T::regs().cr().modify(|reg| reg.set_adstp(true));
+            while T::regs().cr().read().adstart() {
                 // spin
             }
+        }
 
-            // Configure ADC
-            #[cfg(not(rcc_g0))]
-            T::regs()
-                .cfgr()
-                .modify(|reg| reg.set_res(self.resolution.res()));
-            #[cfg(rcc_g0)]
-            T::regs()
-                .cfgr1()
-                .modify(|reg| reg.set_res(self.resolution.res()));
+        T::regs().ier().modify(|reg| reg.set_eosie(false));
+        Self::reset_regular();
 
-            // Configure channel
-            Self::set_channel_sample_time(pin.channel(), self.sample_time);
+        T::regs().cr().modify(|reg| reg.set_addis(true));
+    }
 
-            // Select channel
-            #[cfg(not(rcc_g0))]
-            T::regs().sqr1().write(|reg| reg.set_sq(0, pin.channel()));
-            #[cfg(rcc_g0)]
-            T::regs()
-                .chselr()
-                .write(|reg| reg.set_chsel(pin.channel() as u32));
+    /// Turns off the DMA and continuous modes, and the trigger of the regular conversions.
+    unsafe fn reset_regular() {
+        #[cfg(not(rcc_g0))]
+        T::regs().cfgr().modify(|reg| {
+            reg.set_dmaen(false);
+            reg.set_dmacfg(false);
+            reg.set_ovrmod(false);
+            reg.set_cont(false);
+            reg.set_exten(Exten::DISABLED);
+        });
+        #[cfg(rcc_g0)]
+        T::regs().cfgr1().modify(|reg| {
+            reg.set_dmaen(false);
+            reg.set_dmacfg(false);
+            reg.set_ovrmod(false);
+            reg.set_cont(false);
+            reg.set_exten(Exten::DISABLED);
+        });
+    }
+
+    /// Converts `pin`, waiting for the end of the conversion with the interrupt.
+    ///
+    /// The driver must have been created with [`new_with_irq`](Self::new_with_irq).
+    pub async fn read_async(&mut self, pin: &mut impl AdcPin<T>) -> u16 {
+        unsafe {
+            self.enable();
+            self.set_sequence(core::iter::once(pin.channel()));
+            self.configure_regular(RegularDma::None, false);
+        }
+        let _on_drop = OnDrop::new(|| unsafe { Self::stop_regular() });
+
+        // See the erratum in `read`.
+        #[cfg(any(rcc_l4, rcc_g4))]
+        let _ = self.convert_async().await;
+
+        self.convert_async().await
+    }
+
+    /// Converts `pin`, polling for the end of the conversion.
+    pub fn read(&mut self, pin: &mut impl AdcPin<T>) -> u16 {
+        unsafe {
+            self.enable();
+            self.set_sequence(core::iter::once(pin.channel()));
+            self.configure_regular(RegularDma::None, false);
 
             // Some models are affected by an erratum:
             // If we perform conversions slower than 1 kHz, the first read ADC value can be
@@ -394,12 +626,148 @@ impl<'d, T: Instance> Adc<'d, T> {
 
             let val = self.convert();
 
-            T::regs().cr().modify(|reg| reg.set_addis(true));
+            Self::stop_regular();
 
             val
         }
     }
 
+    /// Converts the sequence of `pins` until `buf` is full, with a DMA channel.
+    ///
+    /// The sequence holds up to 16 pins, or 8 on STM32G0. `buf.len()` must be a multiple of
+    /// `pins.len()`, the samples of each pass being stored in the order of `pins`. Without a
+    /// trigger, the passes run back to back.
+    pub async fn read_sequence<D: RxDma<T>>(
+        &mut self,
+        pins: &mut [&mut dyn AdcPin<T>],
+        dma: impl Unborrow<Target = D>,
+        buf: &mut [u16],
+    ) {
+        assert!(!pins.is_empty() && buf.len() % pins.len() == 0);
+        unborrow!(dma);
+
+        let dr = unsafe {
+            self.enable();
+            self.set_sequence(pins.iter().map(|pin| pin.channel()));
+            let cont = self.trigger.is_none() && buf.len() > pins.len();
+            self.configure_regular(RegularDma::OneShot, cont);
+            T::regs().dr().ptr() as *mut u16
+        };
+        let _on_drop = OnDrop::new(|| unsafe { Self::stop_regular() });
+
+        let request = dma.request();
+        let transfer = crate::dma::read(&mut dma, request, dr, buf);
+        unsafe { T::regs().cr().modify(|reg| reg.set_adstart(true)) };
+        transfer.await;
+    }
+
+    /// Converts the injected sequence of up to 4 `pins`, into the start of `buf`.
+    ///
+    /// The conversion starts right away, or on the next injected trigger event if any. The driver
+    /// must have been created with [`new_with_irq`](Self::new_with_irq).
+    #[cfg(not(rcc_g0))]
+    pub async fn read_injected(&mut self, pins: &mut [&mut dyn AdcPin<T>], buf: &mut [u16]) {
+        assert!(!pins.is_empty() && pins.len() <= 4 && buf.len() >= pins.len());
+        let len = pins.len();
+
+        unsafe {
+            self.enable();
+
+            for pin in pins.iter() {
+                Self::set_channel_sample_time(pin.channel(), self.sample_time);
+            }
+            let trigger = self.injected_trigger;
+            T::regs().jsqr().write(|reg| {
+                reg.set_jl(len as u8 - 1);
+                if let Some(trigger) = trigger {
+                    reg.set_jextsel(trigger.source);
+                    reg.set_jexten(Jexten(trigger.edge as u8));
+                }
+                for (i, pin) in pins.iter().enumerate() {
+                    reg.set_jsq(i, pin.channel());
+                }
+            });
+
+            T::regs().isr().write(|reg| {
+                reg.set_jeoc(true);
+                reg.set_jeos(true);
+            });
+            T::regs().ier().modify(|reg| reg.set_jeosie(true));
+            T::regs().cr().modify(|reg| reg.set_jadstart(true));
+        }
+        let _on_drop = OnDrop::new(|| unsafe {
+            // With a trigger, the ADC waits for the next event.
+            if T::regs().cr().read().jadstart() {
+                T::regs().cr().modify(|reg| reg.set_jadstp(true));
+                while T::regs().cr().read().jadstart() {
+                    // spin
+                }
+            }
+            T::regs().ier().modify(|reg| reg.set_jeosie(false));
+            T::regs().cr().modify(|reg| reg.set_addis(true));
+        });
+
+        poll_fn(|cx| {
+            T::waker().register(cx.waker());
+
+            if unsafe { T::regs().isr().read().jeos() } {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        for (i, sample) in buf[..len].iter_mut().enumerate() {
+            *sample = unsafe { T::regs().jdr(i).read().0 as u16 };
+        }
+    }
+
+    /// Starts converting `pin` continuously, storing the samples into `dma_buf` with a DMA
+    /// channel in circular mode.
+    ///
+    /// Without a trigger, the conversions run back to back, at a rate set by the ADC clock, the
+    /// sample time and the resolution. They stop when the returned [`ContinuousAdc`] is dropped.
+    pub fn start_continuous<'a, D: RxDma<T>>(
+        &'a mut self,
+        pin: &'a mut impl AdcPin<T>,
+        dma: impl Unborrow<Target = D> + 'a,
+        dma_buf: &'a mut [u16],
+    ) -> ContinuousAdc<'a, T, D> {
+        unborrow!(dma);
+        assert!(dma_buf.len() <= 0xFFFF);
+
+        let mut ring_buf = DmaRingBuffer::new(dma_buf);
+        unsafe {
+            self.enable();
+            self.set_sequence(core::iter::once(pin.channel()));
+
+            // A sample not read in time by the DMA is overwritten, instead of stopping the
+            // conversions. The ring buffer detects the DMA falling behind the reader.
+            let cont = self.trigger.is_none();
+            self.configure_regular(RegularDma::Circular, cont);
+
+            let request = dma.request();
+            dma.start_circular_read(
+                request,
+                T::regs().dr().ptr() as *mut u16,
+                ring_buf.dma_buf(),
+            );
+
+            T::regs().cr().modify(|reg| {
+                reg.set_adstart(true);
+            });
+        }
+
+        ContinuousAdc {
+            dma,
+            ring_buf,
+            #[cfg(feature = "low-power")]
+            _stop_veto: crate::low_power::StopVeto::new(),
+            phantom: PhantomData,
+        }
+    }
+
     #[cfg(rcc_g0)]
     unsafe fn set_channel_sample_time(_ch: u8, sample_time: SampleTime) {
         T::regs()
@@ -420,3 +788,85 @@ impl<'d, T: Instance> Adc<'d, T> {
         }
     }
 }
+
+/// Masks the pending interrupts of `T`, and wakes its task.
+pub(crate) fn on_interrupt<T: Instance>() {
+    unsafe {
+        let isr = T::regs().isr().read();
+        let ier = T::regs().ier().read();
+
+        let eos = isr.eos() && ier.eosie();
+        #[cfg(not(rcc_g0))]
+        let jeos = isr.jeos() && ier.jeosie();
+        #[cfg(rcc_g0)]
+        let jeos = false;
+
+        if eos || jeos {
+            T::regs().ier().modify(|reg| {
+                if eos {
+                    reg.set_eosie(false);
+                }
+                #[cfg(not(rcc_g0))]
+                if jeos {
+                    reg.set_jeosie(false);
+                }
+            });
+            T::waker().wake();
+        }
+    }
+}
+
+/// Continuous conversions started by [`Adc::start_continuous`].
+pub struct ContinuousAdc<'a, T: Instance, D: RxDma<T>> {
+    dma: D,
+    ring_buf: DmaRingBuffer<'a, u16>,
+    /// The DMA stops in STOP mode.
+    #[cfg(feature = "low-power")]
+    _stop_veto: crate::low_power::StopVeto,
+    phantom: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: Instance, D: RxDma<T>> ContinuousAdc<'a, T, D> {
+    /// Reads the samples converted since the last read into `buf`, waiting until at least one
+    /// is available.
+    ///
+    /// Returns an error if the samples weren't read fast enough and were overwritten. The unread
+    /// samples are then dropped.
+    pub async fn read(&mut self, buf: &mut [u16]) -> Result<usize, OverrunError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        poll_fn(|cx| {
+            self.dma.set_waker(cx.waker());
+
+            match self.ring_buf.read(&mut self.dma, buf) {
+                Ok(0) => Poll::Pending,
+                Ok(n) => Poll::Ready(Ok(n)),
+                Err(e) => {
+                    self.ring_buf.clear(&mut self.dma);
+                    Poll::Ready(Err(e))
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl<'a, T: Instance, D: RxDma<T>> Drop for ContinuousAdc<'a, T, D> {
+    fn drop(&mut self) {
+        unsafe {
+            T::regs().cr().modify(|reg| reg.set_adstp(true));
+            while T::regs().cr().read().adstart() {
+                // spin
+            }
+
+            Adc::<T>::reset_regular();
+        }
+
+        self.dma.request_stop();
+        while self.dma.is_running() {}
+
+        unsafe { T::regs().cr().modify(|reg| reg.set_addis(true)) };
+    }
+}
@@ -1,17 +1,56 @@
+use core::cell::RefCell;
 use core::marker::PhantomData;
 use core::ops::{Deref, DerefMut};
+use core::task::Poll;
 
+use embassy::blocking_mutex::{CriticalSectionMutex, Mutex as _};
+use embassy::interrupt::{Interrupt, InterruptExt};
+#[cfg(feature = "_time-driver")]
+use embassy::time::Instant;
 use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
 use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
 
 use crate::gpio::{
     sealed::AFType::{OutputOpenDrain, OutputPushPull},
     Pin,
 };
+use crate::pac::can::vals::{Ide, Rtr};
 use crate::{peripherals, rcc::RccPeripheral};
 
+use super::{BusState, CanFrame, Envelope, Error, Filter, RxQueue};
+
 pub use bxcan::*;
 
+/// Number of received frames buffered in software, in addition to the two 3-frame hardware FIFOs.
+const RX_QUEUE_LEN: usize = 16;
+
+pub struct State {
+    tx_waker: AtomicWaker,
+    rx_waker: AtomicWaker,
+    err_waker: AtomicWaker,
+    rx_queue: CriticalSectionMutex<RefCell<RxQueue<Envelope<Frame>, RX_QUEUE_LEN>>>,
+}
+
+impl State {
+    pub const fn new() -> Self {
+        const NONE: Option<Envelope<Frame>> = None;
+        Self {
+            tx_waker: AtomicWaker::new(),
+            rx_waker: AtomicWaker::new(),
+            err_waker: AtomicWaker::new(),
+            rx_queue: CriticalSectionMutex::new(RefCell::new(RxQueue::new([NONE; RX_QUEUE_LEN]))),
+        }
+    }
+}
+
+/// Async CAN driver.
+///
+/// Received frames are moved from the hardware FIFOs to a software queue by the RX interrupts,
+/// so don't use `receive` from the underlying [`bxcan::Can`], use [`Can::read`] instead. The
+/// other `bxcan::Can` methods, for example to configure the bit timing, are available through
+/// `Deref`.
 pub struct Can<'d, T: Instance + bxcan::Instance> {
     phantom: PhantomData<&'d mut T>,
     can: bxcan::Can<T>,
@@ -22,8 +61,12 @@ impl<'d, T: Instance + bxcan::Instance> Can<'d, T> {
         peri: impl Unborrow<Target = T> + 'd,
         rx: impl Unborrow<Target = impl RxPin<T>> + 'd,
         tx: impl Unborrow<Target = impl TxPin<T>> + 'd,
+        tx_irq: impl Unborrow<Target = impl TxInterrupt<T>> + 'd,
+        rx0_irq: impl Unborrow<Target = impl Rx0Interrupt<T>> + 'd,
+        rx1_irq: impl Unborrow<Target = impl Rx1Interrupt<T>> + 'd,
+        sce_irq: impl Unborrow<Target = impl SceInterrupt<T>> + 'd,
     ) -> Self {
-        unborrow!(peri, rx, tx);
+        unborrow!(peri, rx, tx, tx_irq, rx0_irq, rx1_irq, sce_irq);
 
         unsafe {
             rx.set_as_af(rx.af_num(), OutputOpenDrain);
@@ -33,17 +76,230 @@ impl<'d, T: Instance + bxcan::Instance> Can<'d, T> {
         T::enable();
         T::reset();
 
+        T::state().rx_queue.lock(|q| q.borrow_mut().clear());
+
+        tx_irq.set_handler(Self::on_tx);
+        rx0_irq.set_handler(Self::on_rx0);
+        rx1_irq.set_handler(Self::on_rx1);
+        sce_irq.set_handler(Self::on_sce);
+        tx_irq.unpend();
+        tx_irq.enable();
+        rx0_irq.unpend();
+        rx0_irq.enable();
+        rx1_irq.unpend();
+        rx1_irq.enable();
+        sce_irq.unpend();
+        sce_irq.enable();
+
+        let can = bxcan::Can::builder(peri).enable();
+
+        critical_section::with(|_| unsafe {
+            T::regs().ier().modify(|w| {
+                w.set_fmpie(0, true);
+                w.set_fmpie(1, true);
+                w.set_epvie(true);
+                w.set_bofie(true);
+                w.set_errie(true);
+            });
+        });
+
         Self {
             phantom: PhantomData,
-            can: bxcan::Can::builder(peri).enable(),
+            can,
         }
     }
+
+    unsafe fn on_tx(_: *mut ()) {
+        // Clear the request completed flags, the interrupt would fire again as soon as `write`
+        // enables it otherwise. Then let the waiting task retry.
+        T::regs().tsr().write(|w| {
+            for mailbox in 0..3 {
+                w.set_rqcp(mailbox, true);
+            }
+        });
+        T::regs().ier().modify(|w| w.set_tmeie(false));
+        T::state().tx_waker.wake();
+    }
+
+    unsafe fn on_rx0(_: *mut ()) {
+        Self::receive_fifo(0);
+    }
+
+    unsafe fn on_rx1(_: *mut ()) {
+        Self::receive_fifo(1);
+    }
+
+    unsafe fn receive_fifo(fifo: usize) {
+        let regs = T::regs();
+        let state = T::state();
+
+        loop {
+            let rfr = regs.rfr(fifo).read();
+            if rfr.fovr() {
+                state.rx_queue.lock(|q| q.borrow_mut().set_overrun());
+            }
+            if rfr.fmp() == 0 {
+                break;
+            }
+
+            let mailbox = regs.rx(fifo);
+            let rir = mailbox.rir().read();
+            let dlc = mailbox.rdtr().read().dlc().min(8) as usize;
+            let rdlr = mailbox.rdlr().read();
+            let rdhr = mailbox.rdhr().read();
+            let mut data = [0; 8];
+            for i in 0..4 {
+                data[i] = rdlr.data(i);
+                data[4 + i] = rdhr.data(i);
+            }
+
+            // Release the mailbox, and clear the full/overrun flags.
+            regs.rfr(fifo).write(|w| {
+                w.set_rfom(true);
+                w.set_full(true);
+                w.set_fovr(true);
+            });
+
+            let id: Id = if rir.ide() == Ide::EXTENDED {
+                ExtendedId::new_unchecked(rir.exid()).into()
+            } else {
+                StandardId::new_unchecked(rir.stid()).into()
+            };
+            let frame = if rir.rtr() == Rtr::REMOTE {
+                Frame::new_remote(id, dlc as u8)
+            } else {
+                Frame::new_data(id, Data::new(&data[..dlc]).unwrap())
+            };
+
+            let envelope = Envelope {
+                #[cfg(feature = "_time-driver")]
+                ts: Instant::now(),
+                frame,
+            };
+            state.rx_queue.lock(|q| q.borrow_mut().push(envelope));
+        }
+
+        state.rx_waker.wake();
+    }
+
+    unsafe fn on_sce(_: *mut ()) {
+        T::regs().msr().write(|w| w.set_erri(true));
+        T::state().err_waker.wake();
+    }
+
+    /// Queues a frame for transmission, waiting for a free transmit mailbox if needed.
+    ///
+    /// If a lower priority frame was pending in a mailbox, it's replaced by `frame` and
+    /// returned.
+    pub async fn write(&mut self, frame: &Frame) -> Result<Option<Frame>, Error> {
+        poll_fn(|cx| {
+            T::state().tx_waker.register(cx.waker());
+
+            if self.bus_state() == BusState::BusOff {
+                return Poll::Ready(Err(Error::BusOff));
+            }
+
+            match self.can.transmit(frame) {
+                Ok(dropped) => Poll::Ready(Ok(dropped)),
+                Err(nb::Error::WouldBlock) => {
+                    critical_section::with(|_| unsafe {
+                        T::regs().ier().modify(|w| w.set_tmeie(true));
+                    });
+                    Poll::Pending
+                }
+                Err(nb::Error::Other(e)) => match e {},
+            }
+        })
+        .await
+    }
+
+    /// Waits until a frame is received.
+    ///
+    /// Returns [`Error::Overrun`] once if frames were lost since the previous read.
+    pub async fn read(&mut self) -> Result<Envelope<Frame>, Error> {
+        poll_fn(|cx| {
+            T::state().rx_waker.register(cx.waker());
+            match self.try_read() {
+                Some(res) => Poll::Ready(res),
+                None => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Returns a received frame, or `None` if no frame is available.
+    pub fn try_read(&mut self) -> Option<Result<Envelope<Frame>, Error>> {
+        T::state().rx_queue.lock(|q| q.borrow_mut().pop())
+    }
+
+    /// Returns the current fault confinement state.
+    pub fn bus_state(&self) -> BusState {
+        let esr = unsafe { T::regs().esr().read() };
+        if esr.boff() {
+            BusState::BusOff
+        } else if esr.epvf() {
+            BusState::ErrorPassive
+        } else if esr.ewgf() {
+            BusState::ErrorWarning
+        } else {
+            BusState::ErrorActive
+        }
+    }
+
+    /// Returns the transmit error counter.
+    pub fn tx_error_count(&self) -> u8 {
+        unsafe { T::regs().esr().read().tec() }
+    }
+
+    /// Returns the receive error counter.
+    pub fn rx_error_count(&self) -> u8 {
+        unsafe { T::regs().esr().read().rec() }
+    }
+
+    /// Waits until the controller enters the error passive or bus-off state, and returns it.
+    ///
+    /// Returns immediately if the controller is already in one of these states.
+    pub async fn wait_bus_error(&mut self) -> BusState {
+        poll_fn(|cx| {
+            T::state().err_waker.register(cx.waker());
+            match self.bus_state() {
+                state @ (BusState::ErrorPassive | BusState::BusOff) => Poll::Ready(state),
+                _ => Poll::Pending,
+            }
+        })
+        .await
+    }
+}
+
+impl<'d, T: Instance + bxcan::FilterOwner> Can<'d, T> {
+    /// Configures filter bank 0 to accept all frames, and disables the other banks.
+    pub fn accept_all(&mut self) {
+        self.can
+            .modify_filters()
+            .clear()
+            .enable_bank(0, filter::Mask32::accept_all());
+    }
+
+    /// Configures filter `bank`.
+    pub fn set_filter(&mut self, bank: u8, filter: Filter) {
+        let config = match filter {
+            Filter::AcceptAll => filter::Mask32::accept_all(),
+            Filter::Standard { id, mask } => filter::Mask32::frames_with_std_id(id, mask),
+            Filter::Extended { id, mask } => filter::Mask32::frames_with_ext_id(id, mask),
+        };
+        self.can.modify_filters().enable_bank(bank, config);
+    }
+
+    /// Disables filter `bank`.
+    pub fn disable_filter(&mut self, bank: u8) {
+        self.can.modify_filters().disable_bank(bank);
+    }
 }
 
 impl<'d, T: Instance + bxcan::Instance> Drop for Can<'d, T> {
     fn drop(&mut self) {
         // Cannot call `free()` because it moves the instance.
-        // Manually reset the peripheral.
+        // Manually reset the peripheral, this also disables all its interrupts.
         unsafe {
             T::regs().mcr().write(|w| w.set_reset(true));
         }
@@ -65,11 +321,44 @@ impl<'d, T: Instance + bxcan::Instance> DerefMut for Can<'d, T> {
     }
 }
 
+impl CanFrame for Frame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        Some(Frame::new_data(id, Data::new(data)?))
+    }
+
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        if dlc > 8 {
+            return None;
+        }
+        Some(Frame::new_remote(id, dlc as u8))
+    }
+
+    fn id(&self) -> Id {
+        Frame::id(self)
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        Frame::is_remote_frame(self)
+    }
+
+    fn dlc(&self) -> usize {
+        Frame::dlc(self) as usize
+    }
+
+    fn data(&self) -> &[u8] {
+        match Frame::data(self) {
+            Some(data) => data,
+            None => &[],
+        }
+    }
+}
+
 pub(crate) mod sealed {
     use super::*;
 
     pub trait Instance {
         fn regs() -> &'static crate::pac::can::Can;
+        fn state() -> &'static State;
     }
 
     pub trait RxPin<T: Instance>: Pin {
@@ -79,11 +368,20 @@ pub(crate) mod sealed {
     pub trait TxPin<T: Instance>: Pin {
         fn af_num(&self) -> u8;
     }
+
+    pub trait TxInterrupt<T: Instance> {}
+    pub trait Rx0Interrupt<T: Instance> {}
+    pub trait Rx1Interrupt<T: Instance> {}
+    pub trait SceInterrupt<T: Instance> {}
 }
 
 pub trait Instance: sealed::Instance + RccPeripheral {}
 pub trait RxPin<T: Instance>: sealed::RxPin<T> {}
 pub trait TxPin<T: Instance>: sealed::TxPin<T> {}
+pub trait TxInterrupt<T: Instance>: sealed::TxInterrupt<T> + Interrupt {}
+pub trait Rx0Interrupt<T: Instance>: sealed::Rx0Interrupt<T> + Interrupt {}
+pub trait Rx1Interrupt<T: Instance>: sealed::Rx1Interrupt<T> + Interrupt {}
+pub trait SceInterrupt<T: Instance>: sealed::SceInterrupt<T> + Interrupt {}
 
 crate::pac::peripherals!(
     (can, $inst:ident) => {
@@ -91,6 +389,11 @@ crate::pac::peripherals!(
             fn regs() -> &'static crate::pac::can::Can {
                 &crate::pac::$inst
             }
+
+            fn state() -> &'static State {
+                static STATE: State = State::new();
+                &STATE
+            }
         }
 
         impl Instance for peripherals::$inst {}
@@ -125,6 +428,28 @@ crate::pac::peripherals!(
     };
 );
 
+macro_rules! impl_irq {
+    ($inst:ident, $irq:ident, $signal:ident) => {
+        impl sealed::$signal<peripherals::$inst> for crate::interrupt::$irq {}
+        impl $signal<peripherals::$inst> for crate::interrupt::$irq {}
+    };
+}
+
+crate::pac::interrupts!(
+    ($inst:ident, can, $block:ident, TX, $irq:ident) => {
+        impl_irq!($inst, $irq, TxInterrupt);
+    };
+    ($inst:ident, can, $block:ident, RX0, $irq:ident) => {
+        impl_irq!($inst, $irq, Rx0Interrupt);
+    };
+    ($inst:ident, can, $block:ident, RX1, $irq:ident) => {
+        impl_irq!($inst, $irq, Rx1Interrupt);
+    };
+    ($inst:ident, can, $block:ident, SCE, $irq:ident) => {
+        impl_irq!($inst, $irq, SceInterrupt);
+    };
+);
+
 macro_rules! impl_pin {
     ($inst:ident, $pin:ident, $signal:ident, $af:expr) => {
         impl $signal<peripherals::$inst> for peripherals::$pin {}
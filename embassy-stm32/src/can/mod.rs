@@ -1,5 +1,136 @@
 #![macro_use]
 
+//! Controller Area Network (CAN).
+//!
+//! The frame and filter types here are shared by the bxCAN driver in this module and the FDCAN
+//! driver in [`crate::fdcan`], so application code can be written against both.
+
+#[cfg(can)]
 #[cfg_attr(can_bxcan, path = "bxcan.rs")]
 mod _version;
+#[cfg(can)]
 pub use _version::*;
+
+pub use bxcan::{ExtendedId, Id, StandardId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Received frames were lost because the receive queue was full.
+    Overrun,
+    /// The controller is in the bus-off state and can't transmit.
+    BusOff,
+}
+
+/// Fault confinement state of the controller, from the error counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusState {
+    /// Both error counters are below 96.
+    ErrorActive,
+    /// One of the error counters reached the warning limit (96).
+    ErrorWarning,
+    /// One of the error counters is above 127, the controller no longer sends active error flags.
+    ErrorPassive,
+    /// The transmit error counter is above 255, the controller is disconnected from the bus.
+    BusOff,
+}
+
+/// An acceptance filter, matching received frames on their identifier.
+///
+/// Matching frames are stored in the first receive FIFO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Accepts all frames.
+    AcceptAll,
+    /// Accepts standard frames whose identifier matches `id` on the bits set in `mask`.
+    Standard { id: StandardId, mask: StandardId },
+    /// Accepts extended frames whose identifier matches `id` on the bits set in `mask`.
+    Extended { id: ExtendedId, mask: ExtendedId },
+}
+
+/// Operations common to the frames of all CAN drivers.
+pub trait CanFrame: Sized {
+    /// Creates a data frame, or returns `None` if `data` is too long for a classic frame.
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self>;
+
+    /// Creates a remote frame, or returns `None` if `dlc` is larger than 8.
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self>;
+
+    fn id(&self) -> Id;
+
+    fn is_remote_frame(&self) -> bool;
+
+    /// Returns the data length code, which is the length of the data for classic frames.
+    fn dlc(&self) -> usize;
+
+    /// Returns the data, which is empty for remote frames.
+    fn data(&self) -> &[u8];
+}
+
+/// A received frame, with the time it was received.
+#[derive(Debug, Clone)]
+pub struct Envelope<F> {
+    /// Time the frame was taken from the hardware FIFO.
+    #[cfg(feature = "_time-driver")]
+    pub ts: embassy::time::Instant,
+    /// The frame.
+    pub frame: F,
+}
+
+/// Software queue of received frames, filled by the interrupt handlers.
+pub(crate) struct RxQueue<T, const N: usize> {
+    buf: [Option<T>; N],
+    start: usize,
+    len: usize,
+    overrun: bool,
+}
+
+impl<T, const N: usize> RxQueue<T, N> {
+    /// `buf` must be all `None`, it's taken as an argument so this can be used in a `static`.
+    pub(crate) const fn new(buf: [Option<T>; N]) -> Self {
+        Self {
+            buf,
+            start: 0,
+            len: 0,
+            overrun: false,
+        }
+    }
+
+    pub(crate) fn push(&mut self, item: T) {
+        if self.len == N {
+            self.overrun = true;
+            return;
+        }
+        self.buf[(self.start + self.len) % N] = Some(item);
+        self.len += 1;
+    }
+
+    /// Returns the next item, or `Err(Error::Overrun)` once if items were lost.
+    pub(crate) fn pop(&mut self) -> Option<Result<T, Error>> {
+        if self.overrun {
+            self.overrun = false;
+            return Some(Err(Error::Overrun));
+        }
+        if self.len == 0 {
+            return None;
+        }
+        let item = self.buf[self.start].take();
+        self.start = (self.start + 1) % N;
+        self.len -= 1;
+        item.map(Ok)
+    }
+
+    pub(crate) fn set_overrun(&mut self) {
+        self.overrun = true;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for item in self.buf.iter_mut() {
+            *item = None;
+        }
+        self.start = 0;
+        self.len = 0;
+        self.overrun = false;
+    }
+}
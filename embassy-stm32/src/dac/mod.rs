@@ -3,10 +3,84 @@
 #[cfg_attr(dac_v1, path = "v1.rs")]
 #[cfg_attr(dac_v2, path = "v2.rs")]
 mod _version;
+use crate::dma;
 use crate::gpio::NoPin;
 use crate::peripherals;
 pub use _version::*;
 
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    UnconfiguredChannel,
+    InvalidValue,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Channel {
+    Ch1,
+    Ch2,
+}
+
+pub enum Alignment {
+    Left,
+    Right,
+}
+
+pub enum Value {
+    Bit8(u8),
+    Bit12(u16, Alignment),
+}
+
+/// Samples written to a channel by DMA, one per trigger event.
+pub enum ValueArray<'a> {
+    Bit8(&'a [u8]),
+    Bit12Left(&'a [u16]),
+    Bit12Right(&'a [u16]),
+}
+
+/// Waveform added by the hardware to the data holding register value on each trigger event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Wave {
+    /// Output the data holding register value as is.
+    Disabled,
+    /// Pseudo-random noise, keeping the given number of low bits (1 to 12) of the LFSR.
+    Noise(u8),
+    /// Triangle going from 0 to 2^n - 1 and back, for the given number of bits n (1 to 12).
+    Triangle(u8),
+}
+
+impl Wave {
+    /// Returns the WAVE and MAMP field values of the channel's CR bits.
+    fn bits(&self) -> Result<(u8, u8), Error> {
+        let (wave, bits) = match *self {
+            Wave::Disabled => return Ok((0b00, 0)),
+            Wave::Noise(bits) => (0b01, bits),
+            Wave::Triangle(bits) => (0b10, bits),
+        };
+        if bits == 0 || bits > 12 {
+            return Err(Error::InvalidValue);
+        }
+        Ok((wave, bits - 1))
+    }
+}
+
+/// Writes `buf` to a data holding register, one word per DMA request of the DAC channel.
+async fn dma_write<W: dma::Word>(
+    dma: impl dma::Channel,
+    request: dma::Request,
+    buf: &[W],
+    reg_addr: *mut W,
+    circular: bool,
+) {
+    if circular {
+        dma::write_circular(dma, request, buf, reg_addr).await
+    } else {
+        dma::write(dma, request, buf, reg_addr).await
+    }
+}
+
 pub(crate) mod sealed {
     use crate::gpio::OptionalPin;
 
@@ -15,11 +89,24 @@ pub(crate) mod sealed {
     }
 
     pub trait DacPin<T: Instance, const C: u8>: OptionalPin {}
+
+    pub trait DmaCh1<T: Instance> {
+        fn request(&self) -> super::dma::Request;
+    }
+
+    pub trait DmaCh2<T: Instance> {
+        fn request(&self) -> super::dma::Request;
+    }
 }
 
+#[cfg(dac_v1)]
+pub trait Instance: sealed::Instance + crate::rcc::RccPeripheral + 'static {}
+#[cfg(not(dac_v1))]
 pub trait Instance: sealed::Instance + 'static {}
 
 pub trait DacPin<T: Instance, const C: u8>: sealed::DacPin<T, C> + 'static {}
+pub trait DmaCh1<T: Instance>: sealed::DmaCh1<T> + dma::Channel {}
+pub trait DmaCh2<T: Instance>: sealed::DmaCh2<T> + dma::Channel {}
 
 impl<T: Instance, const C: u8> DacPin<T, C> for NoPin {}
 impl<T: Instance, const C: u8> sealed::DacPin<T, C> for NoPin {}
@@ -52,3 +139,40 @@ crate::pac::peripheral_pins!(
         }
     };
 );
+
+#[allow(unused)]
+macro_rules! impl_dma {
+    ($inst:ident, {dmamux: $dmamux:ident}, $signal:ident, $request:expr) => {
+        impl<T> sealed::$signal<peripherals::$inst> for T
+        where
+            T: crate::dma::MuxChannel<Mux = crate::dma::$dmamux>,
+        {
+            fn request(&self) -> dma::Request {
+                $request
+            }
+        }
+
+        impl<T> $signal<peripherals::$inst> for T where
+            T: crate::dma::MuxChannel<Mux = crate::dma::$dmamux>
+        {
+        }
+    };
+    ($inst:ident, {channel: $channel:ident}, $signal:ident, $request:expr) => {
+        impl sealed::$signal<peripherals::$inst> for peripherals::$channel {
+            fn request(&self) -> dma::Request {
+                $request
+            }
+        }
+
+        impl $signal<peripherals::$inst> for peripherals::$channel {}
+    };
+}
+
+crate::pac::peripheral_dma_channels! {
+    ($peri:ident, dac, $kind:ident, CH1, $channel:tt, $request:expr) => {
+        impl_dma!($peri, $channel, DmaCh1, $request);
+    };
+    ($peri:ident, dac, $kind:ident, CH2, $channel:tt, $request:expr) => {
+        impl_dma!($peri, $channel, DmaCh2, $request);
+    };
+}
@@ -1 +1,285 @@
+use crate::dac::{
+    dma_write, Alignment, Channel, DacPin, DmaCh1, DmaCh2, Error, Instance, Value, ValueArray, Wave,
+};
+use crate::gpio::AnyPin;
+use crate::pac::dac;
+use core::marker::PhantomData;
+use embassy::util::Unborrow;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
 
+/// Trigger of channel 1, the same triggers being available to channel 2.
+///
+/// On STM32F105/107, `Tim8` is the TRGO of TIM3.
+pub enum Ch1Trigger {
+    Tim6,
+    #[cfg(not(rcc_l1))]
+    Tim8,
+    Tim7,
+    #[cfg(not(rcc_l1))]
+    Tim5,
+    #[cfg(rcc_l1)]
+    Tim9,
+    Tim2,
+    Tim4,
+    Exti9,
+    Software,
+}
+
+impl Ch1Trigger {
+    /// Returns the TSEL field value, the same for both channels.
+    fn tsel(&self) -> u8 {
+        match self {
+            Ch1Trigger::Tim6 => 0b000,
+            #[cfg(not(rcc_l1))]
+            Ch1Trigger::Tim8 => 0b001,
+            Ch1Trigger::Tim7 => 0b010,
+            #[cfg(not(rcc_l1))]
+            Ch1Trigger::Tim5 => 0b011,
+            #[cfg(rcc_l1)]
+            Ch1Trigger::Tim9 => 0b011,
+            Ch1Trigger::Tim2 => 0b100,
+            Ch1Trigger::Tim4 => 0b101,
+            Ch1Trigger::Exti9 => 0b110,
+            Ch1Trigger::Software => 0b111,
+        }
+    }
+}
+
+pub type Ch2Trigger = Ch1Trigger;
+
+pub struct Dac<'d, T: Instance> {
+    ch1: Option<AnyPin>,
+    ch2: Option<AnyPin>,
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> Dac<'d, T> {
+    pub fn new(
+        _peri: impl Unborrow<Target = T> + 'd,
+        ch1: impl Unborrow<Target = impl DacPin<T, 1>>,
+        ch2: impl Unborrow<Target = impl DacPin<T, 2>>,
+    ) -> Self {
+        unborrow!(ch1, ch2);
+
+        T::enable();
+
+        let ch1 = ch1.degrade_optional();
+        let ch2 = ch2.degrade_optional();
+
+        unsafe {
+            T::regs().cr().modify(|reg| {
+                if ch1.is_some() {
+                    reg.set_en1(true);
+                }
+                if ch2.is_some() {
+                    reg.set_en2(true);
+                }
+            });
+        }
+
+        Self {
+            ch1,
+            ch2,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Checks that `ch` is configured.
+    fn check_channel(&self, ch: Channel) -> Result<(), Error> {
+        match ch {
+            Channel::Ch1 if self.ch1.is_some() => Ok(()),
+            Channel::Ch2 if self.ch2.is_some() => Ok(()),
+            _ => Err(Error::UnconfiguredChannel),
+        }
+    }
+
+    fn set_channel_enable(&mut self, ch: Channel, on: bool) -> Result<(), Error> {
+        self.check_channel(ch)?;
+        unsafe {
+            T::regs().cr().modify(|reg| match ch {
+                Channel::Ch1 => reg.set_en1(on),
+                Channel::Ch2 => reg.set_en2(on),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn enable_channel(&mut self, ch: Channel) -> Result<(), Error> {
+        self.set_channel_enable(ch, true)
+    }
+
+    pub fn disable_channel(&mut self, ch: Channel) -> Result<(), Error> {
+        self.set_channel_enable(ch, false)
+    }
+
+    fn select_trigger(&mut self, ch: Channel, trigger: Ch1Trigger) -> Result<(), Error> {
+        self.check_channel(ch)?;
+        let tsel = trigger.tsel();
+        unsafe {
+            T::regs().cr().modify(|reg| match ch {
+                Channel::Ch1 => {
+                    reg.set_en1(false);
+                    reg.set_tsel1(dac::vals::Tsel1(tsel));
+                    reg.set_ten1(true);
+                }
+                Channel::Ch2 => {
+                    reg.set_en2(false);
+                    reg.set_tsel2(dac::vals::Tsel2(tsel));
+                    reg.set_ten2(true);
+                }
+            })
+        }
+        Ok(())
+    }
+
+    pub fn select_trigger_ch1(&mut self, trigger: Ch1Trigger) -> Result<(), Error> {
+        self.select_trigger(Channel::Ch1, trigger)
+    }
+
+    pub fn select_trigger_ch2(&mut self, trigger: Ch2Trigger) -> Result<(), Error> {
+        self.select_trigger(Channel::Ch2, trigger)
+    }
+
+    pub fn trigger(&mut self, ch: Channel) -> Result<(), Error> {
+        self.check_channel(ch)?;
+        unsafe {
+            T::regs().swtrigr().write(|reg| match ch {
+                Channel::Ch1 => reg.set_swtrig1(true),
+                Channel::Ch2 => reg.set_swtrig2(true),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn trigger_all(&mut self) {
+        unsafe {
+            T::regs().swtrigr().write(|reg| {
+                reg.set_swtrig1(true);
+                reg.set_swtrig2(true);
+            })
+        }
+    }
+
+    pub fn set(&mut self, ch: Channel, value: Value) -> Result<(), Error> {
+        self.check_channel(ch)?;
+        let regs = T::regs();
+        unsafe {
+            match (ch, value) {
+                (Channel::Ch1, Value::Bit8(v)) => regs.dhr8r1().write(|reg| reg.set_dacc1dhr(v)),
+                (Channel::Ch1, Value::Bit12(v, Alignment::Left)) => {
+                    regs.dhr12l1().write(|reg| reg.set_dacc1dhr(v))
+                }
+                (Channel::Ch1, Value::Bit12(v, Alignment::Right)) => {
+                    regs.dhr12r1().write(|reg| reg.set_dacc1dhr(v))
+                }
+                (Channel::Ch2, Value::Bit8(v)) => regs.dhr8r2().write(|reg| reg.set_dacc2dhr(v)),
+                (Channel::Ch2, Value::Bit12(v, Alignment::Left)) => {
+                    regs.dhr12l2().write(|reg| reg.set_dacc2dhr(v))
+                }
+                (Channel::Ch2, Value::Bit12(v, Alignment::Right)) => {
+                    regs.dhr12r2().write(|reg| reg.set_dacc2dhr(v))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Selects the waveform generated on each trigger event of a channel, which requires a
+    /// trigger to be selected.
+    ///
+    /// Like when selecting a trigger, the channel is disabled and has to be enabled again.
+    pub fn set_wave(&mut self, ch: Channel, wave: Wave) -> Result<(), Error> {
+        self.check_channel(ch)?;
+        let (wave, mamp) = wave.bits()?;
+        unsafe {
+            T::regs().cr().modify(|reg| match ch {
+                Channel::Ch1 => {
+                    reg.set_en1(false);
+                    reg.set_wave1(dac::vals::Wave1(wave));
+                    reg.set_mamp1(dac::vals::Mamp1(mamp));
+                }
+                Channel::Ch2 => {
+                    reg.set_en2(false);
+                    reg.set_wave2(dac::vals::Wave2(wave));
+                    reg.set_mamp2(dac::vals::Mamp2(mamp));
+                }
+            })
+        }
+        Ok(())
+    }
+
+    /// Outputs `data` on channel 1 using DMA, one sample per trigger event.
+    ///
+    /// In circular mode, `data` is output over and over and the returned future never
+    /// completes: drop it to stop.
+    pub async fn write_ch1(
+        &mut self,
+        dma: impl Unborrow<Target = impl DmaCh1<T>>,
+        data: ValueArray<'_>,
+        circular: bool,
+    ) -> Result<(), Error> {
+        self.check_channel(Channel::Ch1)?;
+        unborrow!(dma);
+        let request = dma.request();
+        let regs = T::regs();
+        let dhr = match data {
+            ValueArray::Bit8(_) => regs.dhr8r1().ptr() as *mut u32,
+            ValueArray::Bit12Left(_) => regs.dhr12l1().ptr() as *mut u32,
+            ValueArray::Bit12Right(_) => regs.dhr12r1().ptr() as *mut u32,
+        };
+        self.dma_write(dma, request, Channel::Ch1, data, dhr, circular)
+            .await;
+        Ok(())
+    }
+
+    /// Outputs `data` on channel 2 using DMA, one sample per trigger event.
+    ///
+    /// In circular mode, `data` is output over and over and the returned future never
+    /// completes: drop it to stop.
+    pub async fn write_ch2(
+        &mut self,
+        dma: impl Unborrow<Target = impl DmaCh2<T>>,
+        data: ValueArray<'_>,
+        circular: bool,
+    ) -> Result<(), Error> {
+        self.check_channel(Channel::Ch2)?;
+        unborrow!(dma);
+        let request = dma.request();
+        let regs = T::regs();
+        let dhr = match data {
+            ValueArray::Bit8(_) => regs.dhr8r2().ptr() as *mut u32,
+            ValueArray::Bit12Left(_) => regs.dhr12l2().ptr() as *mut u32,
+            ValueArray::Bit12Right(_) => regs.dhr12r2().ptr() as *mut u32,
+        };
+        self.dma_write(dma, request, Channel::Ch2, data, dhr, circular)
+            .await;
+        Ok(())
+    }
+
+    async fn dma_write(
+        &mut self,
+        dma: impl crate::dma::Channel,
+        request: crate::dma::Request,
+        ch: Channel,
+        data: ValueArray<'_>,
+        dhr: *mut u32,
+        circular: bool,
+    ) {
+        let set_dmaen = move |on| unsafe {
+            T::regs().cr().modify(|reg| match ch {
+                Channel::Ch1 => reg.set_dmaen1(on),
+                Channel::Ch2 => reg.set_dmaen2(on),
+            })
+        };
+        set_dmaen(true);
+        let _on_drop = OnDrop::new(|| set_dmaen(false));
+
+        match data {
+            ValueArray::Bit8(buf) => dma_write(dma, request, buf, dhr as *mut u8, circular).await,
+            ValueArray::Bit12Left(buf) | ValueArray::Bit12Right(buf) => {
+                dma_write(dma, request, buf, dhr as *mut u16, circular).await
+            }
+        }
+    }
+}
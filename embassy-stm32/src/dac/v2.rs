@@ -1,8 +1,11 @@
-use crate::dac::{DacPin, Instance};
+use crate::dac::{
+    dma_write, Alignment, Channel, DacPin, DmaCh1, DmaCh2, Error, Instance, Value, ValueArray, Wave,
+};
 use crate::gpio::AnyPin;
 use crate::pac::dac;
 use core::marker::PhantomData;
 use embassy::util::Unborrow;
+use embassy_hal_common::drop::OnDrop;
 use embassy_hal_common::unborrow;
 
 /// Sadly we cannot use `RccPeripheral::enable` since devices are quite inconsistent DAC clock
@@ -16,18 +19,6 @@ unsafe fn enable() {
     crate::pac::RCC.apb1enr1().modify(|w| w.set_dac1en(true));
 }
 
-#[derive(Debug)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum Error {
-    UnconfiguredChannel,
-    InvalidValue,
-}
-
-pub enum Channel {
-    Ch1,
-    Ch2,
-}
-
 pub enum Ch1Trigger {
     Tim6,
     Tim3,
@@ -78,16 +69,6 @@ impl Ch2Trigger {
     }
 }
 
-pub enum Alignment {
-    Left,
-    Right,
-}
-
-pub enum Value {
-    Bit8(u8),
-    Bit12(u16, Alignment),
-}
-
 pub struct Dac<'d, T: Instance> {
     ch1: Option<AnyPin>,
     ch2: Option<AnyPin>,
@@ -176,6 +157,7 @@ impl<'d, T: Instance> Dac<'d, T> {
         unsafe {
             T::regs().cr().modify(|reg| {
                 reg.set_tsel1(trigger.tsel());
+                reg.set_ten1(true);
             })
         }
         Ok(())
@@ -189,6 +171,7 @@ impl<'d, T: Instance> Dac<'d, T> {
         unsafe {
             T::regs().cr().modify(|reg| {
                 reg.set_tsel2(trigger.tsel());
+                reg.set_ten2(true);
             })
         }
         Ok(())
@@ -272,4 +255,108 @@ impl<'d, T: Instance> Dac<'d, T> {
             }
         }
     }
+
+    /// Selects the waveform generated on each trigger event of a channel, which requires a
+    /// trigger to be selected.
+    ///
+    /// Like when selecting a trigger, the channel is disabled and has to be enabled again.
+    pub fn set_wave(&mut self, ch: Channel, wave: Wave) -> Result<(), Error> {
+        let (wave, mamp) = wave.bits()?;
+        self.set_channel_enable(ch, false)?;
+        unsafe {
+            T::regs().cr().modify(|reg| match ch {
+                Channel::Ch1 => {
+                    reg.set_wave1(dac::vals::Wave1(wave));
+                    reg.set_mamp1(dac::vals::Mamp1(mamp));
+                }
+                Channel::Ch2 => {
+                    reg.set_wave2(dac::vals::Wave2(wave));
+                    reg.set_mamp2(dac::vals::Mamp2(mamp));
+                }
+            })
+        }
+        Ok(())
+    }
+
+    /// Outputs `data` on channel 1 using DMA, one sample per trigger event.
+    ///
+    /// In circular mode, `data` is output over and over and the returned future never
+    /// completes: drop it to stop.
+    pub async fn write_ch1(
+        &mut self,
+        dma: impl Unborrow<Target = impl DmaCh1<T>>,
+        data: ValueArray<'_>,
+        circular: bool,
+    ) -> Result<(), Error> {
+        if self.ch1.is_none() {
+            return Err(Error::UnconfiguredChannel);
+        }
+        unborrow!(dma);
+        let request = dma.request();
+        let regs = T::regs();
+
+        unsafe {
+            regs.cr().modify(|reg| reg.set_dmaen1(true));
+        }
+        let _on_drop = OnDrop::new(|| unsafe {
+            T::regs().cr().modify(|reg| reg.set_dmaen1(false));
+        });
+
+        match data {
+            ValueArray::Bit8(buf) => {
+                let dhr = regs.dhr8r1().ptr() as *mut u8;
+                dma_write(dma, request, buf, dhr, circular).await
+            }
+            ValueArray::Bit12Left(buf) => {
+                let dhr = regs.dhr12l1().ptr() as *mut u16;
+                dma_write(dma, request, buf, dhr, circular).await
+            }
+            ValueArray::Bit12Right(buf) => {
+                let dhr = regs.dhr12r1().ptr() as *mut u16;
+                dma_write(dma, request, buf, dhr, circular).await
+            }
+        }
+        Ok(())
+    }
+
+    /// Outputs `data` on channel 2 using DMA, one sample per trigger event.
+    ///
+    /// In circular mode, `data` is output over and over and the returned future never
+    /// completes: drop it to stop.
+    pub async fn write_ch2(
+        &mut self,
+        dma: impl Unborrow<Target = impl DmaCh2<T>>,
+        data: ValueArray<'_>,
+        circular: bool,
+    ) -> Result<(), Error> {
+        if self.ch2.is_none() {
+            return Err(Error::UnconfiguredChannel);
+        }
+        unborrow!(dma);
+        let request = dma.request();
+        let regs = T::regs();
+
+        unsafe {
+            regs.cr().modify(|reg| reg.set_dmaen2(true));
+        }
+        let _on_drop = OnDrop::new(|| unsafe {
+            T::regs().cr().modify(|reg| reg.set_dmaen2(false));
+        });
+
+        match data {
+            ValueArray::Bit8(buf) => {
+                let dhr = regs.dhr8r2().ptr() as *mut u8;
+                dma_write(dma, request, buf, dhr, circular).await
+            }
+            ValueArray::Bit12Left(buf) => {
+                let dhr = regs.dhr12l2().ptr() as *mut u16;
+                dma_write(dma, request, buf, dhr, circular).await
+            }
+            ValueArray::Bit12Right(buf) => {
+                let dhr = regs.dhr12r2().ptr() as *mut u16;
+                dma_write(dma, request, buf, dhr, circular).await
+            }
+        }
+        Ok(())
+    }
 }
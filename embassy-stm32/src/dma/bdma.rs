@@ -3,6 +3,7 @@
 use core::sync::atomic::{fence, Ordering};
 use core::task::Waker;
 
+use atomic_polyfill::AtomicUsize;
 use embassy::interrupt::{Interrupt, InterruptExt};
 use embassy::waitqueue::AtomicWaker;
 
@@ -28,13 +29,17 @@ const CH_COUNT: usize = pac::peripheral_count!(bdma) * 8;
 
 struct State {
     ch_wakers: [AtomicWaker; CH_COUNT],
+    /// Number of full transfers completed in circular mode.
+    complete_count: [AtomicUsize; CH_COUNT],
 }
 
 impl State {
     const fn new() -> Self {
         const AW: AtomicWaker = AtomicWaker::new();
+        const AU: AtomicUsize = AtomicUsize::new(0);
         Self {
             ch_wakers: [AW; CH_COUNT],
+            complete_count: [AU; CH_COUNT],
         }
     }
 }
@@ -61,9 +66,23 @@ unsafe fn on_irq() {
 
                 for chn in 0..pac::dma_channels_count!($dma) {
                     let cr = pac::$dma.ch(chn).cr();
-                    if isr.tcif(chn) && cr.read().tcie() {
+                    let n = dma_num!($dma) * 8 + chn;
+                    if cr.read().circ() == vals::Circ::ENABLED {
+                        // Keep running, only clear the flags which were read.
+                        let tcif = isr.tcif(chn) && cr.read().tcie();
+                        let htif = isr.htif(chn) && cr.read().htie();
+                        if tcif || htif {
+                            pac::$dma.ifcr().write(|w| {
+                                w.set_tcif(chn, tcif);
+                                w.set_htif(chn, htif);
+                            });
+                            if tcif {
+                                STATE.complete_count[n].fetch_add(1, Ordering::Release);
+                            }
+                            STATE.ch_wakers[n].wake();
+                        }
+                    } else if isr.tcif(chn) && cr.read().tcie() {
                         cr.write(|_| ()); // Disable channel interrupts with the default value.
-                        let n = dma_num!($dma) * 8 + chn;
                         STATE.ch_wakers[n].wake();
                     }
                 }
@@ -100,6 +119,7 @@ pac::dma_channels! {
                     buf.as_ptr() as *mut u32,
                     buf.len(),
                     true,
+                    false,
                     vals::Size::from(W::bits()),
                     #[cfg(dmamux)]
                     <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
@@ -121,6 +141,7 @@ pac::dma_channels! {
                     buf.as_ptr() as *mut u32,
                     count,
                     false,
+                    false,
                     vals::Size::from(W::bits()),
                     #[cfg(dmamux)]
                     <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
@@ -140,6 +161,49 @@ pac::dma_channels! {
                     buf.as_ptr() as *mut u32,
                     buf.len(),
                     true,
+                    false,
+                    vals::Size::from(W::bits()),
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_CH_NUM,
+                );
+            }
+
+            unsafe fn start_circular_read<W: Word>(&mut self, request: Request, reg_addr: *mut W, buf: &mut [W]) {
+                low_level_api::reset_complete_count(dma_num!($dma_peri) * 8 + $channel_num);
+                low_level_api::start_transfer(
+                    pac::$dma_peri,
+                    $channel_num,
+                    #[cfg(any(bdma_v2, dmamux))]
+                    request,
+                    vals::Dir::FROMPERIPHERAL,
+                    reg_addr as *const u32,
+                    buf.as_ptr() as *mut u32,
+                    buf.len(),
+                    true,
+                    true,
+                    vals::Size::from(W::bits()),
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_CH_NUM,
+                );
+            }
+
+            unsafe fn start_circular_write<W: Word>(&mut self, request: Request, buf: &[W], reg_addr: *mut W) {
+                low_level_api::reset_complete_count(dma_num!($dma_peri) * 8 + $channel_num);
+                low_level_api::start_transfer(
+                    pac::$dma_peri,
+                    $channel_num,
+                    #[cfg(any(bdma_v2, dmamux))]
+                    request,
+                    vals::Dir::FROMMEMORY,
+                    reg_addr as *const u32,
+                    buf.as_ptr() as *mut u32,
+                    buf.len(),
+                    true,
+                    true,
                     vals::Size::from(W::bits()),
                     #[cfg(dmamux)]
                     <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
@@ -159,6 +223,10 @@ pac::dma_channels! {
                 unsafe {low_level_api::get_remaining_transfers(pac::$dma_peri, $channel_num)}
             }
 
+            fn complete_count(&mut self) -> usize {
+                unsafe {low_level_api::get_complete_count(pac::$dma_peri, $channel_num, dma_num!($dma_peri) * 8 + $channel_num)}
+            }
+
             fn set_waker(&mut self, waker: &Waker) {
                 unsafe {low_level_api::set_waker(dma_num!($dma_peri) * 8 + $channel_num, waker )}
             }
@@ -189,6 +257,7 @@ mod low_level_api {
         mem_addr: *mut u32,
         mem_len: usize,
         incr_mem: bool,
+        circular: bool,
         data_size: vals::Size,
         #[cfg(dmamux)] dmamux_regs: pac::dmamux::Dmamux,
         #[cfg(dmamux)] dmamux_ch_num: u8,
@@ -221,6 +290,10 @@ mod low_level_api {
                 w.set_minc(vals::Inc::DISABLED);
             }
             w.set_dir(dir);
+            if circular {
+                w.set_circ(vals::Circ::ENABLED);
+                w.set_htie(true);
+            }
             w.set_teie(true);
             w.set_tcie(true);
             w.set_en(true);
@@ -253,6 +326,24 @@ mod low_level_api {
         ch.ndtr().read().ndt()
     }
 
+    pub unsafe fn reset_complete_count(state_number: usize) {
+        STATE.complete_count[state_number].store(0, Ordering::Relaxed);
+    }
+
+    /// Gets the number of full transfers completed in circular mode, including one whose
+    /// interrupt wasn't handled yet.
+    pub unsafe fn get_complete_count(dma: pac::bdma::Dma, ch: u8, state_number: usize) -> usize {
+        // The interrupt handler can't clear the flag between the two reads.
+        critical_section::with(|_| {
+            let count = STATE.complete_count[state_number].load(Ordering::Acquire);
+            if dma.isr().read().tcif(ch as _) {
+                count.wrapping_add(1)
+            } else {
+                count
+            }
+        })
+    }
+
     /// Sets the waker for the specified DMA channel
     pub unsafe fn set_waker(state_number: usize, waker: &Waker) {
         STATE.ch_wakers[state_number].register(waker);
@@ -261,6 +352,7 @@ mod low_level_api {
     pub unsafe fn reset_status(dma: pac::bdma::Dma, channel_number: u8) {
         dma.ifcr().write(|w| {
             w.set_tcif(channel_number as _, true);
+            w.set_htif(channel_number as _, true);
             w.set_teif(channel_number as _, true);
         });
     }
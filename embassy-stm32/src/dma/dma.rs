@@ -1,6 +1,7 @@
 use core::sync::atomic::{fence, Ordering};
 use core::task::Waker;
 
+use atomic_polyfill::AtomicUsize;
 use embassy::interrupt::{Interrupt, InterruptExt};
 use embassy::waitqueue::AtomicWaker;
 
@@ -25,13 +26,17 @@ impl From<WordSize> for vals::Size {
 
 struct State {
     ch_wakers: [AtomicWaker; CH_COUNT],
+    /// Number of full transfers completed in circular mode.
+    complete_count: [AtomicUsize; CH_COUNT],
 }
 
 impl State {
     const fn new() -> Self {
         const AW: AtomicWaker = AtomicWaker::new();
+        const AU: AtomicUsize = AtomicUsize::new(0);
         Self {
             ch_wakers: [AW; CH_COUNT],
+            complete_count: [AU; CH_COUNT],
         }
     }
 }
@@ -55,10 +60,24 @@ unsafe fn on_irq() {
 
                 for chn in 0..4 {
                     let cr = pac::$dma.st(isrn * 4 + chn).cr();
-
-                    if isr.tcif(chn) && cr.read().tcie() {
+                    let n = dma_num!($dma) * 8 + isrn * 4 + chn;
+
+                    if cr.read().circ() == vals::Circ::ENABLED {
+                        // Keep running, only clear the flags which were read.
+                        let tcif = isr.tcif(chn) && cr.read().tcie();
+                        let htif = isr.htif(chn) && cr.read().htie();
+                        if tcif || htif {
+                            pac::$dma.ifcr(isrn).write(|w| {
+                                w.set_tcif(chn, tcif);
+                                w.set_htif(chn, htif);
+                            });
+                            if tcif {
+                                STATE.complete_count[n].fetch_add(1, Ordering::Release);
+                            }
+                            STATE.ch_wakers[n].wake();
+                        }
+                    } else if isr.tcif(chn) && cr.read().tcie() {
                         cr.write(|_| ()); // Disable channel interrupts with the default value.
-                        let n = dma_num!($dma) * 8 + isrn * 4 + chn;
                         STATE.ch_wakers[n].wake();
                     }
                 }
@@ -94,6 +113,7 @@ pac::dma_channels! {
                     buf.as_ptr() as *mut u32,
                     buf.len(),
                     true,
+                    false,
                     vals::Size::from(W::bits()),
                     #[cfg(dmamux)]
                     <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
@@ -113,6 +133,7 @@ pac::dma_channels! {
                     buf.as_ptr() as *mut u32,
                     count,
                     false,
+                    false,
                     vals::Size::from(W::bits()),
                     #[cfg(dmamux)]
                     <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
@@ -131,6 +152,47 @@ pac::dma_channels! {
                     buf.as_ptr() as *mut u32,
                     buf.len(),
                     true,
+                    false,
+                    vals::Size::from(W::bits()),
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_CH_NUM,
+                );
+            }
+
+            unsafe fn start_circular_read<W: Word>(&mut self, request: Request, reg_addr: *mut W, buf: &mut [W]) {
+                low_level_api::reset_complete_count(dma_num!($dma_peri) * 8 + $channel_num);
+                low_level_api::start_transfer(
+                    pac::$dma_peri,
+                    $channel_num,
+                    request,
+                    vals::Dir::PERIPHERALTOMEMORY,
+                    reg_addr as *const u32,
+                    buf.as_ptr() as *mut u32,
+                    buf.len(),
+                    true,
+                    true,
+                    vals::Size::from(W::bits()),
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_CH_NUM,
+                );
+            }
+
+            unsafe fn start_circular_write<W: Word>(&mut self, request: Request, buf: &[W], reg_addr: *mut W) {
+                low_level_api::reset_complete_count(dma_num!($dma_peri) * 8 + $channel_num);
+                low_level_api::start_transfer(
+                    pac::$dma_peri,
+                    $channel_num,
+                    request,
+                    vals::Dir::MEMORYTOPERIPHERAL,
+                    reg_addr as *const u32,
+                    buf.as_ptr() as *mut u32,
+                    buf.len(),
+                    true,
+                    true,
                     vals::Size::from(W::bits()),
                     #[cfg(dmamux)]
                     <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
@@ -151,6 +213,10 @@ pac::dma_channels! {
                 unsafe {low_level_api::get_remaining_transfers(pac::$dma_peri, $channel_num)}
             }
 
+            fn complete_count(&mut self) -> usize {
+                unsafe {low_level_api::get_complete_count(pac::$dma_peri, $channel_num, dma_num!($dma_peri) * 8 + $channel_num)}
+            }
+
             fn set_waker(&mut self, waker: &Waker) {
                 unsafe {low_level_api::set_waker(dma_num!($dma_peri) * 8 + $channel_num, waker )}
             }
@@ -181,6 +247,7 @@ mod low_level_api {
         mem_addr: *mut u32,
         mem_len: usize,
         incr_mem: bool,
+        circular: bool,
         data_size: vals::Size,
         #[cfg(dmamux)] dmamux_regs: pac::dmamux::Dmamux,
         #[cfg(dmamux)] dmamux_ch_num: u8,
@@ -208,6 +275,10 @@ mod low_level_api {
                 w.set_minc(vals::Inc::FIXED);
             }
             w.set_pinc(vals::Inc::FIXED);
+            if circular {
+                w.set_circ(vals::Circ::ENABLED);
+                w.set_htie(true);
+            }
             w.set_teie(true);
             w.set_tcie(true);
             #[cfg(dma_v1)]
@@ -252,6 +323,27 @@ mod low_level_api {
         ch.ndtr().read().ndt()
     }
 
+    pub unsafe fn reset_complete_count(state_number: usize) {
+        STATE.complete_count[state_number].store(0, Ordering::Relaxed);
+    }
+
+    /// Gets the number of full transfers completed in circular mode, including one whose
+    /// interrupt wasn't handled yet.
+    pub unsafe fn get_complete_count(dma: pac::dma::Dma, ch: u8, state_number: usize) -> usize {
+        let isrn = ch as usize / 4;
+        let isrbit = ch as usize % 4;
+
+        // The interrupt handler can't clear the flag between the two reads.
+        critical_section::with(|_| {
+            let count = STATE.complete_count[state_number].load(Ordering::Acquire);
+            if dma.isr(isrn).read().tcif(isrbit) {
+                count.wrapping_add(1)
+            } else {
+                count
+            }
+        })
+    }
+
     /// Sets the waker for the specified DMA channel
     pub unsafe fn set_waker(state_number: usize, waker: &Waker) {
         STATE.ch_wakers[state_number].register(waker);
@@ -263,6 +355,7 @@ mod low_level_api {
 
         dma.ifcr(isrn).write(|w| {
             w.set_tcif(isrbit, true);
+            w.set_htif(isrbit, true);
             w.set_teif(isrbit, true);
         });
     }
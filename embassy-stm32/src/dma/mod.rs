@@ -4,6 +4,7 @@ mod bdma;
 mod dma;
 #[cfg(dmamux)]
 mod dmamux;
+mod ringbuffer;
 
 #[cfg(dmamux)]
 pub use dmamux::*;
@@ -22,6 +23,13 @@ pub use transfers::*;
 #[cfg(not(feature = "unstable-pac"))]
 pub(crate) use transfers::*;
 
+#[cfg(feature = "unstable-pac")]
+pub use ringbuffer::{DmaCtrl, DmaRingBuffer, OverrunError};
+
+#[cfg(not(feature = "unstable-pac"))]
+#[allow(unused)]
+pub(crate) use ringbuffer::{DmaCtrl, DmaRingBuffer, OverrunError};
+
 #[cfg(any(bdma_v2, dma_v2, dmamux))]
 pub type Request = u8;
 #[cfg(not(any(bdma_v2, dma_v2, dmamux)))]
@@ -69,6 +77,34 @@ pub(crate) mod sealed {
             buf: &mut [W],
         );
 
+        /// Starts this channel for reading a stream of words into `buf` in circular mode, wrapping
+        /// around forever.
+        ///
+        /// The waker is called at each half and full transfer.
+        ///
+        /// Safety:
+        /// - `buf` must be alive until the channel is stopped.
+        /// - `reg_addr` must be a valid peripheral register address to read from.
+        unsafe fn start_circular_read<W: super::Word>(
+            &mut self,
+            request: Request,
+            reg_addr: *mut W,
+            buf: &mut [W],
+        );
+
+        /// Starts this channel for writing the words of `buf` in circular mode, wrapping around
+        /// forever.
+        ///
+        /// Safety:
+        /// - `buf` must be alive until the channel is stopped.
+        /// - `reg_addr` must be a valid peripheral register address to write to.
+        unsafe fn start_circular_write<W: super::Word>(
+            &mut self,
+            request: Request,
+            buf: &[W],
+            reg_addr: *mut W,
+        );
+
         /// Requests the channel to stop.
         /// NOTE: The channel does not immediately stop, you have to wait
         /// for `is_running() = false`.
@@ -82,6 +118,10 @@ pub(crate) mod sealed {
         /// Returns the total number of remaining transfers.
         fn remaining_transfers(&mut self) -> u16;
 
+        /// Returns the number of full transfers completed in circular mode since the channel was
+        /// started, including one whose interrupt is still pending.
+        fn complete_count(&mut self) -> usize;
+
         /// Sets the waker that is called when this channel stops (either completed or manually stopped)
         fn set_waker(&mut self, waker: &Waker);
     }
@@ -134,6 +174,8 @@ mod transfers {
 
         Transfer {
             channel,
+            #[cfg(feature = "low-power")]
+            _stop_veto: crate::low_power::StopVeto::new(),
             _phantom: PhantomData,
         }
     }
@@ -152,6 +194,29 @@ mod transfers {
 
         Transfer {
             channel,
+            #[cfg(feature = "low-power")]
+            _stop_veto: crate::low_power::StopVeto::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Writes `buf` to `reg_addr` over and over, until the returned future is dropped.
+    #[allow(unused)]
+    pub fn write_circular<'a, W: Word>(
+        channel: impl Unborrow<Target = impl Channel> + 'a,
+        request: Request,
+        buf: &'a [W],
+        reg_addr: *mut W,
+    ) -> impl Future<Output = ()> + 'a {
+        assert!(buf.len() <= 0xFFFF);
+        unborrow!(channel);
+
+        unsafe { channel.start_circular_write::<W>(request, buf, reg_addr) };
+
+        Transfer {
+            channel,
+            #[cfg(feature = "low-power")]
+            _stop_veto: crate::low_power::StopVeto::new(),
             _phantom: PhantomData,
         }
     }
@@ -170,12 +235,17 @@ mod transfers {
 
         Transfer {
             channel,
+            #[cfg(feature = "low-power")]
+            _stop_veto: crate::low_power::StopVeto::new(),
             _phantom: PhantomData,
         }
     }
 
     struct Transfer<'a, C: Channel> {
         channel: C,
+        /// The DMA stops in STOP mode.
+        #[cfg(feature = "low-power")]
+        _stop_veto: crate::low_power::StopVeto,
         _phantom: PhantomData<&'a mut C>,
     }
 
@@ -202,6 +272,16 @@ mod transfers {
 
 pub trait Channel: sealed::Channel + Unborrow<Target = Self> + 'static {}
 
+impl<C: Channel> DmaCtrl for C {
+    fn ndtr(&mut self) -> usize {
+        self.remaining_transfers() as usize
+    }
+
+    fn complete_count(&mut self) -> usize {
+        sealed::Channel::complete_count(self)
+    }
+}
+
 pub struct NoDma;
 
 unsafe impl Unborrow for NoDma {
@@ -0,0 +1,251 @@
+//! Reader for a buffer written in circular mode by a DMA channel.
+//!
+//! The channel wraps around the buffer forever, and counts its wraps (transfer complete events).
+//! The reader tracks the number of words it consumed: together with the wrap count and the
+//! position of the channel in the buffer, which is `len - NDTR`, this tells how many words are
+//! unread, and whether the channel overwrote some before they were read.
+
+use core::cmp::min;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// Access to the channel writing the buffer.
+pub trait DmaCtrl {
+    /// Returns the number of transfers left before the channel wraps around the buffer.
+    fn ndtr(&mut self) -> usize;
+
+    /// Returns the number of times the channel wrapped around the buffer since it was started,
+    /// wrapping around on overflow.
+    ///
+    /// This must include a wrap whose interrupt wasn't handled yet.
+    fn complete_count(&mut self) -> usize;
+}
+
+/// The channel overwrote words which had not been read yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OverrunError;
+
+pub struct DmaRingBuffer<'a, W: Copy> {
+    dma_buf: &'a mut [W],
+    /// Index of the next word to read.
+    first: usize,
+    /// Number of words read since the channel was started, wrapping around on overflow.
+    read_pos: usize,
+}
+
+impl<'a, W: Copy> DmaRingBuffer<'a, W> {
+    /// Creates a reader for a channel which was just started on `dma_buf`.
+    pub fn new(dma_buf: &'a mut [W]) -> Self {
+        assert!(!dma_buf.is_empty());
+        Self {
+            dma_buf,
+            first: 0,
+            read_pos: 0,
+        }
+    }
+
+    /// Returns the length of the buffer.
+    pub fn cap(&self) -> usize {
+        self.dma_buf.len()
+    }
+
+    /// Returns the buffer, to start the channel on it.
+    pub fn dma_buf(&mut self) -> &mut [W] {
+        self.dma_buf
+    }
+
+    /// Returns the number of words written by the channel since it was started, wrapping around
+    /// on overflow, and the index of the next word it writes.
+    fn write_pos(&self, dma: &mut impl DmaCtrl) -> (usize, usize) {
+        let cap = self.cap();
+        loop {
+            let count = dma.complete_count();
+            compiler_fence(Ordering::SeqCst);
+            let ndtr = dma.ndtr();
+            compiler_fence(Ordering::SeqCst);
+            // NDTR is reloaded when the channel wraps: if the count didn't change, NDTR belongs to
+            // the same lap.
+            if dma.complete_count() == count {
+                // NDTR should never read 0, as it's reloaded right away. If it does, assume the
+                // wrap isn't counted yet, which under-estimates the number of unread words.
+                let index = (cap - ndtr) % cap;
+                return (count.wrapping_mul(cap).wrapping_add(index), index);
+            }
+        }
+    }
+
+    /// Returns the number of unread words.
+    pub fn len(&self, dma: &mut impl DmaCtrl) -> Result<usize, OverrunError> {
+        let len = self.write_pos(dma).0.wrapping_sub(self.read_pos);
+        if len > self.cap() {
+            Err(OverrunError)
+        } else {
+            Ok(len)
+        }
+    }
+
+    /// Discards the unread words, to recover from an overrun.
+    pub fn clear(&mut self, dma: &mut impl DmaCtrl) {
+        // The position wraps around at `usize::MAX`, which isn't a multiple of the capacity, so
+        // the index can't be derived from it.
+        let (pos, index) = self.write_pos(dma);
+        self.first = index;
+        self.read_pos = pos;
+    }
+
+    /// Returns the unread words up to the end of the buffer, without marking them as read.
+    ///
+    /// The channel keeps writing the buffer: the words are only valid if [`Self::consume`]
+    /// doesn't return an error afterwards.
+    pub fn fill_buf(&mut self, dma: &mut impl DmaCtrl) -> Result<&[W], OverrunError> {
+        let len = min(self.len(dma)?, self.cap() - self.first);
+        Ok(&self.dma_buf[self.first..self.first + len])
+    }
+
+    /// Marks `amt` words as read.
+    ///
+    /// Returns an error if the channel overwrote some of them since they were returned by
+    /// [`Self::fill_buf`]. The reader then has to be cleared.
+    pub fn consume(&mut self, dma: &mut impl DmaCtrl, amt: usize) -> Result<(), OverrunError> {
+        compiler_fence(Ordering::SeqCst);
+        // The oldest consumed word is overwritten once the channel wrote a full buffer after it.
+        if self.write_pos(dma).0.wrapping_sub(self.read_pos) > self.cap() {
+            return Err(OverrunError);
+        }
+        self.first = (self.first + amt) % self.cap();
+        self.read_pos = self.read_pos.wrapping_add(amt);
+        Ok(())
+    }
+
+    /// Reads unread words into `buf`, returning how many were read.
+    ///
+    /// On error, the reader has to be cleared.
+    pub fn read(&mut self, dma: &mut impl DmaCtrl, buf: &mut [W]) -> Result<usize, OverrunError> {
+        let len = min(self.len(dma)?, buf.len());
+        let tail = min(len, self.cap() - self.first);
+        for (i, w) in buf[..len].iter_mut().enumerate() {
+            let index = if i < tail { self.first + i } else { i - tail };
+            // The channel writes the buffer behind our back.
+            *w = unsafe { ptr::read_volatile(&self.dma_buf[index]) };
+        }
+        self.consume(dma, len)?;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A channel whose position is set by the tests.
+    struct TestDma {
+        count: usize,
+        ndtr: usize,
+    }
+
+    impl DmaCtrl for TestDma {
+        fn ndtr(&mut self) -> usize {
+            self.ndtr
+        }
+
+        fn complete_count(&mut self) -> usize {
+            self.count
+        }
+    }
+
+    /// Writes `n` increasing values after `pos` like the channel would, and returns the new
+    /// position.
+    fn write(buf: &mut DmaRingBuffer<u8>, dma: &mut TestDma, pos: usize, n: usize) -> usize {
+        let cap = buf.cap();
+        for i in pos..pos + n {
+            buf.dma_buf()[i % cap] = i as u8;
+        }
+        let pos = pos + n;
+        dma.count = pos / cap;
+        dma.ndtr = cap - pos % cap;
+        pos
+    }
+
+    #[test]
+    fn read_and_wrap() {
+        let mut mem = [0u8; 8];
+        let mut buf = DmaRingBuffer::new(&mut mem);
+        let mut dma = TestDma { count: 0, ndtr: 8 };
+        let mut out = [0u8; 16];
+
+        assert_eq!(buf.read(&mut dma, &mut out), Ok(0));
+
+        let pos = write(&mut buf, &mut dma, 0, 5);
+        assert_eq!(buf.read(&mut dma, &mut out[..3]), Ok(3));
+        assert_eq!(out[..3], [0, 1, 2]);
+
+        // Crosses the end of the buffer.
+        write(&mut buf, &mut dma, pos, 6);
+        assert_eq!(buf.len(&mut dma), Ok(8));
+        assert_eq!(buf.read(&mut dma, &mut out), Ok(8));
+        assert_eq!(out[..8], [3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(buf.read(&mut dma, &mut out), Ok(0));
+    }
+
+    #[test]
+    fn overrun() {
+        let mut mem = [0u8; 8];
+        let mut buf = DmaRingBuffer::new(&mut mem);
+        let mut dma = TestDma { count: 0, ndtr: 8 };
+        let mut out = [0u8; 8];
+
+        // A full buffer is fine, one more word overwrites the oldest one.
+        let pos = write(&mut buf, &mut dma, 0, 8);
+        assert_eq!(buf.len(&mut dma), Ok(8));
+        let pos = write(&mut buf, &mut dma, pos, 1);
+        assert_eq!(buf.read(&mut dma, &mut out), Err(OverrunError));
+
+        buf.clear(&mut dma);
+        assert_eq!(buf.len(&mut dma), Ok(0));
+        write(&mut buf, &mut dma, pos, 2);
+        assert_eq!(buf.read(&mut dma, &mut out), Ok(2));
+        assert_eq!(out[..2], [9, 10]);
+    }
+
+    #[test]
+    fn overwritten_while_reading() {
+        let mut mem = [0u8; 8];
+        let mut buf = DmaRingBuffer::new(&mut mem);
+        let mut dma = TestDma { count: 0, ndtr: 8 };
+
+        let pos = write(&mut buf, &mut dma, 0, 6);
+        assert_eq!(buf.fill_buf(&mut dma), Ok(&[0, 1, 2, 3, 4, 5][..]));
+
+        // Still fine when the channel comes right behind the returned words.
+        let pos = write(&mut buf, &mut dma, pos, 2);
+        assert_eq!(buf.consume(&mut dma, 1), Ok(()));
+        let pos = write(&mut buf, &mut dma, pos, 1);
+        assert_eq!(buf.fill_buf(&mut dma), Ok(&[1, 2, 3, 4, 5, 6, 7][..]));
+
+        // The channel overwrote word 1 with word 9 before it was consumed.
+        write(&mut buf, &mut dma, pos, 1);
+        assert_eq!(buf.consume(&mut dma, 7), Err(OverrunError));
+    }
+
+    #[test]
+    fn count_overflow() {
+        let mut mem = [0u8; 3];
+        let mut buf = DmaRingBuffer::new(&mut mem);
+        let mut out = [0u8; 3];
+
+        // The channel wraps often enough for the count to overflow, which happens at a position
+        // that isn't a multiple of the capacity.
+        let mut dma = TestDma {
+            count: usize::MAX,
+            ndtr: 1,
+        };
+        buf.clear(&mut dma);
+        buf.dma_buf()[2] = 1;
+        buf.dma_buf()[0] = 2;
+        dma.count = 0;
+        dma.ndtr = 2;
+        assert_eq!(buf.read(&mut dma, &mut out), Ok(2));
+        assert_eq!(out[..2], [1, 2]);
+    }
+}
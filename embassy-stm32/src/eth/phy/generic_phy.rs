@@ -0,0 +1,408 @@
+//! Generic IEEE 802.3 clause 22 Ethernet PHY
+//!
+//! Only the standard registers are used, so this works with most 10/100 PHYs (LAN8720, DP83848,
+//! KSZ8081, ...). The mode of the link is resolved from the abilities advertised by both ends.
+
+use super::{Duplex, LinkMode, Speed, StationManagement, PHY};
+
+#[allow(dead_code)]
+mod phy_consts {
+    pub const PHY_REG_BCR: u8 = 0x00;
+    pub const PHY_REG_BSR: u8 = 0x01;
+    pub const PHY_REG_ID1: u8 = 0x02;
+    pub const PHY_REG_ID2: u8 = 0x03;
+    pub const PHY_REG_ANTX: u8 = 0x04;
+    pub const PHY_REG_ANRX: u8 = 0x05;
+
+    pub const PHY_REG_BCR_FD: u16 = 1 << 8;
+    pub const PHY_REG_BCR_ANRST: u16 = 1 << 9;
+    pub const PHY_REG_BCR_ISOLATE: u16 = 1 << 10;
+    pub const PHY_REG_BCR_POWERDN: u16 = 1 << 11;
+    pub const PHY_REG_BCR_AN: u16 = 1 << 12;
+    pub const PHY_REG_BCR_100M: u16 = 1 << 13;
+    pub const PHY_REG_BCR_LOOPBACK: u16 = 1 << 14;
+    pub const PHY_REG_BCR_RESET: u16 = 1 << 15;
+
+    pub const PHY_REG_BSR_UP: u16 = 1 << 2;
+    pub const PHY_REG_BSR_FAULT: u16 = 1 << 4;
+    pub const PHY_REG_BSR_ANDONE: u16 = 1 << 5;
+    pub const PHY_REG_BSR_10BASE_HD: u16 = 1 << 11;
+    pub const PHY_REG_BSR_10BASE_FD: u16 = 1 << 12;
+    pub const PHY_REG_BSR_100BASE_HD: u16 = 1 << 13;
+    pub const PHY_REG_BSR_100BASE_FD: u16 = 1 << 14;
+
+    // Same layout in the advertisement and link partner ability registers
+    pub const PHY_REG_AN_SELECTOR_MASK: u16 = 0x1f;
+    pub const PHY_REG_AN_SELECTOR_802_3: u16 = 0x01;
+    pub const PHY_REG_AN_10BASE_HD: u16 = 1 << 5;
+    pub const PHY_REG_AN_10BASE_FD: u16 = 1 << 6;
+    pub const PHY_REG_AN_100BASE_HD: u16 = 1 << 7;
+    pub const PHY_REG_AN_100BASE_FD: u16 = 1 << 8;
+    pub const PHY_REG_AN_PAUSE: u16 = 1 << 10;
+    pub const PHY_REG_AN_ASYM_PAUSE: u16 = 1 << 11;
+}
+use self::phy_consts::*;
+
+/// Abilities advertised for autonegotiation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Abilities {
+    pub base10_half_duplex: bool,
+    pub base10_full_duplex: bool,
+    pub base100_half_duplex: bool,
+    pub base100_full_duplex: bool,
+    pub pause: bool,
+    pub asymmetric_pause: bool,
+}
+
+impl Abilities {
+    /// Decodes the advertisement or the link partner ability register.
+    pub fn from_register(reg: u16) -> Self {
+        Self {
+            base10_half_duplex: reg & PHY_REG_AN_10BASE_HD != 0,
+            base10_full_duplex: reg & PHY_REG_AN_10BASE_FD != 0,
+            base100_half_duplex: reg & PHY_REG_AN_100BASE_HD != 0,
+            base100_full_duplex: reg & PHY_REG_AN_100BASE_FD != 0,
+            pause: reg & PHY_REG_AN_PAUSE != 0,
+            asymmetric_pause: reg & PHY_REG_AN_ASYM_PAUSE != 0,
+        }
+    }
+
+    /// Encodes the advertisement register.
+    pub fn to_register(&self) -> u16 {
+        let mut reg = PHY_REG_AN_SELECTOR_802_3;
+        for &(ability, bit) in [
+            (self.base10_half_duplex, PHY_REG_AN_10BASE_HD),
+            (self.base10_full_duplex, PHY_REG_AN_10BASE_FD),
+            (self.base100_half_duplex, PHY_REG_AN_100BASE_HD),
+            (self.base100_full_duplex, PHY_REG_AN_100BASE_FD),
+            (self.pause, PHY_REG_AN_PAUSE),
+            (self.asymmetric_pause, PHY_REG_AN_ASYM_PAUSE),
+        ]
+        .iter()
+        {
+            if ability {
+                reg |= bit;
+            }
+        }
+        reg
+    }
+
+    /// Decodes the modes supported by the PHY from its status register.
+    fn from_status(bsr: u16) -> Self {
+        Self {
+            base10_half_duplex: bsr & PHY_REG_BSR_10BASE_HD != 0,
+            base10_full_duplex: bsr & PHY_REG_BSR_10BASE_FD != 0,
+            base100_half_duplex: bsr & PHY_REG_BSR_100BASE_HD != 0,
+            base100_full_duplex: bsr & PHY_REG_BSR_100BASE_FD != 0,
+            pause: false,
+            asymmetric_pause: false,
+        }
+    }
+
+    /// Returns the best mode advertised by both `self` and `other`, if any.
+    pub fn best_common_mode(&self, other: &Self) -> Option<LinkMode> {
+        let mode = |speed, duplex| Some(LinkMode { speed, duplex });
+        if self.base100_full_duplex && other.base100_full_duplex {
+            mode(Speed::Mbps100, Duplex::Full)
+        } else if self.base100_half_duplex && other.base100_half_duplex {
+            mode(Speed::Mbps100, Duplex::Half)
+        } else if self.base10_full_duplex && other.base10_full_duplex {
+            mode(Speed::Mbps10, Duplex::Full)
+        } else if self.base10_half_duplex && other.base10_half_duplex {
+            mode(Speed::Mbps10, Duplex::Half)
+        } else {
+            None
+        }
+    }
+}
+
+/// Resolves the mode of the link from the registers of the PHY, or returns `None` if it is down.
+fn link_mode(bcr: u16, bsr: u16, antx: u16, anrx: u16) -> Option<LinkMode> {
+    if bsr & PHY_REG_BSR_UP == 0 {
+        return None;
+    }
+
+    if bcr & PHY_REG_BCR_AN == 0 {
+        // Forced mode
+        return Some(LinkMode {
+            speed: if bcr & PHY_REG_BCR_100M != 0 {
+                Speed::Mbps100
+            } else {
+                Speed::Mbps10
+            },
+            duplex: if bcr & PHY_REG_BCR_FD != 0 {
+                Duplex::Full
+            } else {
+                Duplex::Half
+            },
+        });
+    }
+
+    if bsr & PHY_REG_BSR_ANDONE == 0 {
+        return None;
+    }
+    Abilities::from_register(antx).best_common_mode(&Abilities::from_register(anrx))
+}
+
+/// Generic IEEE 802.3 clause 22 Ethernet PHY
+pub struct GenericPHY;
+
+unsafe impl PHY for GenericPHY {
+    /// Reset PHY and wait for it to come out of reset.
+    fn phy_reset<S: StationManagement>(sm: &mut S) {
+        sm.smi_write(PHY_REG_BCR, PHY_REG_BCR_RESET);
+        while sm.smi_read(PHY_REG_BCR) & PHY_REG_BCR_RESET == PHY_REG_BCR_RESET {}
+    }
+
+    /// PHY initialisation: advertises all the modes the PHY supports, with pause frames.
+    fn phy_init<S: StationManagement>(sm: &mut S) {
+        let bsr = sm.smi_read(PHY_REG_BSR);
+        let abilities = Abilities {
+            pause: true,
+            asymmetric_pause: true,
+            ..Abilities::from_status(bsr)
+        };
+        Self::advertise(sm, &abilities);
+    }
+
+    fn poll_link<S: StationManagement>(sm: &mut S) -> bool {
+        Self::poll_link_mode(sm).is_some()
+    }
+
+    fn poll_link_mode<S: StationManagement>(sm: &mut S) -> Option<LinkMode> {
+        // The link status is latched low: the first read reports whether the link went down since
+        // the last one.
+        sm.smi_read(PHY_REG_BSR);
+        let bsr = sm.smi_read(PHY_REG_BSR);
+        if bsr & PHY_REG_BSR_UP == 0 {
+            return None;
+        }
+
+        let bcr = sm.smi_read(PHY_REG_BCR);
+        let antx = sm.smi_read(PHY_REG_ANTX);
+        let anrx = sm.smi_read(PHY_REG_ANRX);
+        link_mode(bcr, bsr, antx, anrx)
+    }
+}
+
+/// Public functions for a generic PHY
+impl GenericPHY {
+    /// Advertises `abilities`, and restarts autonegotiation.
+    pub fn advertise<S: StationManagement>(sm: &mut S, abilities: &Abilities) {
+        sm.smi_write(PHY_REG_ANTX, abilities.to_register());
+        Self::restart_autonegotiation(sm);
+    }
+
+    /// Enables and restarts autonegotiation. The link goes down until it completes.
+    pub fn restart_autonegotiation<S: StationManagement>(sm: &mut S) {
+        let bcr = sm.smi_read(PHY_REG_BCR);
+        sm.smi_write(
+            PHY_REG_BCR,
+            (bcr | PHY_REG_BCR_AN | PHY_REG_BCR_ANRST)
+                & !(PHY_REG_BCR_POWERDN | PHY_REG_BCR_ISOLATE),
+        );
+    }
+
+    /// Disables autonegotiation, and forces the link into `mode`.
+    pub fn force_link_mode<S: StationManagement>(sm: &mut S, mode: LinkMode) {
+        let mut bcr =
+            sm.smi_read(PHY_REG_BCR) & !(PHY_REG_BCR_AN | PHY_REG_BCR_100M | PHY_REG_BCR_FD);
+        if mode.speed == Speed::Mbps100 {
+            bcr |= PHY_REG_BCR_100M;
+        }
+        if mode.duplex == Duplex::Full {
+            bcr |= PHY_REG_BCR_FD;
+        }
+        sm.smi_write(PHY_REG_BCR, bcr);
+    }
+
+    /// Returns the abilities advertised by the link partner, or `None` if autonegotiation has
+    /// not completed.
+    pub fn link_partner_abilities<S: StationManagement>(sm: &mut S) -> Option<Abilities> {
+        if sm.smi_read(PHY_REG_BSR) & PHY_REG_BSR_ANDONE == 0 {
+            return None;
+        }
+        Some(Abilities::from_register(sm.smi_read(PHY_REG_ANRX)))
+    }
+
+    /// Returns the identifier of the PHY: the OUI of its manufacturer, its model and revision
+    /// numbers.
+    pub fn identifier<S: StationManagement>(sm: &mut S) -> u32 {
+        (sm.smi_read(PHY_REG_ID1) as u32) << 16 | sm.smi_read(PHY_REG_ID2) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PHY registers, with the reset and restart bits clearing themselves.
+    struct MockSmi {
+        regs: [u16; 32],
+        writes: usize,
+    }
+
+    impl MockSmi {
+        fn new(bsr: u16, antx: u16, anrx: u16) -> Self {
+            let mut regs = [0; 32];
+            regs[PHY_REG_BCR as usize] = PHY_REG_BCR_AN;
+            regs[PHY_REG_BSR as usize] = bsr;
+            regs[PHY_REG_ANTX as usize] = antx;
+            regs[PHY_REG_ANRX as usize] = anrx;
+            Self { regs, writes: 0 }
+        }
+    }
+
+    unsafe impl StationManagement for MockSmi {
+        fn smi_read(&mut self, reg: u8) -> u16 {
+            self.regs[reg as usize]
+        }
+
+        fn smi_write(&mut self, reg: u8, val: u16) {
+            self.writes += 1;
+            self.regs[reg as usize] = match reg {
+                PHY_REG_BCR => val & !(PHY_REG_BCR_RESET | PHY_REG_BCR_ANRST),
+                _ => val,
+            };
+        }
+    }
+
+    const ALL_MODES_BSR: u16 = PHY_REG_BSR_10BASE_HD
+        | PHY_REG_BSR_10BASE_FD
+        | PHY_REG_BSR_100BASE_HD
+        | PHY_REG_BSR_100BASE_FD;
+    const ALL_MODES_AN: u16 = PHY_REG_AN_SELECTOR_802_3
+        | PHY_REG_AN_10BASE_HD
+        | PHY_REG_AN_10BASE_FD
+        | PHY_REG_AN_100BASE_HD
+        | PHY_REG_AN_100BASE_FD;
+    const UP: u16 = PHY_REG_BSR_UP | PHY_REG_BSR_ANDONE;
+
+    fn mode(speed: Speed, duplex: Duplex) -> Option<LinkMode> {
+        Some(LinkMode { speed, duplex })
+    }
+
+    #[test]
+    fn abilities_round_trip() {
+        let abilities = Abilities {
+            base10_full_duplex: true,
+            base100_full_duplex: true,
+            pause: true,
+            ..Abilities::default()
+        };
+        let reg = abilities.to_register();
+        assert_eq!(reg & PHY_REG_AN_SELECTOR_MASK, PHY_REG_AN_SELECTOR_802_3);
+        assert_eq!(Abilities::from_register(reg), abilities);
+    }
+
+    #[test]
+    fn init_advertises_supported_modes() {
+        let mut sm = MockSmi::new(PHY_REG_BSR_10BASE_FD | PHY_REG_BSR_100BASE_FD, 0, 0);
+        GenericPHY::phy_reset(&mut sm);
+        GenericPHY::phy_init(&mut sm);
+
+        let advertised = Abilities::from_register(sm.regs[PHY_REG_ANTX as usize]);
+        assert_eq!(
+            advertised,
+            Abilities {
+                base10_full_duplex: true,
+                base100_full_duplex: true,
+                pause: true,
+                asymmetric_pause: true,
+                ..Abilities::default()
+            }
+        );
+        assert_ne!(sm.regs[PHY_REG_BCR as usize] & PHY_REG_BCR_AN, 0);
+    }
+
+    #[test]
+    fn resolves_best_common_mode() {
+        let mut sm = MockSmi::new(UP | ALL_MODES_BSR, ALL_MODES_AN, ALL_MODES_AN);
+        assert_eq!(
+            GenericPHY::poll_link_mode(&mut sm),
+            mode(Speed::Mbps100, Duplex::Full)
+        );
+
+        let partner = PHY_REG_AN_SELECTOR_802_3 | PHY_REG_AN_10BASE_FD | PHY_REG_AN_100BASE_HD;
+        sm.regs[PHY_REG_ANRX as usize] = partner;
+        assert_eq!(
+            GenericPHY::poll_link_mode(&mut sm),
+            mode(Speed::Mbps100, Duplex::Half)
+        );
+
+        sm.regs[PHY_REG_ANTX as usize] = PHY_REG_AN_SELECTOR_802_3 | PHY_REG_AN_10BASE_FD;
+        assert_eq!(
+            GenericPHY::poll_link_mode(&mut sm),
+            mode(Speed::Mbps10, Duplex::Full)
+        );
+        assert!(GenericPHY::poll_link(&mut sm));
+    }
+
+    #[test]
+    fn no_link_without_common_mode() {
+        let mut sm = MockSmi::new(
+            UP,
+            PHY_REG_AN_SELECTOR_802_3 | PHY_REG_AN_100BASE_FD,
+            PHY_REG_AN_SELECTOR_802_3 | PHY_REG_AN_10BASE_HD,
+        );
+        assert_eq!(GenericPHY::poll_link_mode(&mut sm), None);
+        assert!(!GenericPHY::poll_link(&mut sm));
+    }
+
+    #[test]
+    fn no_link_until_autonegotiation_completes() {
+        let mut sm = MockSmi::new(PHY_REG_BSR_UP, ALL_MODES_AN, ALL_MODES_AN);
+        assert_eq!(GenericPHY::poll_link_mode(&mut sm), None);
+        assert_eq!(GenericPHY::link_partner_abilities(&mut sm), None);
+
+        sm.regs[PHY_REG_BSR as usize] |= PHY_REG_BSR_ANDONE;
+        assert_eq!(
+            GenericPHY::link_partner_abilities(&mut sm),
+            Some(Abilities::from_register(ALL_MODES_AN))
+        );
+
+        sm.regs[PHY_REG_BSR as usize] &= !PHY_REG_BSR_UP;
+        assert_eq!(GenericPHY::poll_link_mode(&mut sm), None);
+    }
+
+    #[test]
+    fn forced_mode() {
+        let mut sm = MockSmi::new(PHY_REG_BSR_UP, ALL_MODES_AN, 0);
+        GenericPHY::force_link_mode(
+            &mut sm,
+            LinkMode {
+                speed: Speed::Mbps10,
+                duplex: Duplex::Half,
+            },
+        );
+        assert_eq!(
+            GenericPHY::poll_link_mode(&mut sm),
+            mode(Speed::Mbps10, Duplex::Half)
+        );
+
+        GenericPHY::force_link_mode(
+            &mut sm,
+            LinkMode {
+                speed: Speed::Mbps100,
+                duplex: Duplex::Full,
+            },
+        );
+        assert_eq!(
+            GenericPHY::poll_link_mode(&mut sm),
+            mode(Speed::Mbps100, Duplex::Full)
+        );
+
+        GenericPHY::restart_autonegotiation(&mut sm);
+        assert_eq!(GenericPHY::poll_link_mode(&mut sm), None);
+        assert_eq!(sm.writes, 3);
+    }
+
+    #[test]
+    fn identifier() {
+        let mut sm = MockSmi::new(0, 0, 0);
+        sm.regs[PHY_REG_ID1 as usize] = 0x0007;
+        sm.regs[PHY_REG_ID2 as usize] = 0xc0f1;
+        assert_eq!(GenericPHY::identifier(&mut sm), 0x0007_c0f1);
+    }
+}
@@ -0,0 +1,67 @@
+//! Ethernet PHY interface, independent of the MAC
+
+pub mod generic_phy;
+
+/// Station Management Interface (SMI) on an ethernet PHY
+///
+/// # Safety
+///
+/// The methods cannot move out of self
+pub unsafe trait StationManagement {
+    /// Read a register over SMI.
+    fn smi_read(&mut self, reg: u8) -> u16;
+    /// Write a register over SMI.
+    fn smi_write(&mut self, reg: u8, val: u16);
+}
+
+/// Traits for an Ethernet PHY
+///
+/// # Safety
+///
+/// The methods cannot move S
+pub unsafe trait PHY {
+    /// Reset PHY and wait for it to come out of reset.
+    fn phy_reset<S: StationManagement>(sm: &mut S);
+    /// PHY initialisation.
+    fn phy_init<S: StationManagement>(sm: &mut S);
+    /// Poll link to see if it is up and FD with 100Mbps
+    fn poll_link<S: StationManagement>(sm: &mut S) -> bool;
+    /// Poll link to see if it is up, and in which mode.
+    ///
+    /// The MAC is reconfigured for the mode returned. By default, a link accepted by
+    /// [`Self::poll_link`] is 100Mbps full duplex.
+    fn poll_link_mode<S: StationManagement>(sm: &mut S) -> Option<LinkMode> {
+        if Self::poll_link(sm) {
+            Some(LinkMode {
+                speed: Speed::Mbps100,
+                duplex: Duplex::Full,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Speed of an Ethernet link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Speed {
+    Mbps10,
+    Mbps100,
+}
+
+/// Duplex mode of an Ethernet link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Duplex {
+    Half,
+    Full,
+}
+
+/// Mode an Ethernet link is up in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LinkMode {
+    pub speed: Speed,
+    pub duplex: Duplex,
+}
@@ -0,0 +1,155 @@
+/// Bookkeeping of a ring of `N` descriptors shared with the DMA.
+///
+/// Descriptors are handed to the DMA at the head, and taken back at the tail once the DMA is done
+/// with them. The DMA walks the ring in the same order, so the descriptors it owns are always the
+/// `len` ones starting at the tail.
+pub(crate) struct Ring<const N: usize> {
+    tail: usize,
+    len: usize,
+}
+
+impl<const N: usize> Ring<N> {
+    pub const fn new() -> Self {
+        Self { tail: 0, len: 0 }
+    }
+
+    /// Forgets all the descriptors handed to the DMA, and restarts at the first one.
+    pub fn reset(&mut self) {
+        self.tail = 0;
+        self.len = 0;
+    }
+
+    /// Returns the number of descriptors handed to the DMA and not taken back yet.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Returns the index of the next descriptor to hand to the DMA, unless they all are.
+    pub fn head(&self) -> Option<usize> {
+        if self.is_full() {
+            None
+        } else {
+            Some((self.tail + self.len) % N)
+        }
+    }
+
+    /// Records that the descriptor returned by [`Self::head`] was handed to the DMA.
+    pub fn push(&mut self) {
+        assert!(!self.is_full());
+        self.len += 1;
+    }
+
+    /// Returns the index of the oldest descriptor handed to the DMA, unless there is none.
+    pub fn tail(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.tail)
+        }
+    }
+
+    /// Records that the descriptor returned by [`Self::tail`] was taken back from the DMA.
+    pub fn pop(&mut self) {
+        assert!(!self.is_empty());
+        self.tail = (self.tail + 1) % N;
+        self.len -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_and_drains_in_order() {
+        let mut ring = Ring::<3>::new();
+        assert_eq!(ring.tail(), None);
+
+        for i in 0..3 {
+            assert_eq!(ring.head(), Some(i));
+            ring.push();
+        }
+        assert!(ring.is_full());
+        assert_eq!(ring.head(), None);
+
+        for i in 0..3 {
+            assert_eq!(ring.tail(), Some(i));
+            ring.pop();
+        }
+        assert!(ring.is_empty());
+        assert_eq!(ring.tail(), None);
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut ring = Ring::<4>::new();
+        for _ in 0..3 {
+            ring.push();
+        }
+        ring.pop();
+        ring.pop();
+
+        // Descriptors 2, 3 and 0 are handed to the DMA.
+        ring.push();
+        ring.push();
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.tail(), Some(2));
+        assert_eq!(ring.head(), Some(1));
+
+        ring.pop();
+        ring.pop();
+        assert_eq!(ring.tail(), Some(0));
+        assert_eq!(ring.head(), Some(1));
+    }
+
+    #[test]
+    fn head_meets_tail_when_empty() {
+        // Once every descriptor was taken back, the DMA is suspended on the one after the last
+        // it used, which is where the next one is handed to it.
+        let mut ring = Ring::<2>::new();
+        ring.push();
+        ring.push();
+        ring.pop();
+        ring.pop();
+        assert_eq!(ring.head(), Some(0));
+
+        ring.push();
+        ring.pop();
+        assert_eq!(ring.head(), Some(1));
+        assert_eq!(ring.tail(), None);
+    }
+
+    #[test]
+    fn reset() {
+        let mut ring = Ring::<2>::new();
+        ring.push();
+        ring.pop();
+        ring.push();
+        ring.reset();
+        assert!(ring.is_empty());
+        assert_eq!(ring.head(), Some(0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn push_when_full() {
+        let mut ring = Ring::<1>::new();
+        ring.push();
+        ring.push();
+    }
+
+    #[test]
+    #[should_panic]
+    fn pop_when_empty() {
+        let mut ring = Ring::<1>::new();
+        ring.pop();
+    }
+}
@@ -0,0 +1,246 @@
+use core::sync::atomic::{compiler_fence, fence, Ordering};
+
+use embassy_net::{Packet, PacketBox, PacketBoxExt, PacketBuf};
+use stm32_metapac::eth::vals::{DmaomrSr, Rpd};
+use vcell::VolatileCell;
+
+use super::ring::Ring;
+use crate::pac::ETH;
+
+mod rx_consts {
+    /// Owned by DMA engine
+    pub const RXDESC_0_OWN: u32 = 1 << 31;
+    /// First descriptor
+    pub const RXDESC_0_FS: u32 = 1 << 9;
+    /// Last descriptor
+    pub const RXDESC_0_LS: u32 = 1 << 8;
+    /// Error summary
+    pub const RXDESC_0_ES: u32 = 1 << 15;
+    /// Frame length
+    pub const RXDESC_0_FL_MASK: u32 = 0x3FFF;
+    pub const RXDESC_0_FL_SHIFT: usize = 16;
+
+    pub const RXDESC_1_RBS_MASK: u32 = 0x1fff;
+    /// Second address chained
+    pub const RXDESC_1_RCH: u32 = 1 << 14;
+    /// End Of Ring
+    pub const RXDESC_1_RER: u32 = 1 << 15;
+}
+
+use rx_consts::*;
+
+/// Receive Descriptor representation
+///
+/// * rdes0: OWN and Status
+/// * rdes1: allocated buffer length
+/// * rdes2: data buffer address
+/// * rdes3: next descriptor address
+#[repr(C)]
+struct RDes {
+    rdes0: VolatileCell<u32>,
+    rdes1: VolatileCell<u32>,
+    rdes2: VolatileCell<u32>,
+    rdes3: VolatileCell<u32>,
+}
+
+impl RDes {
+    pub const fn new() -> Self {
+        Self {
+            rdes0: VolatileCell::new(0),
+            rdes1: VolatileCell::new(0),
+            rdes2: VolatileCell::new(0),
+            rdes3: VolatileCell::new(0),
+        }
+    }
+
+    /// Return true if this RDes is acceptable to us
+    #[inline(always)]
+    pub fn valid(&self) -> bool {
+        // Write-back descriptor is valid if:
+        //
+        // Contains first buffer of packet AND contains last buf of
+        // packet AND no errors
+        (self.rdes0.get() & (RXDESC_0_ES | RXDESC_0_FS | RXDESC_0_LS))
+            == (RXDESC_0_FS | RXDESC_0_LS)
+    }
+
+    /// Return true if this RDes is not currently owned by the DMA
+    #[inline(always)]
+    pub fn available(&self) -> bool {
+        self.rdes0.get() & RXDESC_0_OWN == 0 // Owned by us
+    }
+
+    /// Configures the reception buffer address and length and passed descriptor ownership to the DMA
+    #[inline(always)]
+    pub fn set_ready(&mut self, buf_addr: u32, buf_len: usize) {
+        self.rdes1
+            .set((self.rdes1.get() & !RXDESC_1_RBS_MASK) | (buf_len as u32) & RXDESC_1_RBS_MASK);
+        self.rdes2.set(buf_addr);
+
+        // "Preceding reads and writes cannot be moved past subsequent writes."
+        fence(Ordering::Release);
+
+        compiler_fence(Ordering::Release);
+
+        // Also drops the status of the previous reception.
+        self.rdes0.set(RXDESC_0_OWN);
+
+        // Used to flush the store buffer as fast as possible to make the buffer available for the
+        // DMA.
+        fence(Ordering::SeqCst);
+    }
+
+    // points to next descriptor (RCH)
+    #[inline(always)]
+    fn set_buffer2(&mut self, buffer: *const u8) {
+        self.rdes3.set(buffer as u32);
+    }
+
+    #[inline(always)]
+    fn set_end_of_ring(&mut self) {
+        self.rdes1.set(self.rdes1.get() | RXDESC_1_RER);
+    }
+
+    #[inline(always)]
+    fn packet_len(&self) -> usize {
+        ((self.rdes0.get() >> RXDESC_0_FL_SHIFT) & RXDESC_0_FL_MASK) as usize
+    }
+
+    pub fn setup(&mut self, next: Option<&Self>) {
+        // Defer this initialization to this function, so we can have `RingEntry` on bss.
+        self.rdes0.set(0);
+        self.rdes1.set(RXDESC_1_RCH);
+
+        match next {
+            Some(next) => self.set_buffer2(next as *const _ as *const u8),
+            None => {
+                self.set_buffer2(0 as *const u8);
+                self.set_end_of_ring();
+            }
+        }
+    }
+}
+
+/// Rx ring of descriptors and packets
+///
+/// Every descriptor handed to the DMA holds an empty packet buffer. Received packets are taken
+/// from the tail of the ring, and their descriptors are handed back to the DMA at its head with a
+/// new buffer, when one can be allocated. The DMA suspends when it reaches a descriptor it does
+/// not own, until it is polled again.
+pub(crate) struct RDesRing<const N: usize> {
+    descriptors: [RDes; N],
+    buffers: [Option<PacketBox>; N],
+    ring: Ring<N>,
+}
+
+impl<const N: usize> RDesRing<N> {
+    pub const fn new() -> Self {
+        const RDES: RDes = RDes::new();
+        const BUFFERS: Option<PacketBox> = None;
+
+        Self {
+            descriptors: [RDES; N],
+            buffers: [BUFFERS; N],
+            ring: Ring::new(),
+        }
+    }
+
+    pub(crate) fn init(&mut self) {
+        assert!(N > 0);
+
+        {
+            let mut previous: Option<&mut RDes> = None;
+            for entry in self.descriptors.iter_mut() {
+                if let Some(prev) = &mut previous {
+                    prev.setup(Some(entry));
+                }
+                previous = Some(entry);
+            }
+
+            if let Some(entry) = &mut previous {
+                entry.setup(None);
+            }
+        }
+        for buf in self.buffers.iter_mut() {
+            buf.take();
+        }
+        self.ring.reset();
+
+        self.refill();
+        if self.ring.is_empty() {
+            panic!("Could not allocate at least one buffer for Ethernet receiving");
+        }
+
+        // Register rxdescriptor start
+        // NOTE (unsafe) Used for atomic writes
+        unsafe {
+            ETH.ethernet_dma()
+                .dmardlar()
+                .write(|w| w.0 = &self.descriptors as *const _ as u32);
+        };
+        // We already have fences in `set_ready`, which is called in `refill`
+
+        // Start receive
+        unsafe {
+            ETH.ethernet_dma()
+                .dmaomr()
+                .modify(|w| w.set_sr(DmaomrSr::STARTED))
+        };
+
+        self.demand_poll();
+    }
+
+    fn demand_poll(&self) {
+        unsafe { ETH.ethernet_dma().dmarpdr().write(|w| w.set_rpd(Rpd::POLL)) };
+    }
+
+    /// Hands the free descriptors back to the DMA, as long as buffers can be allocated.
+    fn refill(&mut self) {
+        let mut refilled = false;
+        while let Some(index) = self.ring.head() {
+            let pkt = match PacketBox::new(Packet::new()) {
+                Some(pkt) => pkt,
+                None => break,
+            };
+            self.descriptors[index].set_ready(pkt.as_ptr() as u32, pkt.len());
+            self.buffers[index] = Some(pkt);
+            self.ring.push();
+            refilled = true;
+        }
+
+        if refilled {
+            // The DMA may be suspended on one of these descriptors.
+            self.demand_poll();
+        }
+    }
+
+    pub(crate) fn pop_packet(&mut self) -> Option<PacketBuf> {
+        // Not sure if the contents of the write buffer on the M7 can affects reads, so we are using
+        // a DMB here just in case, it also serves as a hint to the compiler that we're syncing the
+        // buffer (I think .-.)
+        fence(Ordering::SeqCst);
+
+        let mut pkt = None;
+        while let Some(index) = self.ring.tail() {
+            let descriptor = &self.descriptors[index];
+            if !descriptor.available() {
+                break;
+            }
+
+            let valid = descriptor.valid();
+            let len = descriptor.packet_len();
+            let buf = self.buffers[index].take();
+            self.ring.pop();
+
+            // Frames received with errors are dropped.
+            if valid {
+                assert!(buf.is_some());
+                pkt = buf.map(|p| p.slice(0..len));
+                break;
+            }
+        }
+
+        self.refill();
+        pkt
+    }
+}
@@ -4,6 +4,7 @@ use embassy_net::PacketBuf;
 use stm32_metapac::eth::vals::St;
 use vcell::VolatileCell;
 
+use super::ring::Ring;
 use crate::pac::ETH;
 
 #[non_exhaustive]
@@ -15,7 +16,7 @@ pub enum Error {
     TransmissionError,
 }
 
-/// Transmit and Receive Descriptor fields
+/// Transmit Descriptor fields
 #[allow(dead_code)]
 mod tx_consts {
     pub const TXDESC_0_OWN: u32 = 1 << 31;
@@ -30,16 +31,18 @@ mod tx_consts {
     pub const TXDESC_0_TCH: u32 = 1 << 20;
     // Error status
     pub const TXDESC_0_ES: u32 = 1 << 15;
+    // Status written back by the DMA
+    pub const TXDESC_0_STATUS_MASK: u32 = 0x3_ffff;
 
     // Transmit buffer size
     pub const TXDESC_1_TBS_SHIFT: usize = 0;
-    pub const TXDESC_1_TBS_MASK: u32 = 0x0fff << TXDESC_1_TBS_SHIFT;
+    pub const TXDESC_1_TBS_MASK: u32 = 0x1fff << TXDESC_1_TBS_SHIFT;
 }
 use tx_consts::*;
 
 /// Transmit Descriptor representation
 ///
-/// * tdes0: control
+/// * tdes0: control and status
 /// * tdes1: buffer lengths
 /// * tdes2: data buffer address
 /// * tdes3: next descriptor address
@@ -66,13 +69,14 @@ impl TDes {
         (self.tdes0.get() & TXDESC_0_OWN) == 0
     }
 
-    /// Pass ownership to the DMA engine
+    /// Pass ownership to the DMA engine, dropping the status of the previous transmission
     fn set_owned(&mut self) {
         // "Preceding reads and writes cannot be moved past subsequent writes."
         fence(Ordering::Release);
 
         compiler_fence(Ordering::Release);
-        self.tdes0.set(self.tdes0.get() | TXDESC_0_OWN);
+        self.tdes0
+            .set((self.tdes0.get() & !TXDESC_0_STATUS_MASK) | TXDESC_0_OWN);
 
         // Used to flush the store buffer as fast as possible to make the buffer available for the
         // DMA.
@@ -88,7 +92,7 @@ impl TDes {
             .set((self.tdes1.get() & !TXDESC_1_TBS_MASK) | ((len as u32) << TXDESC_1_TBS_SHIFT));
     }
 
-    // points to next descriptor (RCH)
+    // points to next descriptor (TCH)
     fn set_buffer2(&mut self, buffer: *const u8) {
         self.tdes3.set(buffer as u32);
     }
@@ -112,10 +116,14 @@ impl TDes {
     }
 }
 
+/// Tx ring of descriptors and packets
+///
+/// Packets are queued at the head of the ring, and their buffers are released from its tail once
+/// the DMA has sent them, so that several packets can be in flight at once.
 pub(crate) struct TDesRing<const N: usize> {
     descriptors: [TDes; N],
     buffers: [Option<PacketBuf>; N],
-    next_entry: usize,
+    ring: Ring<N>,
 }
 
 impl<const N: usize> TDesRing<N> {
@@ -126,7 +134,7 @@ impl<const N: usize> TDesRing<N> {
         Self {
             descriptors: [TDES; N],
             buffers: [BUFFERS; N],
-            next_entry: 0,
+            ring: Ring::new(),
         }
     }
 
@@ -151,7 +159,10 @@ impl<const N: usize> TDesRing<N> {
                 entry.setup(None);
             }
         }
-        self.next_entry = 0;
+        for buf in self.buffers.iter_mut() {
+            buf.take();
+        }
+        self.ring.reset();
 
         // Register txdescriptor start
         // NOTE (unsafe) Used for atomic writes
@@ -161,10 +172,6 @@ impl<const N: usize> TDesRing<N> {
                 .write(|w| w.0 = &self.descriptors as *const _ as u32);
         }
 
-        // "Preceding reads and writes cannot be moved past subsequent writes."
-        #[cfg(feature = "fence")]
-        fence(Ordering::Release);
-
         // We don't need a compiler fence here because all interactions with `Descriptor` are
         // volatiles
 
@@ -178,15 +185,12 @@ impl<const N: usize> TDesRing<N> {
 
     /// Return true if a TDes is available for use
     pub(crate) fn available(&self) -> bool {
-        self.descriptors[self.next_entry].available()
+        !self.ring.is_full()
     }
 
     pub(crate) fn transmit(&mut self, pkt: PacketBuf) -> Result<(), Error> {
-        if !self.available() {
-            return Err(Error::NoBufferAvailable);
-        }
-
-        let descriptor = &mut self.descriptors[self.next_entry];
+        let index = self.ring.head().ok_or(Error::NoBufferAvailable)?;
+        let descriptor = &mut self.descriptors[index];
 
         let pkt_len = pkt.len();
         let address = pkt.as_ptr() as *const u8;
@@ -194,45 +198,40 @@ impl<const N: usize> TDesRing<N> {
         descriptor.set_buffer1(address);
         descriptor.set_buffer1_len(pkt_len);
 
-        self.buffers[self.next_entry].replace(pkt);
+        self.buffers[index].replace(pkt);
 
         descriptor.set_owned();
+        self.ring.push();
 
-        // Ensure changes to the descriptor are committed before DMA engine sees tail pointer store.
-        // This will generate an DMB instruction.
-        // "Preceding reads and writes cannot be moved past subsequent writes."
-        fence(Ordering::Release);
-
-        // Move the tail pointer (TPR) to the next descriptor
-        self.next_entry = (self.next_entry + 1) % N;
-
-        // Request the DMA engine to poll the latest tx descriptor
-        unsafe { ETH.ethernet_dma().dmatpdr().modify(|w| w.0 = 1) }
+        // Request the DMA engine to poll the latest tx descriptor, in case it is suspended on it
+        unsafe { ETH.ethernet_dma().dmatpdr().write(|w| w.0 = 1) }
         Ok(())
     }
 
+    /// Releases the buffers of the packets sent since the last call.
+    ///
+    /// Returns an error if any of them could not be sent.
     pub(crate) fn on_interrupt(&mut self) -> Result<(), Error> {
-        let previous = (self.next_entry + N - 1) % N;
-        let td = &self.descriptors[previous];
-
         // DMB to ensure that we are reading an updated value, probably not needed at the hardware
         // level, but this is also a hint to the compiler that we're syncing on the buffer.
         fence(Ordering::SeqCst);
 
-        let tdes0 = td.tdes0.get();
-
-        if tdes0 & TXDESC_0_OWN != 0 {
-            // Transmission isn't done yet, probably a receive interrupt that fired this
-            return Ok(());
-        }
+        let mut result = Ok(());
+        while let Some(index) = self.ring.tail() {
+            let tdes0 = self.descriptors[index].tdes0.get();
+            if tdes0 & TXDESC_0_OWN != 0 {
+                // Transmission isn't done yet
+                break;
+            }
 
-        // Release the buffer
-        self.buffers[previous].take();
+            // Release the buffer
+            self.buffers[index].take();
+            self.ring.pop();
 
-        if tdes0 & TXDESC_0_ES != 0 {
-            Err(Error::TransmissionError)
-        } else {
-            Ok(())
+            if tdes0 & TXDESC_0_ES != 0 {
+                result = Err(Error::TransmissionError);
+            }
         }
+        result
     }
 }
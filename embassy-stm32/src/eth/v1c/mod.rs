@@ -17,11 +17,17 @@ use crate::pac::gpio::vals::Ospeedr;
 use crate::pac::{ETH, RCC, SYSCFG};
 use crate::peripherals;
 
+// The descriptors are the same as on v1.
+#[path = "../v1/descriptors.rs"]
 mod descriptors;
+#[path = "../v1/ring.rs"]
+mod ring;
+#[path = "../v1/rx_desc.rs"]
 mod rx_desc;
+#[path = "../v1/tx_desc.rs"]
 mod tx_desc;
 
-use super::{StationManagement, PHY};
+use super::{Duplex, LinkMode, Speed, StationManagement, PHY};
 use descriptors::DescriptorRing;
 use stm32_metapac::eth::vals::{
     Apcs, Cr, Dm, DmaomrSr, Fes, Ftf, Ifg, MbProgress, Mw, Pbl, Rsf, St, Tsf,
@@ -40,6 +46,7 @@ pub struct Ethernet<'d, P: PHY, const TX: usize, const RX: usize> {
     clock_range: Cr,
     phy_addr: u8,
     mac_addr: [u8; 6],
+    link_mode: LinkMode,
 }
 
 impl<'d, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, P, TX, RX> {
@@ -170,6 +177,10 @@ impl<'d, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, P, TX, RX> {
             clock_range,
             phy_addr,
             mac_addr,
+            link_mode: LinkMode {
+                speed: Speed::Mbps100,
+                duplex: Duplex::Full,
+            },
         };
 
         this.state.with(|s| {
@@ -202,6 +213,24 @@ impl<'d, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, P, TX, RX> {
 
         this
     }
+
+    /// Configures the MAC for the mode the link is up in.
+    fn set_link_mode(&mut self, mode: LinkMode) {
+        // NOTE(unsafe) This register isn't used in the interrupt and we have `&mut self`
+        unsafe {
+            ETH.ethernet_mac().maccr().modify(|w| {
+                w.set_fes(match mode.speed {
+                    Speed::Mbps10 => Fes::FES10,
+                    Speed::Mbps100 => Fes::FES100,
+                });
+                w.set_dm(match mode.duplex {
+                    Duplex::Half => Dm::HALFDUPLEX,
+                    Duplex::Full => Dm::FULLDUPLEX,
+                });
+            });
+        }
+        self.link_mode = mode;
+    }
 }
 
 unsafe impl<'d, P: PHY, const TX: usize, const RX: usize> StationManagement
@@ -267,10 +296,14 @@ impl<'d, P: PHY, const TX: usize, const RX: usize> Device for Ethernet<'d, P, TX
     }
 
     fn link_state(&mut self) -> LinkState {
-        if P::poll_link(self) {
-            LinkState::Up
-        } else {
-            LinkState::Down
+        match P::poll_link_mode(self) {
+            Some(mode) => {
+                if mode != self.link_mode {
+                    self.set_link_mode(mode);
+                }
+                LinkState::Up
+            }
+            None => LinkState::Down,
         }
     }
 
@@ -330,8 +363,9 @@ impl<'d, const TX: usize, const RX: usize> PeripheralState for Inner<'d, TX, RX>
     type Interrupt = crate::interrupt::ETH;
 
     fn on_interrupt(&mut self) {
-        unwrap!(self.desc_ring.tx.on_interrupt());
-        self.desc_ring.rx.on_interrupt();
+        if let Err(e) = self.desc_ring.tx.on_interrupt() {
+            warn!("ethernet transmission failed: {:?}", e);
+        }
 
         WAKER.wake();
 
@@ -339,7 +373,8 @@ impl<'d, const TX: usize, const RX: usize> PeripheralState for Inner<'d, TX, RX>
         unsafe {
             let dma = ETH.ethernet_dma();
 
-            dma.dmasr().modify(|w| {
+            // The flags are cleared by writing 1s.
+            dma.dmasr().write(|w| {
                 w.set_ts(true);
                 w.set_rs(true);
                 w.set_nis(true);
@@ -16,7 +16,7 @@ use crate::pac::{ETH, RCC, SYSCFG};
 use crate::peripherals;
 
 mod descriptors;
-use super::{StationManagement, PHY};
+use super::{Duplex, LinkMode, Speed, StationManagement, PHY};
 use descriptors::DescriptorRing;
 
 pub struct State<'d, const TX: usize, const RX: usize>(StateStorage<Inner<'d, TX, RX>>);
@@ -32,6 +32,7 @@ pub struct Ethernet<'d, P: PHY, const TX: usize, const RX: usize> {
     clock_range: u8,
     phy_addr: u8,
     mac_addr: [u8; 6],
+    link_mode: LinkMode,
 }
 
 impl<'d, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, P, TX, RX> {
@@ -159,6 +160,10 @@ impl<'d, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, P, TX, RX> {
             clock_range,
             phy_addr,
             mac_addr,
+            link_mode: LinkMode {
+                speed: Speed::Mbps100,
+                duplex: Duplex::Full,
+            },
         };
 
         this.state.with(|s| {
@@ -191,6 +196,18 @@ impl<'d, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, P, TX, RX> {
 
         this
     }
+
+    /// Configures the MAC for the mode the link is up in.
+    fn set_link_mode(&mut self, mode: LinkMode) {
+        // NOTE(unsafe) This register isn't used in the interrupt and we have `&mut self`
+        unsafe {
+            ETH.ethernet_mac().maccr().modify(|w| {
+                w.set_fes(mode.speed == Speed::Mbps100);
+                w.set_dm(mode.duplex == Duplex::Full);
+            });
+        }
+        self.link_mode = mode;
+    }
 }
 
 unsafe impl<'d, P: PHY, const TX: usize, const RX: usize> StationManagement
@@ -256,10 +273,14 @@ impl<'d, P: PHY, const TX: usize, const RX: usize> Device for Ethernet<'d, P, TX
     }
 
     fn link_state(&mut self) -> LinkState {
-        if P::poll_link(self) {
-            LinkState::Up
-        } else {
-            LinkState::Down
+        match P::poll_link_mode(self) {
+            Some(mode) => {
+                if mode != self.link_mode {
+                    self.set_link_mode(mode);
+                }
+                LinkState::Up
+            }
+            None => LinkState::Down,
         }
     }
 
@@ -48,6 +48,8 @@ pub unsafe fn on_irq() {
     let bits = EXTI.pr(0).read().0;
     #[cfg(any(exti_g0, exti_l5, exti_u5))]
     let bits = EXTI.rpr(0).read().0 | EXTI.fpr(0).read().0;
+    // The lines above the GPIO ones are internal lines handled by their peripheral's driver.
+    let bits = bits & ((1 << EXTI_COUNT) - 1);
 
     // Mask all the channels that fired.
     cpu_regs().imr(0).modify(|w| w.0 &= !bits);
@@ -67,6 +69,25 @@ pub unsafe fn on_irq() {
     }
 }
 
+/// Enables the rising edge interrupt of an internal (non GPIO) line, such as the RTC alarm.
+///
+/// On the families where the line is a direct line, only the interrupt mask is set.
+pub(crate) unsafe fn enable_internal_line(line: usize) {
+    cortex_m::interrupt::free(|_| {
+        EXTI.rtsr(0).modify(|w| w.set_line(line, true));
+        clear_internal_line(line);
+        cpu_regs().imr(0).modify(|w| w.set_line(line, true));
+    });
+}
+
+/// Clears the pending flag of an internal line.
+pub(crate) unsafe fn clear_internal_line(line: usize) {
+    #[cfg(not(any(exti_g0, exti_l5, exti_u5)))]
+    EXTI.pr(0).write(|w| w.set_line(line, true));
+    #[cfg(any(exti_g0, exti_l5, exti_u5))]
+    EXTI.rpr(0).write(|w| w.set_line(line, true));
+}
+
 struct BitIter(u32);
 
 impl Iterator for BitIter {
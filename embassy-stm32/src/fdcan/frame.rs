@@ -0,0 +1,342 @@
+use crate::can::{CanFrame, ExtendedId, Id, StandardId};
+
+/// Maximum data length of an FD frame.
+pub const MAX_DATA_LEN: usize = 64;
+
+// Message RAM element header bits, shared by TX buffer (T0/T1) and RX FIFO (R0/R1) elements.
+const H0_XTD: u32 = 1 << 30;
+const H0_RTR: u32 = 1 << 29;
+const H1_FDF: u32 = 1 << 21;
+const H1_BRS: u32 = 1 << 20;
+
+/// A classic CAN or CAN FD frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    id: Id,
+    remote: bool,
+    fd: bool,
+    brs: bool,
+    len: u8,
+    data: [u8; MAX_DATA_LEN],
+}
+
+impl Frame {
+    /// Creates a classic data frame, or returns `None` if `data` is longer than 8 bytes.
+    pub fn new_data(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        if data.len() > 8 {
+            return None;
+        }
+        Some(Self::new_inner(id.into(), false, false, data))
+    }
+
+    /// Creates an FD data frame, or returns `None` if `data` doesn't have a valid FD length
+    /// (0 to 8, 12, 16, 20, 24, 32, 48 or 64 bytes).
+    ///
+    /// If `brs` is set, the data phase is sent at the data bit rate.
+    pub fn new_fd(id: impl Into<Id>, data: &[u8], brs: bool) -> Option<Self> {
+        len_to_dlc(data.len())?;
+        Some(Self::new_inner(id.into(), true, brs, data))
+    }
+
+    /// Creates a remote frame, or returns `None` if `dlc` is larger than 8.
+    pub fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        if dlc > 8 {
+            return None;
+        }
+        Some(Self {
+            id: id.into(),
+            remote: true,
+            fd: false,
+            brs: false,
+            len: dlc as u8,
+            data: [0; MAX_DATA_LEN],
+        })
+    }
+
+    fn new_inner(id: Id, fd: bool, brs: bool, data: &[u8]) -> Self {
+        let mut buf = [0; MAX_DATA_LEN];
+        buf[..data.len()].copy_from_slice(data);
+        Self {
+            id,
+            remote: false,
+            fd,
+            brs,
+            len: data.len() as u8,
+            data: buf,
+        }
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn is_remote_frame(&self) -> bool {
+        self.remote
+    }
+
+    /// Returns `true` for FD frames.
+    pub fn is_fd_frame(&self) -> bool {
+        self.fd
+    }
+
+    /// Returns `true` if the data phase of this FD frame is sent at the data bit rate.
+    pub fn bit_rate_switching(&self) -> bool {
+        self.brs
+    }
+
+    /// Returns the data length code, which is the data length in bytes for classic frames.
+    pub fn dlc(&self) -> u8 {
+        if self.remote {
+            self.len
+        } else {
+            // `len` is always a valid length.
+            len_to_dlc(self.len as usize).unwrap()
+        }
+    }
+
+    /// Returns the data, which is empty for remote frames.
+    pub fn data(&self) -> &[u8] {
+        if self.remote {
+            &[]
+        } else {
+            &self.data[..self.len as usize]
+        }
+    }
+
+    /// Returns the first two words of the message RAM TX buffer element.
+    pub(crate) fn header(&self) -> [u32; 2] {
+        let mut h0 = match self.id {
+            Id::Standard(id) => (id.as_raw() as u32) << 18,
+            Id::Extended(id) => id.as_raw() | H0_XTD,
+        };
+        if self.remote {
+            h0 |= H0_RTR;
+        }
+
+        let mut h1 = (self.dlc() as u32) << 16;
+        if self.fd {
+            h1 |= H1_FDF;
+        }
+        if self.brs {
+            h1 |= H1_BRS;
+        }
+
+        [h0, h1]
+    }
+
+    /// Returns the data of the message RAM TX buffer element, rounded up to whole words.
+    pub(crate) fn data_words(&self) -> impl Iterator<Item = u32> + '_ {
+        let len = (self.data().len() + 3) / 4;
+        self.data[..len * 4]
+            .chunks(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Decodes a message RAM RX FIFO element, `read_word(n)` returning its word `n`.
+    pub(crate) fn from_element(mut read_word: impl FnMut(usize) -> u32) -> Self {
+        let h0 = read_word(0);
+        let h1 = read_word(1);
+
+        let id: Id = if h0 & H0_XTD != 0 {
+            ExtendedId::new(h0 & 0x1FFF_FFFF).unwrap().into()
+        } else {
+            StandardId::new(((h0 >> 18) & 0x7FF) as u16).unwrap().into()
+        };
+        let fd = h1 & H1_FDF != 0;
+        let dlc = ((h1 >> 16) & 0xF) as u8;
+
+        // Classic frames can have a DLC up to 15, meaning 8 bytes.
+        let len = if fd {
+            dlc_to_len(dlc)
+        } else {
+            dlc.min(8) as usize
+        };
+
+        if !fd && h0 & H0_RTR != 0 {
+            return Self {
+                id,
+                remote: true,
+                fd: false,
+                brs: false,
+                len: len as u8,
+                data: [0; MAX_DATA_LEN],
+            };
+        }
+
+        let mut data = [0; MAX_DATA_LEN];
+        for (i, chunk) in data[..(len + 3) / 4 * 4].chunks_mut(4).enumerate() {
+            chunk.copy_from_slice(&read_word(2 + i).to_le_bytes());
+        }
+
+        Self {
+            id,
+            remote: false,
+            fd,
+            brs: h1 & H1_BRS != 0,
+            len: len as u8,
+            data,
+        }
+    }
+}
+
+impl CanFrame for Frame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        Frame::new_data(id, data)
+    }
+
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        Frame::new_remote(id, dlc)
+    }
+
+    fn id(&self) -> Id {
+        Frame::id(self)
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        Frame::is_remote_frame(self)
+    }
+
+    fn dlc(&self) -> usize {
+        Frame::dlc(self) as usize
+    }
+
+    fn data(&self) -> &[u8] {
+        Frame::data(self)
+    }
+}
+
+/// Returns the data length of an FD frame with data length code `dlc`.
+pub fn dlc_to_len(dlc: u8) -> usize {
+    match dlc {
+        0..=8 => dlc as usize,
+        9 => 12,
+        10 => 16,
+        11 => 20,
+        12 => 24,
+        13 => 32,
+        14 => 48,
+        _ => 64,
+    }
+}
+
+/// Returns the data length code of an FD frame with `len` bytes of data, or `None` if `len`
+/// isn't a valid FD length.
+pub fn len_to_dlc(len: usize) -> Option<u8> {
+    match len {
+        0..=8 => Some(len as u8),
+        12 => Some(9),
+        16 => Some(10),
+        20 => Some(11),
+        24 => Some(12),
+        32 => Some(13),
+        48 => Some(14),
+        64 => Some(15),
+        _ => None,
+    }
+}
+
+/// Returns a standard filter element accepting the frames matching `id` on the bits set in
+/// `mask`, storing them in RX FIFO 0.
+pub(crate) fn standard_filter(id: StandardId, mask: StandardId) -> u32 {
+    // SFT = classic filter (2), SFEC = store in FIFO 0 (1).
+    (2 << 30) | (1 << 27) | ((id.as_raw() as u32) << 16) | mask.as_raw() as u32
+}
+
+/// Returns an extended filter element accepting the frames matching `id` on the bits set in
+/// `mask`, storing them in RX FIFO 0.
+pub(crate) fn extended_filter(id: ExtendedId, mask: ExtendedId) -> [u32; 2] {
+    // EFEC = store in FIFO 0 (1), EFT = classic filter (2).
+    [(1 << 29) | id.as_raw(), (2 << 30) | mask.as_raw()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(frame: &Frame) -> Frame {
+        let mut element = [0u32; 18];
+        element[..2].copy_from_slice(&frame.header());
+        for (i, w) in frame.data_words().enumerate() {
+            element[2 + i] = w;
+        }
+        Frame::from_element(|n| element[n])
+    }
+
+    #[test]
+    fn dlc_len() {
+        for dlc in 0..16 {
+            assert_eq!(len_to_dlc(dlc_to_len(dlc)), Some(dlc));
+        }
+        assert_eq!(len_to_dlc(9), None);
+        assert_eq!(len_to_dlc(65), None);
+    }
+
+    #[test]
+    fn classic_lengths() {
+        let id = StandardId::new(0x123).unwrap();
+        assert!(Frame::new_data(id, &[0; 8]).is_some());
+        assert!(Frame::new_data(id, &[0; 9]).is_none());
+        assert!(Frame::new_fd(id, &[0; 12], true).is_some());
+        assert!(Frame::new_fd(id, &[0; 13], true).is_none());
+        assert!(Frame::new_remote(id, 9).is_none());
+    }
+
+    #[test]
+    fn standard_header() {
+        let frame = Frame::new_data(StandardId::new(0x7FF).unwrap(), &[1, 2, 3]).unwrap();
+        assert_eq!(frame.header(), [0x7FF << 18, 3 << 16]);
+        let mut words = frame.data_words();
+        assert_eq!(words.next(), Some(0x0003_0201));
+        assert_eq!(words.next(), None);
+    }
+
+    #[test]
+    fn extended_fd_header() {
+        let data = [0xAA; 20];
+        let frame = Frame::new_fd(ExtendedId::new(0x1234_5678).unwrap(), &data, true).unwrap();
+        assert_eq!(
+            frame.header(),
+            [0x1234_5678 | H0_XTD, (11 << 16) | H1_FDF | H1_BRS]
+        );
+        assert_eq!(frame.data_words().count(), 5);
+    }
+
+    #[test]
+    fn element_roundtrip() {
+        let mut data = [0; 64];
+        for (i, b) in data.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        let frames = [
+            Frame::new_data(StandardId::new(0x42).unwrap(), &data[..5]).unwrap(),
+            Frame::new_data(ExtendedId::new(0x1FFF_FFFF).unwrap(), &[]).unwrap(),
+            Frame::new_remote(StandardId::new(0x1).unwrap(), 4).unwrap(),
+            Frame::new_fd(StandardId::new(0x100).unwrap(), &data[..48], false).unwrap(),
+            Frame::new_fd(ExtendedId::new(0x100).unwrap(), &data, true).unwrap(),
+        ];
+        for frame in &frames {
+            assert_eq!(&roundtrip(frame), frame);
+        }
+    }
+
+    #[test]
+    fn classic_dlc_above_8() {
+        let frame = Frame::from_element(|n| match n {
+            0 => 0x10 << 18,
+            1 => 15 << 16,
+            _ => 0x0101_0101,
+        });
+        assert_eq!(frame.data(), &[1; 8]);
+    }
+
+    #[test]
+    fn filters() {
+        let id = StandardId::new(0x123).unwrap();
+        let mask = StandardId::new(0x7F0).unwrap();
+        assert_eq!(standard_filter(id, mask), 0x8923_07F0);
+
+        let id = ExtendedId::new(0x1234_5678).unwrap();
+        let mask = ExtendedId::new(0x1FFF_FF00).unwrap();
+        assert_eq!(extended_filter(id, mask), [0x3234_5678, 0x9FFF_FF00]);
+    }
+}
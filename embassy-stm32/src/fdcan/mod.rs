@@ -0,0 +1,841 @@
+#![macro_use]
+
+//! FDCAN driver, for classic CAN and CAN FD.
+//!
+//! The registers are accessed directly rather than through the PAC, as the register layout
+//! differs between the G0/G4/U5 and H7 flavours of the peripheral.
+
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use core::ptr;
+use core::task::Poll;
+
+use embassy::blocking_mutex::{CriticalSectionMutex, Mutex as _};
+use embassy::interrupt::{Interrupt, InterruptExt};
+#[cfg(feature = "_time-driver")]
+use embassy::time::Instant;
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::can::RxQueue;
+use crate::gpio::{
+    sealed::AFType::{OutputOpenDrain, OutputPushPull},
+    Pin,
+};
+use crate::peripherals;
+use crate::rcc::RccPeripheral;
+use crate::time::Hertz;
+
+mod frame;
+mod timing;
+
+pub use crate::can::{BusState, CanFrame, Envelope, Error, ExtendedId, Filter, Id, StandardId};
+pub use frame::{dlc_to_len, len_to_dlc, Frame, MAX_DATA_LEN};
+pub use timing::BitTiming;
+
+use timing::{DATA_LIMITS, NOMINAL_LIMITS};
+
+/// Number of received frames buffered in software, in addition to the hardware RX FIFOs.
+const RX_QUEUE_LEN: usize = 16;
+
+/// Size of the RX FIFO and TX buffer elements, with room for 64 data bytes.
+const ELEMENT_BYTES: usize = 72;
+
+#[cfg(rcc_g0)]
+const MESSAGE_RAM: usize = 0x4000_B400;
+#[cfg(rcc_g4)]
+const MESSAGE_RAM: usize = 0x4000_A400;
+#[cfg(any(rcc_h7, rcc_h7ab, rcc_u5))]
+const MESSAGE_RAM: usize = 0x4000_AC00;
+
+/// Size of the message RAM shared by all instances, in words.
+#[cfg(any(rcc_h7, rcc_h7ab))]
+pub const MESSAGE_RAM_WORDS: usize = 2560;
+
+// On G0/G4/U5, each instance has a fixed message RAM layout.
+#[cfg(not(any(rcc_h7, rcc_h7ab)))]
+mod fixed_layout {
+    pub const INSTANCE_BYTES: usize = 0x350;
+    pub const STD_FILTERS: usize = 28;
+    pub const EXT_FILTERS: usize = 8;
+    pub const STD_FILTERS_OFFSET: usize = 0x000;
+    pub const EXT_FILTERS_OFFSET: usize = 0x070;
+    pub const RX_FIFO0_OFFSET: usize = 0x0B0;
+    pub const RX_FIFO1_OFFSET: usize = 0x188;
+    pub const TX_BUFFERS_OFFSET: usize = 0x278;
+    pub const TX_BUFFERS: usize = 3;
+}
+
+// Register offsets.
+const DBTP: usize = 0x0C;
+const TEST: usize = 0x10;
+const CCCR: usize = 0x18;
+const NBTP: usize = 0x1C;
+const ECR: usize = 0x40;
+const PSR: usize = 0x44;
+const TDCR: usize = 0x48;
+const IR: usize = 0x50;
+const IE: usize = 0x54;
+const ILS: usize = 0x58;
+const ILE: usize = 0x5C;
+
+#[cfg(not(any(rcc_h7, rcc_h7ab)))]
+mod reg {
+    pub const RXGFC: usize = 0x80;
+    pub const RXFS: [usize; 2] = [0x90, 0x98];
+    pub const RXFA: [usize; 2] = [0x94, 0x9C];
+    pub const TXBC: usize = 0xC0;
+    pub const TXFQS: usize = 0xC4;
+    pub const TXBAR: usize = 0xCC;
+    pub const TXBTIE: usize = 0xDC;
+
+    pub const IR_RF0N: u32 = 1 << 0;
+    pub const IR_RF0L: u32 = 1 << 2;
+    pub const IR_RF1N: u32 = 1 << 3;
+    pub const IR_RF1L: u32 = 1 << 5;
+    pub const IR_TC: u32 = 1 << 7;
+    pub const IR_EP: u32 = 1 << 17;
+    pub const IR_EW: u32 = 1 << 18;
+    pub const IR_BO: u32 = 1 << 19;
+
+    pub const TXBC_TFQM: u32 = 1 << 24;
+}
+
+#[cfg(any(rcc_h7, rcc_h7ab))]
+mod reg {
+    pub const GFC: usize = 0x80;
+    pub const SIDFC: usize = 0x84;
+    pub const XIDFC: usize = 0x88;
+    pub const RXFC: [usize; 2] = [0xA0, 0xB0];
+    pub const RXFS: [usize; 2] = [0xA4, 0xB4];
+    pub const RXFA: [usize; 2] = [0xA8, 0xB8];
+    pub const RXESC: usize = 0xBC;
+    pub const TXBC: usize = 0xC0;
+    pub const TXFQS: usize = 0xC4;
+    pub const TXESC: usize = 0xC8;
+    pub const TXBAR: usize = 0xD0;
+    pub const TXBTIE: usize = 0xE0;
+
+    pub const IR_RF0N: u32 = 1 << 0;
+    pub const IR_RF0L: u32 = 1 << 3;
+    pub const IR_RF1N: u32 = 1 << 4;
+    pub const IR_RF1L: u32 = 1 << 7;
+    pub const IR_TC: u32 = 1 << 9;
+    pub const IR_EP: u32 = 1 << 23;
+    pub const IR_EW: u32 = 1 << 24;
+    pub const IR_BO: u32 = 1 << 25;
+
+    pub const TXBC_TFQM: u32 = 1 << 30;
+}
+
+use reg::*;
+
+const CCCR_INIT: u32 = 1 << 0;
+const CCCR_CCE: u32 = 1 << 1;
+const CCCR_MON: u32 = 1 << 5;
+const CCCR_DAR: u32 = 1 << 6;
+const CCCR_TEST: u32 = 1 << 7;
+const CCCR_FDOE: u32 = 1 << 8;
+const CCCR_BRSE: u32 = 1 << 9;
+const TEST_LBCK: u32 = 1 << 4;
+const DBTP_TDC: u32 = 1 << 23;
+const PSR_EP: u32 = 1 << 5;
+const PSR_EW: u32 = 1 << 6;
+const PSR_BO: u32 = 1 << 7;
+const TXFQS_TFQF: u32 = 1 << 21;
+const ILE_EINT0: u32 = 1 << 0;
+/// Reject the frames not matching any filter (ANFS = ANFE = 2).
+const GFC_REJECT_NON_MATCHING: u32 = (2 << 4) | (2 << 2);
+
+const IR_RX: u32 = IR_RF0N | IR_RF0L | IR_RF1N | IR_RF1L;
+const IR_ERR: u32 = IR_EP | IR_EW | IR_BO;
+
+/// Raw access to the registers of an instance.
+#[derive(Clone, Copy)]
+pub(crate) struct Regs(usize);
+
+impl Regs {
+    unsafe fn read(self, offset: usize) -> u32 {
+        ptr::read_volatile((self.0 + offset) as *const u32)
+    }
+
+    unsafe fn write(self, offset: usize, value: u32) {
+        ptr::write_volatile((self.0 + offset) as *mut u32, value)
+    }
+
+    unsafe fn modify(self, offset: usize, f: impl FnOnce(u32) -> u32) {
+        self.write(offset, f(self.read(offset)))
+    }
+}
+
+/// Addresses of the message RAM sections of an instance.
+#[derive(Clone, Copy)]
+struct Layout {
+    std_filters: usize,
+    std_filter_count: usize,
+    ext_filters: usize,
+    ext_filter_count: usize,
+    rx_fifos: [usize; 2],
+    tx_buffers: usize,
+    tx_buffer_count: usize,
+}
+
+impl Layout {
+    #[cfg(not(any(rcc_h7, rcc_h7ab)))]
+    fn get<T: Instance>() -> Self {
+        use fixed_layout::*;
+
+        let base = MESSAGE_RAM + T::index() * INSTANCE_BYTES;
+        Self {
+            std_filters: base + STD_FILTERS_OFFSET,
+            std_filter_count: STD_FILTERS,
+            ext_filters: base + EXT_FILTERS_OFFSET,
+            ext_filter_count: EXT_FILTERS,
+            rx_fifos: [base + RX_FIFO0_OFFSET, base + RX_FIFO1_OFFSET],
+            tx_buffers: base + TX_BUFFERS_OFFSET,
+            tx_buffer_count: TX_BUFFERS,
+        }
+    }
+
+    /// Reads back the layout programmed by [`configure_message_ram`].
+    #[cfg(any(rcc_h7, rcc_h7ab))]
+    fn get<T: Instance>() -> Self {
+        let regs = T::regs();
+        let (sidfc, xidfc, rxf0c, rxf1c, txbc) = unsafe {
+            (
+                regs.read(SIDFC),
+                regs.read(XIDFC),
+                regs.read(RXFC[0]),
+                regs.read(RXFC[1]),
+                regs.read(TXBC),
+            )
+        };
+        // The start addresses are byte offsets in bits 15:2.
+        let addr = |r: u32| MESSAGE_RAM + (r & 0xFFFC) as usize;
+        Self {
+            std_filters: addr(sidfc),
+            std_filter_count: ((sidfc >> 16) & 0xFF) as usize,
+            ext_filters: addr(xidfc),
+            ext_filter_count: ((xidfc >> 16) & 0xFF) as usize,
+            rx_fifos: [addr(rxf0c), addr(rxf1c)],
+            tx_buffers: addr(txbc),
+            tx_buffer_count: ((txbc >> 24) & 0x3F) as usize,
+        }
+    }
+}
+
+/// Message RAM allocation of an instance, on H7 where the instances share the message RAM.
+///
+/// All RX FIFO and TX buffer elements have room for 64 data bytes (18 words).
+#[cfg(any(rcc_h7, rcc_h7ab))]
+#[derive(Debug, Clone, Copy)]
+pub struct MessageRamConfig {
+    /// Start of the allocation in the message RAM, in words.
+    pub offset: u16,
+    /// Number of standard filters, up to 128.
+    pub std_filters: u8,
+    /// Number of extended filters, up to 64.
+    pub ext_filters: u8,
+    /// Number of RX FIFO 0 elements, up to 64.
+    pub rx_fifo0: u8,
+    /// Number of RX FIFO 1 elements, up to 64.
+    pub rx_fifo1: u8,
+    /// Number of TX queue elements, 1 to 32.
+    pub tx_buffers: u8,
+}
+
+#[cfg(any(rcc_h7, rcc_h7ab))]
+impl MessageRamConfig {
+    /// Default allocation for instance `index`: each instance gets half of the message RAM.
+    pub const fn default_for(index: usize) -> Self {
+        Self {
+            offset: (index * MESSAGE_RAM_WORDS / 2) as u16,
+            std_filters: 32,
+            ext_filters: 16,
+            rx_fifo0: 32,
+            rx_fifo1: 8,
+            tx_buffers: 16,
+        }
+    }
+
+    /// Size of the allocation, in words.
+    pub const fn words(&self) -> usize {
+        self.std_filters as usize
+            + 2 * self.ext_filters as usize
+            + (self.rx_fifo0 as usize + self.rx_fifo1 as usize + self.tx_buffers as usize)
+                * (ELEMENT_BYTES / 4)
+    }
+}
+
+/// Operating mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mode {
+    Normal,
+    /// Transmitted frames are received back, and nothing is sent on the bus.
+    InternalLoopback,
+    /// Transmitted frames are received back and sent on the bus, acknowledge errors are
+    /// ignored.
+    ExternalLoopback,
+    /// Frames are received but the controller doesn't transmit, not even acknowledges.
+    BusMonitoring,
+}
+
+/// FDCAN configuration.
+///
+/// See the `Default` impl for suitable default values.
+#[non_exhaustive]
+pub struct Config {
+    /// Nominal bit rate, used for the arbitration phase and for classic frames.
+    pub bitrate: u32,
+    /// Enables transmitting and receiving FD frames.
+    pub fd: bool,
+    /// Data phase bit rate of FD frames with bit rate switching. `None` disables bit rate
+    /// switching.
+    pub data_bitrate: Option<u32>,
+    pub mode: Mode,
+    /// Retransmits frames that lost arbitration or were disturbed by errors.
+    pub automatic_retransmit: bool,
+    /// Frequency of the FDCAN kernel clock, `None` meaning the frequency of the APB clock.
+    ///
+    /// On G0 and G4, the driver selects PCLK as kernel clock. On H7 and U5 the kernel clock is
+    /// HSE after reset: select it in RCC and set its frequency here.
+    pub kernel_clock: Option<Hertz>,
+    /// Message RAM allocation, `None` meaning [`MessageRamConfig::default_for`] the instance.
+    #[cfg(any(rcc_h7, rcc_h7ab))]
+    pub message_ram: Option<MessageRamConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bitrate: 500_000,
+            fd: false,
+            data_bitrate: None,
+            mode: Mode::Normal,
+            automatic_retransmit: true,
+            kernel_clock: None,
+            #[cfg(any(rcc_h7, rcc_h7ab))]
+            message_ram: None,
+        }
+    }
+}
+
+pub struct State {
+    tx_waker: AtomicWaker,
+    rx_waker: AtomicWaker,
+    err_waker: AtomicWaker,
+    rx_queue: CriticalSectionMutex<RefCell<RxQueue<Envelope<Frame>, RX_QUEUE_LEN>>>,
+}
+
+impl State {
+    pub const fn new() -> Self {
+        const NONE: Option<Envelope<Frame>> = None;
+        Self {
+            tx_waker: AtomicWaker::new(),
+            rx_waker: AtomicWaker::new(),
+            err_waker: AtomicWaker::new(),
+            rx_queue: CriticalSectionMutex::new(RefCell::new(RxQueue::new([NONE; RX_QUEUE_LEN]))),
+        }
+    }
+}
+
+/// Async FDCAN driver.
+///
+/// All filters are disabled after creation, and frames not matching any filter are rejected:
+/// configure filters with [`Fdcan::set_filter`] or [`Fdcan::accept_all`] to receive frames.
+/// Accepted frames are stored in RX FIFO 0, from which the interrupt handler moves them to a
+/// software queue.
+///
+/// Frames are transmitted in identifier priority order.
+pub struct Fdcan<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+    fd: bool,
+}
+
+impl<'d, T: Instance> Fdcan<'d, T> {
+    pub fn new(
+        _peri: impl Unborrow<Target = T> + 'd,
+        rx: impl Unborrow<Target = impl RxPin<T>> + 'd,
+        tx: impl Unborrow<Target = impl TxPin<T>> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(rx, tx, irq);
+
+        unsafe {
+            rx.set_as_af(rx.af_num(), OutputOpenDrain);
+            tx.set_as_af(tx.af_num(), OutputPushPull);
+        }
+
+        // The clock and the reset are shared by all instances, so don't reset the peripheral.
+        T::enable();
+
+        #[cfg(any(rcc_g0, rcc_g4))]
+        critical_section::with(|_| unsafe {
+            // FDCANSEL = PCLK
+            #[cfg(rcc_g0)]
+            crate::pac::RCC.ccipr2().modify(|w| w.0 &= !(0b11 << 8));
+            #[cfg(rcc_g4)]
+            crate::pac::RCC
+                .ccipr()
+                .modify(|w| w.0 = (w.0 & !(0b11 << 24)) | (0b10 << 24));
+        });
+
+        let kernel_clock = config.kernel_clock.unwrap_or_else(T::frequency).0;
+        let nominal = unwrap!(BitTiming::calculate(
+            kernel_clock,
+            config.bitrate,
+            &NOMINAL_LIMITS
+        ));
+        let data = config.data_bitrate.map(|bitrate| {
+            let timing = unwrap!(BitTiming::calculate(kernel_clock, bitrate, &DATA_LIMITS));
+            (bitrate, timing)
+        });
+
+        let regs = T::regs();
+        unsafe {
+            regs.modify(CCCR, |v| v | CCCR_INIT);
+            while regs.read(CCCR) & CCCR_INIT == 0 {}
+            regs.modify(CCCR, |v| v | CCCR_CCE);
+
+            #[cfg(any(rcc_h7, rcc_h7ab))]
+            configure_message_ram(
+                regs,
+                &config
+                    .message_ram
+                    .unwrap_or_else(|| MessageRamConfig::default_for(T::index())),
+            );
+            #[cfg(not(any(rcc_h7, rcc_h7ab)))]
+            {
+                let base = MESSAGE_RAM + T::index() * fixed_layout::INSTANCE_BYTES;
+                for i in 0..fixed_layout::INSTANCE_BYTES / 4 {
+                    ptr::write_volatile((base + 4 * i) as *mut u32, 0);
+                }
+                regs.write(TXBC, TXBC_TFQM);
+            }
+
+            let layout = Layout::get::<T>();
+
+            #[cfg(not(any(rcc_h7, rcc_h7ab)))]
+            regs.write(
+                RXGFC,
+                ((layout.ext_filter_count as u32) << 24)
+                    | ((layout.std_filter_count as u32) << 16)
+                    | GFC_REJECT_NON_MATCHING,
+            );
+            #[cfg(any(rcc_h7, rcc_h7ab))]
+            regs.write(GFC, GFC_REJECT_NON_MATCHING);
+
+            regs.write(NBTP, nominal.nbtp());
+            if let Some((bitrate, timing)) = data {
+                if bitrate > 1_000_000 {
+                    // At high data rates, the transceiver delay can exceed the sample point.
+                    regs.write(DBTP, timing.dbtp() | DBTP_TDC);
+                    regs.write(TDCR, timing.tdc_offset().min(127) << 8);
+                } else {
+                    regs.write(DBTP, timing.dbtp());
+                }
+            }
+
+            let mut cccr =
+                regs.read(CCCR) & !(CCCR_MON | CCCR_DAR | CCCR_TEST | CCCR_FDOE | CCCR_BRSE);
+            if !config.automatic_retransmit {
+                cccr |= CCCR_DAR;
+            }
+            if config.fd {
+                cccr |= CCCR_FDOE;
+                if data.is_some() {
+                    cccr |= CCCR_BRSE;
+                }
+            }
+            match config.mode {
+                Mode::Normal => {}
+                Mode::InternalLoopback => cccr |= CCCR_TEST | CCCR_MON,
+                Mode::ExternalLoopback => cccr |= CCCR_TEST,
+                Mode::BusMonitoring => cccr |= CCCR_MON,
+            }
+            regs.write(CCCR, cccr);
+            if matches!(config.mode, Mode::InternalLoopback | Mode::ExternalLoopback) {
+                regs.write(TEST, TEST_LBCK);
+            }
+
+            regs.write(IR, 0xFFFF_FFFF);
+            regs.write(IE, IR_RX | IR_ERR | IR_TC);
+            regs.write(ILS, 0);
+            regs.write(ILE, ILE_EINT0);
+            regs.write(TXBTIE, u32::MAX >> (32 - layout.tx_buffer_count));
+        }
+
+        T::state().rx_queue.lock(|q| q.borrow_mut().clear());
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        // Leave the initialization mode, the controller joins the bus after 11 recessive bits.
+        unsafe {
+            regs.modify(CCCR, |v| v & !CCCR_INIT);
+            while regs.read(CCCR) & CCCR_INIT != 0 {}
+        }
+
+        Self {
+            phantom: PhantomData,
+            fd: config.fd,
+        }
+    }
+
+    unsafe fn on_interrupt(_: *mut ()) {
+        let regs = T::regs();
+        let state = T::state();
+
+        let ir = regs.read(IR);
+        regs.write(IR, ir);
+
+        if ir & IR_RX != 0 {
+            let layout = Layout::get::<T>();
+            Self::receive_fifo(&layout, 0);
+            Self::receive_fifo(&layout, 1);
+            if ir & (IR_RF0L | IR_RF1L) != 0 {
+                state.rx_queue.lock(|q| q.borrow_mut().set_overrun());
+            }
+            state.rx_waker.wake();
+        }
+
+        if ir & IR_TC != 0 {
+            state.tx_waker.wake();
+        }
+
+        if ir & IR_ERR != 0 {
+            state.err_waker.wake();
+        }
+    }
+
+    unsafe fn receive_fifo(layout: &Layout, fifo: usize) {
+        let regs = T::regs();
+        let state = T::state();
+
+        loop {
+            let status = regs.read(RXFS[fifo]);
+            if status & 0x7F == 0 {
+                break;
+            }
+            let index = ((status >> 8) & 0x3F) as usize;
+            let element = layout.rx_fifos[fifo] + index * ELEMENT_BYTES;
+            let frame =
+                Frame::from_element(|n| ptr::read_volatile((element + 4 * n) as *const u32));
+            regs.write(RXFA[fifo], index as u32);
+
+            let envelope = Envelope {
+                #[cfg(feature = "_time-driver")]
+                ts: Instant::now(),
+                frame,
+            };
+            state.rx_queue.lock(|q| q.borrow_mut().push(envelope));
+        }
+    }
+
+    /// Queues a frame for transmission, waiting for room in the TX queue if needed.
+    ///
+    /// # Panics
+    /// Panics if `frame` is an FD frame and FD operation isn't enabled in the [`Config`].
+    pub async fn write(&mut self, frame: &Frame) -> Result<(), Error> {
+        assert!(self.fd || !frame.is_fd_frame());
+
+        poll_fn(|cx| {
+            T::state().tx_waker.register(cx.waker());
+
+            if self.bus_state() == BusState::BusOff {
+                return Poll::Ready(Err(Error::BusOff));
+            }
+
+            let regs = T::regs();
+            let fqs = unsafe { regs.read(TXFQS) };
+            if fqs & TXFQS_TFQF != 0 {
+                return Poll::Pending;
+            }
+
+            let index = ((fqs >> 16) & 0x1F) as usize;
+            let element = Layout::get::<T>().tx_buffers + index * ELEMENT_BYTES;
+            unsafe {
+                let header = frame.header();
+                let words = header.iter().copied().chain(frame.data_words());
+                for (n, word) in words.enumerate() {
+                    ptr::write_volatile((element + 4 * n) as *mut u32, word);
+                }
+                regs.write(TXBAR, 1 << index);
+            }
+
+            Poll::Ready(Ok(()))
+        })
+        .await
+    }
+
+    /// Waits until a frame is received.
+    ///
+    /// Returns [`Error::Overrun`] once if frames were lost since the previous read.
+    pub async fn read(&mut self) -> Result<Envelope<Frame>, Error> {
+        poll_fn(|cx| {
+            T::state().rx_waker.register(cx.waker());
+            match self.try_read() {
+                Some(res) => Poll::Ready(res),
+                None => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Returns a received frame, or `None` if no frame is available.
+    pub fn try_read(&mut self) -> Option<Result<Envelope<Frame>, Error>> {
+        T::state().rx_queue.lock(|q| q.borrow_mut().pop())
+    }
+
+    /// Disables all filters, and configures filter 0 to accept all frames.
+    pub fn accept_all(&mut self) {
+        let layout = Layout::get::<T>();
+        for index in 0..layout.std_filter_count.max(layout.ext_filter_count) {
+            self.disable_filter(index);
+        }
+        self.set_filter(0, Filter::AcceptAll);
+    }
+
+    /// Configures filter `index`.
+    ///
+    /// There are separate filter lists for standard and extended frames, `index` is in the
+    /// list matching `filter`. [`Filter::AcceptAll`] uses the entries `index` of both lists.
+    ///
+    /// # Panics
+    /// Panics if `index` is out of range for the list: see [`Fdcan::std_filter_count`] and
+    /// [`Fdcan::ext_filter_count`].
+    pub fn set_filter(&mut self, index: usize, filter: Filter) {
+        let zero_std = StandardId::new(0).unwrap();
+        let zero_ext = ExtendedId::new(0).unwrap();
+        match filter {
+            Filter::AcceptAll => {
+                self.write_std_filter(index, frame::standard_filter(zero_std, zero_std));
+                self.write_ext_filter(index, frame::extended_filter(zero_ext, zero_ext));
+            }
+            Filter::Standard { id, mask } => {
+                self.write_std_filter(index, frame::standard_filter(id, mask))
+            }
+            Filter::Extended { id, mask } => {
+                self.write_ext_filter(index, frame::extended_filter(id, mask))
+            }
+        }
+    }
+
+    /// Disables the entries `index` of both filter lists, if they exist.
+    pub fn disable_filter(&mut self, index: usize) {
+        let layout = Layout::get::<T>();
+        if index < layout.std_filter_count {
+            self.write_std_filter(index, 0);
+        }
+        if index < layout.ext_filter_count {
+            self.write_ext_filter(index, [0, 0]);
+        }
+    }
+
+    /// Returns the number of standard filters.
+    pub fn std_filter_count(&self) -> usize {
+        Layout::get::<T>().std_filter_count
+    }
+
+    /// Returns the number of extended filters.
+    pub fn ext_filter_count(&self) -> usize {
+        Layout::get::<T>().ext_filter_count
+    }
+
+    fn write_std_filter(&mut self, index: usize, element: u32) {
+        let layout = Layout::get::<T>();
+        assert!(index < layout.std_filter_count);
+        unsafe { ptr::write_volatile((layout.std_filters + 4 * index) as *mut u32, element) }
+    }
+
+    fn write_ext_filter(&mut self, index: usize, element: [u32; 2]) {
+        let layout = Layout::get::<T>();
+        assert!(index < layout.ext_filter_count);
+        let addr = layout.ext_filters + 8 * index;
+        unsafe {
+            ptr::write_volatile(addr as *mut u32, element[0]);
+            ptr::write_volatile((addr + 4) as *mut u32, element[1]);
+        }
+    }
+
+    /// Returns the current fault confinement state.
+    pub fn bus_state(&self) -> BusState {
+        let psr = unsafe { T::regs().read(PSR) };
+        if psr & PSR_BO != 0 {
+            BusState::BusOff
+        } else if psr & PSR_EP != 0 {
+            BusState::ErrorPassive
+        } else if psr & PSR_EW != 0 {
+            BusState::ErrorWarning
+        } else {
+            BusState::ErrorActive
+        }
+    }
+
+    /// Returns the transmit error counter.
+    pub fn tx_error_count(&self) -> u8 {
+        unsafe { T::regs().read(ECR) as u8 }
+    }
+
+    /// Returns the receive error counter.
+    pub fn rx_error_count(&self) -> u8 {
+        ((unsafe { T::regs().read(ECR) } >> 8) & 0x7F) as u8
+    }
+
+    /// Waits until the controller enters the error passive or bus-off state, and returns it.
+    ///
+    /// Returns immediately if the controller is already in one of these states.
+    pub async fn wait_bus_error(&mut self) -> BusState {
+        poll_fn(|cx| {
+            T::state().err_waker.register(cx.waker());
+            match self.bus_state() {
+                state @ (BusState::ErrorPassive | BusState::BusOff) => Poll::Ready(state),
+                _ => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Starts the recovery from the bus-off state.
+    ///
+    /// The controller enters the initialization mode when going bus-off, and rejoins the bus
+    /// after 129 occurrences of 11 recessive bits once this is called.
+    pub fn recover(&mut self) {
+        unsafe { T::regs().modify(CCCR, |v| v & !CCCR_INIT) }
+    }
+}
+
+impl<'d, T: Instance> Drop for Fdcan<'d, T> {
+    fn drop(&mut self) {
+        // The clock is shared by all instances, leave it enabled.
+        let regs = T::regs();
+        unsafe {
+            regs.modify(CCCR, |v| v | CCCR_INIT);
+            regs.write(ILE, 0);
+            regs.write(IE, 0);
+        }
+    }
+}
+
+/// Programs the message RAM layout of an instance, and clears its filters.
+///
+/// Must be called with CCCR.CCE set.
+#[cfg(any(rcc_h7, rcc_h7ab))]
+unsafe fn configure_message_ram(regs: Regs, config: &MessageRamConfig) {
+    assert!(config.std_filters <= 128);
+    assert!(config.ext_filters <= 64);
+    assert!(config.rx_fifo0 <= 64 && config.rx_fifo1 <= 64);
+    assert!(config.tx_buffers >= 1 && config.tx_buffers <= 32);
+    assert!(config.offset as usize + config.words() <= MESSAGE_RAM_WORDS);
+
+    // Byte offsets in the message RAM.
+    let mut offset = config.offset as u32 * 4;
+    let start = offset as usize;
+
+    regs.write(SIDFC, ((config.std_filters as u32) << 16) | offset);
+    offset += config.std_filters as u32 * 4;
+    regs.write(XIDFC, ((config.ext_filters as u32) << 16) | offset);
+    offset += config.ext_filters as u32 * 8;
+    regs.write(RXFC[0], ((config.rx_fifo0 as u32) << 16) | offset);
+    offset += config.rx_fifo0 as u32 * ELEMENT_BYTES as u32;
+    regs.write(RXFC[1], ((config.rx_fifo1 as u32) << 16) | offset);
+    offset += config.rx_fifo1 as u32 * ELEMENT_BYTES as u32;
+    regs.write(
+        TXBC,
+        TXBC_TFQM | ((config.tx_buffers as u32) << 24) | offset,
+    );
+    offset += config.tx_buffers as u32 * ELEMENT_BYTES as u32;
+
+    // 64 byte data fields.
+    regs.write(RXESC, 0x777);
+    regs.write(TXESC, 0x7);
+
+    for addr in (start..offset as usize).step_by(4) {
+        ptr::write_volatile((MESSAGE_RAM + addr) as *mut u32, 0);
+    }
+}
+
+pub(crate) mod sealed {
+    use super::*;
+
+    pub trait Instance {
+        fn regs() -> Regs;
+        fn index() -> usize;
+        fn state() -> &'static State;
+    }
+
+    pub trait RxPin<T: Instance>: Pin {
+        fn af_num(&self) -> u8;
+    }
+
+    pub trait TxPin<T: Instance>: Pin {
+        fn af_num(&self) -> u8;
+    }
+}
+
+pub trait Instance: sealed::Instance + RccPeripheral {
+    type Interrupt: Interrupt;
+}
+pub trait RxPin<T: Instance>: sealed::RxPin<T> {}
+pub trait TxPin<T: Instance>: sealed::TxPin<T> {}
+
+macro_rules! fdcan_index {
+    (FDCAN1) => {
+        0
+    };
+    (FDCAN2) => {
+        1
+    };
+    (FDCAN3) => {
+        2
+    };
+}
+
+crate::pac::interrupts!(
+    ($inst:ident, fdcan, $block:ident, IT0, $irq:ident) => {
+        impl sealed::Instance for peripherals::$inst {
+            fn regs() -> Regs {
+                Regs(crate::pac::$inst.0 as usize)
+            }
+
+            fn index() -> usize {
+                fdcan_index!($inst)
+            }
+
+            fn state() -> &'static State {
+                static STATE: State = State::new();
+                &STATE
+            }
+        }
+
+        impl Instance for peripherals::$inst {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+);
+
+macro_rules! impl_pin {
+    ($inst:ident, $pin:ident, $signal:ident, $af:expr) => {
+        impl $signal<peripherals::$inst> for peripherals::$pin {}
+
+        impl sealed::$signal<peripherals::$inst> for peripherals::$pin {
+            fn af_num(&self) -> u8 {
+                $af
+            }
+        }
+    };
+}
+
+crate::pac::peripheral_pins!(
+    ($inst:ident, fdcan, FDCAN, $pin:ident, TX, $af:expr) => {
+        impl_pin!($inst, $pin, TxPin, $af);
+    };
+    ($inst:ident, fdcan, FDCAN, $pin:ident, RX, $af:expr) => {
+        impl_pin!($inst, $pin, RxPin, $af);
+    };
+);
@@ -0,0 +1,162 @@
+/// Bit timing, in time quanta of `prescaler` kernel clock cycles.
+///
+/// A bit is made of the sync segment (one time quantum), `seg1` and `seg2`, the sample point
+/// being between `seg1` and `seg2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BitTiming {
+    pub prescaler: u16,
+    pub seg1: u16,
+    pub seg2: u8,
+    pub sjw: u8,
+}
+
+/// Maximum values of the bit timing fields.
+pub(crate) struct Limits {
+    prescaler: u16,
+    seg1: u16,
+    seg2: u8,
+    sjw: u8,
+}
+
+/// Limits of the nominal (arbitration phase) bit timing.
+pub(crate) const NOMINAL_LIMITS: Limits = Limits {
+    prescaler: 512,
+    seg1: 256,
+    seg2: 128,
+    sjw: 128,
+};
+
+/// Limits of the data phase bit timing.
+pub(crate) const DATA_LIMITS: Limits = Limits {
+    prescaler: 32,
+    seg1: 32,
+    seg2: 16,
+    sjw: 16,
+};
+
+impl BitTiming {
+    /// Calculates a bit timing for `bitrate` with a sample point close to 87.5%, or returns
+    /// `None` if `bitrate` can't be obtained exactly from `clock`.
+    ///
+    /// The smallest possible prescaler is used, for the best resolution.
+    pub(crate) fn calculate(clock: u32, bitrate: u32, limits: &Limits) -> Option<Self> {
+        if bitrate == 0 {
+            return None;
+        }
+
+        for prescaler in 1..=limits.prescaler as u32 {
+            let div = prescaler * bitrate;
+            if clock % div != 0 {
+                continue;
+            }
+            let quanta = clock / div;
+            if quanta < 4 {
+                // Quanta only decrease with larger prescalers.
+                return None;
+            }
+
+            let seg2 = quanta - (quanta * 7 + 4) / 8;
+            let seg2 = seg2.max(1).min(limits.seg2 as u32);
+            let seg1 = quanta - 1 - seg2;
+            if seg1 > limits.seg1 as u32 {
+                continue;
+            }
+
+            return Some(Self {
+                prescaler: prescaler as u16,
+                seg1: seg1 as u16,
+                seg2: seg2 as u8,
+                sjw: seg2.min(limits.sjw as u32) as u8,
+            });
+        }
+
+        None
+    }
+
+    /// Returns the NBTP register value.
+    pub(crate) fn nbtp(&self) -> u32 {
+        ((self.sjw as u32 - 1) << 25)
+            | ((self.prescaler as u32 - 1) << 16)
+            | ((self.seg1 as u32 - 1) << 8)
+            | (self.seg2 as u32 - 1)
+    }
+
+    /// Returns the DBTP register value, with transceiver delay compensation disabled.
+    pub(crate) fn dbtp(&self) -> u32 {
+        ((self.prescaler as u32 - 1) << 16)
+            | ((self.seg1 as u32 - 1) << 8)
+            | ((self.seg2 as u32 - 1) << 4)
+            | (self.sjw as u32 - 1)
+    }
+
+    /// Returns the transceiver delay compensation offset placing the secondary sample point at
+    /// the sample point, in kernel clock cycles.
+    pub(crate) fn tdc_offset(&self) -> u32 {
+        self.prescaler as u32 * (self.seg1 as u32 + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nominal() {
+        let t = BitTiming::calculate(170_000_000, 500_000, &NOMINAL_LIMITS).unwrap();
+        assert_eq!(
+            t,
+            BitTiming {
+                prescaler: 2,
+                seg1: 148,
+                seg2: 21,
+                sjw: 21
+            }
+        );
+        assert_eq!(t.nbtp(), (20 << 25) | (1 << 16) | (147 << 8) | 20);
+    }
+
+    #[test]
+    fn data() {
+        let t = BitTiming::calculate(170_000_000, 2_000_000, &DATA_LIMITS).unwrap();
+        assert_eq!(
+            t,
+            BitTiming {
+                prescaler: 5,
+                seg1: 14,
+                seg2: 2,
+                sjw: 2
+            }
+        );
+        assert_eq!(t.dbtp(), (4 << 16) | (13 << 8) | (1 << 4) | 1);
+        assert_eq!(t.tdc_offset(), 75);
+    }
+
+    #[test]
+    fn sample_point() {
+        for &(clock, bitrate) in &[
+            (80_000_000, 1_000_000),
+            (48_000_000, 125_000),
+            (40_000_000, 5_000_000),
+        ] {
+            let t = BitTiming::calculate(clock, bitrate, &DATA_LIMITS).unwrap();
+            let quanta = 1 + t.seg1 as u32 + t.seg2 as u32;
+            assert_eq!(clock, t.prescaler as u32 * quanta * bitrate);
+            let sample_point = (1 + t.seg1 as u32) * 1000 / quanta;
+            assert!(
+                sample_point >= 800 && sample_point <= 900,
+                "{}",
+                sample_point
+            );
+        }
+    }
+
+    #[test]
+    fn impossible() {
+        assert_eq!(
+            BitTiming::calculate(8_000_000, 3_000_000, &NOMINAL_LIMITS),
+            None
+        );
+        assert_eq!(BitTiming::calculate(170_000_000, 0, &NOMINAL_LIMITS), None);
+    }
+}
@@ -0,0 +1,85 @@
+//! F0/F1/F3 flash: half-word programming, uniform pages erased through FLASH_AR.
+
+use core::ptr;
+
+use super::layout::{Sector, SectorMap};
+use super::{Error, FLASH_BASE};
+use crate::pac::FLASH;
+
+pub(crate) const WRITE_SIZE: usize = 2;
+
+pub(crate) const SECTOR_MAP: SectorMap = SectorMap::uniform(crate::pac::FLASH_ERASE_SIZE as u32);
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+
+pub(crate) unsafe fn unlock() {
+    if FLASH.cr().read().lock() {
+        #[cfg(rcc_f1)]
+        {
+            FLASH.keyr().write(|w| w.set_key(KEY1));
+            FLASH.keyr().write(|w| w.set_key(KEY2));
+        }
+        #[cfg(not(rcc_f1))]
+        {
+            FLASH.keyr().write(|w| w.set_fkeyr(KEY1));
+            FLASH.keyr().write(|w| w.set_fkeyr(KEY2));
+        }
+    }
+}
+
+pub(crate) unsafe fn lock() {
+    FLASH.cr().modify(|w| w.set_lock(true));
+}
+
+pub(crate) unsafe fn blocking_write(offset: u32, buf: &[u8; WRITE_SIZE]) -> Result<(), Error> {
+    FLASH.cr().modify(|w| w.set_pg(true));
+    ptr::write_volatile(
+        (FLASH_BASE + offset as usize) as *mut u16,
+        u16::from_le_bytes(*buf),
+    );
+    let res = wait_ready();
+    FLASH.cr().modify(|w| w.set_pg(false));
+    res
+}
+
+pub(crate) unsafe fn blocking_erase_sector(sector: &Sector) -> Result<(), Error> {
+    FLASH.cr().modify(|w| w.set_per(true));
+    FLASH
+        .ar()
+        .write(|w| w.set_far(FLASH_BASE as u32 + sector.start));
+    FLASH.cr().modify(|w| w.set_strt(true));
+    let res = wait_ready();
+    FLASH.cr().modify(|w| w.set_per(false));
+    res
+}
+
+unsafe fn wait_ready() -> Result<(), Error> {
+    loop {
+        let sr = FLASH.sr().read();
+        if sr.bsy() {
+            continue;
+        }
+        #[cfg(rcc_f1)]
+        let write_protected = sr.wrprterr();
+        #[cfg(not(rcc_f1))]
+        let write_protected = sr.wrprt();
+
+        // The flags are cleared by writing 1.
+        FLASH.sr().write(|w| {
+            w.set_eop(true);
+            w.set_pgerr(true);
+            #[cfg(rcc_f1)]
+            w.set_wrprterr(true);
+            #[cfg(not(rcc_f1))]
+            w.set_wrprt(true);
+        });
+        return if write_protected {
+            Err(Error::WriteProtected)
+        } else if sr.pgerr() {
+            Err(Error::Programming)
+        } else {
+            Ok(())
+        };
+    }
+}
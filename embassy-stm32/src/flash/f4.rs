@@ -0,0 +1,105 @@
+//! F4/F7 flash: sectors of different sizes, 32-bit programming.
+//!
+//! Programming 32 bits at a time requires a supply voltage of 2.7 V to 3.6 V.
+
+use core::ptr;
+
+use super::layout::{self, Sector, SectorMap};
+use super::{Error, FLASH_BASE};
+use crate::pac::flash::vals::Psize;
+use crate::pac::FLASH;
+
+pub(crate) const WRITE_SIZE: usize = 4;
+
+#[cfg(not(all(
+    rcc_f7,
+    not(any(stm32f722, stm32f723, stm32f730, stm32f732, stm32f733))
+)))]
+pub(crate) const SECTOR_MAP: SectorMap = layout::F4_SECTOR_MAP;
+// F7s with more than 512 KiB of flash have sectors twice as large.
+#[cfg(all(
+    rcc_f7,
+    not(any(stm32f722, stm32f723, stm32f730, stm32f732, stm32f733))
+))]
+pub(crate) const SECTOR_MAP: SectorMap = layout::F7_LARGE_SECTOR_MAP;
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+
+pub(crate) unsafe fn unlock() {
+    if FLASH.cr().read().lock() {
+        FLASH.keyr().write(|w| w.set_key(KEY1));
+        FLASH.keyr().write(|w| w.set_key(KEY2));
+    }
+}
+
+pub(crate) unsafe fn lock() {
+    FLASH.cr().modify(|w| w.set_lock(true));
+}
+
+pub(crate) unsafe fn blocking_write(offset: u32, buf: &[u8; WRITE_SIZE]) -> Result<(), Error> {
+    FLASH.cr().modify(|w| {
+        w.set_psize(Psize::PSIZE32);
+        w.set_pg(true);
+    });
+    ptr::write_volatile(
+        (FLASH_BASE + offset as usize) as *mut u32,
+        u32::from_le_bytes(*buf),
+    );
+    let res = wait_ready();
+    FLASH.cr().modify(|w| w.set_pg(false));
+    res
+}
+
+pub(crate) unsafe fn blocking_erase_sector(sector: &Sector) -> Result<(), Error> {
+    // The sectors of the second bank of dual bank F4s are numbered from 0b10000.
+    let snb = (sector.bank << 4) | sector.index as u8;
+    FLASH.cr().modify(|w| {
+        w.set_psize(Psize::PSIZE32);
+        w.set_ser(true);
+        w.set_snb(snb);
+    });
+    FLASH.cr().modify(|w| w.set_strt(true));
+    let res = wait_ready();
+    FLASH.cr().modify(|w| {
+        w.set_ser(false);
+        w.set_snb(0);
+    });
+    res
+}
+
+unsafe fn wait_ready() -> Result<(), Error> {
+    loop {
+        let sr = FLASH.sr().read();
+        if sr.bsy() {
+            continue;
+        }
+        // F7s report erase sequence errors in place of the programming sequence errors.
+        #[cfg(rcc_f7)]
+        let sequence = sr.pgperr() || sr.erserr();
+        #[cfg(not(rcc_f7))]
+        let sequence = sr.pgperr() || sr.pgserr();
+
+        // The flags are cleared by writing 1.
+        FLASH.sr().write(|w| {
+            w.set_eop(true);
+            w.set_operr(true);
+            w.set_wrperr(true);
+            w.set_pgaerr(true);
+            w.set_pgperr(true);
+            #[cfg(rcc_f7)]
+            w.set_erserr(true);
+            #[cfg(not(rcc_f7))]
+            w.set_pgserr(true);
+        });
+        return if sr.wrperr() {
+            Err(Error::WriteProtected)
+        } else if sequence {
+            Err(Error::Sequence)
+        } else if sr.operr() || sr.pgaerr() {
+            Err(Error::Programming)
+        } else {
+            Ok(())
+        };
+    }
+}
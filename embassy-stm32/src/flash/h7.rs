@@ -0,0 +1,105 @@
+//! H7 flash: uniform sectors, programmed by 256-bit flash words, with a register set per bank.
+
+use core::ptr;
+
+use super::layout::{Sector, SectorMap};
+use super::{Error, FLASH_BASE};
+use crate::pac::FLASH;
+
+pub(crate) const WRITE_SIZE: usize = 32;
+
+pub(crate) const SECTOR_MAP: SectorMap = SectorMap::uniform(crate::pac::FLASH_ERASE_SIZE as u32);
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+
+/// Programming by 64 bits.
+const PSIZE_X64: u8 = 0b11;
+
+fn bank_count() -> usize {
+    super::BANKS.len()
+}
+
+pub(crate) unsafe fn unlock() {
+    for n in 0..bank_count() {
+        let bank = FLASH.bank(n);
+        if bank.cr().read().lock() {
+            bank.keyr().write(|w| w.set_keyr(KEY1));
+            bank.keyr().write(|w| w.set_keyr(KEY2));
+        }
+    }
+}
+
+pub(crate) unsafe fn lock() {
+    for n in 0..bank_count() {
+        FLASH.bank(n).cr().modify(|w| w.set_lock(true));
+    }
+}
+
+pub(crate) unsafe fn blocking_write(offset: u32, buf: &[u8; WRITE_SIZE]) -> Result<(), Error> {
+    let bank = unwrap!(super::layout::get_sector(offset, super::BANKS, &SECTOR_MAP)).bank;
+    let regs = FLASH.bank(bank as usize);
+
+    regs.cr().modify(|w| {
+        w.set_psize(PSIZE_X64);
+        w.set_pg(true);
+    });
+
+    // The programming starts once the whole flash word was written.
+    let dst = (FLASH_BASE + offset as usize) as *mut u32;
+    for (i, word) in buf.chunks(4).enumerate() {
+        ptr::write_volatile(
+            dst.add(i),
+            u32::from_le_bytes([word[0], word[1], word[2], word[3]]),
+        );
+    }
+    cortex_m::asm::dsb();
+
+    let res = wait_ready(bank as usize);
+    regs.cr().modify(|w| w.set_pg(false));
+    res
+}
+
+pub(crate) unsafe fn blocking_erase_sector(sector: &Sector) -> Result<(), Error> {
+    let regs = FLASH.bank(sector.bank as usize);
+    regs.cr().modify(|w| {
+        w.set_psize(PSIZE_X64);
+        w.set_ser(true);
+        w.set_snb(sector.index as u8);
+    });
+    regs.cr().modify(|w| w.set_start(true));
+    let res = wait_ready(sector.bank as usize);
+    regs.cr().modify(|w| {
+        w.set_ser(false);
+        w.set_snb(0);
+    });
+    res
+}
+
+unsafe fn wait_ready(bank: usize) -> Result<(), Error> {
+    let regs = FLASH.bank(bank);
+    loop {
+        let sr = regs.sr().read();
+        if sr.bsy() || sr.qw() {
+            continue;
+        }
+        // The flags are cleared through the clear control register.
+        regs.ccr().write(|w| {
+            w.set_clr_eop(true);
+            w.set_clr_wrperr(true);
+            w.set_clr_pgserr(true);
+            w.set_clr_strberr(true);
+            w.set_clr_incerr(true);
+            w.set_clr_operr(true);
+        });
+        return if sr.wrperr() {
+            Err(Error::WriteProtected)
+        } else if sr.pgserr() || sr.strberr() || sr.incerr() {
+            Err(Error::Sequence)
+        } else if sr.operr() {
+            Err(Error::Programming)
+        } else {
+            Ok(())
+        };
+    }
+}
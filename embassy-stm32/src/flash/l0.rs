@@ -0,0 +1,77 @@
+//! L0/L1 flash: 32-bit programming, small pages erased by writing a word to them.
+
+use core::ptr;
+
+use super::layout::{Sector, SectorMap};
+use super::{Error, FLASH_BASE};
+use crate::pac::FLASH;
+
+pub(crate) const WRITE_SIZE: usize = 4;
+
+pub(crate) const SECTOR_MAP: SectorMap = SectorMap::uniform(crate::pac::FLASH_ERASE_SIZE as u32);
+
+const PEKEY1: u32 = 0x89AB_CDEF;
+const PEKEY2: u32 = 0x0203_0405;
+const PRGKEY1: u32 = 0x8C9D_AEBF;
+const PRGKEY2: u32 = 0x1314_1516;
+
+pub(crate) unsafe fn unlock() {
+    if FLASH.pecr().read().pelock() {
+        FLASH.pekeyr().write(|w| w.set_pekeyr(PEKEY1));
+        FLASH.pekeyr().write(|w| w.set_pekeyr(PEKEY2));
+    }
+    if FLASH.pecr().read().prglock() {
+        FLASH.prgkeyr().write(|w| w.set_prgkeyr(PRGKEY1));
+        FLASH.prgkeyr().write(|w| w.set_prgkeyr(PRGKEY2));
+    }
+}
+
+pub(crate) unsafe fn lock() {
+    // Setting PELOCK also locks the program memory.
+    FLASH.pecr().modify(|w| w.set_pelock(true));
+}
+
+pub(crate) unsafe fn blocking_write(offset: u32, buf: &[u8; WRITE_SIZE]) -> Result<(), Error> {
+    ptr::write_volatile(
+        (FLASH_BASE + offset as usize) as *mut u32,
+        u32::from_le_bytes(*buf),
+    );
+    wait_ready()
+}
+
+pub(crate) unsafe fn blocking_erase_sector(sector: &Sector) -> Result<(), Error> {
+    FLASH.pecr().modify(|w| {
+        w.set_erase(true);
+        w.set_prog(true);
+    });
+    ptr::write_volatile((FLASH_BASE + sector.start as usize) as *mut u32, 0);
+    let res = wait_ready();
+    FLASH.pecr().modify(|w| {
+        w.set_erase(false);
+        w.set_prog(false);
+    });
+    res
+}
+
+unsafe fn wait_ready() -> Result<(), Error> {
+    loop {
+        let sr = FLASH.sr().read();
+        if sr.bsy() {
+            continue;
+        }
+        // The flags are cleared by writing 1.
+        FLASH.sr().write(|w| {
+            w.set_eop(true);
+            w.set_wrperr(true);
+            w.set_pgaerr(true);
+            w.set_sizerr(true);
+        });
+        return if sr.wrperr() {
+            Err(Error::WriteProtected)
+        } else if sr.pgaerr() || sr.sizerr() {
+            Err(Error::Programming)
+        } else {
+            Ok(())
+        };
+    }
+}
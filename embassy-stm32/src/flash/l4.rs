@@ -0,0 +1,89 @@
+//! G0/G4/L4/WB/WL flash: uniform pages, 64-bit programming.
+//!
+//! The page size depends on the chip, and on the bank configuration of G47x/G48x and L4+.
+
+use core::ptr;
+
+use super::layout::{Sector, SectorMap};
+use super::{Error, FLASH_BASE};
+use crate::pac::FLASH;
+
+pub(crate) const WRITE_SIZE: usize = 8;
+
+pub(crate) const SECTOR_MAP: SectorMap = SectorMap::uniform(crate::pac::FLASH_ERASE_SIZE as u32);
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+
+pub(crate) unsafe fn unlock() {
+    if FLASH.cr().read().lock() {
+        FLASH.keyr().write(|w| w.set_keyr(KEY1));
+        FLASH.keyr().write(|w| w.set_keyr(KEY2));
+    }
+}
+
+pub(crate) unsafe fn lock() {
+    FLASH.cr().modify(|w| w.set_lock(true));
+}
+
+pub(crate) unsafe fn blocking_write(offset: u32, buf: &[u8; WRITE_SIZE]) -> Result<(), Error> {
+    FLASH.cr().modify(|w| w.set_pg(true));
+
+    // Both words must be written back to back, the programming starts after the second.
+    let dst = (FLASH_BASE + offset as usize) as *mut u32;
+    ptr::write_volatile(dst, u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]));
+    ptr::write_volatile(
+        dst.add(1),
+        u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+    );
+
+    let res = wait_ready();
+    FLASH.cr().modify(|w| w.set_pg(false));
+    res
+}
+
+pub(crate) unsafe fn blocking_erase_sector(sector: &Sector) -> Result<(), Error> {
+    FLASH.cr().modify(|w| {
+        w.set_per(true);
+        w.set_pnb(sector.index as u8);
+        #[cfg(any(rcc_g0, rcc_g4, rcc_l4))]
+        w.set_bker(sector.bank == 1);
+    });
+    FLASH.cr().modify(|w| w.set_strt(true));
+    let res = wait_ready();
+    FLASH.cr().modify(|w| {
+        w.set_per(false);
+        w.set_pnb(0);
+    });
+    res
+}
+
+unsafe fn wait_ready() -> Result<(), Error> {
+    loop {
+        let sr = FLASH.sr().read();
+        if sr.bsy() {
+            continue;
+        }
+        // The flags are cleared by writing 1.
+        FLASH.sr().write(|w| {
+            w.set_eop(true);
+            w.set_operr(true);
+            w.set_progerr(true);
+            w.set_wrperr(true);
+            w.set_pgaerr(true);
+            w.set_sizerr(true);
+            w.set_pgserr(true);
+            w.set_miserr(true);
+            w.set_fasterr(true);
+        });
+        return if sr.wrperr() {
+            Err(Error::WriteProtected)
+        } else if sr.pgserr() || sr.miserr() || sr.fasterr() {
+            Err(Error::Sequence)
+        } else if sr.operr() || sr.progerr() || sr.pgaerr() || sr.sizerr() {
+            Err(Error::Programming)
+        } else {
+            Ok(())
+        };
+    }
+}
@@ -0,0 +1,199 @@
+//! Flash sector maps.
+//!
+//! The banks and, for the flashes with uniform pages, the page size come from the chip
+//! metadata. The F2/F4/F7 families describe the sector sizes of a bank with a [`SectorMap`].
+
+/// A flash bank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bank {
+    /// Offset of the bank from the start of the flash.
+    pub offset: u32,
+    /// Size of the bank, in bytes.
+    pub size: u32,
+}
+
+/// Sector sizes of a bank: the sizes in `head`, then sectors of `tail` bytes up to the end of
+/// the bank.
+pub(crate) struct SectorMap {
+    pub head: &'static [u32],
+    pub tail: u32,
+}
+
+impl SectorMap {
+    pub(crate) const fn uniform(size: u32) -> Self {
+        Self {
+            head: &[],
+            tail: size,
+        }
+    }
+
+    /// Returns the size of the largest sector.
+    pub(crate) const fn max_sector_size(&self) -> u32 {
+        let mut max = self.tail;
+        let mut i = 0;
+        while i < self.head.len() {
+            if self.head[i] > max {
+                max = self.head[i];
+            }
+            i += 1;
+        }
+        max
+    }
+}
+
+const K: u32 = 1024;
+
+/// Sectors of the F2/F4 banks, and of the F7s with up to 512 KiB of flash.
+pub(crate) const F4_SECTOR_MAP: SectorMap = SectorMap {
+    head: &[16 * K, 16 * K, 16 * K, 16 * K, 64 * K],
+    tail: 128 * K,
+};
+
+/// Sectors of the F7s with more than 512 KiB of flash.
+pub(crate) const F7_LARGE_SECTOR_MAP: SectorMap = SectorMap {
+    head: &[32 * K, 32 * K, 32 * K, 32 * K, 128 * K],
+    tail: 256 * K,
+};
+
+/// An erasable sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Sector {
+    /// Index of the bank the sector is in.
+    pub bank: u8,
+    /// Index of the sector in its bank.
+    pub index: u16,
+    /// Offset of the sector from the start of the flash.
+    pub start: u32,
+    /// Size of the sector, in bytes.
+    pub size: u32,
+}
+
+/// Returns the sector containing `offset`, or `None` if `offset` is outside of the banks.
+pub(crate) fn get_sector(offset: u32, banks: &[Bank], map: &SectorMap) -> Option<Sector> {
+    let (bank_index, bank) = banks
+        .iter()
+        .enumerate()
+        .find(|(_, b)| offset >= b.offset && offset - b.offset < b.size)?;
+
+    let mut start = bank.offset;
+    for (index, &size) in map.head.iter().enumerate() {
+        if offset < start + size {
+            return Some(Sector {
+                bank: bank_index as u8,
+                index: index as u16,
+                start,
+                size,
+            });
+        }
+        start += size;
+    }
+
+    let n = (offset - start) / map.tail;
+    Some(Sector {
+        bank: bank_index as u8,
+        index: (map.head.len() as u32 + n) as u16,
+        start: start + n * map.tail,
+        size: map.tail,
+    })
+}
+
+/// Returns the total size of the banks.
+pub(crate) fn total_size(banks: &[Bank]) -> u32 {
+    banks.iter().map(|b| b.size).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // STM32F429ZI: 2 MiB, dual bank.
+    const F4_BANKS: &[Bank] = &[
+        Bank {
+            offset: 0,
+            size: 1024 * K,
+        },
+        Bank {
+            offset: 1024 * K,
+            size: 1024 * K,
+        },
+    ];
+
+    #[test]
+    fn f4_sectors() {
+        let sector = |offset| get_sector(offset, F4_BANKS, &F4_SECTOR_MAP).unwrap();
+
+        assert_eq!(
+            sector(0),
+            Sector {
+                bank: 0,
+                index: 0,
+                start: 0,
+                size: 16 * K
+            }
+        );
+        assert_eq!(sector(48 * K + 1).index, 3);
+        assert_eq!(
+            sector(64 * K),
+            Sector {
+                bank: 0,
+                index: 4,
+                start: 64 * K,
+                size: 64 * K
+            }
+        );
+        assert_eq!(
+            sector(1024 * K - 1),
+            Sector {
+                bank: 0,
+                index: 11,
+                start: 896 * K,
+                size: 128 * K
+            }
+        );
+        assert_eq!(
+            sector(1024 * K + 20 * K),
+            Sector {
+                bank: 1,
+                index: 1,
+                start: 1040 * K,
+                size: 16 * K
+            }
+        );
+        assert_eq!(sector(2048 * K - 1).index, 11);
+        assert_eq!(get_sector(2048 * K, F4_BANKS, &F4_SECTOR_MAP), None);
+    }
+
+    #[test]
+    fn uniform_pages() {
+        // STM32G474RE in single bank mode: 512 KiB of 4 KiB pages.
+        let banks = &[Bank {
+            offset: 0,
+            size: 512 * K,
+        }];
+        let map = SectorMap::uniform(4 * K);
+        assert_eq!(
+            get_sector(511 * K, banks, &map),
+            Some(Sector {
+                bank: 0,
+                index: 127,
+                start: 508 * K,
+                size: 4 * K
+            })
+        );
+        assert_eq!(get_sector(512 * K, banks, &map), None);
+        assert_eq!(total_size(banks), 512 * K);
+    }
+
+    #[test]
+    fn max_sector_size() {
+        assert_eq!(F4_SECTOR_MAP.max_sector_size(), 128 * K);
+        assert_eq!(F7_LARGE_SECTOR_MAP.max_sector_size(), 256 * K);
+        assert_eq!(SectorMap::uniform(128).max_sector_size(), 128);
+        let map = SectorMap {
+            head: &[256 * K],
+            tail: 128 * K,
+        };
+        assert_eq!(map.max_sector_size(), 256 * K);
+    }
+}
@@ -0,0 +1,266 @@
+//! Internal flash.
+//!
+//! The flash is available through the blocking `embedded-storage` traits and the async
+//! `embassy::traits::flash::Flash` trait. Offsets are relative to the start of the flash, and
+//! the CPU stalls on flash reads while the flash is being written or erased, so the async
+//! versions complete without yielding.
+//!
+//! The sectors can have different sizes (F4/F7): erase ranges must start and end on sector
+//! boundaries, see [`Flash::sector`].
+
+use core::future::Future;
+use core::marker::PhantomData;
+use core::slice;
+
+use embassy::traits::flash::Error as FlashError;
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+use crate::peripherals::FLASH;
+
+mod layout;
+pub use layout::{Bank, Sector};
+
+#[cfg_attr(any(rcc_f0, rcc_f0x0, rcc_f1, rcc_f3), path = "f0.rs")]
+#[cfg_attr(any(rcc_f4, rcc_f410, rcc_f7), path = "f4.rs")]
+#[cfg_attr(any(rcc_g0, rcc_g4, rcc_l4, rcc_wb, rcc_wl5), path = "l4.rs")]
+#[cfg_attr(any(rcc_l0, rcc_l1), path = "l0.rs")]
+#[cfg_attr(rcc_h7, path = "h7.rs")]
+mod family;
+
+/// Address of the flash in the memory map.
+pub const FLASH_BASE: usize = crate::pac::FLASH_BASE;
+/// Size of the flash, in bytes.
+pub const FLASH_SIZE: usize = crate::pac::FLASH_SIZE;
+/// Write granularity, in bytes.
+pub const WRITE_SIZE: usize = family::WRITE_SIZE;
+/// Size of the largest sector, in bytes.
+pub const MAX_ERASE_SIZE: usize = family::SECTOR_MAP.max_sector_size() as usize;
+
+const BANKS: &[Bank] = &{
+    let mut banks = [Bank { offset: 0, size: 0 }; crate::generated::FLASH_BANKS.len()];
+    let mut i = 0;
+    while i < banks.len() {
+        let (offset, size) = crate::generated::FLASH_BANKS[i];
+        banks[i] = Bank { offset, size };
+        i += 1;
+    }
+    banks
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    OutOfBounds,
+    Unaligned,
+    /// The target is write protected.
+    WriteProtected,
+    /// The flash reported a programming error, for example a write to a location that wasn't
+    /// erased.
+    Programming,
+    /// The flash reported a sequence or parallelism error.
+    Sequence,
+}
+
+impl NorFlashError for Error {
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Self::Unaligned => NorFlashErrorKind::NotAligned,
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+impl From<Error> for FlashError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Unaligned => FlashError::AddressMisaligned,
+            _ => FlashError::Failed,
+        }
+    }
+}
+
+pub struct Flash<'d> {
+    _p: PhantomData<&'d mut FLASH>,
+}
+
+impl<'d> Flash<'d> {
+    pub fn new(_p: impl Unborrow<Target = FLASH> + 'd) -> Self {
+        unborrow!(_p);
+
+        Self { _p: PhantomData }
+    }
+
+    /// Returns the banks of the flash.
+    pub fn banks(&self) -> &'static [Bank] {
+        BANKS
+    }
+
+    /// Returns the sector containing `offset`, or `None` if `offset` is out of bounds.
+    pub fn sector(&self, offset: u32) -> Option<Sector> {
+        layout::get_sector(offset, BANKS, &family::SECTOR_MAP)
+    }
+
+    pub fn blocking_read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Error> {
+        check_bounds(offset, bytes.len())?;
+
+        let flash_data = unsafe {
+            slice::from_raw_parts((FLASH_BASE + offset as usize) as *const u8, bytes.len())
+        };
+        bytes.copy_from_slice(flash_data);
+        Ok(())
+    }
+
+    /// Writes `bytes` at `offset`, which must both be aligned to [`WRITE_SIZE`].
+    ///
+    /// The target must have been erased.
+    pub fn blocking_write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error> {
+        check_bounds(offset, bytes.len())?;
+        if offset as usize % WRITE_SIZE != 0 || bytes.len() % WRITE_SIZE != 0 {
+            return Err(Error::Unaligned);
+        }
+
+        unsafe {
+            family::unlock();
+            let mut res = Ok(());
+            for (i, chunk) in bytes.chunks(WRITE_SIZE).enumerate() {
+                let mut buf = [0; WRITE_SIZE];
+                buf.copy_from_slice(chunk);
+                res = family::blocking_write(offset + (i * WRITE_SIZE) as u32, &buf);
+                if res.is_err() {
+                    break;
+                }
+            }
+            family::lock();
+            res
+        }
+    }
+
+    /// Erases the sectors from `from` to `to`, which must both be sector boundaries.
+    pub fn blocking_erase(&mut self, from: u32, to: u32) -> Result<(), Error> {
+        if from > to || to as usize > FLASH_SIZE {
+            return Err(Error::OutOfBounds);
+        }
+
+        // Check the alignment before erasing anything.
+        let mut offset = from;
+        while offset < to {
+            let sector = self.sector(offset).ok_or(Error::OutOfBounds)?;
+            if sector.start != offset || sector.start + sector.size > to {
+                return Err(Error::Unaligned);
+            }
+            offset += sector.size;
+        }
+
+        unsafe {
+            family::unlock();
+            let mut res = Ok(());
+            let mut offset = from;
+            while offset < to {
+                let sector = match self.sector(offset) {
+                    Some(sector) => sector,
+                    None => {
+                        res = Err(Error::OutOfBounds);
+                        break;
+                    }
+                };
+                res = family::blocking_erase_sector(&sector);
+                if res.is_err() {
+                    break;
+                }
+                offset += sector.size;
+            }
+            family::lock();
+            res
+        }
+    }
+}
+
+fn check_bounds(offset: u32, len: usize) -> Result<(), Error> {
+    if offset as usize + len > FLASH_SIZE {
+        return Err(Error::OutOfBounds);
+    }
+    Ok(())
+}
+
+impl<'d> ErrorType for Flash<'d> {
+    type Error = Error;
+}
+
+impl<'d> ReadNorFlash for Flash<'d> {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.blocking_read(offset, bytes)
+    }
+
+    fn capacity(&self) -> usize {
+        FLASH_SIZE
+    }
+}
+
+impl<'d> NorFlash for Flash<'d> {
+    const WRITE_SIZE: usize = WRITE_SIZE;
+    /// Any range aligned to the largest sector size starts and ends on sector boundaries.
+    const ERASE_SIZE: usize = MAX_ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        self.blocking_erase(from, to)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.blocking_write(offset, bytes)
+    }
+}
+
+impl<'d> embassy::traits::flash::Flash for Flash<'d> {
+    type ReadFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
+    type WriteFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
+    type ErasePageFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
+
+    fn read<'a>(&'a mut self, address: usize, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move { Ok(self.blocking_read(address as u32, data)?) }
+    }
+
+    fn write<'a>(&'a mut self, address: usize, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move { Ok(self.blocking_write(address as u32, data)?) }
+    }
+
+    /// Erases the sector starting at `address`.
+    fn erase<'a>(&'a mut self, address: usize) -> Self::ErasePageFuture<'a> {
+        async move {
+            let sector = self.sector(address as u32).ok_or(Error::OutOfBounds)?;
+            Ok(self.blocking_erase(address as u32, sector.start + sector.size)?)
+        }
+    }
+
+    fn size(&self) -> usize {
+        FLASH_SIZE
+    }
+
+    fn read_size(&self) -> usize {
+        1
+    }
+
+    fn write_size(&self) -> usize {
+        WRITE_SIZE
+    }
+
+    /// Returns the size of the largest sector.
+    fn erase_size(&self) -> usize {
+        MAX_ERASE_SIZE
+    }
+}
@@ -404,9 +404,10 @@ pub(crate) mod sealed {
             let crlh = if n < 8 { 0 } else { 1 };
             match af_type {
                 AFType::Input => {
+                    // Floating input: CNF=0b00 would be the analog mode, with the input buffer off.
                     r.cr(crlh).modify(|w| {
                         w.set_mode(n % 8, vals::Mode::INPUT);
-                        w.set_cnf(n % 8, vals::Cnf::PUSHPULL);
+                        w.set_cnf(n % 8, vals::Cnf::OPENDRAIN);
                     });
                 }
                 AFType::OutputPushPull => {
@@ -2,10 +2,14 @@
 
 use embassy::interrupt::Interrupt;
 
+#[cfg_attr(i2c_v1, path = "slave_v1.rs")]
+#[cfg_attr(i2c_v2, path = "slave_v2.rs")]
+mod _slave;
 #[cfg_attr(i2c_v1, path = "v1.rs")]
 #[cfg_attr(i2c_v2, path = "v2.rs")]
 mod _version;
 use crate::{dma, peripherals};
+pub use _slave::*;
 pub use _version::*;
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -19,6 +23,56 @@ pub enum Error {
     ZeroLengthTransfer,
 }
 
+/// Own address of an [`I2cSlave`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Address {
+    SevenBit(u8),
+    TenBit(u16),
+}
+
+#[non_exhaustive]
+#[derive(Clone, Copy)]
+pub struct SlaveConfig {
+    /// Primary own address.
+    pub address: Address,
+    /// Second 7-bit own address the target answers to.
+    pub secondary_address: Option<u8>,
+    /// Answer to the general call address (0).
+    pub general_call: bool,
+}
+
+impl SlaveConfig {
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            secondary_address: None,
+            general_call: false,
+        }
+    }
+}
+
+/// Transfer requested by the controller, as seen from the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command {
+    /// The controller reads: answer with [`I2cSlave::respond_to_read`].
+    Read,
+    /// The controller writes: receive with [`I2cSlave::respond_to_write`].
+    Write,
+}
+
+/// Transaction started by the controller addressing the target, returned by
+/// [`I2cSlave::listen`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Request {
+    pub command: Command,
+    /// Address used by the controller: one of the own addresses, or `SevenBit(0)` for a general
+    /// call.
+    pub address: Address,
+}
+
 pub(crate) mod sealed {
     use super::dma;
     use crate::gpio::Pin;
@@ -51,6 +105,14 @@ pub trait Instance: sealed::Instance + 'static {
     type Interrupt: Interrupt;
 }
 
+/// Instance whose error interrupt is separate from the event interrupt.
+///
+/// The target needs it, as the NACK ending a read is an error event. [`I2cSlave::new`] takes it
+/// along with the event interrupt.
+pub trait ErrorInstance: Instance {
+    type ErrorInterrupt: Interrupt;
+}
+
 pub trait SclPin<T: Instance>: sealed::SclPin<T> + 'static {}
 
 pub trait SdaPin<T: Instance>: sealed::SdaPin<T> + 'static {}
@@ -96,6 +158,14 @@ crate::pac::interrupts!(
     };
 );
 
+crate::pac::interrupts!(
+    ($inst:ident, i2c, $block:ident, ER, $irq:ident) => {
+        impl ErrorInstance for peripherals::$inst {
+            type ErrorInterrupt = crate::interrupt::$irq;
+        }
+    };
+);
+
 macro_rules! impl_pin {
     ($inst:ident, $pin:ident, $signal:ident, $af:expr) => {
         impl $signal<peripherals::$inst> for peripherals::$pin {}
@@ -0,0 +1,514 @@
+//! I2C target (slave) mode.
+//!
+//! The peripheral stretches the clock while the application prepares a response to a read, and
+//! once two received bytes are waiting to be read. The data phase ends when the controller sends
+//! a STOP, a repeated START, the latter being returned by the next [`I2cSlave::listen`], or when
+//! it NACKs the last byte it reads. The bytes are moved by the CPU from the interrupts, or by the
+//! DMA channels with the `_dma` responses.
+
+use core::marker::PhantomData;
+use core::task::{Context, Poll};
+
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::dma::NoDma;
+use crate::gpio::sealed::AFType::OutputOpenDrain;
+use crate::i2c::{
+    Address, Command, Error, ErrorInstance, Instance, Request, SclPin, SdaPin, SlaveConfig,
+};
+use crate::pac;
+use crate::pac::i2c;
+
+const I2C_COUNT: usize = pac::peripheral_count!(i2c);
+
+/// OAR1 bit without field, which must be kept at 1 by software.
+const OAR1_BIT14: u32 = 1 << 14;
+
+/// Value sent once the response to a read is exhausted.
+const FILLER: u8 = 0xff;
+
+static WAKERS: [AtomicWaker; I2C_COUNT] = {
+    const AW: AtomicWaker = AtomicWaker::new();
+    [AW; I2C_COUNT]
+};
+
+/// I2C target, answering transfers started by a controller.
+pub struct I2cSlave<'d, T: ErrorInstance, TXDMA = NoDma, RXDMA = NoDma> {
+    config: SlaveConfig,
+    phantom: PhantomData<&'d mut T>,
+    irq: T::Interrupt,
+    err_irq: T::ErrorInterrupt,
+    tx_dma: TXDMA,
+    rx_dma: RXDMA,
+}
+
+impl<'d, T: ErrorInstance, TXDMA, RXDMA> I2cSlave<'d, T, TXDMA, RXDMA> {
+    pub fn new(
+        _peri: impl Unborrow<Target = T> + 'd,
+        scl: impl Unborrow<Target = impl SclPin<T>> + 'd,
+        sda: impl Unborrow<Target = impl SdaPin<T>> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        err_irq: impl Unborrow<Target = T::ErrorInterrupt> + 'd,
+        tx_dma: impl Unborrow<Target = TXDMA> + 'd,
+        rx_dma: impl Unborrow<Target = RXDMA> + 'd,
+        config: SlaveConfig,
+    ) -> Self {
+        unborrow!(irq, err_irq, scl, sda, tx_dma, rx_dma);
+
+        T::enable();
+
+        unsafe {
+            scl.set_as_af(scl.af_num(), OutputOpenDrain);
+            sda.set_as_af(sda.af_num(), OutputOpenDrain);
+        }
+
+        // The controller drives the clock, the peripheral clock frequency is only used for the
+        // data setup time.
+        let freq = T::frequency().0 / 1_000_000;
+        assert!(freq >= 2 && freq <= 50);
+
+        let regs = T::regs();
+        unsafe {
+            regs.cr1().modify(|w| w.set_pe(false));
+            regs.cr2().modify(|w| w.set_freq(freq as u8));
+
+            regs.oar1().write(|w| {
+                w.0 = OAR1_BIT14;
+                match config.address {
+                    Address::SevenBit(addr) => {
+                        w.set_addmode(i2c::vals::Addmode::ADD7);
+                        w.set_add((addr as u16) << 1);
+                    }
+                    Address::TenBit(addr) => {
+                        w.set_addmode(i2c::vals::Addmode::ADD10);
+                        w.set_add(addr & 0x3ff);
+                    }
+                }
+            });
+            regs.oar2().write(|w| {
+                if let Some(addr) = config.secondary_address {
+                    w.set_endual(i2c::vals::Endual::DUAL);
+                    w.set_add2(addr);
+                }
+            });
+
+            regs.cr1().modify(|w| {
+                w.set_engc(config.general_call);
+                w.set_pe(true);
+            });
+            // ACK is cleared while the peripheral is disabled.
+            regs.cr1().modify(|w| w.set_ack(true));
+        }
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        err_irq.set_handler(Self::on_interrupt);
+        err_irq.unpend();
+        err_irq.enable();
+
+        Self {
+            config,
+            phantom: PhantomData,
+            irq,
+            err_irq,
+            tx_dma,
+            rx_dma,
+        }
+    }
+
+    unsafe fn on_interrupt(_: *mut ()) {
+        // The flags are handled by the task, which enables the interrupts it waits for again.
+        T::regs().cr2().modify(|w| {
+            w.set_itevten(false);
+            w.set_itbufen(false);
+            w.set_iterren(false);
+        });
+        WAKERS[T::state_number()].wake();
+    }
+
+    /// Waits for the controller to address the target.
+    ///
+    /// [`Self::respond_to_read`] or [`Self::respond_to_write`] (or their DMA counterparts) must
+    /// follow. The clock is stretched until the response to a read starts.
+    pub async fn listen(&mut self) -> Result<Request, Error> {
+        let regs = T::regs();
+        poll_fn(|cx| unsafe {
+            WAKERS[T::state_number()].register(cx.waker());
+
+            let sr1 = Self::check_and_clear_error_flags()?;
+
+            if sr1.addr() {
+                // Reading SR2 after SR1 clears ADDR.
+                let sr2 = regs.sr2().read();
+                return Poll::Ready(Ok(self.request(sr2)));
+            }
+
+            // Left over from a transaction which ended without data, e.g. an address probe.
+            if sr1.af() {
+                regs.sr1().modify(|w| w.set_af(false));
+            }
+            if sr1.stopf() {
+                Self::clear_stopf();
+            }
+
+            Self::enable_interrupts(|w| {
+                w.set_itevten(true);
+                w.set_iterren(true);
+            });
+            Poll::Pending
+        })
+        .await
+    }
+
+    fn request(&self, sr2: i2c::regs::Sr2) -> Request {
+        let command = if sr2.tra() {
+            Command::Read
+        } else {
+            Command::Write
+        };
+
+        let address = if sr2.gencall() {
+            Address::SevenBit(0)
+        } else if sr2.dualf() {
+            Address::SevenBit(unwrap!(self.config.secondary_address))
+        } else {
+            self.config.address
+        };
+
+        Request { command, address }
+    }
+
+    /// Sends `bytes` to the controller, after a [`Command::Read`] request.
+    ///
+    /// 0xFF bytes are sent if the controller reads more. Returns the number of bytes of
+    /// `bytes` read by the controller.
+    pub async fn respond_to_read(&mut self, bytes: &[u8]) -> Result<usize, Error> {
+        let mut tx = TxProgress::default();
+
+        poll_fn(|cx| Self::poll_transmit(cx, bytes, &mut tx, false)).await?;
+
+        Ok(tx.written)
+    }
+
+    /// Receives the bytes written by the controller into `buffer`, after a [`Command::Write`]
+    /// request.
+    ///
+    /// Returns the number of bytes received, or [`Error::Overrun`] if the controller wrote more
+    /// than `buffer` holds. The extra bytes are dropped.
+    pub async fn respond_to_write(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let mut rx = RxProgress::default();
+
+        poll_fn(|cx| Self::poll_receive(cx, buffer, &mut rx, false)).await?;
+
+        rx.result(rx.received)
+    }
+
+    /// Same as [`Self::respond_to_read`], with the bytes moved by the TX DMA channel.
+    pub async fn respond_to_read_dma(&mut self, bytes: &[u8]) -> Result<usize, Error>
+    where
+        TXDMA: crate::i2c::TxDma<T>,
+    {
+        if bytes.is_empty() {
+            return self.respond_to_read(bytes).await;
+        }
+        assert!(bytes.len() <= 0xFFFF);
+
+        let regs = T::regs();
+        let ch = StopOnDrop::new(&mut self.tx_dma);
+        let request = ch.0.request();
+        unsafe {
+            ch.0.start_write(request, bytes, regs.dr().ptr() as *mut u8);
+            regs.cr2().modify(|w| w.set_dmaen(true));
+        }
+        let _disable_dma = DisableDmaOnDrop::<T>::new();
+
+        // The channel writes the first byte, the CPU only sends filler bytes once it's done.
+        let mut tx = TxProgress {
+            started: true,
+            ..Default::default()
+        };
+        let res = poll_fn(|cx| {
+            ch.0.set_waker(cx.waker());
+            Self::poll_transmit(cx, &[], &mut tx, ch.0.is_running())
+        })
+        .await;
+
+        let remaining = ch.stop();
+        if tx.unsent {
+            // The channel wrote a byte to DR while the controller NACKed the previous one. DR is
+            // only emptied by disabling the peripheral, which also clears ACK.
+            unsafe {
+                regs.cr1().modify(|w| w.set_pe(false));
+                regs.cr1().modify(|w| w.set_pe(true));
+                regs.cr1().modify(|w| w.set_ack(true));
+            }
+        }
+        res?;
+        Ok(bytes.len() - remaining - tx.unsent as usize)
+    }
+
+    /// Same as [`Self::respond_to_write`], with the bytes moved by the RX DMA channel.
+    pub async fn respond_to_write_dma(&mut self, buffer: &mut [u8]) -> Result<usize, Error>
+    where
+        RXDMA: crate::i2c::RxDma<T>,
+    {
+        if buffer.is_empty() {
+            return self.respond_to_write(buffer).await;
+        }
+        assert!(buffer.len() <= 0xFFFF);
+
+        let regs = T::regs();
+        let len = buffer.len();
+        let ch = StopOnDrop::new(&mut self.rx_dma);
+        let request = ch.0.request();
+        unsafe {
+            ch.0.start_read(request, regs.dr().ptr() as *mut u8, buffer);
+            regs.cr2().modify(|w| w.set_dmaen(true));
+        }
+        let _disable_dma = DisableDmaOnDrop::<T>::new();
+
+        let mut rx = RxProgress::default();
+        let res = poll_fn(|cx| {
+            ch.0.set_waker(cx.waker());
+            // The CPU only drops the bytes which don't fit, once the channel filled `buffer`.
+            Self::poll_receive(cx, &mut [], &mut rx, ch.0.is_running())
+        })
+        .await;
+
+        let mut received = len - ch.stop();
+        // The last byte may not have been moved yet when the STOP condition was seen.
+        unsafe {
+            if regs.sr1().read().rx_ne() {
+                let byte = regs.dr().read().dr();
+                if received < len {
+                    buffer[received] = byte;
+                    received += 1;
+                } else {
+                    rx.overrun = true;
+                }
+            }
+        }
+        res?;
+        rx.result(received)
+    }
+
+    fn poll_transmit(
+        cx: &mut Context<'_>,
+        bytes: &[u8],
+        tx: &mut TxProgress,
+        dma_running: bool,
+    ) -> Poll<Result<(), Error>> {
+        let regs = T::regs();
+        WAKERS[T::state_number()].register(cx.waker());
+
+        unsafe {
+            loop {
+                let sr1 = Self::check_and_clear_error_flags()?;
+
+                // NACK of the last byte read by the controller.
+                if sr1.af() {
+                    regs.sr1().modify(|w| w.set_af(false));
+                    tx.unsent = !sr1.tx_e();
+                    return Poll::Ready(Ok(()));
+                }
+                if sr1.stopf() {
+                    Self::clear_stopf();
+                    return Poll::Ready(Ok(()));
+                }
+                if sr1.addr() {
+                    return Poll::Ready(Ok(()));
+                }
+
+                // A byte is only written once the previous one was sent (BTF), else it would be
+                // left in DR and sent first by the next response if the controller NACKs.
+                if sr1.tx_e() && (sr1.btf() || !tx.started) && !dma_running {
+                    tx.started = true;
+                    let byte = match bytes.get(tx.written) {
+                        Some(&byte) => {
+                            tx.written += 1;
+                            byte
+                        }
+                        None => FILLER,
+                    };
+                    regs.dr().write(|w| w.set_dr(byte));
+                    continue;
+                }
+
+                break;
+            }
+
+            let first = !tx.started;
+            Self::enable_interrupts(|w| {
+                w.set_itevten(true);
+                w.set_itbufen(first);
+                w.set_iterren(true);
+            });
+        }
+        Poll::Pending
+    }
+
+    fn poll_receive(
+        cx: &mut Context<'_>,
+        buffer: &mut [u8],
+        rx: &mut RxProgress,
+        dma_running: bool,
+    ) -> Poll<Result<(), Error>> {
+        let regs = T::regs();
+        WAKERS[T::state_number()].register(cx.waker());
+
+        unsafe {
+            loop {
+                let sr1 = Self::check_and_clear_error_flags()?;
+
+                if sr1.rx_ne() && !dma_running {
+                    let byte = regs.dr().read().dr();
+                    match buffer.get_mut(rx.received) {
+                        Some(b) => {
+                            *b = byte;
+                            rx.received += 1;
+                        }
+                        None => rx.overrun = true,
+                    }
+                    continue;
+                }
+
+                if sr1.stopf() {
+                    Self::clear_stopf();
+                    return Poll::Ready(Ok(()));
+                }
+                if sr1.addr() {
+                    return Poll::Ready(Ok(()));
+                }
+
+                break;
+            }
+
+            Self::enable_interrupts(|w| {
+                w.set_itevten(true);
+                w.set_itbufen(!dma_running);
+                w.set_iterren(true);
+            });
+        }
+        Poll::Pending
+    }
+
+    unsafe fn clear_stopf() {
+        // STOPF is cleared by writing CR1 after reading SR1.
+        T::regs().cr1().modify(|_| {});
+    }
+
+    unsafe fn enable_interrupts(f: impl FnOnce(&mut i2c::regs::Cr2)) {
+        // The interrupt handler disables them concurrently.
+        critical_section::with(|_| T::regs().cr2().modify(f));
+    }
+
+    unsafe fn check_and_clear_error_flags() -> Result<i2c::regs::Sr1, Error> {
+        let regs = T::regs();
+        let sr1 = regs.sr1().read();
+
+        if sr1.berr() {
+            regs.sr1().modify(|w| w.set_berr(false));
+            Err(Error::Bus)
+        } else if sr1.arlo() {
+            regs.sr1().modify(|w| w.set_arlo(false));
+            Err(Error::Arbitration)
+        } else if sr1.ovr() {
+            regs.sr1().modify(|w| w.set_ovr(false));
+            Err(Error::Overrun)
+        } else {
+            Ok(sr1)
+        }
+    }
+}
+
+#[derive(Default)]
+struct TxProgress {
+    /// Number of bytes of the response written by the CPU.
+    written: usize,
+    /// A byte was written to DR.
+    started: bool,
+    /// The last byte written was not sent.
+    unsent: bool,
+}
+
+#[derive(Default)]
+struct RxProgress {
+    /// Number of bytes stored by the CPU.
+    received: usize,
+    /// Bytes were dropped.
+    overrun: bool,
+}
+
+impl RxProgress {
+    fn result(&self, received: usize) -> Result<usize, Error> {
+        if self.overrun {
+            Err(Error::Overrun)
+        } else {
+            Ok(received)
+        }
+    }
+}
+
+impl<'d, T: ErrorInstance, TXDMA, RXDMA> Drop for I2cSlave<'d, T, TXDMA, RXDMA> {
+    fn drop(&mut self) {
+        self.irq.disable();
+        self.irq.remove_handler();
+        self.err_irq.disable();
+        self.err_irq.remove_handler();
+
+        unsafe { T::regs().cr1().modify(|w| w.set_pe(false)) };
+    }
+}
+
+/// Stops the channel if the transfer is dropped.
+struct StopOnDrop<'a, C: crate::dma::Channel>(&'a mut C);
+
+impl<'a, C: crate::dma::Channel> StopOnDrop<'a, C> {
+    fn new(ch: &'a mut C) -> Self {
+        Self(ch)
+    }
+
+    /// Stops the channel, and returns the number of transfers it didn't do.
+    fn stop(self) -> usize {
+        self.0.request_stop();
+        while self.0.is_running() {}
+        self.0.remaining_transfers() as usize
+    }
+}
+
+impl<'a, C: crate::dma::Channel> Drop for StopOnDrop<'a, C> {
+    fn drop(&mut self) {
+        self.0.request_stop();
+        while self.0.is_running() {}
+    }
+}
+
+/// Disables the DMA requests once the transfer is done or dropped.
+struct DisableDmaOnDrop<T: Instance> {
+    /// The DMA stops in STOP mode.
+    #[cfg(feature = "low-power")]
+    _stop_veto: crate::low_power::StopVeto,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Instance> DisableDmaOnDrop<T> {
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "low-power")]
+            _stop_veto: crate::low_power::StopVeto::new(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Instance> Drop for DisableDmaOnDrop<T> {
+    fn drop(&mut self) {
+        unsafe { T::regs().cr2().modify(|w| w.set_dmaen(false)) }
+    }
+}
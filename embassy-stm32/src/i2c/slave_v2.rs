@@ -0,0 +1,540 @@
+//! I2C target (slave) mode.
+//!
+//! The peripheral stretches the clock from the address match until the application responds, so
+//! a response can be prepared after the request is known. The data phase ends when the controller
+//! sends a STOP or a repeated START, the latter being returned by the next [`I2cSlave::listen`].
+
+use core::marker::PhantomData;
+use core::task::{Context, Poll};
+
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use super::_version::Timings;
+use crate::dma::NoDma;
+use crate::gpio::sealed::AFType::OutputOpenDrain;
+use crate::i2c::{
+    Address, Command, Error, ErrorInstance, Instance, Request, SclPin, SdaPin, SlaveConfig,
+};
+use crate::pac;
+use crate::pac::i2c;
+use crate::time::Hertz;
+
+const I2C_COUNT: usize = pac::peripheral_count!(i2c);
+
+/// Value sent once the response to a read is exhausted.
+const FILLER: u8 = 0xff;
+
+static WAKERS: [AtomicWaker; I2C_COUNT] = {
+    const AW: AtomicWaker = AtomicWaker::new();
+    [AW; I2C_COUNT]
+};
+
+/// I2C target, answering transfers started by a controller.
+pub struct I2cSlave<'d, T: ErrorInstance, TXDMA = NoDma, RXDMA = NoDma> {
+    config: SlaveConfig,
+    phantom: PhantomData<&'d mut T>,
+    irq: T::Interrupt,
+    err_irq: T::ErrorInterrupt,
+    tx_dma: TXDMA,
+    rx_dma: RXDMA,
+}
+
+impl<'d, T: ErrorInstance, TXDMA, RXDMA> I2cSlave<'d, T, TXDMA, RXDMA> {
+    pub fn new(
+        _peri: impl Unborrow<Target = T> + 'd,
+        scl: impl Unborrow<Target = impl SclPin<T>> + 'd,
+        sda: impl Unborrow<Target = impl SdaPin<T>> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        err_irq: impl Unborrow<Target = T::ErrorInterrupt> + 'd,
+        tx_dma: impl Unborrow<Target = TXDMA> + 'd,
+        rx_dma: impl Unborrow<Target = RXDMA> + 'd,
+        config: SlaveConfig,
+    ) -> Self {
+        unborrow!(irq, err_irq, scl, sda, tx_dma, rx_dma);
+
+        T::enable();
+
+        unsafe {
+            scl.set_as_af(scl.af_num(), OutputOpenDrain);
+            sda.set_as_af(sda.af_num(), OutputOpenDrain);
+        }
+
+        // The controller drives the clock, only the data setup and hold times are used. The
+        // standard mode ones are the longest, and suit all modes thanks to clock stretching.
+        let timings = Timings::new(T::frequency(), Hertz(100_000));
+
+        let regs = T::regs();
+        unsafe {
+            regs.cr1().modify(|w| {
+                w.set_pe(false);
+                w.set_anfoff(false);
+            });
+            regs.timingr().write(|w| {
+                w.set_presc(timings.prescale);
+                w.set_sdadel(timings.sdadel);
+                w.set_scldel(timings.scldel);
+            });
+
+            // The own addresses can only be changed while disabled.
+            regs.oar1().modify(|w| w.set_oa1en(false));
+            regs.oar2().modify(|w| w.set_oa2en(false));
+            regs.oar1().write(|w| {
+                match config.address {
+                    Address::SevenBit(addr) => {
+                        w.set_oa1mode(i2c::vals::Addmode::BIT7);
+                        w.set_oa1((addr as u16) << 1);
+                    }
+                    Address::TenBit(addr) => {
+                        w.set_oa1mode(i2c::vals::Addmode::BIT10);
+                        w.set_oa1(addr & 0x3ff);
+                    }
+                }
+                w.set_oa1en(true);
+            });
+            if let Some(addr) = config.secondary_address {
+                regs.oar2().write(|w| {
+                    w.set_oa2(addr);
+                    w.set_oa2en(true);
+                });
+            }
+
+            regs.cr1().modify(|w| {
+                w.set_gcen(config.general_call);
+                w.set_pe(true);
+            });
+        }
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        err_irq.set_handler(Self::on_interrupt);
+        err_irq.unpend();
+        err_irq.enable();
+
+        Self {
+            config,
+            phantom: PhantomData,
+            irq,
+            err_irq,
+            tx_dma,
+            rx_dma,
+        }
+    }
+
+    unsafe fn on_interrupt(_: *mut ()) {
+        // The flags are handled by the task, which enables the interrupts it waits for again.
+        T::regs().cr1().modify(|w| {
+            w.set_addrie(false);
+            w.set_stopie(false);
+            w.set_nackie(false);
+            w.set_rxie(false);
+            w.set_txie(false);
+            w.set_errie(false);
+        });
+        WAKERS[T::state_number()].wake();
+    }
+
+    /// Waits for the controller to address the target.
+    ///
+    /// The clock is stretched until the request is answered with [`Self::respond_to_read`] or
+    /// [`Self::respond_to_write`] (or their DMA counterparts), which must follow. A read from a
+    /// 10-bit address is preceded by a write request without data, answered by an empty
+    /// [`Self::respond_to_write`].
+    pub async fn listen(&mut self) -> Result<Request, Error> {
+        let regs = T::regs();
+        poll_fn(|cx| unsafe {
+            WAKERS[T::state_number()].register(cx.waker());
+
+            let isr = regs.isr().read();
+            Self::check_and_clear_error_flags(isr)?;
+
+            if isr.addr() {
+                return Poll::Ready(Ok(self.request(isr)));
+            }
+
+            // Left over from a transaction which ended without data, e.g. an address probe.
+            if isr.stopf() || isr.nackf() {
+                regs.icr().write(|w| {
+                    w.set_stopcf(true);
+                    w.set_nackcf(true);
+                });
+            }
+
+            Self::enable_interrupts(|w| {
+                w.set_addrie(true);
+                w.set_errie(true);
+            });
+            Poll::Pending
+        })
+        .await
+    }
+
+    fn request(&self, isr: i2c::regs::Isr) -> Request {
+        let command = if isr.dir() == i2c::vals::Dir::READ {
+            Command::Read
+        } else {
+            Command::Write
+        };
+
+        // The code of a 10-bit address is its header, which can't be a 7-bit address.
+        let code = isr.addcode();
+        let address = if code == 0 || self.config.secondary_address == Some(code) {
+            Address::SevenBit(code)
+        } else {
+            self.config.address
+        };
+
+        Request { command, address }
+    }
+
+    /// Sends `bytes` to the controller, after a [`Command::Read`] request.
+    ///
+    /// 0xFF bytes are sent if the controller reads more. Returns the number of bytes of
+    /// `bytes` read by the controller.
+    pub async fn respond_to_read(&mut self, bytes: &[u8]) -> Result<usize, Error> {
+        let mut tx = TxProgress::default();
+
+        Self::start_response(true);
+        poll_fn(|cx| Self::poll_transmit(cx, bytes, &mut tx, false)).await?;
+
+        Ok(tx.sent(tx.written))
+    }
+
+    /// Receives the bytes written by the controller into `buffer`, after a [`Command::Write`]
+    /// request.
+    ///
+    /// Returns the number of bytes received, or [`Error::Overrun`] if the controller wrote more
+    /// than `buffer` holds. The extra bytes are dropped.
+    pub async fn respond_to_write(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let mut rx = RxProgress::default();
+
+        Self::start_response(false);
+        poll_fn(|cx| Self::poll_receive(cx, buffer, &mut rx, false)).await?;
+
+        rx.result(rx.received)
+    }
+
+    /// Same as [`Self::respond_to_read`], with the bytes moved by the TX DMA channel.
+    pub async fn respond_to_read_dma(&mut self, bytes: &[u8]) -> Result<usize, Error>
+    where
+        TXDMA: crate::i2c::TxDma<T>,
+    {
+        if bytes.is_empty() {
+            return self.respond_to_read(bytes).await;
+        }
+        assert!(bytes.len() <= 0xFFFF);
+
+        let regs = T::regs();
+        let ch = StopOnDrop::new(&mut self.tx_dma);
+        let request = ch.0.request();
+        unsafe {
+            ch.0.start_write(request, bytes, regs.txdr().ptr() as *mut u8);
+            regs.cr1().modify(|w| w.set_txdmaen(true));
+        }
+        let _disable_dma = DisableDmaOnDrop::<T>::new();
+
+        let mut tx = TxProgress::default();
+        Self::start_response(true);
+        let res = poll_fn(|cx| {
+            ch.0.set_waker(cx.waker());
+            // The CPU only sends filler bytes, once the channel wrote all the bytes.
+            Self::poll_transmit(cx, &[], &mut tx, ch.0.is_running())
+        })
+        .await;
+
+        let remaining = ch.stop();
+        res?;
+        Ok(tx.sent(bytes.len() - remaining))
+    }
+
+    /// Same as [`Self::respond_to_write`], with the bytes moved by the RX DMA channel.
+    pub async fn respond_to_write_dma(&mut self, buffer: &mut [u8]) -> Result<usize, Error>
+    where
+        RXDMA: crate::i2c::RxDma<T>,
+    {
+        if buffer.is_empty() {
+            return self.respond_to_write(buffer).await;
+        }
+        assert!(buffer.len() <= 0xFFFF);
+
+        let regs = T::regs();
+        let len = buffer.len();
+        let ch = StopOnDrop::new(&mut self.rx_dma);
+        let request = ch.0.request();
+        unsafe {
+            ch.0.start_read(request, regs.rxdr().ptr() as *mut u8, buffer);
+            regs.cr1().modify(|w| w.set_rxdmaen(true));
+        }
+        let _disable_dma = DisableDmaOnDrop::<T>::new();
+
+        let mut rx = RxProgress::default();
+        Self::start_response(false);
+        let res = poll_fn(|cx| {
+            ch.0.set_waker(cx.waker());
+            // The CPU only drops the bytes which don't fit, once the channel filled `buffer`.
+            Self::poll_receive(cx, &mut [], &mut rx, ch.0.is_running())
+        })
+        .await;
+
+        let mut received = len - ch.stop();
+        // The last byte may not have been moved yet when the STOP condition was seen.
+        unsafe {
+            if regs.isr().read().rxne() {
+                let byte = regs.rxdr().read().rxdata();
+                if received < len {
+                    buffer[received] = byte;
+                    received += 1;
+                } else {
+                    rx.overrun = true;
+                }
+            }
+        }
+        res?;
+        rx.result(received)
+    }
+
+    /// Releases the clock stretched since the address match.
+    fn start_response(transmit: bool) {
+        let regs = T::regs();
+        unsafe {
+            if transmit {
+                // Drop the byte left unsent by the previous response.
+                regs.isr().modify(|w| w.set_txe(true));
+            }
+            regs.icr().write(|w| w.set_addrcf(true));
+        }
+    }
+
+    fn poll_transmit(
+        cx: &mut Context<'_>,
+        bytes: &[u8],
+        tx: &mut TxProgress,
+        dma_running: bool,
+    ) -> Poll<Result<(), Error>> {
+        let regs = T::regs();
+        WAKERS[T::state_number()].register(cx.waker());
+
+        unsafe {
+            loop {
+                let isr = regs.isr().read();
+                Self::check_and_clear_error_flags(isr)?;
+
+                // The controller doesn't want more bytes, and ends the transaction next.
+                if isr.nackf() {
+                    regs.icr().write(|w| w.set_nackcf(true));
+                    continue;
+                }
+
+                if isr.stopf() || isr.addr() {
+                    // A byte still in TXDR was not sent.
+                    tx.unsent = !isr.txe();
+                    if isr.stopf() {
+                        regs.icr().write(|w| w.set_stopcf(true));
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+
+                if isr.txis() && !dma_running {
+                    let byte = match bytes.get(tx.written) {
+                        Some(&byte) => {
+                            tx.written += 1;
+                            byte
+                        }
+                        None => {
+                            tx.filler = true;
+                            FILLER
+                        }
+                    };
+                    regs.txdr().write(|w| w.set_txdata(byte));
+                    continue;
+                }
+
+                break;
+            }
+
+            Self::enable_interrupts(|w| {
+                w.set_txie(!dma_running);
+                w.set_nackie(true);
+                w.set_stopie(true);
+                w.set_addrie(true);
+                w.set_errie(true);
+            });
+        }
+        Poll::Pending
+    }
+
+    fn poll_receive(
+        cx: &mut Context<'_>,
+        buffer: &mut [u8],
+        rx: &mut RxProgress,
+        dma_running: bool,
+    ) -> Poll<Result<(), Error>> {
+        let regs = T::regs();
+        WAKERS[T::state_number()].register(cx.waker());
+
+        unsafe {
+            loop {
+                let isr = regs.isr().read();
+                Self::check_and_clear_error_flags(isr)?;
+
+                if isr.rxne() && !dma_running {
+                    let byte = regs.rxdr().read().rxdata();
+                    match buffer.get_mut(rx.received) {
+                        Some(b) => {
+                            *b = byte;
+                            rx.received += 1;
+                        }
+                        None => rx.overrun = true,
+                    }
+                    continue;
+                }
+
+                if isr.stopf() {
+                    regs.icr().write(|w| w.set_stopcf(true));
+                    return Poll::Ready(Ok(()));
+                }
+                if isr.addr() {
+                    return Poll::Ready(Ok(()));
+                }
+
+                break;
+            }
+
+            Self::enable_interrupts(|w| {
+                w.set_rxie(!dma_running);
+                w.set_stopie(true);
+                w.set_addrie(true);
+                w.set_errie(true);
+            });
+        }
+        Poll::Pending
+    }
+
+    unsafe fn enable_interrupts(f: impl FnOnce(&mut i2c::regs::Cr1)) {
+        // The interrupt handler disables them concurrently.
+        critical_section::with(|_| T::regs().cr1().modify(f));
+    }
+
+    unsafe fn check_and_clear_error_flags(isr: i2c::regs::Isr) -> Result<(), Error> {
+        let regs = T::regs();
+        if isr.berr() {
+            regs.icr().write(|w| w.set_berrcf(true));
+            Err(Error::Bus)
+        } else if isr.arlo() {
+            regs.icr().write(|w| w.set_arlocf(true));
+            Err(Error::Arbitration)
+        } else if isr.ovr() {
+            regs.icr().write(|w| w.set_ovrcf(true));
+            Err(Error::Overrun)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Default)]
+struct TxProgress {
+    /// Number of bytes of the response written by the CPU.
+    written: usize,
+    /// The response was exhausted, and filler bytes were written.
+    filler: bool,
+    /// The last byte written was not sent.
+    unsent: bool,
+}
+
+impl TxProgress {
+    /// Returns the number of bytes of the response sent, out of `written`.
+    fn sent(&self, written: usize) -> usize {
+        if self.unsent && !self.filler {
+            written.saturating_sub(1)
+        } else {
+            written
+        }
+    }
+}
+
+#[derive(Default)]
+struct RxProgress {
+    /// Number of bytes stored by the CPU.
+    received: usize,
+    /// Bytes were dropped.
+    overrun: bool,
+}
+
+impl RxProgress {
+    fn result(&self, received: usize) -> Result<usize, Error> {
+        if self.overrun {
+            Err(Error::Overrun)
+        } else {
+            Ok(received)
+        }
+    }
+}
+
+impl<'d, T: ErrorInstance, TXDMA, RXDMA> Drop for I2cSlave<'d, T, TXDMA, RXDMA> {
+    fn drop(&mut self) {
+        self.irq.disable();
+        self.irq.remove_handler();
+        self.err_irq.disable();
+        self.err_irq.remove_handler();
+
+        unsafe { T::regs().cr1().modify(|w| w.set_pe(false)) };
+    }
+}
+
+/// Stops the channel if the transfer is dropped.
+struct StopOnDrop<'a, C: crate::dma::Channel>(&'a mut C);
+
+impl<'a, C: crate::dma::Channel> StopOnDrop<'a, C> {
+    fn new(ch: &'a mut C) -> Self {
+        Self(ch)
+    }
+
+    /// Stops the channel, and returns the number of transfers it didn't do.
+    fn stop(self) -> usize {
+        self.0.request_stop();
+        while self.0.is_running() {}
+        self.0.remaining_transfers() as usize
+    }
+}
+
+impl<'a, C: crate::dma::Channel> Drop for StopOnDrop<'a, C> {
+    fn drop(&mut self) {
+        self.0.request_stop();
+        while self.0.is_running() {}
+    }
+}
+
+/// Disables the DMA requests once the transfer is done or dropped.
+struct DisableDmaOnDrop<T: Instance> {
+    /// The DMA stops in STOP mode.
+    #[cfg(feature = "low-power")]
+    _stop_veto: crate::low_power::StopVeto,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Instance> DisableDmaOnDrop<T> {
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "low-power")]
+            _stop_veto: crate::low_power::StopVeto::new(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Instance> Drop for DisableDmaOnDrop<T> {
+    fn drop(&mut self) {
+        unsafe {
+            T::regs().cr1().modify(|w| {
+                w.set_txdmaen(false);
+                w.set_rxdmaen(false);
+            });
+        }
+    }
+}
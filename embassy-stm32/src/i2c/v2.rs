@@ -732,16 +732,16 @@ impl Stop {
     }
 }
 
-struct Timings {
-    prescale: u8,
-    scll: u8,
-    sclh: u8,
-    sdadel: u8,
-    scldel: u8,
+pub(super) struct Timings {
+    pub(super) prescale: u8,
+    pub(super) scll: u8,
+    pub(super) sclh: u8,
+    pub(super) sdadel: u8,
+    pub(super) scldel: u8,
 }
 
 impl Timings {
-    fn new(i2cclk: Hertz, freq: Hertz) -> Self {
+    pub(super) fn new(i2cclk: Hertz, freq: Hertz) -> Self {
         let i2cclk = i2cclk.0;
         let freq = freq.0;
         // Refer to RM0433 Rev 7 Figure 539 for setup and hold timing:
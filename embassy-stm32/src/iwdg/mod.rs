@@ -0,0 +1,67 @@
+//! Independent watchdog.
+//!
+//! The IWDG is clocked by the LSI and resets the chip if it isn't petted before its timeout.
+//! It keeps running in the low power modes and can't be stopped once started, except by a
+//! reset. The cause of the last reset is available from [`crate::rcc::reset_cause`].
+
+use core::marker::PhantomData;
+
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+
+use crate::pac::iwdg::vals::{Key, Pr};
+use crate::pac::IWDG;
+use crate::peripherals;
+use crate::rcc::LSI_FREQ;
+
+mod timeout;
+use timeout::Timeout;
+
+pub struct IndependentWatchdog<'d> {
+    timeout: Timeout,
+    _p: PhantomData<&'d mut peripherals::IWDG>,
+}
+
+impl<'d> IndependentWatchdog<'d> {
+    /// Starts the watchdog with a timeout of `timeout_us` microseconds, rounded to the
+    /// resolution of the prescaler.
+    ///
+    /// The maximum timeout is 4096 * 256 LSI periods: about 32 seconds (28 seconds on L0/L1, 26
+    /// seconds on F0/F1/F3), and is not accurate as the LSI frequency varies a lot between
+    /// chips.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `timeout_us` is longer than the maximum timeout.
+    pub fn new(_p: impl Unborrow<Target = peripherals::IWDG> + 'd, timeout_us: u32) -> Self {
+        unborrow!(_p);
+
+        let timeout = unwrap!(Timeout::calculate(LSI_FREQ.0, timeout_us));
+
+        unsafe {
+            // Starting the watchdog also starts the LSI.
+            IWDG.kr().write(|w| w.set_key(Key::START));
+            IWDG.kr().write(|w| w.set_key(Key::ENABLE));
+            IWDG.pr().write(|w| w.set_pr(Pr(timeout.pr)));
+            IWDG.rlr().write(|w| w.set_rl(timeout.rlr));
+            // The registers are updated in the LSI clock domain.
+            while IWDG.sr().read().pvu() || IWDG.sr().read().rvu() {}
+            IWDG.kr().write(|w| w.set_key(Key::RESET));
+        }
+
+        Self {
+            timeout,
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns the timeout, in microseconds, at the nominal LSI frequency.
+    pub fn timeout_us(&self) -> u32 {
+        self.timeout.micros(LSI_FREQ.0)
+    }
+
+    /// Pets the watchdog, restarting its timeout.
+    pub fn pet(&mut self) {
+        unsafe { IWDG.kr().write(|w| w.set_key(Key::RESET)) }
+    }
+}
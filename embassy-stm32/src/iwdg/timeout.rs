@@ -0,0 +1,96 @@
+/// Largest reload value.
+const MAX_RELOAD: u32 = 0xfff;
+/// Largest prescaler register value, dividing the clock by 256.
+const MAX_PRESCALER: u8 = 6;
+
+/// Prescaler and reload register values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Timeout {
+    /// PR value: the clock is divided by `4 << pr`.
+    pub pr: u8,
+    /// RLR value: the watchdog resets the chip after `rlr + 1` prescaled clock periods.
+    pub rlr: u16,
+}
+
+impl Timeout {
+    /// Calculates the register values for a timeout of `timeout_us` microseconds, rounded to
+    /// the nearest prescaled clock period, or returns `None` if it is too long.
+    ///
+    /// The smallest possible prescaler is used, for the best resolution.
+    pub(crate) fn calculate(clock: u32, timeout_us: u32) -> Option<Self> {
+        for pr in 0..=MAX_PRESCALER {
+            let div = 4u64 << pr;
+            let periods = (timeout_us as u64 * clock as u64 + div * 500_000) / (div * 1_000_000);
+            let periods = periods.max(1);
+            if periods <= MAX_RELOAD as u64 + 1 {
+                return Some(Self {
+                    pr,
+                    rlr: (periods - 1) as u16,
+                });
+            }
+        }
+        None
+    }
+
+    /// Returns the actual timeout, in microseconds.
+    pub(crate) fn micros(&self, clock: u32) -> u32 {
+        ((self.rlr as u64 + 1) * (4 << self.pr) * 1_000_000 / clock as u64) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smallest_prescaler() {
+        // 1 s at 32 kHz: 32000 / 8 = 4000 periods.
+        assert_eq!(
+            Timeout::calculate(32_000, 1_000_000),
+            Some(Timeout { pr: 1, rlr: 3999 })
+        );
+        // 10 ms at 40 kHz: 400 / 4 = 100 periods.
+        assert_eq!(
+            Timeout::calculate(40_000, 10_000),
+            Some(Timeout { pr: 0, rlr: 99 })
+        );
+    }
+
+    #[test]
+    fn limits() {
+        // Shorter than a period.
+        assert_eq!(
+            Timeout::calculate(32_000, 1),
+            Some(Timeout { pr: 0, rlr: 0 })
+        );
+        // 4096 * 256 / 32000 = 32.768 s
+        assert_eq!(
+            Timeout::calculate(32_000, 32_768_000),
+            Some(Timeout { pr: 6, rlr: 0xfff })
+        );
+        assert_eq!(Timeout::calculate(32_000, 33_000_000), None);
+        // 4096 * 256 / 37000 = 28.34 s
+        assert!(Timeout::calculate(37_000, 28_000_000).is_some());
+        assert_eq!(Timeout::calculate(37_000, 29_000_000), None);
+        // 4096 * 256 / 40000 = 26.21 s
+        assert!(Timeout::calculate(40_000, 26_000_000).is_some());
+        assert_eq!(Timeout::calculate(40_000, 27_000_000), None);
+    }
+
+    #[test]
+    fn accuracy() {
+        for &clock in &[32_000, 37_000, 40_000] {
+            for &timeout_us in &[500, 1_000, 123_456, 1_000_000, 5_000_000, 20_000_000] {
+                let t = Timeout::calculate(clock, timeout_us).unwrap();
+                let period_us = (4 << t.pr) * 1_000_000 / clock;
+                let error = (t.micros(clock) as i64 - timeout_us as i64).abs();
+                assert!(
+                    error <= period_us as i64 / 2 + 1,
+                    "{} {}",
+                    clock,
+                    timeout_us
+                );
+            }
+        }
+    }
+}
@@ -19,13 +19,14 @@ pub mod dma;
 pub mod gpio;
 pub mod rcc;
 #[cfg(feature = "_time-driver")]
+#[cfg_attr(feature = "time-driver-lptim1", path = "time_driver_lptim.rs")]
 mod time_driver;
 
 // Sometimes-present hardware
 
 #[cfg(adc)]
 pub mod adc;
-#[cfg(can)]
+#[cfg(any(can, fdcan))]
 pub mod can;
 #[cfg(dac)]
 pub mod dac;
@@ -35,24 +36,55 @@ pub mod dcmi;
 pub mod eth;
 #[cfg(exti)]
 pub mod exti;
+#[cfg(fdcan)]
+pub mod fdcan;
+#[cfg(all(
+    flash,
+    any(
+        rcc_f0, rcc_f0x0, rcc_f1, rcc_f3, rcc_f4, rcc_f410, rcc_f7, rcc_g0, rcc_g4, rcc_h7, rcc_l0,
+        rcc_l1, rcc_l4, rcc_wb, rcc_wl5
+    )
+))]
+pub mod flash;
 #[cfg(i2c)]
 pub mod i2c;
+#[cfg(all(iwdg, not(any(rcc_h7, rcc_h7ab))))]
+pub mod iwdg;
 
 #[cfg(crc)]
 pub mod crc;
 pub mod pwm;
+#[cfg(any(quadspi, octospi))]
+pub mod qspi;
 #[cfg(rng)]
 pub mod rng;
+#[cfg(all(
+    rtc,
+    any(
+        rcc_f3, rcc_f4, rcc_f410, rcc_f7, rcc_g0, rcc_g4, rcc_h7, rcc_l0, rcc_l1, rcc_l4, rcc_wb,
+        rcc_wl5
+    )
+))]
+pub mod rtc;
 #[cfg(sdmmc)]
 pub mod sdmmc;
 #[cfg(spi)]
 pub mod spi;
 #[cfg(usart)]
 pub mod usart;
+#[cfg(usb)]
+pub mod usb;
+#[cfg(all(any(otgfs, otghs), any(feature = "usb-otg-fs", feature = "usb-otg-hs")))]
+pub mod usb_otg;
+#[cfg(all(wwdg, not(any(rcc_h7, rcc_h7ab))))]
+pub mod wwdg;
 
 #[cfg(feature = "subghz")]
 pub mod subghz;
 
+#[cfg(feature = "low-power")]
+pub mod low_power;
+
 // This must go last, so that it sees all the impl_foo! macros defined earlier.
 mod generated {
 
@@ -102,6 +134,8 @@ pub fn init(config: Config) -> Peripherals {
         #[cfg(exti)]
         exti::init();
 
+        #[cfg(feature = "low-power")]
+        low_power::set_rcc_config(config.rcc);
         rcc::init(config.rcc);
 
         // must be after rcc init
@@ -0,0 +1,182 @@
+//! Low power executor, entering STOP mode when idle.
+//!
+//! STOP mode stops the high speed clocks and most peripherals, but keeps the RAM, the registers
+//! and the LSE running. The [`Executor`] enters it when no task is ready, no [`StopVeto`] exists
+//! and the next timer alarm is far enough away, and restores the clock configuration passed to
+//! [`crate::init`] when waking up.
+//!
+//! The time driver must keep counting in STOP mode, so this requires the `time-driver-lptim1`
+//! feature. Peripherals which can't work in STOP mode must hold a [`StopVeto`] while they are
+//! busy, as DMA transfers do.
+
+use core::marker::PhantomData;
+use core::ptr;
+
+use atomic_polyfill::{AtomicBool, AtomicU32, Ordering};
+use embassy::executor::{raw, Spawner};
+use embassy::time::Duration;
+
+#[cfg(not(rcc_l0))]
+use crate::pac::pwr::vals::Lpms;
+#[cfg(rcc_l0)]
+use crate::pac::pwr::vals::{Mode, Pdds};
+use crate::pac::rcc::vals::Sw;
+use crate::pac::{PWR, RCC};
+use crate::rcc;
+
+#[cfg(not(feature = "time-driver-lptim1"))]
+compile_error!("the `low-power` feature needs the `time-driver-lptim1` feature");
+
+#[cfg(not(any(rcc_g0, rcc_g4, rcc_l0, rcc_l4, rcc_wl5)))]
+compile_error!("the `low-power` feature is only supported on the G0, G4, L0, L4 and WL families");
+
+/// Number of existing [`StopVeto`]s.
+static VETOES: AtomicU32 = AtomicU32::new(0);
+
+/// Set when a task is woken, cleared before polling the tasks.
+static SIGNALED: AtomicBool = AtomicBool::new(false);
+
+/// Clock configuration restored when waking up from STOP mode.
+static mut RCC_CONFIG: Option<rcc::Config> = None;
+
+pub(crate) unsafe fn set_rcc_config(config: rcc::Config) {
+    RCC_CONFIG = Some(config);
+}
+
+/// Prevents the [`Executor`] from entering STOP mode as long as it exists.
+///
+/// The executor still sleeps, in SLEEP mode.
+pub struct StopVeto {
+    _private: (),
+}
+
+impl StopVeto {
+    pub fn new() -> Self {
+        VETOES.fetch_add(1, Ordering::Relaxed);
+        Self { _private: () }
+    }
+}
+
+impl Drop for StopVeto {
+    fn drop(&mut self) {
+        VETOES.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns the system clock switch status, which changes when waking up from STOP mode if the
+/// system clock was not MSI or HSI16.
+unsafe fn sws() -> Sw {
+    RCC.cfgr().read().sws()
+}
+
+/// Selects STOP mode, instead of STANDBY or SHUTDOWN, for the deep sleep.
+unsafe fn select_stop_mode() {
+    // STOP2 keeps more of the regulator off than STOP1, and LPTIM1 runs in both.
+    #[cfg(any(rcc_l4, rcc_wl5))]
+    PWR.cr1().modify(|w| w.set_lpms(Lpms::STOP2));
+    #[cfg(any(rcc_g0, rcc_g4))]
+    PWR.cr1().modify(|w| w.set_lpms(Lpms::STOP1));
+    #[cfg(rcc_l0)]
+    PWR.cr().modify(|w| {
+        w.set_pdds(Pdds::STOP_MODE);
+        w.set_lpsdsr(Mode::LOW_POWER_MODE);
+    });
+}
+
+/// Thread mode executor entering STOP mode when idle.
+///
+/// It is used like [`embassy::executor::Executor`]. The `#[embassy::main]` macro uses that
+/// executor, so the entry point has to be written by hand:
+///
+/// ```ignore
+/// #[cortex_m_rt::entry]
+/// fn main() -> ! {
+///     let p = embassy_stm32::init(Default::default());
+///     let executor = EXECUTOR.put(Executor::new());
+///     executor.run(|spawner| unwrap!(spawner.spawn(run(p))));
+/// }
+/// ```
+pub struct Executor {
+    inner: raw::Executor,
+    min_stop_ticks: u64,
+    not_send: PhantomData<*mut ()>,
+}
+
+impl Executor {
+    /// Creates an executor entering STOP mode when the next alarm is at least 1 ms away.
+    pub fn new() -> Self {
+        Self {
+            inner: raw::Executor::new(
+                |_| {
+                    SIGNALED.store(true, Ordering::Relaxed);
+                    cortex_m::asm::sev()
+                },
+                ptr::null_mut(),
+            ),
+            min_stop_ticks: Duration::from_millis(1).as_ticks(),
+            not_send: PhantomData,
+        }
+    }
+
+    /// Sets how far away the next alarm must be to enter STOP mode.
+    ///
+    /// Waking up from STOP mode takes a few microseconds, plus the time to restart the PLL or
+    /// the HSE if the system clock uses them.
+    pub fn set_min_stop_duration(&mut self, duration: Duration) {
+        self.min_stop_ticks = duration.as_ticks();
+    }
+
+    /// Runs the executor, see [`embassy::executor::Executor::run`].
+    ///
+    /// This function never returns.
+    pub fn run(&'static mut self, init: impl FnOnce(Spawner)) -> ! {
+        init(self.inner.spawner());
+
+        loop {
+            SIGNALED.store(false, Ordering::Relaxed);
+            unsafe { self.inner.poll() };
+
+            // Interrupts stay masked until the clocks are restored: WFI still wakes up on a
+            // pending interrupt, which then runs when leaving the critical section.
+            cortex_m::interrupt::free(|_| unsafe {
+                if SIGNALED.load(Ordering::Relaxed) {
+                    return;
+                }
+
+                if self.stop_allowed() {
+                    self.stop();
+                } else {
+                    cortex_m::asm::wfi();
+                }
+            });
+        }
+    }
+
+    fn stop_allowed(&self) -> bool {
+        if VETOES.load(Ordering::Relaxed) != 0 {
+            return false;
+        }
+        match crate::time_driver::time_until_next_alarm() {
+            Some(ticks) => ticks >= self.min_stop_ticks,
+            None => true,
+        }
+    }
+
+    unsafe fn stop(&self) {
+        let mut scb = cortex_m::Peripherals::steal().SCB;
+
+        select_stop_mode();
+        let sws = sws();
+
+        scb.set_sleepdeep();
+        cortex_m::asm::wfi();
+        scb.clear_sleepdeep();
+
+        // The system clock falls back to MSI or HSI16 on wake up, and the PLL and HSE are off.
+        if sws() != sws {
+            if let Some(config) = RCC_CONFIG {
+                rcc::init(config);
+            }
+        }
+    }
+}
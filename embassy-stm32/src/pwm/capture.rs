@@ -0,0 +1,215 @@
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use super::*;
+
+/// Edges captured by an input channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CaptureEdge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// A capture was overwritten by the next one before being read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OvercaptureError;
+
+/// Timer capturing the value of its counter on the edges of its inputs.
+pub struct InputCapture<'d, T: CaptureCompareInstance> {
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: CaptureCompareInstance> InputCapture<'d, T> {
+    /// Starts the counter, ticking at `tick_freq` and wrapping around every 65536 ticks.
+    ///
+    /// The channels are enabled with [`Self::enable`].
+    pub fn new<F: Into<Hertz>>(
+        _tim: impl Unborrow<Target = T> + 'd,
+        ch1: impl Unborrow<Target = impl CapturePin<T, Ch1>> + 'd,
+        ch2: impl Unborrow<Target = impl CapturePin<T, Ch2>> + 'd,
+        ch3: impl Unborrow<Target = impl CapturePin<T, Ch3>> + 'd,
+        ch4: impl Unborrow<Target = impl CapturePin<T, Ch4>> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        tick_freq: F,
+    ) -> Self {
+        unborrow!(ch1, ch2, ch3, ch4, irq);
+
+        T::enable();
+        T::reset();
+
+        unsafe {
+            ch1.configure();
+            ch2.configure();
+            ch3.configure();
+            ch4.configure();
+        }
+
+        set_tick_freq::<T>(tick_freq.into());
+
+        irq.set_handler(on_interrupt::<T>);
+        irq.unpend();
+        irq.enable();
+
+        let r = T::regs();
+        unsafe {
+            r.arr().write(|w| w.set_arr(u16::MAX));
+            r.cr1().write(|w| w.set_cen(true));
+        }
+
+        Self {
+            phantom: PhantomData,
+        }
+    }
+
+    /// Starts capturing `edge` on `channel`.
+    pub fn enable(&mut self, channel: Channel, edge: CaptureEdge) {
+        let r = T::regs();
+        unsafe {
+            r.ccer().modify(|w| w.set_cce(channel.index(), false));
+            set_input::<T>(channel, Input::Own);
+            set_capture_edge::<T>(channel, edge);
+            // Drop the captures of a previous use.
+            clear_flags::<T>(|w| {
+                w.set_ccif(channel.index(), false);
+                w.set_ccof(channel.index(), false);
+            });
+            r.ccer().modify(|w| w.set_cce(channel.index(), true));
+        }
+    }
+
+    pub fn disable(&mut self, channel: Channel) {
+        unsafe {
+            T::regs()
+                .ccer()
+                .modify(|w| w.set_cce(channel.index(), false))
+        }
+    }
+
+    /// Returns the current value of the counter.
+    pub fn count(&self) -> u16 {
+        unsafe { T::regs().cnt().read().cnt() }
+    }
+
+    /// Waits for a capture on `channel`, and returns the captured value of the counter.
+    ///
+    /// The oldest unread capture is returned first: if another one happened since, the older one
+    /// is lost and [`OvercaptureError`] is returned instead. The next call then returns the newer
+    /// capture.
+    pub async fn capture(&mut self, channel: Channel) -> Result<u16, OvercaptureError> {
+        poll_fn(|cx| {
+            T::waker().register(cx.waker());
+            unsafe { poll_capture::<T>(channel) }
+        })
+        .await
+    }
+}
+
+/// Timer measuring the period and the width of the pulses of a PWM signal on its first channel.
+///
+/// The counter restarts at each rising edge. The period and the pulse width are only correct if
+/// they are shorter than 65536 ticks.
+pub struct PwmInput<'d, T: CaptureCompareInstance> {
+    /// The first period is measured from an arbitrary counter value.
+    synced: bool,
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: CaptureCompareInstance> PwmInput<'d, T> {
+    /// Starts measuring the signal on `pin`, with a counter ticking at `tick_freq`.
+    pub fn new<F: Into<Hertz>>(
+        _tim: impl Unborrow<Target = T> + 'd,
+        pin: impl Unborrow<Target = impl CapturePin<T, Ch1>> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        tick_freq: F,
+    ) -> Self {
+        unborrow!(pin, irq);
+
+        T::enable();
+        T::reset();
+
+        unsafe { pin.configure() };
+
+        set_tick_freq::<T>(tick_freq.into());
+
+        irq.set_handler(on_interrupt::<T>);
+        irq.unpend();
+        irq.enable();
+
+        let r = T::regs();
+        unsafe {
+            r.arr().write(|w| w.set_arr(u16::MAX));
+
+            // Channel 1 captures the rising edges, channel 2 the falling ones, of TI1.
+            set_input::<T>(Channel::Ch1, Input::Own);
+            set_capture_edge::<T>(Channel::Ch1, CaptureEdge::Rising);
+            set_input::<T>(Channel::Ch2, Input::Other);
+            set_capture_edge::<T>(Channel::Ch2, CaptureEdge::Falling);
+            // The rising edges also reset the counter.
+            set_slave_mode::<T>(Sms::RESET_MODE, Ts::TI1FP1);
+
+            r.ccer().modify(|w| {
+                w.set_cce(0, true);
+                w.set_cce(1, true);
+            });
+            r.cr1().write(|w| w.set_cen(true));
+        }
+
+        Self {
+            synced: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Waits for the end of a period, and returns its length and the width of its pulse, in
+    /// ticks.
+    pub async fn measure(&mut self) -> Result<(u16, u16), OvercaptureError> {
+        poll_fn(|cx| {
+            T::waker().register(cx.waker());
+            loop {
+                let period = match unsafe { poll_capture::<T>(Channel::Ch1) } {
+                    Poll::Ready(Ok(period)) => period,
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => return Poll::Pending,
+                };
+                // The falling edge of the period was captured before its end.
+                let width = unsafe { T::regs().ccr(1).read().ccr() };
+
+                if self.synced {
+                    return Poll::Ready(Ok((period, width)));
+                }
+                self.synced = true;
+            }
+        })
+        .await
+    }
+}
+
+/// Returns the oldest unread capture of `channel`, or enables its interrupt if there is none.
+unsafe fn poll_capture<T: CaptureCompareInstance>(
+    channel: Channel,
+) -> Poll<Result<u16, OvercaptureError>> {
+    let r = T::regs();
+    let sr = r.sr().read();
+
+    if sr.ccof(channel.index()) {
+        clear_flags::<T>(|w| w.set_ccof(channel.index(), false));
+        return Poll::Ready(Err(OvercaptureError));
+    }
+
+    if sr.ccif(channel.index()) {
+        // Reading the capture clears its flag.
+        return Poll::Ready(Ok(r.ccr(channel.index()).read().ccr()));
+    }
+
+    enable_interrupts::<T>(|w| w.set_ccie(channel.index(), true));
+    // A capture may have happened before the interrupt was enabled: it fires right away then.
+    Poll::Pending
+}
@@ -0,0 +1,97 @@
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+
+use super::*;
+
+/// PWM on an advanced-control timer, with complementary outputs on the first three channels.
+///
+/// The dead-time inserted between the falling edge of an output and the rising edge of its
+/// complement keeps both switches of a half-bridge from conducting at the same time.
+pub struct ComplementaryPwm<'d, T: AdvancedInstance> {
+    inner: Pwm<'d, T>,
+}
+
+impl<'d, T: AdvancedInstance> ComplementaryPwm<'d, T> {
+    /// Starts the timer at `freq`, without dead-time. The channels are enabled with
+    /// [`Self::enable`].
+    pub fn new<F: Into<Hertz>>(
+        tim: impl Unborrow<Target = T> + 'd,
+        ch1: impl Unborrow<Target = impl PwmPin<T, Ch1>> + 'd,
+        ch1n: impl Unborrow<Target = impl ComplementaryPwmPin<T, Ch1>> + 'd,
+        ch2: impl Unborrow<Target = impl PwmPin<T, Ch2>> + 'd,
+        ch2n: impl Unborrow<Target = impl ComplementaryPwmPin<T, Ch2>> + 'd,
+        ch3: impl Unborrow<Target = impl PwmPin<T, Ch3>> + 'd,
+        ch3n: impl Unborrow<Target = impl ComplementaryPwmPin<T, Ch3>> + 'd,
+        ch4: impl Unborrow<Target = impl PwmPin<T, Ch4>> + 'd,
+        freq: F,
+    ) -> Self {
+        unborrow!(ch1n, ch2n, ch3n);
+
+        let inner = Pwm::new(tim, ch1, ch2, ch3, ch4, freq);
+
+        unsafe {
+            ch1n.configure();
+            ch2n.configure();
+            ch3n.configure();
+
+            // The outputs of advanced-control timers are gated by MOE.
+            T::regs_advanced().bdtr().modify(|w| w.set_moe(true));
+        }
+
+        Self { inner }
+    }
+
+    /// Enables `channel` and its complementary output, if any.
+    pub fn enable(&mut self, channel: Channel) {
+        self.set_enable(channel, true)
+    }
+
+    pub fn disable(&mut self, channel: Channel) {
+        self.set_enable(channel, false)
+    }
+
+    fn set_enable(&mut self, channel: Channel, enable: bool) {
+        unsafe {
+            T::regs_advanced().ccer().modify(|w| {
+                w.set_cce(channel.index(), enable);
+                if channel.index() < 3 {
+                    w.set_ccne(channel.index(), enable);
+                }
+            })
+        }
+    }
+
+    pub fn set_freq<F: Into<Hertz>>(&mut self, freq: F) {
+        self.inner.set_freq(freq)
+    }
+
+    pub fn get_max_duty(&self) -> u32 {
+        self.inner.get_max_duty()
+    }
+
+    pub fn set_duty(&mut self, channel: Channel, duty: u32) {
+        self.inner.set_duty(channel, duty)
+    }
+
+    /// Sets the dead-time to at least `ns` nanoseconds, rounded up to the resolution of the
+    /// dead-time generator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the dead-time is longer than 1008 ticks of the timer clock.
+    pub fn set_dead_time_ns(&mut self, ns: u32) {
+        let clock = T::frequency().0 as u64;
+        let ticks = (ns as u64 * clock + 999_999_999) / 1_000_000_000;
+        assert!(ticks <= dead_time::MAX_TICKS as u64);
+        let dtg = unwrap!(dead_time::dtg(ticks as u32));
+
+        unsafe { T::regs_advanced().bdtr().modify(|w| w.set_dtg(dtg)) }
+    }
+
+    /// Returns the dead-time, in nanoseconds.
+    pub fn dead_time_ns(&self) -> u32 {
+        let dtg = unsafe { T::regs_advanced().bdtr().read().dtg() };
+        let ticks = dead_time::ticks(dtg) as u64;
+        (ticks * 1_000_000_000 / T::frequency().0 as u64) as u32
+    }
+}
@@ -0,0 +1,79 @@
+//! Dead-time generator setting (DTG field of BDTR).
+//!
+//! The dead-time is counted in ticks of the dead-time clock, with a resolution which gets coarser
+//! as it gets longer:
+//!
+//! | DTG[7:5] | Dead-time             | Range       |
+//! |----------|-----------------------|-------------|
+//! | 0xx      | DTG[6:0]              | 0..=127     |
+//! | 10x      | (64 + DTG[5:0]) * 2   | 128..=254   |
+//! | 110      | (32 + DTG[4:0]) * 8   | 256..=504   |
+//! | 111      | (32 + DTG[4:0]) * 16  | 512..=1008  |
+
+/// Longest dead-time, in ticks.
+pub(crate) const MAX_TICKS: u32 = 1008;
+
+/// Returns the DTG value for the shortest dead-time of at least `ticks`, or `None` if it is longer
+/// than [`MAX_TICKS`].
+pub(crate) fn dtg(ticks: u32) -> Option<u8> {
+    let dtg = if ticks <= 127 {
+        ticks
+    } else if ticks <= 254 {
+        0b1000_0000 | ((ticks + 1) / 2 - 64)
+    } else if ticks <= 504 {
+        0b1100_0000 | ((ticks + 7) / 8 - 32)
+    } else if ticks <= MAX_TICKS {
+        0b1110_0000 | ((ticks + 15) / 16 - 32)
+    } else {
+        return None;
+    };
+    Some(dtg as u8)
+}
+
+/// Returns the dead-time inserted by `dtg`, in ticks.
+pub(crate) fn ticks(dtg: u8) -> u32 {
+    let dtg = dtg as u32;
+    match dtg >> 5 {
+        0b000..=0b011 => dtg,
+        0b100 | 0b101 => (64 + (dtg & 0x3f)) * 2,
+        0b110 => (32 + (dtg & 0x1f)) * 8,
+        _ => (32 + (dtg & 0x1f)) * 16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(dtg(0), Some(0));
+        assert_eq!(dtg(127), Some(127));
+        assert_eq!(dtg(128), Some(0b1000_0000));
+        assert_eq!(dtg(254), Some(0b1011_1111));
+        assert_eq!(dtg(256), Some(0b1100_0000));
+        assert_eq!(dtg(504), Some(0b1101_1111));
+        assert_eq!(dtg(512), Some(0b1110_0000));
+        assert_eq!(dtg(MAX_TICKS), Some(0xff));
+        assert_eq!(dtg(MAX_TICKS + 1), None);
+    }
+
+    #[test]
+    fn rounds_up() {
+        assert_eq!(ticks(dtg(129).unwrap()), 130);
+        assert_eq!(ticks(dtg(255).unwrap()), 256);
+        assert_eq!(ticks(dtg(505).unwrap()), 512);
+        assert_eq!(ticks(dtg(1000).unwrap()), 1008);
+    }
+
+    #[test]
+    fn shortest_dead_time() {
+        for t in 0..=MAX_TICKS {
+            let dt = ticks(dtg(t).unwrap());
+            assert!(dt >= t);
+            // No shorter setting is still long enough.
+            let shorter = (0..=255u8).map(ticks).filter(|&d| d >= t).min();
+            assert_eq!(Some(dt), shorter);
+        }
+    }
+}
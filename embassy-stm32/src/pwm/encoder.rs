@@ -0,0 +1,136 @@
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use super::*;
+
+/// Edges counted by an [`Encoder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EncoderMode {
+    /// Counts the edges of channel 1, depending on the level of channel 2: 2 counts per cycle.
+    Ch1Edges,
+    /// Counts the edges of channel 2, depending on the level of channel 1: 2 counts per cycle.
+    Ch2Edges,
+    /// Counts the edges of both channels: 4 counts per cycle.
+    BothEdges,
+}
+
+impl EncoderMode {
+    fn sms(self) -> Sms {
+        match self {
+            EncoderMode::Ch2Edges => Sms::ENCODER_MODE_1,
+            EncoderMode::Ch1Edges => Sms::ENCODER_MODE_2,
+            EncoderMode::BothEdges => Sms::ENCODER_MODE_3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+/// Timer counting the steps of a quadrature encoder connected to its first two channels.
+///
+/// The count goes up when channel 1 leads channel 2, and wraps around at 65536. Channels 3 and
+/// 4 are used to detect count changes.
+pub struct Encoder<'d, T: CaptureCompareInstance> {
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: CaptureCompareInstance> Encoder<'d, T> {
+    pub fn new(
+        _tim: impl Unborrow<Target = T> + 'd,
+        ch1: impl Unborrow<Target = impl CapturePin<T, Ch1>> + 'd,
+        ch2: impl Unborrow<Target = impl CapturePin<T, Ch2>> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        mode: EncoderMode,
+    ) -> Self {
+        unborrow!(ch1, ch2, irq);
+
+        T::enable();
+        T::reset();
+
+        unsafe {
+            ch1.configure();
+            ch2.configure();
+        }
+
+        irq.set_handler(on_interrupt::<T>);
+        irq.unpend();
+        irq.enable();
+
+        let r = T::regs();
+        unsafe {
+            r.arr().write(|w| w.set_arr(u16::MAX));
+            set_input::<T>(Channel::Ch1, Input::Own);
+            set_input::<T>(Channel::Ch2, Input::Own);
+            set_slave_mode::<T>(mode.sms(), Ts::ITR0);
+            r.cr1().write(|w| w.set_cen(true));
+        }
+
+        Self {
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn count(&self) -> u16 {
+        unsafe { T::regs().cnt().read().cnt() }
+    }
+
+    pub fn set_count(&mut self, count: u16) {
+        unsafe { T::regs().cnt().write(|w| w.set_cnt(count)) }
+    }
+
+    /// Returns the direction of the last count change.
+    pub fn direction(&self) -> Direction {
+        if unsafe { T::regs().cr1().read().dir() } == stm32_metapac::timer::vals::Dir::DOWN {
+            Direction::Down
+        } else {
+            Direction::Up
+        }
+    }
+
+    /// Waits for the count to change from `count`, and returns the new count.
+    ///
+    /// Pass the last count returned to not miss any change.
+    pub async fn wait_count_changed(&mut self, count: u16) -> u16 {
+        let r = T::regs();
+        poll_fn(|cx| {
+            T::waker().register(cx.waker());
+            unsafe {
+                let now = r.cnt().read().cnt();
+                if now != count {
+                    return Poll::Ready(now);
+                }
+
+                // Compare matches one step away in both directions.
+                r.ccr(2).write(|w| w.set_ccr(count.wrapping_add(1)));
+                r.ccr(3).write(|w| w.set_ccr(count.wrapping_sub(1)));
+                clear_flags::<T>(|w| {
+                    w.set_ccif(2, false);
+                    w.set_ccif(3, false);
+                });
+                enable_interrupts::<T>(|w| {
+                    w.set_ccie(2, true);
+                    w.set_ccie(3, true);
+                });
+
+                // The count may have changed before the compares were set.
+                let now = r.cnt().read().cnt();
+                if now != count {
+                    return Poll::Ready(now);
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
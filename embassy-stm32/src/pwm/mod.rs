@@ -1,10 +1,25 @@
+use crate::dma;
 use crate::gpio;
 use crate::rcc::RccPeripheral;
 use crate::time::Hertz;
 use core::marker::PhantomData;
+use embassy::interrupt::Interrupt;
 use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
 use embassy_hal_common::unborrow;
-use stm32_metapac::timer::vals::Ocm;
+use stm32_metapac::timer::regs::{DierGp, SrGp};
+use stm32_metapac::timer::vals::{CcmrInputCcs, Icf, Ocm, Sms, Ts};
+
+mod capture;
+mod complementary;
+mod dead_time;
+mod encoder;
+mod one_pulse;
+pub use capture::*;
+pub use complementary::*;
+pub use encoder::*;
+pub use one_pulse::*;
 
 pub struct Pwm<'d, T: Instance> {
     phantom: PhantomData<&'d mut T>,
@@ -25,6 +40,21 @@ pub enum Channel {
     Ch4,
 }
 
+impl Channel {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Input captured by a channel.
+#[derive(Clone, Copy)]
+enum Input {
+    /// The input of the channel.
+    Own,
+    /// The input of the other channel of its pair.
+    Other,
+}
+
 impl<'d, T: Instance> Pwm<'d, T> {
     pub fn new<F: Into<Hertz>>(
         _tim: impl Unborrow<Target = T> + 'd,
@@ -127,16 +157,146 @@ impl<'d, T: Instance> Pwm<'d, T> {
             }
         }
     }
+
+    /// Outputs a waveform on `channel`, with the duty cycle of each period taken from `duty`.
+    ///
+    /// The DMA channel loads the next duty cycle at each update event, so the current period
+    /// keeps its duty cycle, and the last one is kept once the waveform is done: end `duty` with
+    /// 0 to leave the output low, e.g. for WS2812 LEDs.
+    pub async fn waveform_dma(
+        &mut self,
+        dma: impl Unborrow<Target = impl UpDma<T>>,
+        channel: Channel,
+        duty: &[u16],
+    ) {
+        unborrow!(dma);
+        let r = T::regs();
+
+        let request = dma.request();
+        let dst = r.ccr(channel.index()).ptr() as *mut u16;
+        let transfer = crate::dma::write(dma, request, duty, dst);
+
+        unsafe { r.dier().modify(|w| w.set_ude(true)) };
+        let _on_drop = OnDrop::new(|| unsafe { r.dier().modify(|w| w.set_ude(false)) });
+
+        transfer.await;
+    }
+}
+
+/// Sets the prescaler for the counter of `T` to tick at `tick_freq`.
+fn set_tick_freq<T: Instance>(tick_freq: Hertz) {
+    let div = T::frequency().0 / tick_freq.0;
+    assert!(div >= 1 && div <= 1 << 16);
+    let r = T::regs();
+    unsafe {
+        r.psc().write(|w| w.set_psc((div - 1) as u16));
+        // The prescaler is only loaded at the next update event.
+        r.egr().write(|w| w.set_ug(true));
+        clear_flags::<T>(|w| w.set_uif(false));
+    }
+}
+
+/// Maps `channel` to `input`, with no prescaler nor filter.
+unsafe fn set_input<T: Instance>(channel: Channel, input: Input) {
+    // The values are named after the channels 3 and 4.
+    let ccs = match input {
+        Input::Own => CcmrInputCcs::TI4,
+        Input::Other => CcmrInputCcs::TI3,
+    };
+    let idx = channel.index() % 2;
+    T::regs().ccmr_input(channel.index() / 2).modify(|w| {
+        w.set_ccs(idx, ccs);
+        w.set_icpsc(idx, 0);
+        w.set_icf(idx, 0);
+    });
+}
+
+/// Sets the edges captured by `channel`, or the polarity of its input in encoder and slave
+/// modes.
+unsafe fn set_capture_edge<T: Instance>(channel: Channel, edge: CaptureEdge) {
+    let (ccp, ccnp) = match edge {
+        CaptureEdge::Rising => (false, false),
+        CaptureEdge::Falling => (true, false),
+        CaptureEdge::Both => (true, true),
+    };
+    T::regs().ccer().modify(|w| {
+        w.set_ccp(channel.index(), ccp);
+        w.set_ccnp(channel.index(), ccnp);
+    });
+}
+
+/// Sets the slave mode and its trigger.
+unsafe fn set_slave_mode<T: Instance>(sms: Sms, ts: Ts) {
+    T::regs().smcr().modify(|w| {
+        w.set_sms(sms);
+        w.set_ts(ts);
+    });
+}
+
+/// Clears the flags of SR set to false by `f`, without touching the others.
+unsafe fn clear_flags<T: Instance>(f: impl FnOnce(&mut SrGp)) {
+    // Writing 1 leaves a flag unchanged.
+    let mut sr = SrGp(!0);
+    f(&mut sr);
+    T::regs().sr().write_value(sr);
+}
+
+/// Enables the interrupts set by `f` in DIER, until the interrupt handler disables them.
+unsafe fn enable_interrupts<T: Instance>(f: impl FnOnce(&mut DierGp)) {
+    critical_section::with(|_| T::regs().dier().modify(f));
+}
+
+unsafe fn on_interrupt<T: CaptureCompareInstance>(_: *mut ()) {
+    let r = T::regs();
+    // The flags are handled by the task, which enables the interrupts it waits for again.
+    let sr = r.sr().read();
+    r.dier().modify(|w| {
+        if sr.uif() {
+            w.set_uie(false);
+        }
+        for i in 0..4 {
+            if sr.ccif(i) {
+                w.set_ccie(i, false);
+            }
+        }
+    });
+    T::waker().wake();
 }
 
 pub(crate) mod sealed {
+    use super::dma;
+    use embassy::waitqueue::AtomicWaker;
+
     pub trait Instance {
         fn regs() -> crate::pac::timer::TimGp16;
     }
+
+    pub trait CaptureCompareInstance: Instance {
+        fn waker() -> &'static AtomicWaker;
+    }
+
+    pub trait AdvancedInstance: Instance {
+        fn regs_advanced() -> crate::pac::timer::TimAdv;
+    }
+
+    pub trait UpDma<T: Instance> {
+        fn request(&self) -> dma::Request;
+    }
 }
 
 pub trait Instance: sealed::Instance + Sized + RccPeripheral + 'static {}
 
+/// Timer with capture/compare channels, and their interrupt.
+pub trait CaptureCompareInstance: sealed::CaptureCompareInstance + Instance {
+    type Interrupt: Interrupt;
+}
+
+/// Advanced-control timer, with complementary outputs.
+pub trait AdvancedInstance: sealed::AdvancedInstance + Instance {}
+
+/// DMA channel triggered by the update event of the timer.
+pub trait UpDma<T: Instance>: sealed::UpDma<T> + dma::Channel {}
+
 #[allow(unused)]
 macro_rules! impl_timer {
     ($inst:ident) => {
@@ -150,6 +310,30 @@ macro_rules! impl_timer {
     };
 }
 
+crate::pac::interrupts!(
+    ($inst:ident, timer, $block:ident, CC, $irq:ident) => {
+        impl sealed::CaptureCompareInstance for crate::peripherals::$inst {
+            fn waker() -> &'static AtomicWaker {
+                static WAKER: AtomicWaker = AtomicWaker::new();
+                &WAKER
+            }
+        }
+
+        impl CaptureCompareInstance for crate::peripherals::$inst {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+    ($inst:ident, timer, TIM_ADV, UP, $irq:ident) => {
+        impl sealed::AdvancedInstance for crate::peripherals::$inst {
+            fn regs_advanced() -> crate::pac::timer::TimAdv {
+                crate::pac::timer::TimAdv(crate::pac::$inst.0)
+            }
+        }
+
+        impl AdvancedInstance for crate::peripherals::$inst {}
+    };
+);
+
 pub trait PwmPin<Timer, Channel>: gpio::OptionalPin {
     unsafe fn configure(&mut self);
 }
@@ -158,6 +342,24 @@ impl<Timer, Channel> PwmPin<Timer, Channel> for gpio::NoPin {
     unsafe fn configure(&mut self) {}
 }
 
+/// Complementary output of a channel of an advanced-control timer.
+pub trait ComplementaryPwmPin<Timer, Channel>: gpio::OptionalPin {
+    unsafe fn configure(&mut self);
+}
+
+impl<Timer, Channel> ComplementaryPwmPin<Timer, Channel> for gpio::NoPin {
+    unsafe fn configure(&mut self) {}
+}
+
+/// Input of a channel, for input capture, encoder and slave modes.
+pub trait CapturePin<Timer, Channel>: gpio::OptionalPin {
+    unsafe fn configure(&mut self);
+}
+
+impl<Timer, Channel> CapturePin<Timer, Channel> for gpio::NoPin {
+    unsafe fn configure(&mut self) {}
+}
+
 #[allow(unused)]
 macro_rules! impl_pwm_pin {
     ($timer:ident, $channel:ident, $pin:ident, $af:expr) => {
@@ -179,17 +381,111 @@ crate::pac::peripherals!(
     (timer, $inst:ident) => { impl_timer!($inst); };
 );
 
+#[allow(unused)]
+macro_rules! impl_complementary_pin {
+    ($timer:ident, $channel:ident, $pin:ident, $af:expr) => {
+        impl crate::pwm::ComplementaryPwmPin<crate::peripherals::$timer, crate::pwm::$channel>
+            for crate::peripherals::$pin
+        {
+            unsafe fn configure(&mut self) {
+                use crate::gpio::sealed::{AFType, Pin};
+                use crate::gpio::Speed;
+                self.set_low();
+                self.set_speed(Speed::VeryHigh);
+                self.set_as_af($af, AFType::OutputPushPull);
+            }
+        }
+    };
+}
+
+#[allow(unused)]
+macro_rules! impl_capture_pin {
+    ($timer:ident, $channel:ident, $pin:ident, $af:expr) => {
+        impl crate::pwm::CapturePin<crate::peripherals::$timer, crate::pwm::$channel>
+            for crate::peripherals::$pin
+        {
+            unsafe fn configure(&mut self) {
+                use crate::gpio::sealed::{AFType, Pin};
+                self.set_as_af($af, AFType::Input);
+            }
+        }
+    };
+}
+
+#[allow(unused)]
+macro_rules! impl_dma {
+    ($inst:ident, {dmamux: $dmamux:ident}, $signal:ident, $request:expr) => {
+        impl<T> sealed::$signal<crate::peripherals::$inst> for T
+        where
+            T: crate::dma::MuxChannel<Mux = crate::dma::$dmamux>,
+        {
+            fn request(&self) -> dma::Request {
+                $request
+            }
+        }
+
+        impl<T> $signal<crate::peripherals::$inst> for T where
+            T: crate::dma::MuxChannel<Mux = crate::dma::$dmamux>
+        {
+        }
+    };
+    ($inst:ident, {channel: $channel:ident}, $signal:ident, $request:expr) => {
+        impl sealed::$signal<crate::peripherals::$inst> for crate::peripherals::$channel {
+            fn request(&self) -> dma::Request {
+                $request
+            }
+        }
+
+        impl $signal<crate::peripherals::$inst> for crate::peripherals::$channel {}
+    };
+}
+
 crate::pac::peripheral_pins!(
     ($inst:ident, timer,TIM_GP16, $pin:ident, CH1, $af:expr) => {
         impl_pwm_pin!($inst, Ch1, $pin, $af);
+        impl_capture_pin!($inst, Ch1, $pin, $af);
     };
     ($inst:ident, timer,TIM_GP16, $pin:ident, CH2, $af:expr) => {
         impl_pwm_pin!($inst, Ch2, $pin, $af);
+        impl_capture_pin!($inst, Ch2, $pin, $af);
     };
     ($inst:ident, timer,TIM_GP16, $pin:ident, CH3, $af:expr) => {
         impl_pwm_pin!($inst, Ch3, $pin, $af);
+        impl_capture_pin!($inst, Ch3, $pin, $af);
     };
     ($inst:ident, timer,TIM_GP16, $pin:ident, CH4, $af:expr) => {
         impl_pwm_pin!($inst, Ch4, $pin, $af);
+        impl_capture_pin!($inst, Ch4, $pin, $af);
+    };
+    ($inst:ident, timer,TIM_ADV, $pin:ident, CH1, $af:expr) => {
+        impl_pwm_pin!($inst, Ch1, $pin, $af);
+        impl_capture_pin!($inst, Ch1, $pin, $af);
+    };
+    ($inst:ident, timer,TIM_ADV, $pin:ident, CH2, $af:expr) => {
+        impl_pwm_pin!($inst, Ch2, $pin, $af);
+        impl_capture_pin!($inst, Ch2, $pin, $af);
+    };
+    ($inst:ident, timer,TIM_ADV, $pin:ident, CH3, $af:expr) => {
+        impl_pwm_pin!($inst, Ch3, $pin, $af);
+        impl_capture_pin!($inst, Ch3, $pin, $af);
+    };
+    ($inst:ident, timer,TIM_ADV, $pin:ident, CH4, $af:expr) => {
+        impl_pwm_pin!($inst, Ch4, $pin, $af);
+        impl_capture_pin!($inst, Ch4, $pin, $af);
+    };
+    ($inst:ident, timer,TIM_ADV, $pin:ident, CH1N, $af:expr) => {
+        impl_complementary_pin!($inst, Ch1, $pin, $af);
+    };
+    ($inst:ident, timer,TIM_ADV, $pin:ident, CH2N, $af:expr) => {
+        impl_complementary_pin!($inst, Ch2, $pin, $af);
+    };
+    ($inst:ident, timer,TIM_ADV, $pin:ident, CH3N, $af:expr) => {
+        impl_complementary_pin!($inst, Ch3, $pin, $af);
     };
 );
+
+crate::pac::peripheral_dma_channels! {
+    ($peri:ident, timer, $kind:ident, UP, $channel:tt, $request:expr) => {
+        impl_dma!($peri, $channel, UpDma, $request);
+    };
+}
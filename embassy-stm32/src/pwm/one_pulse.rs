@@ -0,0 +1,102 @@
+use core::marker::PhantomData;
+
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+
+use super::*;
+
+/// Timer outputting a single pulse on its first channel, after a delay, each time it is
+/// triggered.
+pub struct OnePulse<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> OnePulse<'d, T> {
+    /// Creates a pulse generator started by [`Self::trigger`], with a counter ticking at
+    /// `tick_freq`.
+    pub fn new<F: Into<Hertz>>(
+        tim: impl Unborrow<Target = T> + 'd,
+        out: impl Unborrow<Target = impl PwmPin<T, Ch1>> + 'd,
+        tick_freq: F,
+    ) -> Self {
+        Self::new_inner(tim, out, tick_freq.into())
+    }
+
+    /// Creates a pulse generator also started by the `edge` of the signal on `trigger`.
+    pub fn new_triggered<F: Into<Hertz>>(
+        tim: impl Unborrow<Target = T> + 'd,
+        out: impl Unborrow<Target = impl PwmPin<T, Ch1>> + 'd,
+        trigger: impl Unborrow<Target = impl CapturePin<T, Ch2>> + 'd,
+        edge: CaptureEdge,
+        tick_freq: F,
+    ) -> Self {
+        unborrow!(trigger);
+        assert!(edge != CaptureEdge::Both);
+
+        let this = Self::new_inner(tim, out, tick_freq.into());
+        unsafe {
+            trigger.configure();
+            set_input::<T>(Channel::Ch2, Input::Own);
+            set_capture_edge::<T>(Channel::Ch2, edge);
+            // The trigger sets CEN.
+            set_slave_mode::<T>(Sms::TRIGGER_MODE, Ts::TI2FP2);
+        }
+        this
+    }
+
+    fn new_inner(
+        _tim: impl Unborrow<Target = T> + 'd,
+        out: impl Unborrow<Target = impl PwmPin<T, Ch1>> + 'd,
+        tick_freq: Hertz,
+    ) -> Self {
+        unborrow!(out);
+
+        T::enable();
+        T::reset();
+
+        set_tick_freq::<T>(tick_freq);
+
+        let r = T::regs();
+        unsafe {
+            // Inactive until the counter reaches CCR1, then active until the update event, which
+            // stops the counter.
+            r.ccmr_output(0).modify(|w| w.set_ocm(0, Ocm::PWMMODE2));
+            r.ccr(0).write(|w| w.set_ccr(1));
+            r.arr().write(|w| w.set_arr(1));
+            r.cr1().write(|w| w.set_opm(true));
+            r.ccer().modify(|w| w.set_cce(0, true));
+            out.configure();
+        }
+
+        Self {
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the delay between the trigger and the pulse, and the width of the pulse, in ticks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the delay or the width is 0, or if the pulse ends after 65536 ticks.
+    pub fn set_pulse(&mut self, delay: u16, width: u16) {
+        assert!(delay > 0 && width > 0);
+        let end = delay as u32 + width as u32 - 1;
+        assert!(end <= u16::MAX as u32);
+
+        let r = T::regs();
+        unsafe {
+            r.ccr(0).write(|w| w.set_ccr(delay));
+            r.arr().write(|w| w.set_arr(end as u16));
+        }
+    }
+
+    /// Starts a pulse, unless one is running.
+    pub fn trigger(&mut self) {
+        unsafe { T::regs().cr1().modify(|w| w.set_cen(true)) }
+    }
+
+    /// Returns whether a pulse (or the delay before it) is running.
+    pub fn is_running(&self) -> bool {
+        unsafe { T::regs().cr1().read().cen() }
+    }
+}
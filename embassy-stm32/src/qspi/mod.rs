@@ -0,0 +1,1008 @@
+#![macro_use]
+
+#[cfg_attr(quadspi, path = "quadspi.rs")]
+#[cfg_attr(octospi, path = "octospi.rs")]
+mod _version;
+pub mod sfdp;
+
+use core::future::Future;
+use core::marker::PhantomData;
+use core::task::Poll;
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+use embassy_traits::flash::{Error as FlashError, Flash};
+use futures::future::poll_fn;
+
+use crate::dma;
+use crate::gpio::sealed::AFType;
+use crate::gpio::{AnyPin, Pin as GpioPin};
+use crate::peripherals;
+use crate::rcc::RccPeripheral;
+use sfdp::{AddressBytes, EraseType, FlashParameters, QuadEnable, ReadCommand};
+
+/// Completion of a command, signaled by a status flag and its interrupt.
+#[derive(Clone, Copy)]
+enum Event {
+    TransferComplete,
+    StatusMatch,
+}
+
+impl Event {
+    fn is_set(self, sr: &_version::Sr) -> bool {
+        match self {
+            Event::TransferComplete => sr.tcf(),
+            Event::StatusMatch => sr.smf(),
+        }
+    }
+
+    fn set_interrupt(self, cr: &mut _version::Cr, enabled: bool) {
+        match self {
+            Event::TransferComplete => cr.set_tcie(enabled),
+            Event::StatusMatch => cr.set_smie(enabled),
+        }
+    }
+}
+
+/// Largest transfer of [`QspiFlash`], below the 65535 items of a DMA transfer.
+const MAX_TRANSFER: usize = 0x8000;
+
+const CMD_WRITE_ENABLE: u8 = 0x06;
+const CMD_READ_STATUS: u8 = 0x05;
+const CMD_WRITE_STATUS: u8 = 0x01;
+const CMD_READ_STATUS_2: u8 = 0x35;
+const CMD_WRITE_STATUS_2: u8 = 0x31;
+const CMD_READ_STATUS_2_BIT7: u8 = 0x3F;
+const CMD_WRITE_STATUS_2_BIT7: u8 = 0x3E;
+const CMD_FAST_READ: u8 = 0x0B;
+const CMD_PAGE_PROGRAM: u8 = 0x02;
+const CMD_ENTER_4_BYTE: u8 = 0xB7;
+const CMD_READ_SFDP: u8 = 0x5A;
+/// Write In Progress bit of the status register.
+const STATUS_WIP: u8 = 1 << 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// An access beyond the device size configured in the peripheral.
+    Transfer,
+    /// An access beyond the end of the flash memory.
+    OutOfBounds,
+    /// The flash address isn't a multiple of the erase size.
+    Unaligned,
+    Sfdp(sfdp::Error),
+}
+
+impl From<sfdp::Error> for Error {
+    fn from(e: sfdp::Error) -> Self {
+        Self::Sfdp(e)
+    }
+}
+
+impl From<Error> for FlashError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Unaligned => FlashError::AddressMisaligned,
+            _ => FlashError::Failed,
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// The memory is clocked at the kernel clock divided by `prescaler + 1`.
+    pub prescaler: u8,
+    /// Minimum number of cycles the chip select stays high between commands, 1 to 8.
+    pub cs_high_time: u8,
+    /// Samples the data half a cycle later, to make up for the memory's output delay.
+    pub sample_shift: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            prescaler: 3,
+            cs_high_time: 2,
+            sample_shift: true,
+        }
+    }
+}
+
+/// Number of lines used by a phase of a command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LineMode {
+    /// The phase is skipped.
+    None = 0b00,
+    Single = 0b01,
+    Dual = 0b10,
+    Quad = 0b11,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddressSize {
+    EightBit = 0b00,
+    SixteenBit = 0b01,
+    TwentyFourBit = 0b10,
+    ThirtyTwoBit = 0b11,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum FunctionalMode {
+    IndirectWrite = 0b00,
+    IndirectRead = 0b01,
+    AutoPolling = 0b10,
+    MemoryMapped = 0b11,
+}
+
+/// A command sent to the memory: an instruction, followed by optional address, alternate
+/// byte, dummy cycles and data phases.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Command {
+    pub instruction: u8,
+    pub instruction_mode: LineMode,
+    pub address: u32,
+    pub address_mode: LineMode,
+    pub address_size: AddressSize,
+    /// Byte sent on the address lines after the address, like the mode bits of fast reads.
+    pub alternate_byte: Option<u8>,
+    pub dummy_cycles: u8,
+    pub data_mode: LineMode,
+}
+
+impl Command {
+    /// Returns an instruction sent on a single line, without other phases.
+    pub const fn new(instruction: u8) -> Self {
+        Self {
+            instruction,
+            instruction_mode: LineMode::Single,
+            address: 0,
+            address_mode: LineMode::None,
+            address_size: AddressSize::TwentyFourBit,
+            alternate_byte: None,
+            dummy_cycles: 0,
+            data_mode: LineMode::None,
+        }
+    }
+
+    /// Returns an instruction reading or writing data on a single line.
+    const fn with_data(instruction: u8) -> Self {
+        Self {
+            data_mode: LineMode::Single,
+            ..Self::new(instruction)
+        }
+    }
+}
+
+/// Driver for the QUADSPI or OCTOSPI peripheral, in quad mode.
+pub struct Qspi<'d, T: Instance, Dma> {
+    irq: T::Interrupt,
+    dma: Dma,
+    pins: [AnyPin; 6],
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance, Dma> Qspi<'d, T, Dma> {
+    pub fn new(
+        _peri: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        clk: impl Unborrow<Target = impl ClkPin<T>> + 'd,
+        ncs: impl Unborrow<Target = impl NcsPin<T>> + 'd,
+        io0: impl Unborrow<Target = impl Io0Pin<T>> + 'd,
+        io1: impl Unborrow<Target = impl Io1Pin<T>> + 'd,
+        io2: impl Unborrow<Target = impl Io2Pin<T>> + 'd,
+        io3: impl Unborrow<Target = impl Io3Pin<T>> + 'd,
+        dma: impl Unborrow<Target = Dma> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(irq, clk, ncs, io0, io1, io2, io3, dma);
+
+        T::enable();
+        T::reset();
+
+        critical_section::with(|_| unsafe {
+            configure_pin(&clk, clk.af_num(), false);
+            // Keep the memory deselected, and IO2/IO3 (WP#/HOLD#) inactive outside of quad
+            // transfers.
+            configure_pin(&ncs, ncs.af_num(), true);
+            configure_pin(&io0, io0.af_num(), false);
+            configure_pin(&io1, io1.af_num(), false);
+            configure_pin(&io2, io2.af_num(), true);
+            configure_pin(&io3, io3.af_num(), true);
+
+            _version::configure::<T>(&config);
+        });
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self {
+            irq,
+            dma,
+            pins: [
+                clk.degrade(),
+                ncs.degrade(),
+                io0.degrade(),
+                io1.degrade(),
+                io2.degrade(),
+                io3.degrade(),
+            ],
+            phantom: PhantomData,
+        }
+    }
+
+    fn on_interrupt(_: *mut ()) {
+        unsafe {
+            let sr = T::regs().sr().read();
+            let cr = T::regs().cr().read();
+
+            if (sr.tef() && cr.teie()) || (sr.tcf() && cr.tcie()) || (sr.smf() && cr.smie()) {
+                T::regs().cr().modify(|reg| {
+                    reg.set_teie(reg.teie() && !sr.tef());
+                    reg.set_tcie(reg.tcie() && !sr.tcf());
+                    reg.set_smie(reg.smie() && !sr.smf());
+                });
+                T::state().wake();
+            }
+        }
+    }
+
+    /// Waits for the end of the previous command and starts `cmd`.
+    unsafe fn start(cmd: &Command, mode: FunctionalMode, data_len: usize) {
+        let regs = T::regs();
+        while regs.sr().read().busy() {}
+        Self::clear_flags();
+        _version::start::<T>(cmd, mode, data_len);
+    }
+
+    /// Aborts the ongoing command, if any.
+    fn abort() {
+        unsafe {
+            let regs = T::regs();
+            regs.cr().modify(|reg| {
+                reg.set_dmaen(false);
+                reg.set_teie(false);
+                reg.set_tcie(false);
+                reg.set_smie(false);
+                reg.set_abort(true);
+            });
+            while regs.cr().read().abort() {}
+            Self::clear_flags();
+        }
+    }
+
+    /// Clears the transfer error, transfer complete, status match and timeout flags.
+    unsafe fn clear_flags() {
+        T::regs().fcr().write(|reg| {
+            reg.set_ctef(true);
+            reg.set_ctcf(true);
+            reg.set_csmf(true);
+            reg.set_ctof(true);
+        });
+    }
+
+    /// Waits for `event`, or for a transfer error.
+    async fn wait(&mut self, event: Event) -> Result<(), Error> {
+        unsafe {
+            T::regs().cr().modify(|reg| {
+                event.set_interrupt(reg, true);
+                reg.set_teie(true);
+            });
+        }
+        let on_drop = OnDrop::new(Self::abort);
+
+        let res = poll_fn(|cx| {
+            T::state().register(cx.waker());
+
+            let sr = unsafe { T::regs().sr().read() };
+            if sr.tef() {
+                Poll::Ready(Err(Error::Transfer))
+            } else if event.is_set(&sr) {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        on_drop.defuse();
+        unsafe {
+            T::regs().cr().modify(|reg| {
+                event.set_interrupt(reg, false);
+                reg.set_teie(false);
+                reg.set_dmaen(false);
+            });
+            Self::clear_flags();
+        }
+        if res.is_err() {
+            Self::abort();
+        }
+        res
+    }
+
+    /// Sends a command without data phase.
+    pub async fn command(&mut self, cmd: Command) -> Result<(), Error> {
+        unsafe { Self::start(&cmd, FunctionalMode::IndirectWrite, 0) };
+        self.wait(Event::TransferComplete).await
+    }
+
+    /// Sends `cmd` and reads its data phase into `buf`, of at most 65535 bytes, using DMA.
+    pub async fn read(&mut self, cmd: Command, buf: &mut [u8]) -> Result<(), Error>
+    where
+        Dma: QspiDma<T>,
+    {
+        assert!(!buf.is_empty());
+        let request = self.dma.request();
+        let dr = T::regs().dr().ptr() as *mut u8;
+        {
+            let transfer = dma::read(&mut self.dma, request, dr, buf);
+            let on_drop = OnDrop::new(Self::abort);
+            unsafe {
+                T::regs().cr().modify(|reg| reg.set_dmaen(true));
+                Self::start(&cmd, FunctionalMode::IndirectRead, buf.len());
+            }
+            transfer.await;
+            on_drop.defuse();
+        }
+        self.wait(Event::TransferComplete).await
+    }
+
+    /// Sends `cmd` with the contents of `buf`, of at most 65535 bytes, as data phase, using
+    /// DMA.
+    pub async fn write(&mut self, cmd: Command, buf: &[u8]) -> Result<(), Error>
+    where
+        Dma: QspiDma<T>,
+    {
+        assert!(!buf.is_empty());
+        let request = self.dma.request();
+        let dr = T::regs().dr().ptr() as *mut u8;
+        unsafe {
+            Self::start(&cmd, FunctionalMode::IndirectWrite, buf.len());
+            T::regs().cr().modify(|reg| reg.set_dmaen(true));
+        }
+        {
+            let on_drop = OnDrop::new(Self::abort);
+            dma::write(&mut self.dma, request, buf, dr).await;
+            on_drop.defuse();
+        }
+        self.wait(Event::TransferComplete).await
+    }
+
+    /// Sends `cmd` and reads its data phase into `buf`, polling the FIFO.
+    pub fn blocking_read(&mut self, cmd: Command, buf: &mut [u8]) -> Result<(), Error> {
+        assert!(!buf.is_empty());
+        unsafe {
+            let dr = T::regs().dr().ptr() as *mut u8;
+            Self::start(&cmd, FunctionalMode::IndirectRead, buf.len());
+            for b in buf {
+                Self::blocking_wait(|sr| sr.ftf() || sr.tcf())?;
+                *b = dr.read_volatile();
+            }
+            Self::blocking_wait(|sr| sr.tcf())
+        }
+    }
+
+    /// Sends `cmd` with the contents of `buf` as data phase, polling the FIFO.
+    pub fn blocking_write(&mut self, cmd: Command, buf: &[u8]) -> Result<(), Error> {
+        assert!(!buf.is_empty());
+        unsafe {
+            let dr = T::regs().dr().ptr() as *mut u8;
+            Self::start(&cmd, FunctionalMode::IndirectWrite, buf.len());
+            for b in buf {
+                Self::blocking_wait(|sr| sr.ftf())?;
+                dr.write_volatile(*b);
+            }
+            Self::blocking_wait(|sr| sr.tcf())
+        }
+    }
+
+    unsafe fn blocking_wait(done: impl Fn(&_version::Sr) -> bool) -> Result<(), Error> {
+        loop {
+            let sr = T::regs().sr().read();
+            if sr.tef() {
+                Self::abort();
+                return Err(Error::Transfer);
+            }
+            if done(&sr) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Sends `cmd`, which reads a single byte like the status register, until
+    /// `value & mask == expected`.
+    pub async fn wait_status(&mut self, cmd: Command, mask: u8, expected: u8) -> Result<(), Error> {
+        unsafe {
+            let regs = T::regs();
+            while regs.sr().read().busy() {}
+            regs.psmkr().write(|reg| reg.set_mask(mask as u32));
+            regs.psmar().write(|reg| reg.set_match_(expected as u32));
+            regs.pir().write(|reg| reg.set_interval(0x10));
+            // Stop polling on the first match.
+            regs.cr().modify(|reg| reg.set_apms(true));
+            Self::start(&cmd, FunctionalMode::AutoPolling, 1);
+        }
+        self.wait(Event::StatusMatch).await
+    }
+
+    /// Maps `size` bytes of the memory into the address space, reading them with `cmd`, of
+    /// which the address is ignored.
+    ///
+    /// Indirect commands can be sent again once the returned guard is dropped.
+    pub fn memory_mapped(&mut self, cmd: Command, size: usize) -> MemoryMapped<'_, 'd, T, Dma> {
+        unsafe { Self::start(&cmd, FunctionalMode::MemoryMapped, 0) };
+        MemoryMapped { _qspi: self, size }
+    }
+}
+
+impl<'d, T: Instance, Dma> Drop for Qspi<'d, T, Dma> {
+    fn drop(&mut self) {
+        self.irq.disable();
+        Self::abort();
+        unsafe {
+            T::regs().cr().modify(|reg| reg.set_en(false));
+        }
+        critical_section::with(|_| unsafe {
+            for pin in self.pins.iter() {
+                deconfigure_pin(pin);
+            }
+        });
+        T::disable();
+    }
+}
+
+/// The memory, mapped by [`Qspi::memory_mapped`].
+pub struct MemoryMapped<'a, 'd, T: Instance, Dma> {
+    _qspi: &'a mut Qspi<'d, T, Dma>,
+    size: usize,
+}
+
+impl<'a, 'd, T: Instance, Dma> MemoryMapped<'a, 'd, T, Dma> {
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(T::MEMORY_BASE as *const u8, self.size) }
+    }
+}
+
+impl<'a, 'd, T: Instance, Dma> Drop for MemoryMapped<'a, 'd, T, Dma> {
+    fn drop(&mut self) {
+        Qspi::<'d, T, Dma>::abort();
+    }
+}
+
+/// A NOR flash memory, with its geometry and commands from its parameters.
+pub struct QspiFlash<'d, T: Instance, Dma> {
+    qspi: Qspi<'d, T, Dma>,
+    params: FlashParameters,
+    size: u32,
+    erase: EraseType,
+    address_size: AddressSize,
+    read: Command,
+}
+
+impl<'d, T: Instance, Dma: QspiDma<T>> QspiFlash<'d, T, Dma> {
+    /// Reads the parameters of the memory from its SFDP tables.
+    pub async fn new(mut qspi: Qspi<'d, T, Dma>) -> Result<Self, Error> {
+        let mut header = [0; sfdp::HEADER_LEN];
+        qspi.read(sfdp_command(0), &mut header).await?;
+        let table = sfdp::parse_header(&header)?;
+
+        // Only the first 16 DWORDs are used.
+        let mut buf = [0; 64];
+        let len = table.len.min(buf.len());
+        qspi.read(sfdp_command(table.address), &mut buf[..len])
+            .await?;
+        let params = FlashParameters::parse(&buf[..len])?;
+        trace!("qspi: flash parameters {:?}", params);
+
+        Self::with_parameters(qspi, params).await
+    }
+
+    /// Uses a memory without SFDP tables, switching it to quad reads and 4-byte addresses
+    /// when needed like [`QspiFlash::new`].
+    pub async fn with_parameters(
+        mut qspi: Qspi<'d, T, Dma>,
+        params: FlashParameters,
+    ) -> Result<Self, Error> {
+        let erase = params
+            .smallest_erase()
+            .ok_or(Error::Sfdp(sfdp::Error::Unsupported))?;
+        if params.size > u32::MAX as u64 {
+            return Err(Error::Sfdp(sfdp::Error::Unsupported));
+        }
+        let size = params.size as u32;
+        unsafe { _version::set_device_size::<T>(size) };
+
+        let address_size = match params.address_bytes {
+            AddressBytes::Three if size > 1 << 24 => {
+                return Err(Error::Sfdp(sfdp::Error::Unsupported));
+            }
+            AddressBytes::ThreeOrFour if size > 1 << 24 => {
+                qspi.command(Command::new(CMD_WRITE_ENABLE)).await?;
+                qspi.command(Command::new(CMD_ENTER_4_BYTE)).await?;
+                AddressSize::ThirtyTwoBit
+            }
+            AddressBytes::Four => AddressSize::ThirtyTwoBit,
+            _ => AddressSize::TwentyFourBit,
+        };
+
+        let read = match (params.quad_enable, params.read_1_4_4, params.read_1_1_4) {
+            (Some(quad_enable), Some(read), _) => {
+                enable_quad(&mut qspi, quad_enable).await?;
+                read_command(read, LineMode::Quad, address_size)
+            }
+            (Some(quad_enable), None, Some(read)) => {
+                enable_quad(&mut qspi, quad_enable).await?;
+                read_command(read, LineMode::Single, address_size)
+            }
+            _ => Command {
+                address_mode: LineMode::Single,
+                address_size,
+                dummy_cycles: 8,
+                ..Command::with_data(CMD_FAST_READ)
+            },
+        };
+
+        Ok(Self {
+            qspi,
+            params,
+            size,
+            erase,
+            address_size,
+            read,
+        })
+    }
+
+    pub fn parameters(&self) -> &FlashParameters {
+        &self.params
+    }
+
+    /// Returns the underlying driver, to send custom commands.
+    pub fn qspi(&mut self) -> &mut Qspi<'d, T, Dma> {
+        &mut self.qspi
+    }
+
+    pub async fn read(&mut self, address: u32, buf: &mut [u8]) -> Result<(), Error> {
+        self.check_range(address, buf.len())?;
+        let mut address = address;
+        for chunk in buf.chunks_mut(MAX_TRANSFER) {
+            let cmd = Command {
+                address,
+                ..self.read
+            };
+            self.qspi.read(cmd, chunk).await?;
+            address += chunk.len() as u32;
+        }
+        Ok(())
+    }
+
+    /// Programs `buf` at `address`, one page at a time.
+    pub async fn write(&mut self, address: u32, buf: &[u8]) -> Result<(), Error> {
+        self.check_range(address, buf.len())?;
+        let page_size = self.params.page_size;
+        let mut address = address;
+        let mut buf = buf;
+        while !buf.is_empty() {
+            // Programming wraps around at the end of a page.
+            let len = buf.len().min((page_size - address % page_size) as usize);
+            self.qspi.command(Command::new(CMD_WRITE_ENABLE)).await?;
+            let cmd = Command {
+                address,
+                address_mode: LineMode::Single,
+                address_size: self.address_size,
+                ..Command::with_data(CMD_PAGE_PROGRAM)
+            };
+            self.qspi.write(cmd, &buf[..len]).await?;
+            wait_ready(&mut self.qspi).await?;
+
+            address += len as u32;
+            buf = &buf[len..];
+        }
+        Ok(())
+    }
+
+    /// Erases the block at `address`, of the smallest erase size of the memory.
+    pub async fn erase(&mut self, address: u32) -> Result<(), Error> {
+        if address % self.erase.size != 0 {
+            return Err(Error::Unaligned);
+        }
+        self.check_range(address, self.erase.size as usize)?;
+
+        self.qspi.command(Command::new(CMD_WRITE_ENABLE)).await?;
+        let cmd = Command {
+            address,
+            address_mode: LineMode::Single,
+            address_size: self.address_size,
+            ..Command::new(self.erase.opcode)
+        };
+        self.qspi.command(cmd).await?;
+        wait_ready(&mut self.qspi).await
+    }
+
+    /// Maps the whole memory into the address space, read with the fastest read command.
+    pub fn memory_mapped(&mut self) -> MemoryMapped<'_, 'd, T, Dma> {
+        self.qspi.memory_mapped(self.read, self.size as usize)
+    }
+
+    fn check_range(&self, address: u32, len: usize) -> Result<(), Error> {
+        if address > self.size || len > (self.size - address) as usize {
+            return Err(Error::OutOfBounds);
+        }
+        Ok(())
+    }
+}
+
+impl<'d, T: Instance, Dma: QspiDma<T>> Flash for QspiFlash<'d, T, Dma> {
+    type ReadFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
+    type WriteFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
+    type ErasePageFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), FlashError>> + 'a;
+
+    fn read<'a>(&'a mut self, address: usize, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move { Ok(self.read(address as u32, data).await?) }
+    }
+
+    fn write<'a>(&'a mut self, address: usize, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move { Ok(self.write(address as u32, data).await?) }
+    }
+
+    fn erase<'a>(&'a mut self, address: usize) -> Self::ErasePageFuture<'a> {
+        async move { Ok(self.erase(address as u32).await?) }
+    }
+
+    fn size(&self) -> usize {
+        self.size as usize
+    }
+
+    fn read_size(&self) -> usize {
+        1
+    }
+
+    fn write_size(&self) -> usize {
+        1
+    }
+
+    fn erase_size(&self) -> usize {
+        self.erase.size as usize
+    }
+}
+
+/// Returns the command reading the SFDP tables from `address`.
+const fn sfdp_command(address: u32) -> Command {
+    Command {
+        address,
+        address_mode: LineMode::Single,
+        address_size: AddressSize::TwentyFourBit,
+        dummy_cycles: 8,
+        ..Command::with_data(CMD_READ_SFDP)
+    }
+}
+
+/// Returns the command of a quad fast read, `address_mode` being the lines of the address and
+/// mode bits.
+fn read_command(read: ReadCommand, address_mode: LineMode, address_size: AddressSize) -> Command {
+    let lines = match address_mode {
+        LineMode::Quad => 4,
+        LineMode::Dual => 2,
+        _ => 1,
+    };
+    // Mode bits which don't enable the continuous read mode of any memory, or clocked as dummy
+    // cycles when they don't make a byte.
+    let (alternate_byte, dummy_cycles) = if read.mode_clocks * lines == 8 {
+        (Some(0xFF), read.wait_states)
+    } else {
+        (None, read.mode_clocks + read.wait_states)
+    };
+    Command {
+        instruction: read.opcode,
+        instruction_mode: LineMode::Single,
+        address: 0,
+        address_mode,
+        address_size,
+        alternate_byte,
+        dummy_cycles,
+        data_mode: LineMode::Quad,
+    }
+}
+
+async fn wait_ready<T: Instance, Dma>(qspi: &mut Qspi<'_, T, Dma>) -> Result<(), Error> {
+    qspi.wait_status(Command::with_data(CMD_READ_STATUS), STATUS_WIP, 0)
+        .await
+}
+
+async fn read_register<T: Instance, Dma: QspiDma<T>>(
+    qspi: &mut Qspi<'_, T, Dma>,
+    instruction: u8,
+) -> Result<u8, Error> {
+    let mut buf = [0];
+    qspi.read(Command::with_data(instruction), &mut buf).await?;
+    Ok(buf[0])
+}
+
+async fn write_registers<T: Instance, Dma: QspiDma<T>>(
+    qspi: &mut Qspi<'_, T, Dma>,
+    instruction: u8,
+    values: &[u8],
+) -> Result<(), Error> {
+    qspi.command(Command::new(CMD_WRITE_ENABLE)).await?;
+    qspi.write(Command::with_data(instruction), values).await?;
+    wait_ready(qspi).await
+}
+
+/// Sets the quad enable bit of the memory, unless already set.
+async fn enable_quad<T: Instance, Dma: QspiDma<T>>(
+    qspi: &mut Qspi<'_, T, Dma>,
+    quad_enable: QuadEnable,
+) -> Result<(), Error> {
+    match quad_enable {
+        QuadEnable::None => Ok(()),
+        QuadEnable::Sr2Bit1WriteSr1Sr2 => {
+            let sr1 = read_register(qspi, CMD_READ_STATUS).await?;
+            write_registers(qspi, CMD_WRITE_STATUS, &[sr1, 1 << 1]).await
+        }
+        QuadEnable::Sr1Bit6 => {
+            let sr1 = read_register(qspi, CMD_READ_STATUS).await?;
+            if sr1 & 1 << 6 != 0 {
+                return Ok(());
+            }
+            write_registers(qspi, CMD_WRITE_STATUS, &[sr1 | 1 << 6]).await
+        }
+        QuadEnable::Sr2Bit7 => {
+            let sr2 = read_register(qspi, CMD_READ_STATUS_2_BIT7).await?;
+            if sr2 & 1 << 7 != 0 {
+                return Ok(());
+            }
+            write_registers(qspi, CMD_WRITE_STATUS_2_BIT7, &[sr2 | 1 << 7]).await
+        }
+        QuadEnable::Sr2Bit1ReadSr2 => {
+            let sr2 = read_register(qspi, CMD_READ_STATUS_2).await?;
+            if sr2 & 1 << 1 != 0 {
+                return Ok(());
+            }
+            let sr1 = read_register(qspi, CMD_READ_STATUS).await?;
+            write_registers(qspi, CMD_WRITE_STATUS, &[sr1, sr2 | 1 << 1]).await
+        }
+        QuadEnable::Sr2Bit1WriteSr2 => {
+            let sr2 = read_register(qspi, CMD_READ_STATUS_2).await?;
+            if sr2 & 1 << 1 != 0 {
+                return Ok(());
+            }
+            write_registers(qspi, CMD_WRITE_STATUS_2, &[sr2 | 1 << 1]).await
+        }
+    }
+}
+
+/// # Safety
+///
+/// Access to the GPIO block of `pin` should be exclusive
+unsafe fn configure_pin(pin: &impl crate::gpio::sealed::Pin, af_num: u8, pup: bool) {
+    pin.set_as_af(af_num, AFType::OutputPushPull);
+
+    #[cfg(gpio_v2)]
+    {
+        use crate::pac::gpio::vals::Pupdr;
+
+        pin.set_speed(crate::gpio::Speed::VeryHigh);
+        if pup {
+            let n = pin._pin() as usize;
+            pin.block()
+                .pupdr()
+                .modify(|w| w.set_pupdr(n, Pupdr::PULLUP));
+        }
+    }
+}
+
+/// # Safety
+///
+/// Access to the GPIO block of `pin` should be exclusive
+unsafe fn deconfigure_pin(pin: &impl crate::gpio::sealed::Pin) {
+    pin.set_as_analog();
+
+    #[cfg(gpio_v2)]
+    {
+        use crate::pac::gpio::vals::Pupdr;
+
+        pin.set_speed(crate::gpio::Speed::Low);
+        let n = pin._pin() as usize;
+        pin.block()
+            .pupdr()
+            .modify(|w| w.set_pupdr(n, Pupdr::FLOATING));
+    }
+}
+
+pub(crate) mod sealed {
+    use super::*;
+    use embassy::waitqueue::AtomicWaker;
+
+    pub trait Instance {
+        /// Address of the memory in memory-mapped mode.
+        const MEMORY_BASE: usize;
+
+        fn regs() -> &'static _version::Regs;
+        fn state() -> &'static AtomicWaker;
+    }
+
+    pub trait ClkPin<T: Instance>: GpioPin {
+        fn af_num(&self) -> u8;
+    }
+    pub trait NcsPin<T: Instance>: GpioPin {
+        fn af_num(&self) -> u8;
+    }
+    pub trait Io0Pin<T: Instance>: GpioPin {
+        fn af_num(&self) -> u8;
+    }
+    pub trait Io1Pin<T: Instance>: GpioPin {
+        fn af_num(&self) -> u8;
+    }
+    pub trait Io2Pin<T: Instance>: GpioPin {
+        fn af_num(&self) -> u8;
+    }
+    pub trait Io3Pin<T: Instance>: GpioPin {
+        fn af_num(&self) -> u8;
+    }
+
+    pub trait QspiDma<T: Instance> {
+        fn request(&self) -> dma::Request;
+    }
+}
+
+pub trait Instance: sealed::Instance + RccPeripheral + 'static {
+    type Interrupt: Interrupt;
+}
+pub trait ClkPin<T: Instance>: sealed::ClkPin<T> + 'static {}
+pub trait NcsPin<T: Instance>: sealed::NcsPin<T> + 'static {}
+pub trait Io0Pin<T: Instance>: sealed::Io0Pin<T> + 'static {}
+pub trait Io1Pin<T: Instance>: sealed::Io1Pin<T> + 'static {}
+pub trait Io2Pin<T: Instance>: sealed::Io2Pin<T> + 'static {}
+pub trait Io3Pin<T: Instance>: sealed::Io3Pin<T> + 'static {}
+pub trait QspiDma<T: Instance>: sealed::QspiDma<T> + dma::Channel {}
+
+macro_rules! memory_base {
+    (OCTOSPI2) => {
+        0x7000_0000
+    };
+    ($inst:ident) => {
+        0x9000_0000
+    };
+}
+
+macro_rules! impl_instance {
+    ($inst:ident) => {
+        impl sealed::Instance for peripherals::$inst {
+            const MEMORY_BASE: usize = memory_base!($inst);
+
+            fn regs() -> &'static _version::Regs {
+                &crate::pac::$inst
+            }
+
+            fn state() -> &'static ::embassy::waitqueue::AtomicWaker {
+                static WAKER: ::embassy::waitqueue::AtomicWaker =
+                    ::embassy::waitqueue::AtomicWaker::new();
+                &WAKER
+            }
+        }
+    };
+}
+
+crate::pac::peripherals!(
+    (quadspi, $inst:ident) => {
+        impl_instance!($inst);
+    };
+    (octospi, $inst:ident) => {
+        impl_instance!($inst);
+    };
+);
+
+crate::pac::interrupts!(
+    ($inst:ident, quadspi, $block:ident, GLOBAL, $irq:ident) => {
+        impl Instance for peripherals::$inst {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+    ($inst:ident, octospi, $block:ident, GLOBAL, $irq:ident) => {
+        impl Instance for peripherals::$inst {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+);
+
+macro_rules! impl_pin {
+    ($inst:ident, $pin:ident, $signal:ident, $af:expr) => {
+        impl sealed::$signal<peripherals::$inst> for peripherals::$pin {
+            fn af_num(&self) -> u8 {
+                $af
+            }
+        }
+
+        impl $signal<peripherals::$inst> for peripherals::$pin {}
+    };
+}
+
+crate::pac::peripheral_pins!(
+    ($inst:ident, quadspi, QUADSPI, $pin:ident, CLK, $af:expr) => {
+        impl_pin!($inst, $pin, ClkPin, $af);
+    };
+    ($inst:ident, quadspi, QUADSPI, $pin:ident, BK1_NCS, $af:expr) => {
+        impl_pin!($inst, $pin, NcsPin, $af);
+    };
+    ($inst:ident, quadspi, QUADSPI, $pin:ident, BK1_IO0, $af:expr) => {
+        impl_pin!($inst, $pin, Io0Pin, $af);
+    };
+    ($inst:ident, quadspi, QUADSPI, $pin:ident, BK1_IO1, $af:expr) => {
+        impl_pin!($inst, $pin, Io1Pin, $af);
+    };
+    ($inst:ident, quadspi, QUADSPI, $pin:ident, BK1_IO2, $af:expr) => {
+        impl_pin!($inst, $pin, Io2Pin, $af);
+    };
+    ($inst:ident, quadspi, QUADSPI, $pin:ident, BK1_IO3, $af:expr) => {
+        impl_pin!($inst, $pin, Io3Pin, $af);
+    };
+    ($inst:ident, octospi, OCTOSPI, $pin:ident, CLK, $af:expr) => {
+        impl_pin!($inst, $pin, ClkPin, $af);
+    };
+    ($inst:ident, octospi, OCTOSPI, $pin:ident, NCS, $af:expr) => {
+        impl_pin!($inst, $pin, NcsPin, $af);
+    };
+    ($inst:ident, octospi, OCTOSPI, $pin:ident, IO0, $af:expr) => {
+        impl_pin!($inst, $pin, Io0Pin, $af);
+    };
+    ($inst:ident, octospi, OCTOSPI, $pin:ident, IO1, $af:expr) => {
+        impl_pin!($inst, $pin, Io1Pin, $af);
+    };
+    ($inst:ident, octospi, OCTOSPI, $pin:ident, IO2, $af:expr) => {
+        impl_pin!($inst, $pin, Io2Pin, $af);
+    };
+    ($inst:ident, octospi, OCTOSPI, $pin:ident, IO3, $af:expr) => {
+        impl_pin!($inst, $pin, Io3Pin, $af);
+    };
+);
+
+#[allow(unused)]
+macro_rules! impl_dma {
+    ($inst:ident, {dmamux: $dmamux:ident}, $signal:ident, $request:expr) => {
+        impl<T> sealed::$signal<peripherals::$inst> for T
+        where
+            T: crate::dma::MuxChannel<Mux = crate::dma::$dmamux>,
+        {
+            fn request(&self) -> dma::Request {
+                $request
+            }
+        }
+
+        impl<T> $signal<peripherals::$inst> for T where
+            T: crate::dma::MuxChannel<Mux = crate::dma::$dmamux>
+        {
+        }
+    };
+    ($inst:ident, {channel: $channel:ident}, $signal:ident, $request:expr) => {
+        impl sealed::$signal<peripherals::$inst> for peripherals::$channel {
+            fn request(&self) -> dma::Request {
+                $request
+            }
+        }
+
+        impl $signal<peripherals::$inst> for peripherals::$channel {}
+    };
+}
+
+// The peripherals have a single DMA request, whatever its name.
+crate::pac::peripheral_dma_channels! {
+    ($peri:ident, quadspi, $kind:ident, $request_name:ident, $channel:tt, $request:expr) => {
+        impl_dma!($peri, $channel, QspiDma, $request);
+    };
+    ($peri:ident, octospi, $kind:ident, $request_name:ident, $channel:tt, $request:expr) => {
+        impl_dma!($peri, $channel, QspiDma, $request);
+    };
+}
@@ -0,0 +1,75 @@
+use super::{Command, Config, FunctionalMode, Instance, LineMode};
+
+pub(crate) use crate::pac::octospi::regs::{Cr, Sr};
+pub(crate) use crate::pac::octospi::Octospi as Regs;
+
+/// Micron mode, the standard mode of the memory type field.
+const DCR1_MTYP_STANDARD: u8 = 0b010;
+
+pub(super) unsafe fn configure<T: Instance>(config: &Config) {
+    // The pins reach the peripheral through the I/O manager, of which the reset configuration
+    // maps each OCTOSPI to its own port.
+    crate::pac::peripheral_rcc!(
+        ($inst:ident, octospim, $block:ident, $clock:ident, ($reg:ident, $field:ident, $set_field:ident), $rst:tt) => {
+            crate::pac::RCC.$reg().modify(|reg| {
+                reg.$set_field(true);
+            });
+        };
+    );
+
+    let regs = T::regs();
+
+    // The size of the memory is unknown until `set_device_size`, allow the whole 4 GiB
+    // address range meanwhile.
+    regs.dcr1().write(|reg| {
+        reg.set_mtyp(DCR1_MTYP_STANDARD);
+        reg.set_csht(config.cs_high_time.clamp(1, 8) - 1);
+        reg.set_devsize(31);
+    });
+    regs.dcr2().write(|reg| reg.set_prescaler(config.prescaler));
+    regs.tcr().write(|reg| reg.set_sshift(config.sample_shift));
+    // FIFO threshold of a single byte, for byte DMA transfers.
+    regs.cr().write(|reg| reg.set_en(true));
+}
+
+/// Sets the size of the memory, rounded up to a power of two.
+pub(super) unsafe fn set_device_size<T: Instance>(size: u32) {
+    // The memory has 2^(DEVSIZE + 1) bytes.
+    let devsize = size.next_power_of_two().trailing_zeros().max(1) - 1;
+    T::regs()
+        .dcr1()
+        .modify(|reg| reg.set_devsize(devsize as u8));
+}
+
+/// Starts `cmd`, the peripheral being idle.
+pub(super) unsafe fn start<T: Instance>(cmd: &Command, mode: FunctionalMode, data_len: usize) {
+    let regs = T::regs();
+
+    regs.cr().modify(|reg| reg.set_fmode(mode as u8));
+    if data_len > 0 {
+        regs.dlr().write(|reg| reg.set_dl(data_len as u32 - 1));
+    }
+    let abmode = match cmd.alternate_byte {
+        Some(byte) => {
+            regs.abr().write(|reg| reg.set_alternate(byte as u32));
+            cmd.address_mode
+        }
+        None => LineMode::None,
+    };
+    regs.tcr()
+        .modify(|reg| reg.set_dcyc(cmd.dummy_cycles & 0x1F));
+    regs.ccr().write(|reg| {
+        reg.set_imode(cmd.instruction_mode as u8);
+        reg.set_admode(cmd.address_mode as u8);
+        reg.set_adsize(cmd.address_size as u8);
+        reg.set_abmode(abmode as u8);
+        reg.set_dmode(cmd.data_mode as u8);
+    });
+
+    // The command starts with the write of IR, or of AR when it has an address phase.
+    regs.ir()
+        .write(|reg| reg.set_instruction(cmd.instruction as u32));
+    if cmd.address_mode != LineMode::None && mode != FunctionalMode::MemoryMapped {
+        regs.ar().write(|reg| reg.set_address(cmd.address));
+    }
+}
@@ -0,0 +1,59 @@
+use super::{Command, Config, FunctionalMode, Instance, LineMode};
+
+pub(crate) use crate::pac::quadspi::regs::{Cr, Sr};
+pub(crate) use crate::pac::quadspi::Quadspi as Regs;
+
+pub(super) unsafe fn configure<T: Instance>(config: &Config) {
+    let regs = T::regs();
+
+    // The size of the memory is unknown until `set_device_size`, allow the whole 4 GiB
+    // address range meanwhile.
+    regs.dcr().write(|reg| {
+        reg.set_csht(config.cs_high_time.clamp(1, 8) - 1);
+        reg.set_fsize(31);
+    });
+    // FIFO threshold of a single byte, for byte DMA transfers.
+    regs.cr().write(|reg| {
+        reg.set_prescaler(config.prescaler);
+        reg.set_sshift(config.sample_shift);
+        reg.set_en(true);
+    });
+}
+
+/// Sets the size of the memory, rounded up to a power of two.
+pub(super) unsafe fn set_device_size<T: Instance>(size: u32) {
+    // The memory has 2^(FSIZE + 1) bytes.
+    let fsize = size.next_power_of_two().trailing_zeros().max(1) - 1;
+    T::regs().dcr().modify(|reg| reg.set_fsize(fsize as u8));
+}
+
+/// Starts `cmd`, the peripheral being idle.
+pub(super) unsafe fn start<T: Instance>(cmd: &Command, mode: FunctionalMode, data_len: usize) {
+    let regs = T::regs();
+
+    if data_len > 0 {
+        regs.dlr().write(|reg| reg.set_dl(data_len as u32 - 1));
+    }
+    let abmode = match cmd.alternate_byte {
+        Some(byte) => {
+            regs.abr().write(|reg| reg.set_alternate(byte as u32));
+            cmd.address_mode
+        }
+        None => LineMode::None,
+    };
+
+    // The command starts with the write of CCR, or of AR when it has an address phase.
+    regs.ccr().write(|reg| {
+        reg.set_instruction(cmd.instruction);
+        reg.set_imode(cmd.instruction_mode as u8);
+        reg.set_admode(cmd.address_mode as u8);
+        reg.set_adsize(cmd.address_size as u8);
+        reg.set_abmode(abmode as u8);
+        reg.set_dcyc(cmd.dummy_cycles & 0x1F);
+        reg.set_dmode(cmd.data_mode as u8);
+        reg.set_fmode(mode as u8);
+    });
+    if cmd.address_mode != LineMode::None && mode != FunctionalMode::MemoryMapped {
+        regs.ar().write(|reg| reg.set_address(cmd.address));
+    }
+}
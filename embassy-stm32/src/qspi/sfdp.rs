@@ -0,0 +1,359 @@
+//! Serial Flash Discoverable Parameters (JESD216), describing the geometry and commands of a
+//! NOR flash.
+//!
+//! This module depends on nothing but `core`: `ci.sh` runs its tests on the host with `rustc
+//! --test`.
+
+/// "SFDP", as read from address 0.
+const SIGNATURE: u32 = 0x5044_4653;
+/// ID of the Basic Flash Parameter Table, which is always the first one.
+const BASIC_TABLE_ID: u16 = 0xFF00;
+/// Number of DWORDs of the basic table in the first revision of the standard.
+const BASIC_TABLE_MIN_LEN: usize = 9;
+/// Number of DWORDs of the basic table needed to know how to enable the quad mode.
+const BASIC_TABLE_QER_LEN: usize = 15;
+
+/// Bytes to read at address 0 for [`parse_header`]: the SFDP header and the first parameter
+/// header.
+pub const HEADER_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The memory doesn't answer with the SFDP signature.
+    InvalidSignature,
+    /// The first parameter table isn't the basic one, or is too short.
+    NoBasicTable,
+    /// A field has a value this driver can't handle, like a size over 2^63 bytes.
+    Unsupported,
+}
+
+/// Location of the Basic Flash Parameter Table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TableLocation {
+    /// SFDP address of the table.
+    pub address: u32,
+    /// Length of the table in bytes, a multiple of 4.
+    pub len: usize,
+}
+
+/// Parses the SFDP header and the first parameter header.
+pub fn parse_header(buf: &[u8; HEADER_LEN]) -> Result<TableLocation, Error> {
+    if u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) != SIGNATURE {
+        return Err(Error::InvalidSignature);
+    }
+
+    let param = &buf[8..];
+    let id = u16::from_le_bytes([param[0], param[7]]);
+    let len = param[3] as usize;
+    if id != BASIC_TABLE_ID || len < BASIC_TABLE_MIN_LEN {
+        return Err(Error::NoBasicTable);
+    }
+
+    Ok(TableLocation {
+        address: u32::from_le_bytes([param[4], param[5], param[6], 0]),
+        len: len * 4,
+    })
+}
+
+/// Number of address bytes the memory accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddressBytes {
+    Three,
+    /// 3 bytes by default, 4 bytes after entering the 4-byte address mode.
+    ThreeOrFour,
+    Four,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EraseType {
+    /// Size of the erased block, in bytes.
+    pub size: u32,
+    pub opcode: u8,
+}
+
+/// Fast read command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReadCommand {
+    pub opcode: u8,
+    /// Clock cycles of the mode bits, sent on the address lines after the address.
+    pub mode_clocks: u8,
+    /// Dummy clock cycles after the mode bits.
+    pub wait_states: u8,
+}
+
+/// How the quad mode of the memory is enabled, the QER field of the basic table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum QuadEnable {
+    /// No quad enable bit.
+    None,
+    /// Bit 1 of status register 2, written along with status register 1 by 01h, which can't
+    /// read back status register 2.
+    Sr2Bit1WriteSr1Sr2,
+    /// Bit 6 of status register 1, written by 01h.
+    Sr1Bit6,
+    /// Bit 7 of status register 2, read by 3Fh and written by 3Eh.
+    Sr2Bit7,
+    /// Bit 1 of status register 2, read by 35h and written along with status register 1 by 01h.
+    Sr2Bit1ReadSr2,
+    /// Bit 1 of status register 2, read by 35h and written by 31h.
+    Sr2Bit1WriteSr2,
+}
+
+/// Flash geometry and commands, from the Basic Flash Parameter Table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FlashParameters {
+    /// Size of the memory, in bytes.
+    pub size: u64,
+    /// Size of the pages written by a single program command, in bytes.
+    pub page_size: u32,
+    pub address_bytes: AddressBytes,
+    pub erase_types: [Option<EraseType>; 4],
+    /// Fast read with the data on 4 lines.
+    pub read_1_1_4: Option<ReadCommand>,
+    /// Fast read with the address and data on 4 lines.
+    pub read_1_4_4: Option<ReadCommand>,
+    /// `None` when the table predates the QER field, so the quad reads can't be used safely.
+    pub quad_enable: Option<QuadEnable>,
+}
+
+impl FlashParameters {
+    /// Parses the Basic Flash Parameter Table, of at least 9 DWORDs.
+    pub fn parse(table: &[u8]) -> Result<Self, Error> {
+        let len = table.len() / 4;
+        if len < BASIC_TABLE_MIN_LEN {
+            return Err(Error::NoBasicTable);
+        }
+        // DWORDs are numbered from 1 in the standard.
+        let dword = |n: usize| {
+            let i = (n - 1) * 4;
+            u32::from_le_bytes([table[i], table[i + 1], table[i + 2], table[i + 3]])
+        };
+
+        let dw1 = dword(1);
+        let address_bytes = match (dw1 >> 17) & 0b11 {
+            0b00 => AddressBytes::Three,
+            0b01 => AddressBytes::ThreeOrFour,
+            0b10 => AddressBytes::Four,
+            _ => return Err(Error::Unsupported),
+        };
+
+        let dw2 = dword(2);
+        let size = if dw2 & (1 << 31) == 0 {
+            (dw2 as u64 + 1) / 8
+        } else {
+            match dw2 & !(1 << 31) {
+                n @ 3..=66 => 1 << (n - 3),
+                _ => return Err(Error::Unsupported),
+            }
+        };
+
+        let read_command = |supported: bool, fields: u32| {
+            let opcode = (fields >> 8) as u8;
+            (supported && opcode != 0).then(|| ReadCommand {
+                opcode,
+                mode_clocks: (fields >> 5) as u8 & 0b111,
+                wait_states: fields as u8 & 0b1_1111,
+            })
+        };
+        let dw3 = dword(3);
+        let read_1_4_4 = read_command(dw1 & (1 << 21) != 0, dw3 & 0xFFFF);
+        let read_1_1_4 = read_command(dw1 & (1 << 22) != 0, dw3 >> 16);
+
+        let mut erase_types = [None; 4];
+        for (i, erase_type) in erase_types.iter_mut().enumerate() {
+            let fields = dword(8 + i / 2) >> (16 * (i % 2));
+            let size = fields as u8;
+            if size != 0 && size < 32 {
+                *erase_type = Some(EraseType {
+                    size: 1 << size,
+                    opcode: (fields >> 8) as u8,
+                });
+            }
+        }
+        // The 4 KiB erase of the first DWORD, for tables not listing it among the erase types.
+        if erase_types.iter().all(Option::is_none) && dw1 & 0b11 == 0b01 {
+            erase_types[0] = Some(EraseType {
+                size: 4096,
+                opcode: (dw1 >> 8) as u8,
+            });
+        }
+
+        let page_size = if len >= 11 {
+            1 << ((dword(11) >> 4) & 0b1111)
+        } else {
+            256
+        };
+
+        let quad_enable = if len >= BASIC_TABLE_QER_LEN {
+            Some(match (dword(15) >> 20) & 0b111 {
+                0b000 => QuadEnable::None,
+                0b001 | 0b100 => QuadEnable::Sr2Bit1WriteSr1Sr2,
+                0b010 => QuadEnable::Sr1Bit6,
+                0b011 => QuadEnable::Sr2Bit7,
+                0b101 => QuadEnable::Sr2Bit1ReadSr2,
+                0b110 => QuadEnable::Sr2Bit1WriteSr2,
+                _ => return Err(Error::Unsupported),
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            size,
+            page_size,
+            address_bytes,
+            erase_types,
+            read_1_1_4,
+            read_1_4_4,
+            quad_enable,
+        })
+    }
+
+    /// Returns the erase type with the smallest blocks.
+    pub fn smallest_erase(&self) -> Option<EraseType> {
+        self.erase_types
+            .iter()
+            .flatten()
+            .min_by_key(|erase_type| erase_type.size)
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// SFDP of a 16 MiB W25Q128JV, from address 0 to the end of its basic table.
+    const W25Q128: [u8; 16] = [
+        0x53, 0x46, 0x44, 0x50, 0x06, 0x01, 0x00, 0xFF, 0x00, 0x06, 0x01, 0x10, 0x80, 0x00, 0x00,
+        0xFF,
+    ];
+    const W25Q128_BASIC: [u8; 64] = [
+        0xE5, 0x20, 0xF9, 0xFF, 0xFF, 0xFF, 0xFF, 0x07, 0x44, 0xEB, 0x08, 0x6B, 0x08, 0x3B, 0x42,
+        0xBB, 0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x40, 0xEB, 0x0C, 0x20,
+        0x0F, 0x52, 0x10, 0xD8, 0x00, 0x00, 0x36, 0x02, 0xA6, 0x00, 0x82, 0xEA, 0x14, 0xC9, 0xE9,
+        0x63, 0x76, 0x33, 0x7A, 0x75, 0x7A, 0x75, 0xF7, 0xA2, 0xD5, 0x5C, 0x19, 0xF7, 0x4D, 0xFF,
+        0xE9, 0x30, 0xF8, 0x80,
+    ];
+
+    #[test]
+    fn header() {
+        assert_eq!(
+            parse_header(&W25Q128),
+            Ok(TableLocation {
+                address: 0x80,
+                len: 64,
+            })
+        );
+
+        let mut bad = W25Q128;
+        bad[0] = 0xFF;
+        assert_eq!(parse_header(&bad), Err(Error::InvalidSignature));
+
+        // Another table first.
+        let mut bad = W25Q128;
+        bad[15] = 0x84;
+        assert_eq!(parse_header(&bad), Err(Error::NoBasicTable));
+    }
+
+    #[test]
+    fn basic_table() {
+        let params = FlashParameters::parse(&W25Q128_BASIC).unwrap();
+        assert_eq!(params.size, 16 * 1024 * 1024);
+        assert_eq!(params.page_size, 256);
+        assert_eq!(params.address_bytes, AddressBytes::Three);
+        assert_eq!(
+            params.erase_types,
+            [
+                Some(EraseType {
+                    size: 4096,
+                    opcode: 0x20
+                }),
+                Some(EraseType {
+                    size: 32 * 1024,
+                    opcode: 0x52
+                }),
+                Some(EraseType {
+                    size: 64 * 1024,
+                    opcode: 0xD8
+                }),
+                None,
+            ]
+        );
+        assert_eq!(
+            params.read_1_1_4,
+            Some(ReadCommand {
+                opcode: 0x6B,
+                mode_clocks: 0,
+                wait_states: 8,
+            })
+        );
+        assert_eq!(
+            params.read_1_4_4,
+            Some(ReadCommand {
+                opcode: 0xEB,
+                mode_clocks: 2,
+                wait_states: 4,
+            })
+        );
+        assert_eq!(params.quad_enable, Some(QuadEnable::Sr2Bit1WriteSr1Sr2));
+        assert_eq!(
+            params.smallest_erase(),
+            Some(EraseType {
+                size: 4096,
+                opcode: 0x20
+            })
+        );
+    }
+
+    #[test]
+    fn first_revision_table() {
+        // Only the 9 DWORDs of JESD216, without page size nor quad enable.
+        let params = FlashParameters::parse(&W25Q128_BASIC[..36]).unwrap();
+        assert_eq!(params.page_size, 256);
+        assert_eq!(params.quad_enable, None);
+
+        assert_eq!(
+            FlashParameters::parse(&W25Q128_BASIC[..32]),
+            Err(Error::NoBasicTable)
+        );
+    }
+
+    #[test]
+    fn large_density() {
+        // 2^31 bits, with 4-byte addresses only and no quad reads.
+        let mut table = W25Q128_BASIC;
+        table[2] = 0x84;
+        table[4..8].copy_from_slice(&0x8000_001Fu32.to_le_bytes());
+        let params = FlashParameters::parse(&table).unwrap();
+        assert_eq!(params.size, 256 * 1024 * 1024);
+        assert_eq!(params.address_bytes, AddressBytes::Four);
+        assert_eq!(params.read_1_1_4, None);
+        assert_eq!(params.read_1_4_4, None);
+
+        table[4..8].copy_from_slice(&0x8000_0050u32.to_le_bytes());
+        assert_eq!(FlashParameters::parse(&table), Err(Error::Unsupported));
+    }
+
+    #[test]
+    fn erase_fallback() {
+        // No erase types, only the 4 KiB erase of the first DWORD.
+        let mut table = W25Q128_BASIC;
+        table[28..36].fill(0);
+        let params = FlashParameters::parse(&table).unwrap();
+        assert_eq!(
+            params.smallest_erase(),
+            Some(EraseType {
+                size: 4096,
+                opcode: 0x20
+            })
+        );
+    }
+}
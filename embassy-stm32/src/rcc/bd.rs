@@ -0,0 +1,160 @@
+//! Backup domain clocks.
+//!
+//! The RTC clock source lives in the backup domain, which keeps running (and keeps its
+//! configuration) across resets as long as VBAT or VDD is present.
+
+use crate::pac::rcc::regs;
+use crate::pac::rcc::vals::Rtcsel;
+use crate::pac::{PWR, RCC};
+use crate::time::Hertz;
+
+/// Frequency of the LSE crystal.
+const LSE: u32 = 32_768;
+
+/// Clock source of the RTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RtcClockSource {
+    /// 32.768 kHz crystal on OSC32_IN/OSC32_OUT.
+    LSE,
+    /// 32.768 kHz external clock on OSC32_IN.
+    LSEBypass,
+    /// Internal low speed oscillator. It is not accurate, see the datasheet.
+    LSI,
+}
+
+impl Default for RtcClockSource {
+    fn default() -> Self {
+        Self::LSI
+    }
+}
+
+impl RtcClockSource {
+    fn rtcsel(self) -> Rtcsel {
+        match self {
+            Self::LSE | Self::LSEBypass => Rtcsel::LSE,
+            Self::LSI => Rtcsel::LSI,
+        }
+    }
+
+    /// Nominal frequency of the clock source.
+    pub fn frequency(self) -> Hertz {
+        match self {
+            Self::LSE | Self::LSEBypass => Hertz(LSE),
+            Self::LSI => super::LSI_FREQ,
+        }
+    }
+}
+
+/// Register holding the backup domain control bits: BDCR, or CSR on L0/L1.
+#[cfg(not(any(rcc_l0, rcc_l1)))]
+fn bdcr() -> crate::pac::common::Reg<regs::Bdcr, crate::pac::common::RW> {
+    RCC.bdcr()
+}
+
+/// Register holding the backup domain control bits: BDCR, or CSR on L0/L1.
+#[cfg(any(rcc_l0, rcc_l1))]
+fn bdcr() -> crate::pac::common::Reg<regs::Csr, crate::pac::common::RW> {
+    RCC.csr()
+}
+
+/// Resets the backup domain, which is the only way to change the RTC clock source.
+#[cfg(not(feature = "time-driver-lptim1"))]
+unsafe fn reset_backup_domain() {
+    #[cfg(not(any(rcc_l0, rcc_l1)))]
+    {
+        bdcr().modify(|w| w.set_bdrst(true));
+        bdcr().modify(|w| w.set_bdrst(false));
+    }
+    #[cfg(any(rcc_l0, rcc_l1))]
+    {
+        bdcr().modify(|w| w.set_rtcrst(true));
+        bdcr().modify(|w| w.set_rtcrst(false));
+    }
+}
+
+/// Enables writes to the backup domain (RTC and backup registers).
+pub(crate) unsafe fn enable_backup_access() {
+    #[cfg(not(any(rcc_h7, rcc_wb, rcc_wl5)))]
+    <crate::peripherals::PWR as super::sealed::RccPeripheral>::enable();
+
+    #[cfg(any(rcc_f3, rcc_l0, rcc_l1))]
+    PWR.cr().modify(|w| w.set_dbp(true));
+    #[cfg(not(any(rcc_f3, rcc_l0, rcc_l1)))]
+    PWR.cr1().modify(|w| w.set_dbp(true));
+}
+
+/// Selects `source` as the RTC clock and enables it, returning its frequency.
+///
+/// The backup domain is reset if another source was selected, since the selection can't be
+/// changed otherwise: the calendar and the backup registers are lost. If `source` is already
+/// selected and enabled, the backup domain is left untouched so the calendar keeps running
+/// across resets.
+pub(crate) unsafe fn enable_rtc_clock(source: RtcClockSource) -> Hertz {
+    enable_backup_access();
+
+    let rtcsel = source.rtcsel();
+
+    let reg = bdcr().read();
+    let selected = reg.rtcsel();
+    if selected == rtcsel && reg.rtcen() {
+        // The LSI is in the main domain and is stopped by a reset.
+        if source == RtcClockSource::LSI {
+            enable_lsi();
+        }
+        return source.frequency();
+    }
+    if selected != Rtcsel::NOCLOCK {
+        // The reset would also stop the LSE, which clocks the LPTIM time driver: keep the
+        // current source instead.
+        #[cfg(feature = "time-driver-lptim1")]
+        {
+            let current = if selected == Rtcsel::LSI {
+                RtcClockSource::LSI
+            } else {
+                RtcClockSource::LSE
+            };
+            warn!(
+                "keeping the {:?} RTC clock source, it can't be changed with the LPTIM1 time driver",
+                current
+            );
+            if current == RtcClockSource::LSI {
+                enable_lsi();
+            }
+            bdcr().modify(|w| w.set_rtcen(true));
+            return current.frequency();
+        }
+        #[cfg(not(feature = "time-driver-lptim1"))]
+        reset_backup_domain();
+    }
+
+    match source {
+        RtcClockSource::LSE => enable_lse(false),
+        RtcClockSource::LSEBypass => enable_lse(true),
+        RtcClockSource::LSI => enable_lsi(),
+    }
+
+    bdcr().modify(|w| {
+        w.set_rtcsel(rtcsel);
+        w.set_rtcen(true);
+    });
+
+    source.frequency()
+}
+
+/// Enables the LSE, with an external clock instead of a crystal if `bypass` is set.
+pub(crate) unsafe fn enable_lse(bypass: bool) {
+    enable_backup_access();
+
+    if bdcr().read().lserdy() {
+        return;
+    }
+    bdcr().modify(|w| w.set_lsebyp(bypass));
+    bdcr().modify(|w| w.set_lseon(true));
+    while !bdcr().read().lserdy() {}
+}
+
+unsafe fn enable_lsi() {
+    RCC.csr().modify(|w| w.set_lsion(true));
+    while !RCC.csr().read().lsirdy() {}
+}
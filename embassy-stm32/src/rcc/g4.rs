@@ -69,6 +69,7 @@ impl Into<u8> for AHBPrescaler {
 }
 
 /// Clocks configutation
+#[derive(Clone, Copy)]
 pub struct Config {
     pub mux: ClockSrc,
     pub ahb_pre: AHBPrescaler,
@@ -723,6 +723,8 @@ pub(crate) unsafe fn init(mut config: Config) {
         apb4: core_clocks.pclk4,
         apb1_tim: core_clocks.timx_ker_ck.unwrap_or(core_clocks.pclk1),
         apb2_tim: core_clocks.timy_ker_ck.unwrap_or(core_clocks.pclk2),
+        pll1_q: core_clocks.pll1_q_ck,
+        pll3_q: core_clocks.pll3_q_ck,
     });
 }
 
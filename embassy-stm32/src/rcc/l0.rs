@@ -175,6 +175,7 @@ impl From<MSIRange> for Msirange {
 }
 
 /// Clocks configutation
+#[derive(Clone, Copy)]
 pub struct Config {
     pub mux: ClockSrc,
     pub ahb_pre: AHBPrescaler,
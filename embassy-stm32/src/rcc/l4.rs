@@ -260,6 +260,7 @@ impl From<MSIRange> for u32 {
 }
 
 /// Clocks configutation
+#[derive(Clone, Copy)]
 pub struct Config {
     pub mux: ClockSrc,
     pub ahb_pre: AHBPrescaler,
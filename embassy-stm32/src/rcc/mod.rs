@@ -21,6 +21,34 @@ use core::mem::MaybeUninit;
 mod _version;
 pub use _version::*;
 
+#[cfg(all(
+    rtc,
+    any(
+        rcc_f3, rcc_f4, rcc_f410, rcc_f7, rcc_g0, rcc_g4, rcc_h7, rcc_l0, rcc_l1, rcc_l4, rcc_wb,
+        rcc_wl5
+    )
+))]
+mod bd;
+#[cfg(all(
+    rtc,
+    any(
+        rcc_f3, rcc_f4, rcc_f410, rcc_f7, rcc_g0, rcc_g4, rcc_h7, rcc_l0, rcc_l1, rcc_l4, rcc_wb,
+        rcc_wl5
+    )
+))]
+pub use bd::*;
+
+mod reset;
+pub use reset::*;
+
+/// Typical frequency of the LSI oscillator, which is not accurate (see the datasheet).
+#[cfg(any(rcc_l0, rcc_l1))]
+pub(crate) const LSI_FREQ: Hertz = Hertz(37_000);
+#[cfg(any(rcc_f0, rcc_f0x0, rcc_f1, rcc_f3))]
+pub(crate) const LSI_FREQ: Hertz = Hertz(40_000);
+#[cfg(not(any(rcc_l0, rcc_l1, rcc_f0, rcc_f0x0, rcc_f1, rcc_f3)))]
+pub(crate) const LSI_FREQ: Hertz = Hertz(32_000);
+
 #[derive(Clone, Copy)]
 pub struct Clocks {
     pub sys: Hertz,
@@ -64,6 +92,11 @@ pub struct Clocks {
     #[cfg(any(rcc_f4, rcc_f7))]
     pub pll48: Option<Hertz>,
 
+    #[cfg(rcc_h7)]
+    pub pll1_q: Option<Hertz>,
+    #[cfg(rcc_h7)]
+    pub pll3_q: Option<Hertz>,
+
     #[cfg(rcc_f1)]
     pub adc: Hertz,
 }
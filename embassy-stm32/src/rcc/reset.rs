@@ -0,0 +1,90 @@
+use crate::pac::RCC;
+
+/// Cause of the last reset.
+///
+/// The reset flags accumulate until [`clear_reset_cause`] is called, so it should be called
+/// once the cause was read at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ResetCause {
+    /// Entering STANDBY or STOP mode while forbidden by the option bytes.
+    LowPower,
+    WindowWatchdog,
+    IndependentWatchdog,
+    /// `SCB::sys_reset()`.
+    Software,
+    /// Power-on or brownout reset.
+    PowerOn,
+    /// The NRST pin.
+    Pin,
+    /// No flag is set, for example because they were cleared since the reset.
+    Unknown,
+}
+
+/// Returns the cause of the last reset, read from the reset flags of the RCC.
+///
+/// Several flags can be set, for example the NRST pin is driven low by all the internal resets:
+/// the most specific cause is returned.
+#[cfg(not(any(rcc_h7, rcc_h7ab)))]
+pub fn reset_cause() -> ResetCause {
+    let csr = unsafe { RCC.csr().read() };
+
+    // The brownout flag includes the power-on reset on these families.
+    #[cfg(any(rcc_g0, rcc_g4, rcc_l4, rcc_u5, rcc_wb, rcc_wl5))]
+    let power_on = csr.borrstf();
+    #[cfg(not(any(rcc_g0, rcc_g4, rcc_l4, rcc_u5, rcc_wb, rcc_wl5)))]
+    let power_on = csr.porrstf();
+
+    if csr.lpwrrstf() {
+        ResetCause::LowPower
+    } else if csr.wwdgrstf() {
+        ResetCause::WindowWatchdog
+    } else if csr.iwdgrstf() {
+        ResetCause::IndependentWatchdog
+    } else if csr.sftrstf() {
+        ResetCause::Software
+    } else if power_on {
+        ResetCause::PowerOn
+    } else if csr.pinrstf() {
+        ResetCause::Pin
+    } else {
+        ResetCause::Unknown
+    }
+}
+
+/// Returns the cause of the last reset, read from the reset flags of the RCC.
+///
+/// Several flags can be set, for example the NRST pin is driven low by all the internal resets:
+/// the most specific cause is returned.
+#[cfg(any(rcc_h7, rcc_h7ab))]
+pub fn reset_cause() -> ResetCause {
+    let rsr = unsafe { RCC.rsr().read() };
+
+    if rsr.lpwrrstf() {
+        ResetCause::LowPower
+    } else if rsr.wwdg1rstf() {
+        ResetCause::WindowWatchdog
+    } else if rsr.iwdg1rstf() {
+        ResetCause::IndependentWatchdog
+    } else if rsr.sftrstf() {
+        ResetCause::Software
+    } else if rsr.porrstf() {
+        ResetCause::PowerOn
+    } else if rsr.pinrstf() {
+        ResetCause::Pin
+    } else {
+        ResetCause::Unknown
+    }
+}
+
+/// Clears the reset flags.
+pub fn clear_reset_cause() {
+    #[cfg(not(any(rcc_h7, rcc_h7ab)))]
+    unsafe {
+        RCC.csr().modify(|w| w.set_rmvf(true))
+    };
+    #[cfg(any(rcc_h7, rcc_h7ab))]
+    unsafe {
+        RCC.rsr().modify(|w| w.set_rmvf(true))
+    };
+}
@@ -0,0 +1,300 @@
+//! Calendar and BCD conversions of the RTC registers.
+
+/// Error returned when a date, time or alarm field is out of range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The year is not in 2000..=2099.
+    InvalidYear,
+    InvalidMonth,
+    /// The day is 0 or after the end of the month.
+    InvalidDay,
+    InvalidHour,
+    InvalidMinute,
+    InvalidSecond,
+}
+
+/// Day of the week, numbered as in the RTC registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DayOfWeek {
+    Monday = 1,
+    Tuesday = 2,
+    Wednesday = 3,
+    Thursday = 4,
+    Friday = 5,
+    Saturday = 6,
+    Sunday = 7,
+}
+
+impl DayOfWeek {
+    fn from_number(n: u8) -> Self {
+        match n {
+            1 => Self::Monday,
+            2 => Self::Tuesday,
+            3 => Self::Wednesday,
+            4 => Self::Thursday,
+            5 => Self::Friday,
+            6 => Self::Saturday,
+            // 0 is forbidden, and only found before the calendar is initialized.
+            _ => Self::Sunday,
+        }
+    }
+}
+
+/// A date and time between 2000-01-01 00:00:00 and 2099-12-31 23:59:59.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    year: u16,
+    month: u8,
+    day: u8,
+    day_of_week: DayOfWeek,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+impl DateTime {
+    /// Creates a date and time, checking the fields and calculating the day of the week.
+    pub fn new(
+        year: u16,
+        month: u8,
+        day: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Result<Self, Error> {
+        if !(2000..=2099).contains(&year) {
+            return Err(Error::InvalidYear);
+        }
+        if !(1..=12).contains(&month) {
+            return Err(Error::InvalidMonth);
+        }
+        if day == 0 || day > days_in_month(year, month) {
+            return Err(Error::InvalidDay);
+        }
+        if hour > 23 {
+            return Err(Error::InvalidHour);
+        }
+        if minute > 59 {
+            return Err(Error::InvalidMinute);
+        }
+        if second > 59 {
+            return Err(Error::InvalidSecond);
+        }
+
+        Ok(Self {
+            year,
+            month,
+            day,
+            day_of_week: day_of_week(year, month, day),
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    pub fn year(&self) -> u16 {
+        self.year
+    }
+
+    /// Month, from 1 to 12.
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    /// Day of the month, from 1.
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn day_of_week(&self) -> DayOfWeek {
+        self.day_of_week
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+
+    /// Creates a date and time from the calendar registers, which are trusted to hold a valid
+    /// date as the RTC only counts valid dates.
+    pub(crate) fn from_calendar(
+        year: u16,
+        month: u8,
+        day: u8,
+        day_of_week: u8,
+        hour: u8,
+        minute: u8,
+        second: u8,
+    ) -> Self {
+        Self {
+            year,
+            month,
+            day,
+            day_of_week: DayOfWeek::from_number(day_of_week),
+            hour,
+            minute,
+            second,
+        }
+    }
+}
+
+/// Day of the month or of the week an alarm matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AlarmDay {
+    /// Day of the month, from 1 to 31.
+    Date(u8),
+    WeekDay(DayOfWeek),
+}
+
+/// Fields an alarm must match. `None` fields match any value, so for example an alarm with only
+/// `second` set triggers every minute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Alarm {
+    pub day: Option<AlarmDay>,
+    pub hour: Option<u8>,
+    pub minute: Option<u8>,
+    pub second: Option<u8>,
+}
+
+impl Alarm {
+    /// Checks that the fields are in range.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        match self.day {
+            Some(AlarmDay::Date(d)) if !(1..=31).contains(&d) => return Err(Error::InvalidDay),
+            _ => {}
+        }
+        if self.hour.map_or(false, |h| h > 23) {
+            return Err(Error::InvalidHour);
+        }
+        if self.minute.map_or(false, |m| m > 59) {
+            return Err(Error::InvalidMinute);
+        }
+        if self.second.map_or(false, |s| s > 59) {
+            return Err(Error::InvalidSecond);
+        }
+        Ok(())
+    }
+}
+
+/// Splits a value into its BCD tens and units digits.
+pub(crate) fn byte_to_bcd2(b: u8) -> (u8, u8) {
+    (b / 10, b % 10)
+}
+
+pub(crate) fn bcd2_to_byte((tens, units): (u8, u8)) -> u8 {
+    tens * 10 + units
+}
+
+fn is_leap_year(year: u16) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Calculates the day of the week with Sakamoto's method.
+fn day_of_week(year: u16, month: u8, day: u8) -> DayOfWeek {
+    const T: [u16; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    let y = if month < 3 { year - 1 } else { year };
+    // 0 is Sunday.
+    let n = (y + y / 4 - y / 100 + y / 400 + T[month as usize - 1] + day as u16) % 7;
+    DayOfWeek::from_number(if n == 0 { 7 } else { n as u8 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bcd() {
+        for b in 0..100 {
+            assert_eq!(bcd2_to_byte(byte_to_bcd2(b)), b);
+        }
+        assert_eq!(byte_to_bcd2(59), (5, 9));
+        assert_eq!(bcd2_to_byte((2, 3)), 23);
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(day_of_week(2000, 1, 1), DayOfWeek::Saturday);
+        assert_eq!(day_of_week(2000, 2, 29), DayOfWeek::Tuesday);
+        assert_eq!(day_of_week(2021, 12, 6), DayOfWeek::Monday);
+        assert_eq!(day_of_week(2024, 3, 1), DayOfWeek::Friday);
+        assert_eq!(day_of_week(2099, 12, 31), DayOfWeek::Thursday);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(DateTime::new(2024, 2, 29, 0, 0, 0).is_ok());
+        assert_eq!(DateTime::new(2023, 2, 29, 0, 0, 0), Err(Error::InvalidDay));
+        assert_eq!(DateTime::new(2100, 1, 1, 0, 0, 0), Err(Error::InvalidYear));
+        assert_eq!(
+            DateTime::new(2021, 13, 1, 0, 0, 0),
+            Err(Error::InvalidMonth)
+        );
+        assert_eq!(DateTime::new(2021, 4, 31, 0, 0, 0), Err(Error::InvalidDay));
+        assert_eq!(DateTime::new(2021, 4, 1, 24, 0, 0), Err(Error::InvalidHour));
+        assert_eq!(
+            DateTime::new(2021, 4, 1, 0, 60, 0),
+            Err(Error::InvalidMinute)
+        );
+        assert_eq!(
+            DateTime::new(2021, 4, 1, 0, 0, 60),
+            Err(Error::InvalidSecond)
+        );
+    }
+
+    #[test]
+    fn calendar() {
+        let dt = DateTime::new(2021, 12, 6, 23, 45, 7).unwrap();
+        // Monday is 1.
+        assert_eq!(DateTime::from_calendar(2021, 12, 6, 1, 23, 45, 7), dt);
+    }
+
+    #[test]
+    fn alarms() {
+        let every_minute = Alarm {
+            second: Some(30),
+            ..Default::default()
+        };
+        assert_eq!(every_minute.validate(), Ok(()));
+
+        let weekly = Alarm {
+            day: Some(AlarmDay::WeekDay(DayOfWeek::Sunday)),
+            hour: Some(7),
+            minute: Some(0),
+            second: Some(0),
+        };
+        assert_eq!(weekly.validate(), Ok(()));
+
+        let invalid = Alarm {
+            day: Some(AlarmDay::Date(32)),
+            ..Default::default()
+        };
+        assert_eq!(invalid.validate(), Err(Error::InvalidDay));
+
+        let invalid = Alarm {
+            hour: Some(24),
+            ..Default::default()
+        };
+        assert_eq!(invalid.validate(), Err(Error::InvalidHour));
+    }
+}
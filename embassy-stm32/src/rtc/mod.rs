@@ -0,0 +1,540 @@
+//! Real time clock.
+//!
+//! The RTC keeps a calendar, two alarms, a periodic wakeup timer and backup registers running in
+//! the backup domain, across resets and in the low power modes. It is clocked by the LSE or the
+//! LSI, selected with [`Config::clock_source`].
+//!
+//! The calendar is only initialized if it isn't running yet, so the time set with
+//! [`Rtc::set_datetime`] survives a reset.
+
+use core::future::Future;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU8, Ordering};
+use core::task::Poll;
+
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::pac::rtc::{regs, vals};
+use crate::pac::RTC as REGS;
+use crate::peripherals::RTC;
+use crate::rcc::RtcClockSource;
+
+mod datetime;
+use datetime::{bcd2_to_byte, byte_to_bcd2};
+pub use datetime::{Alarm, AlarmDay, DateTime, DayOfWeek, Error};
+
+/// Interrupt of the alarms, and of the wakeup timer on the families where they share the RTC
+/// interrupt.
+#[cfg(rcc_l0)]
+pub type AlarmInterrupt = crate::interrupt::RTC;
+#[cfg(rcc_g0)]
+pub type AlarmInterrupt = crate::interrupt::RTC_TAMP;
+#[cfg(not(any(rcc_l0, rcc_g0)))]
+pub type AlarmInterrupt = crate::interrupt::RTC_ALARM;
+
+/// Interrupt of the wakeup timer.
+#[cfg(not(any(rcc_l0, rcc_g0)))]
+pub type WakeupInterrupt = crate::interrupt::RTC_WKUP;
+
+/// EXTI lines the RTC events are connected to.
+#[cfg(rcc_l4)]
+const ALARM_EXTI_LINE: usize = 18;
+#[cfg(rcc_g0)]
+const ALARM_EXTI_LINE: usize = 19;
+#[cfg(not(any(rcc_l4, rcc_g0)))]
+const ALARM_EXTI_LINE: usize = 17;
+
+#[cfg(any(rcc_f4, rcc_f410, rcc_f7))]
+const WAKEUP_EXTI_LINE: usize = 22;
+#[cfg(any(rcc_g0, rcc_h7, rcc_wb))]
+const WAKEUP_EXTI_LINE: usize = 19;
+#[cfg(not(any(rcc_f4, rcc_f410, rcc_f7, rcc_g0, rcc_h7, rcc_wb)))]
+const WAKEUP_EXTI_LINE: usize = 20;
+
+/// Number of backup registers.
+#[cfg(any(rcc_g0, rcc_l0))]
+pub const BACKUP_REGISTER_COUNT: usize = 5;
+#[cfg(rcc_f3)]
+pub const BACKUP_REGISTER_COUNT: usize = 16;
+#[cfg(any(rcc_f4, rcc_f410, rcc_l1, rcc_wb, rcc_wl5))]
+pub const BACKUP_REGISTER_COUNT: usize = 20;
+#[cfg(any(rcc_f7, rcc_g4, rcc_h7, rcc_l4))]
+pub const BACKUP_REGISTER_COUNT: usize = 32;
+
+const ALARM_EVENTS: [u8; 2] = [1 << 0, 1 << 1];
+const WAKEUP_EVENT: u8 = 1 << 2;
+
+/// Events that happened since they were last waited for.
+static EVENTS: AtomicU8 = AtomicU8::new(0);
+static ALARM_WAKERS: [AtomicWaker; 2] = [AtomicWaker::new(), AtomicWaker::new()];
+static WAKEUP_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Alarm of the RTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AlarmId {
+    A = 0,
+    B = 1,
+}
+
+/// Clock of the wakeup timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WakeupClock {
+    /// RTC clock divided by 16.
+    Div16 = 0b000,
+    /// RTC clock divided by 8.
+    Div8 = 0b001,
+    /// RTC clock divided by 4.
+    Div4 = 0b010,
+    /// RTC clock divided by 2.
+    Div2 = 0b011,
+    /// The 1 Hz calendar clock.
+    Seconds = 0b100,
+}
+
+#[non_exhaustive]
+pub struct Config {
+    /// Clock of the RTC.
+    ///
+    /// Changing the clock source resets the backup domain, losing the calendar and the backup
+    /// registers. With the `time-driver-lptim1` feature, the source can't be changed once
+    /// selected, as the reset would stop the LSE clocking the time driver: the RTC keeps the
+    /// source selected before, and a warning is logged.
+    pub clock_source: RtcClockSource,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            clock_source: RtcClockSource::LSI,
+        }
+    }
+}
+
+pub struct Rtc<'d> {
+    _p: PhantomData<&'d mut RTC>,
+}
+
+impl<'d> Rtc<'d> {
+    /// Enables the RTC, clocked by `config.clock_source`.
+    ///
+    /// `irq` is the interrupt of the alarms. On L0 and G0 it is also the interrupt of the
+    /// wakeup timer, see [`Rtc::enable_wakeup_interrupt`] for the other families.
+    pub fn new(
+        _p: impl Unborrow<Target = RTC> + 'd,
+        irq: impl Unborrow<Target = AlarmInterrupt> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(_p, irq);
+
+        let clock = unsafe { crate::rcc::enable_rtc_clock(config.clock_source) };
+
+        unsafe {
+            write_protect(false);
+            if !calendar_initialized() {
+                // The calendar was never set, or the backup domain was reset.
+                let (prediv_a, prediv_s) = prescalers(clock.0);
+                enter_init();
+                REGS.prer().write(|w| {
+                    w.set_prediv_a(prediv_a);
+                    w.set_prediv_s(prediv_s);
+                });
+                REGS.cr().modify(|w| w.set_fmt(vals::Fmt::TWENTYFOURHOUR));
+                exit_init();
+            }
+            write_protect(true);
+
+            crate::exti::enable_internal_line(ALARM_EXTI_LINE);
+            #[cfg(any(rcc_l0, rcc_g0))]
+            crate::exti::enable_internal_line(WAKEUP_EXTI_LINE);
+        }
+
+        irq.set_handler(on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self { _p: PhantomData }
+    }
+
+    /// Enables the wakeup timer interrupt, needed by [`Rtc::wait_wakeup`].
+    #[cfg(not(any(rcc_l0, rcc_g0)))]
+    pub fn enable_wakeup_interrupt(&mut self, irq: impl Unborrow<Target = WakeupInterrupt> + 'd) {
+        unborrow!(irq);
+
+        unsafe { crate::exti::enable_internal_line(WAKEUP_EXTI_LINE) };
+
+        irq.set_handler(on_interrupt);
+        irq.unpend();
+        irq.enable();
+    }
+
+    /// Sets the calendar.
+    pub fn set_datetime(&mut self, datetime: DateTime) {
+        let (ht, hu) = byte_to_bcd2(datetime.hour());
+        let (mnt, mnu) = byte_to_bcd2(datetime.minute());
+        let (st, su) = byte_to_bcd2(datetime.second());
+        let (yt, yu) = byte_to_bcd2((datetime.year() - 2000) as u8);
+        let (mt, mu) = byte_to_bcd2(datetime.month());
+        let (dt, du) = byte_to_bcd2(datetime.day());
+
+        unsafe {
+            write_protect(false);
+            enter_init();
+            // 24 hour format, so PM is left cleared.
+            REGS.tr().write(|w| {
+                w.set_ht(ht);
+                w.set_hu(hu);
+                w.set_mnt(mnt);
+                w.set_mnu(mnu);
+                w.set_st(st);
+                w.set_su(su);
+            });
+            REGS.dr().write(|w| {
+                w.set_yt(yt);
+                w.set_yu(yu);
+                w.set_wdu(datetime.day_of_week() as u8);
+                w.set_mt(mt > 0);
+                w.set_mu(mu);
+                w.set_dt(dt);
+                w.set_du(du);
+            });
+            exit_init();
+            write_protect(true);
+        }
+    }
+
+    /// Returns the current date and time, or `None` if the calendar was never set.
+    pub fn now(&self) -> Option<DateTime> {
+        unsafe {
+            if !calendar_initialized() {
+                return None;
+            }
+
+            // Wait for the shadow registers to be synchronized, which isn't the case after a
+            // wakeup from the low power modes.
+            clear_rsf();
+            while !init_status().rsf() {}
+
+            // Reading TR freezes DR until it is read.
+            let tr = REGS.tr().read();
+            let dr = REGS.dr().read();
+            Some(DateTime::from_calendar(
+                2000 + bcd2_to_byte((dr.yt(), dr.yu())) as u16,
+                bcd2_to_byte((dr.mt() as u8, dr.mu())),
+                bcd2_to_byte((dr.dt(), dr.du())),
+                dr.wdu(),
+                bcd2_to_byte((tr.ht(), tr.hu())),
+                bcd2_to_byte((tr.mnt(), tr.mnu())),
+                bcd2_to_byte((tr.st(), tr.su())),
+            ))
+        }
+    }
+
+    /// Configures and enables an alarm. A previous occurrence of the alarm is forgotten.
+    pub fn set_alarm(&mut self, id: AlarmId, alarm: Alarm) -> Result<(), Error> {
+        alarm.validate()?;
+        let i = id as usize;
+
+        unsafe {
+            write_protect(false);
+            REGS.cr().modify(|w| {
+                w.set_alre(i, false);
+                w.set_alrie(i, false);
+            });
+            #[cfg(not(any(rcc_g0, rcc_g4, rcc_wl5)))]
+            while !REGS.isr().read().alrwf(i) {}
+            REGS.alrmr(i).write(|w| write_alarm(w, &alarm));
+            clear_alarm_flag(i);
+            EVENTS.fetch_and(!ALARM_EVENTS[i], Ordering::Relaxed);
+            REGS.cr().modify(|w| {
+                w.set_alre(i, true);
+                w.set_alrie(i, true);
+            });
+            write_protect(true);
+        }
+
+        Ok(())
+    }
+
+    pub fn disable_alarm(&mut self, id: AlarmId) {
+        let i = id as usize;
+        unsafe {
+            write_protect(false);
+            REGS.cr().modify(|w| {
+                w.set_alre(i, false);
+                w.set_alrie(i, false);
+            });
+            write_protect(true);
+        }
+    }
+
+    /// Waits for the next occurrence of an alarm, or returns immediately if it occurred since
+    /// it was last waited for.
+    pub fn wait_alarm(&mut self, id: AlarmId) -> impl Future<Output = ()> + '_ {
+        let i = id as usize;
+        wait_event(&ALARM_WAKERS[i], ALARM_EVENTS[i])
+    }
+
+    /// Starts the wakeup timer, with a period of `period` ticks of `clock`.
+    ///
+    /// With [`WakeupClock::Seconds`], periods from 1 second to 18 hours are possible. With a
+    /// 32.768 kHz clock, [`WakeupClock::Div16`] gives a resolution of 488 µs.
+    pub fn start_wakeup_timer(&mut self, clock: WakeupClock, period: u16) {
+        assert!(period > 0);
+
+        unsafe {
+            write_protect(false);
+            REGS.cr().modify(|w| {
+                w.set_wute(false);
+                w.set_wutie(false);
+            });
+            while !init_status().wutwf() {}
+            REGS.wutr().write(|w| w.set_wut(period - 1));
+            REGS.cr()
+                .modify(|w| w.set_wucksel(vals::Wucksel(clock as u8)));
+            clear_wakeup_flag();
+            EVENTS.fetch_and(!WAKEUP_EVENT, Ordering::Relaxed);
+            REGS.cr().modify(|w| {
+                w.set_wute(true);
+                w.set_wutie(true);
+            });
+            write_protect(true);
+        }
+    }
+
+    pub fn stop_wakeup_timer(&mut self) {
+        unsafe {
+            write_protect(false);
+            REGS.cr().modify(|w| {
+                w.set_wute(false);
+                w.set_wutie(false);
+            });
+            write_protect(true);
+        }
+    }
+
+    /// Waits for the next period of the wakeup timer, or returns immediately if a period
+    /// elapsed since it was last waited for.
+    pub fn wait_wakeup(&mut self) -> impl Future<Output = ()> + '_ {
+        wait_event(&WAKEUP_WAKER, WAKEUP_EVENT)
+    }
+
+    /// Reads a backup register, or returns `None` if `index` is out of range.
+    ///
+    /// The backup registers keep their value across resets, but are cleared when the RTC clock
+    /// source changes.
+    pub fn read_backup_register(&self, index: usize) -> Option<u32> {
+        if index >= BACKUP_REGISTER_COUNT {
+            return None;
+        }
+        Some(unsafe { backup_regs().bkpr(index).read().bkp() })
+    }
+
+    /// Writes a backup register.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than [`BACKUP_REGISTER_COUNT`].
+    pub fn write_backup_register(&mut self, index: usize, value: u32) {
+        assert!(index < BACKUP_REGISTER_COUNT);
+        unsafe { backup_regs().bkpr(index).write(|w| w.set_bkp(value)) }
+    }
+}
+
+impl<'d> Drop for Rtc<'d> {
+    /// Disables the interrupts. The RTC, the alarms and the wakeup timer keep running.
+    fn drop(&mut self) {
+        unsafe {
+            write_protect(false);
+            REGS.cr().modify(|w| {
+                w.set_alrie(0, false);
+                w.set_alrie(1, false);
+                w.set_wutie(false);
+            });
+            write_protect(true);
+        }
+    }
+}
+
+fn wait_event(waker: &'static AtomicWaker, event: u8) -> impl Future<Output = ()> {
+    poll_fn(move |cx| {
+        waker.register(cx.waker());
+        if EVENTS.fetch_and(!event, Ordering::AcqRel) & event != 0 {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+}
+
+unsafe fn on_interrupt(_: *mut ()) {
+    #[cfg(not(any(rcc_g0, rcc_g4, rcc_wl5)))]
+    let flags = REGS.isr().read();
+    #[cfg(any(rcc_g0, rcc_g4, rcc_wl5))]
+    let flags = REGS.sr().read();
+    let enabled = REGS.cr().read();
+
+    let mut events = 0;
+    for i in 0..2 {
+        if flags.alrf(i) && enabled.alrie(i) {
+            events |= ALARM_EVENTS[i];
+            clear_alarm_flag(i);
+            ALARM_WAKERS[i].wake();
+        }
+    }
+    if flags.wutf() && enabled.wutie() {
+        events |= WAKEUP_EVENT;
+        clear_wakeup_flag();
+        WAKEUP_WAKER.wake();
+    }
+    EVENTS.fetch_or(events, Ordering::AcqRel);
+
+    crate::exti::clear_internal_line(ALARM_EXTI_LINE);
+    crate::exti::clear_internal_line(WAKEUP_EXTI_LINE);
+}
+
+fn write_alarm(w: &mut regs::Alrmr, alarm: &Alarm) {
+    fn mask(masked: bool) -> vals::AlrmrMsk {
+        if masked {
+            vals::AlrmrMsk::MASK
+        } else {
+            vals::AlrmrMsk::NOTMASK
+        }
+    }
+
+    w.set_msk4(mask(alarm.day.is_none()));
+    match alarm.day {
+        Some(AlarmDay::Date(d)) => {
+            let (dt, du) = byte_to_bcd2(d);
+            w.set_wdsel(vals::AlrmrWdsel::DATEUNITS);
+            w.set_dt(dt);
+            w.set_du(du);
+        }
+        Some(AlarmDay::WeekDay(d)) => {
+            w.set_wdsel(vals::AlrmrWdsel::WEEKDAY);
+            w.set_du(d as u8);
+        }
+        None => {}
+    }
+    w.set_msk3(mask(alarm.hour.is_none()));
+    let (ht, hu) = byte_to_bcd2(alarm.hour.unwrap_or(0));
+    w.set_ht(ht);
+    w.set_hu(hu);
+    w.set_msk2(mask(alarm.minute.is_none()));
+    let (mnt, mnu) = byte_to_bcd2(alarm.minute.unwrap_or(0));
+    w.set_mnt(mnt);
+    w.set_mnu(mnu);
+    w.set_msk1(mask(alarm.second.is_none()));
+    let (st, su) = byte_to_bcd2(alarm.second.unwrap_or(0));
+    w.set_st(st);
+    w.set_su(su);
+}
+
+/// Registers holding the backup registers.
+#[cfg(not(any(rcc_g0, rcc_g4, rcc_wl5)))]
+fn backup_regs() -> crate::pac::rtc::Rtc {
+    REGS
+}
+#[cfg(any(rcc_g0, rcc_g4, rcc_wl5))]
+fn backup_regs() -> crate::pac::tamp::Tamp {
+    crate::pac::TAMP
+}
+
+/// Returns the register holding INITS, RSF, INITF and WUTWF: ISR, or ICSR on the RTC v3 (G0, G4,
+/// WL), which moved the interrupt flags to SR/SCR.
+#[cfg(not(any(rcc_g0, rcc_g4, rcc_wl5)))]
+unsafe fn init_status() -> regs::Isr {
+    REGS.isr().read()
+}
+#[cfg(any(rcc_g0, rcc_g4, rcc_wl5))]
+unsafe fn init_status() -> regs::Icsr {
+    REGS.icsr().read()
+}
+
+unsafe fn calendar_initialized() -> bool {
+    init_status().inits()
+}
+
+/// Clears flags of ISR, leaving INIT as is.
+///
+/// The flags are cleared by writing 0 and writing 1 has no effect, so a read-modify-write could
+/// clear a flag set in between.
+#[cfg(not(any(rcc_g0, rcc_g4, rcc_wl5)))]
+unsafe fn clear_isr(f: impl FnOnce(&mut regs::Isr)) {
+    critical_section::with(|_| {
+        let init = REGS.isr().read().init();
+        REGS.isr().write(|w| {
+            w.0 = !0;
+            w.set_init(init);
+            f(w);
+        });
+    });
+}
+
+#[cfg(not(any(rcc_g0, rcc_g4, rcc_wl5)))]
+unsafe fn clear_alarm_flag(i: usize) {
+    clear_isr(|w| w.set_alrf(i, false))
+}
+#[cfg(any(rcc_g0, rcc_g4, rcc_wl5))]
+unsafe fn clear_alarm_flag(i: usize) {
+    REGS.scr().write(|w| w.set_calrf(i, true))
+}
+
+#[cfg(not(any(rcc_g0, rcc_g4, rcc_wl5)))]
+unsafe fn clear_wakeup_flag() {
+    clear_isr(|w| w.set_wutf(false))
+}
+#[cfg(any(rcc_g0, rcc_g4, rcc_wl5))]
+unsafe fn clear_wakeup_flag() {
+    REGS.scr().write(|w| w.set_cwutf(true))
+}
+
+#[cfg(not(any(rcc_g0, rcc_g4, rcc_wl5)))]
+unsafe fn clear_rsf() {
+    clear_isr(|w| w.set_rsf(false))
+}
+#[cfg(any(rcc_g0, rcc_g4, rcc_wl5))]
+unsafe fn clear_rsf() {
+    REGS.icsr().modify(|w| w.set_rsf(false))
+}
+
+unsafe fn write_protect(enable: bool) {
+    if enable {
+        REGS.wpr().write(|w| w.set_key(0xff));
+    } else {
+        REGS.wpr().write(|w| w.set_key(0xca));
+        REGS.wpr().write(|w| w.set_key(0x53));
+    }
+}
+
+unsafe fn enter_init() {
+    #[cfg(not(any(rcc_g0, rcc_g4, rcc_wl5)))]
+    clear_isr(|w| w.set_init(vals::Init::INITMODE));
+    #[cfg(any(rcc_g0, rcc_g4, rcc_wl5))]
+    REGS.icsr().modify(|w| w.set_init(vals::Init::INITMODE));
+    while !init_status().initf() {}
+}
+
+unsafe fn exit_init() {
+    #[cfg(not(any(rcc_g0, rcc_g4, rcc_wl5)))]
+    clear_isr(|w| w.set_init(vals::Init::FREERUNNINGMODE));
+    #[cfg(any(rcc_g0, rcc_g4, rcc_wl5))]
+    REGS.icsr()
+        .modify(|w| w.set_init(vals::Init::FREERUNNINGMODE));
+}
+
+/// Returns the asynchronous and synchronous prescaler values (PREDIV_A, PREDIV_S) dividing `clock`
+/// down to the 1 Hz calendar clock.
+///
+/// The largest asynchronous prescaler is used, for the lowest power consumption.
+fn prescalers(clock: u32) -> (u8, u16) {
+    let div_a = unwrap!((1..=128)
+        .rev()
+        .find(|d| clock % d == 0 && clock / d <= 0x8000));
+    ((div_a - 1) as u8, (clock / div_a - 1) as u16)
+}
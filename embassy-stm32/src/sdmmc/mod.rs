@@ -4,4 +4,1490 @@
 #[cfg_attr(sdmmc_v2, path = "v2.rs")]
 mod _version;
 
+use core::default::Default;
+use core::future::Future;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::InterruptExt;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use futures::future::poll_fn;
+use sdio_host::{BusWidth, CardCapacity, CardStatus, CurrentState, SDStatus, CID, CSD, OCR, SCR};
+
+use crate::dma::NoDma;
+use crate::gpio::sealed::AFType;
+use crate::interrupt::Interrupt;
+use crate::pac::sdmmc::Sdmmc as RegBlock;
+use crate::peripherals;
+use crate::rcc::RccPeripheral;
+use crate::time::Hertz;
 pub use _version::*;
+
+/// The signalling scheme used on the SDMMC bus
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Signalling {
+    SDR12,
+    SDR25,
+    SDR50,
+    SDR104,
+    DDR50,
+}
+
+impl Default for Signalling {
+    fn default() -> Self {
+        Signalling::SDR12
+    }
+}
+
+/// A block of data, aligned for the DMA
+#[repr(align(4))]
+#[derive(Clone)]
+pub struct DataBlock(pub [u8; 512]);
+
+impl DataBlock {
+    pub const fn new() -> Self {
+        Self([0; 512])
+    }
+}
+
+/// Errors
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    Timeout,
+    SoftwareTimeout,
+    UnsupportedCardVersion,
+    UnsupportedCardType,
+    Crc,
+    DataCrcFail,
+    RxOverFlow,
+    TxUnderFlow,
+    NoCard,
+    BadClock,
+    SignalingSwitchFailed,
+    PeripheralBusy,
+}
+
+/// A SD command
+struct Cmd {
+    cmd: u8,
+    arg: u32,
+    resp: Response,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// SD Card
+pub struct Card {
+    /// The type of this card
+    pub card_type: CardCapacity,
+    /// Operation Conditions Register
+    pub ocr: OCR,
+    /// Relative Card Address
+    pub rca: u32,
+    /// Card ID
+    pub cid: CID,
+    /// Card Specific Data
+    pub csd: CSD,
+    /// SD CARD Configuration Register
+    pub scr: SCR,
+    /// SD Status
+    pub status: SDStatus,
+}
+impl Card {
+    /// Size in bytes
+    pub fn size(&self) -> u64 {
+        // SDHC / SDXC / SDUC
+        u64::from(self.csd.block_count()) * 512
+    }
+}
+
+#[repr(u8)]
+enum PowerCtrl {
+    Off = 0b00,
+    On = 0b11,
+}
+
+#[repr(u32)]
+#[allow(dead_code)]
+#[allow(non_camel_case_types)]
+enum CmdAppOper {
+    VOLTAGE_WINDOW_SD = 0x8010_0000,
+    HIGH_CAPACITY = 0x4000_0000,
+    SDMMC_STD_CAPACITY = 0x0000_0000,
+    SDMMC_CHECK_PATTERN = 0x0000_01AA,
+    SD_SWITCH_1_8V_CAPACITY = 0x0100_0000,
+}
+
+#[derive(Eq, PartialEq, Copy, Clone)]
+enum Response {
+    None = 0,
+    Short = 1,
+    Long = 3,
+}
+
+/// SDMMC configuration
+///
+/// You should probably change the default clock values to match your configuration
+///
+/// Default values:
+/// hclk = 400_000_000 Hz (sdmmc_v2 only)
+/// kernel_clk: 100_000_000 Hz (48_000_000 Hz on sdmmc_v1)
+/// data_transfer_timeout: 5_000_000
+#[non_exhaustive]
+pub struct Config {
+    /// AHB clock, used to check the SDMMC_CK frequency (sdmmc_v2 only)
+    pub hclk: Hertz,
+    /// SDMMC kernel clock
+    pub kernel_clk: Hertz,
+    /// The timeout to be set for data transfers, in card bus clock periods
+    pub data_transfer_timeout: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            hclk: Hertz(400_000_000),
+            kernel_clk: DEFAULT_KERNEL_CLK,
+            data_transfer_timeout: 5_000_000,
+        }
+    }
+}
+
+/// Sdmmc device
+///
+/// The SDMMC v1 (SDIO) moves the data with the DMA channel `Dma`, the SDMMC v2 with its internal
+/// DMA.
+pub struct Sdmmc<'d, T: Instance, P: Pins<T>, Dma = NoDma> {
+    sdmmc: PhantomData<&'d mut T>,
+    pins: P,
+    irq: T::Interrupt,
+    config: Config,
+    dma: Dma,
+    /// Current clock to card
+    clock: Hertz,
+    /// Current signalling scheme to card
+    signalling: Signalling,
+    /// Card
+    card: Option<Card>,
+}
+
+impl<'d, T: Instance, P: Pins<T>, Dma: SdmmcDma<T>> Sdmmc<'d, T, P, Dma> {
+    unsafe fn new_inner(mut pins: P, irq: T::Interrupt, config: Config, dma: Dma) -> Self {
+        T::enable();
+        T::reset();
+
+        pins.configure();
+
+        let inner = T::inner();
+        let clock = inner.new_inner(config.kernel_clk);
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self {
+            sdmmc: PhantomData,
+            pins,
+            irq,
+            config,
+            dma,
+            clock,
+            signalling: Default::default(),
+            card: None,
+        }
+    }
+
+    /// Initializes the card, switching to the bus width of the pins, and to high-speed mode
+    /// (SDR25) if `freq` is over 25 MHz.
+    #[inline(always)]
+    pub async fn init_card(&mut self, freq: impl Into<Hertz>) -> Result<(), Error> {
+        let inner = T::inner();
+        let freq = freq.into();
+
+        inner
+            .init_card::<T, Dma>(
+                freq,
+                P::BUSWIDTH,
+                &mut self.card,
+                &mut self.signalling,
+                self.config.hclk,
+                self.config.kernel_clk,
+                &mut self.clock,
+                T::state(),
+                self.config.data_transfer_timeout,
+                &mut self.dma,
+            )
+            .await
+    }
+
+    #[inline(always)]
+    pub async fn read_block(
+        &mut self,
+        block_idx: u32,
+        buffer: &mut DataBlock,
+    ) -> Result<(), Error> {
+        self.read_blocks(block_idx, core::slice::from_mut(buffer))
+            .await
+    }
+
+    /// Reads consecutive blocks, starting at `block_idx`, with a single multiple block read
+    /// (CMD18) if there are several of them.
+    pub async fn read_blocks(
+        &mut self,
+        block_idx: u32,
+        buffers: &mut [DataBlock],
+    ) -> Result<(), Error> {
+        let card_capacity = self.card()?.card_type;
+        let inner = T::inner();
+        let state = T::state();
+
+        // NOTE(unsafe) DataBlock uses align 4
+        let buf = unsafe {
+            core::slice::from_raw_parts_mut(buffers.as_mut_ptr() as *mut u32, buffers.len() * 128)
+        };
+        inner
+            .read_blocks::<T, Dma>(
+                block_idx,
+                buf,
+                card_capacity,
+                state,
+                self.config.data_transfer_timeout,
+                &mut self.dma,
+            )
+            .await
+    }
+
+    pub async fn write_block(&mut self, block_idx: u32, buffer: &DataBlock) -> Result<(), Error> {
+        self.write_blocks(block_idx, core::slice::from_ref(buffer))
+            .await
+    }
+
+    /// Writes consecutive blocks, starting at `block_idx`, with a single multiple block write
+    /// (CMD25) if there are several of them.
+    pub async fn write_blocks(
+        &mut self,
+        block_idx: u32,
+        buffers: &[DataBlock],
+    ) -> Result<(), Error> {
+        let card = self.card.as_mut().ok_or(Error::NoCard)?;
+        let inner = T::inner();
+        let state = T::state();
+
+        // NOTE(unsafe) DataBlock uses align 4
+        let buf = unsafe {
+            core::slice::from_raw_parts(buffers.as_ptr() as *const u32, buffers.len() * 128)
+        };
+        inner
+            .write_blocks::<T, Dma>(
+                block_idx,
+                buf,
+                card,
+                state,
+                self.config.data_transfer_timeout,
+                &mut self.dma,
+            )
+            .await
+    }
+
+    /// Get a reference to the initialized card
+    ///
+    /// # Errors
+    ///
+    /// Returns Error::NoCard if [`init_card`](#method.init_card)
+    /// has not previously succeeded
+    #[inline(always)]
+    pub fn card(&self) -> Result<&Card, Error> {
+        self.card.as_ref().ok_or(Error::NoCard)
+    }
+
+    /// Get the current SDMMC bus clock
+    pub fn clock(&self) -> Hertz {
+        self.clock
+    }
+
+    /// Get the current signalling scheme
+    pub fn signalling(&self) -> Signalling {
+        self.signalling
+    }
+
+    #[inline(always)]
+    fn on_interrupt(_: *mut ()) {
+        let regs = T::inner();
+        let state = T::state();
+
+        regs.data_interrupts(false);
+        state.wake();
+    }
+}
+
+impl<'d, T: Instance, P: Pins<T>, Dma> Drop for Sdmmc<'d, T, P, Dma> {
+    fn drop(&mut self) {
+        self.irq.disable();
+        let inner = T::inner();
+        unsafe { inner.on_drop() };
+        self.pins.deconfigure();
+    }
+}
+
+pub struct SdmmcInner(pub(crate) RegBlock);
+
+impl SdmmcInner {
+    /// # Safety
+    ///
+    /// Access to `regs` registers should be exclusive
+    unsafe fn new_inner(&self, kernel_clk: Hertz) -> Hertz {
+        let regs = self.0;
+
+        let clock = self.init_clkcr(kernel_clk);
+
+        // Power off, writen 00: Clock to the card is stopped;
+        // D[7:0], CMD, and CK are driven high.
+        regs.power().modify(|w| w.set_pwrctrl(PowerCtrl::Off as u8));
+
+        clock
+    }
+
+    /// Initializes card (if present) and sets the bus at the
+    /// specified frequency.
+    #[allow(clippy::too_many_arguments)]
+    async fn init_card<T: Instance, Dma: SdmmcDma<T>>(
+        &self,
+        freq: Hertz,
+        bus_width: BusWidth,
+        old_card: &mut Option<Card>,
+        signalling: &mut Signalling,
+        hclk: Hertz,
+        ker_ck: Hertz,
+        clock: &mut Hertz,
+        waker_reg: &AtomicWaker,
+        data_transfer_timeout: u32,
+        dma: &mut Dma,
+    ) -> Result<(), Error> {
+        let regs = self.0;
+
+        // NOTE(unsafe) We have exclusive access to the peripheral
+        unsafe {
+            regs.power().modify(|w| w.set_pwrctrl(PowerCtrl::On as u8));
+            self.cmd(Cmd::idle(), false)?;
+
+            // Check if cards supports CMD8 (with pattern)
+            self.cmd(Cmd::hs_send_ext_csd(0x1AA), false)?;
+            let r1 = regs.respr(0).read().cardstatus1();
+
+            let mut card = if r1 == 0x1AA {
+                // Card echoed back the pattern. Must be at least v2
+                Card::default()
+            } else {
+                return Err(Error::UnsupportedCardVersion);
+            };
+
+            let ocr = loop {
+                // Signal that next command is a app command
+                self.cmd(Cmd::app_cmd(0), false)?; // CMD55
+
+                let arg = CmdAppOper::VOLTAGE_WINDOW_SD as u32
+                    | CmdAppOper::HIGH_CAPACITY as u32
+                    | CmdAppOper::SD_SWITCH_1_8V_CAPACITY as u32;
+
+                // Initialize card
+                match self.cmd(Cmd::app_op_cmd(arg), false) {
+                    // ACMD41
+                    Ok(_) => (),
+                    Err(Error::Crc) => (),
+                    Err(err) => return Err(err),
+                }
+                let ocr: OCR = regs.respr(0).read().cardstatus1().into();
+                if !ocr.is_busy() {
+                    // Power up done
+                    break ocr;
+                }
+            };
+
+            if ocr.high_capacity() {
+                // Card is SDHC or SDXC or SDUC
+                card.card_type = CardCapacity::SDHC;
+            } else {
+                card.card_type = CardCapacity::SDSC;
+            }
+            card.ocr = ocr;
+
+            self.cmd(Cmd::all_send_cid(), false)?; // CMD2
+            let cid0 = regs.respr(0).read().cardstatus1() as u128;
+            let cid1 = regs.respr(1).read().cardstatus1() as u128;
+            let cid2 = regs.respr(2).read().cardstatus1() as u128;
+            let cid3 = regs.respr(3).read().cardstatus1() as u128;
+            let cid = (cid0 << 96) | (cid1 << 64) | (cid2 << 32) | (cid3);
+            card.cid = cid.into();
+
+            self.cmd(Cmd::send_rel_addr(), false)?;
+            card.rca = regs.respr(0).read().cardstatus1() >> 16;
+
+            self.cmd(Cmd::send_csd(card.rca << 16), false)?;
+            let csd0 = regs.respr(0).read().cardstatus1() as u128;
+            let csd1 = regs.respr(1).read().cardstatus1() as u128;
+            let csd2 = regs.respr(2).read().cardstatus1() as u128;
+            let csd3 = regs.respr(3).read().cardstatus1() as u128;
+            let csd = (csd0 << 96) | (csd1 << 64) | (csd2 << 32) | (csd3);
+            card.csd = csd.into();
+
+            self.select_card(Some(&card))?;
+            self.get_scr::<T, Dma>(&mut card, waker_reg, data_transfer_timeout, dma)
+                .await?;
+
+            // Set bus width
+            let (width, acmd_arg) = match bus_width {
+                BusWidth::Eight => unimplemented!(),
+                BusWidth::Four if card.scr.bus_width_four() => (BusWidth::Four, 2),
+                _ => (BusWidth::One, 0),
+            };
+            self.cmd(Cmd::app_cmd(card.rca << 16), false)?;
+            self.cmd(Cmd::cmd6(acmd_arg), false)?;
+
+            // CPSMACT and DPSMACT must be 0 to set WIDBUS
+            self.wait_idle();
+
+            regs.clkcr().modify(|w| {
+                w.set_widbus(match width {
+                    BusWidth::One => 0,
+                    BusWidth::Four => 1,
+                    BusWidth::Eight => 2,
+                    _ => panic!("Invalid Bus Width"),
+                })
+            });
+
+            // Set Clock
+            if freq.0 <= 25_000_000 {
+                // Final clock frequency
+                self.clkcr_set_clkdiv(freq.0, width, hclk, ker_ck, clock)?;
+            } else {
+                // Switch to max clock for SDR12
+                self.clkcr_set_clkdiv(25_000_000, width, hclk, ker_ck, clock)?;
+            }
+
+            // Read status
+            self.read_sd_status::<T, Dma>(&mut card, waker_reg, data_transfer_timeout, dma)
+                .await?;
+
+            if freq.0 > 25_000_000 {
+                // Switch to SDR25, the high-speed mode
+                *signalling = self
+                    .switch_signalling_mode::<T, Dma>(
+                        Signalling::SDR25,
+                        waker_reg,
+                        data_transfer_timeout,
+                        dma,
+                    )
+                    .await?;
+
+                if *signalling == Signalling::SDR25 {
+                    // Set final clock frequency
+                    self.clkcr_set_clkdiv(freq.0, width, hclk, ker_ck, clock)?;
+
+                    if self.read_status(&card)?.state() != CurrentState::Transfer {
+                        return Err(Error::SignalingSwitchFailed);
+                    }
+                }
+            }
+            // Read status after signalling change
+            self.read_sd_status::<T, Dma>(&mut card, waker_reg, data_transfer_timeout, dma)
+                .await?;
+            old_card.replace(card);
+        }
+
+        Ok(())
+    }
+
+    async fn read_blocks<T: Instance, Dma: SdmmcDma<T>>(
+        &self,
+        block_idx: u32,
+        buffer: &mut [u32],
+        capacity: CardCapacity,
+        waker_reg: &AtomicWaker,
+        data_transfer_timeout: u32,
+        dma: &mut Dma,
+    ) -> Result<(), Error> {
+        let blocks = buffer.len() / 128;
+        assert!(blocks > 0);
+
+        // SDSC cards are byte addressed hence the blockaddress is in multiples of 512 bytes
+        let address = match capacity {
+            CardCapacity::SDSC => block_idx * 512,
+            _ => block_idx,
+        };
+        self.cmd(Cmd::set_block_length(512), false)?; // CMD16
+
+        let transfer =
+            unsafe { self.prepare_datapath_read::<T, Dma>(buffer, 9, data_transfer_timeout, dma) };
+        let on_drop = OnDrop::new(|| unsafe { self.on_drop() });
+
+        if blocks == 1 {
+            self.cmd(Cmd::read_single_block(address), true)?; // CMD17
+        } else {
+            self.cmd(Cmd::read_multiple_blocks(address), true)?; // CMD18
+        }
+
+        let res = self.complete_datapath_transfer(waker_reg, transfer).await;
+        on_drop.defuse();
+        match res {
+            Ok(_) => {
+                self.stop_datapath();
+                if blocks > 1 {
+                    self.cmd(Cmd::stop_transmission(), false)?; // CMD12
+                }
+                Ok(())
+            }
+            Err(e) => {
+                self.abort_multiple_blocks(blocks);
+                Err(e)
+            }
+        }
+    }
+
+    async fn write_blocks<T: Instance, Dma: SdmmcDma<T>>(
+        &self,
+        block_idx: u32,
+        buffer: &[u32],
+        card: &mut Card,
+        waker_reg: &AtomicWaker,
+        data_transfer_timeout: u32,
+        dma: &mut Dma,
+    ) -> Result<(), Error> {
+        let blocks = buffer.len() / 128;
+        assert!(blocks > 0);
+
+        // SDSC cards are byte addressed hence the blockaddress is in multiples of 512 bytes
+        let address = match card.card_type {
+            CardCapacity::SDSC => block_idx * 512,
+            _ => block_idx,
+        };
+        self.cmd(Cmd::set_block_length(512), false)?; // CMD16
+
+        let transfer =
+            unsafe { self.prepare_datapath_write::<T, Dma>(buffer, 9, data_transfer_timeout, dma) };
+        let on_drop = OnDrop::new(|| unsafe { self.on_drop() });
+
+        if blocks == 1 {
+            self.cmd(Cmd::write_single_block(address), true)?; // CMD24
+        } else {
+            self.cmd(Cmd::write_multiple_blocks(address), true)?; // CMD25
+        }
+        // NOTE(unsafe) We have exclusive access to the peripheral
+        unsafe { self.start_data_write() };
+
+        let res = self.complete_datapath_transfer(waker_reg, transfer).await;
+        on_drop.defuse();
+        match res {
+            Ok(_) => {
+                self.stop_datapath();
+                if blocks > 1 {
+                    self.cmd(Cmd::stop_transmission(), false)?; // CMD12
+                }
+
+                // TODO: Make this configurable
+                let mut timeout: u32 = 0x00FF_FFFF;
+
+                // Wait for the card to be done programming the blocks
+                while timeout > 0 {
+                    match self.read_status(card)?.state() {
+                        CurrentState::Transfer => return Ok(()),
+                        CurrentState::Receiving | CurrentState::Programming => (), // Try again
+                        _ => return Err(Error::SoftwareTimeout),
+                    }
+                    timeout -= 1;
+                }
+                Err(Error::SoftwareTimeout)
+            }
+            Err(e) => {
+                self.abort_multiple_blocks(blocks);
+                Err(e)
+            }
+        }
+    }
+
+    /// Cleans up after a failed transfer of `blocks` blocks.
+    ///
+    /// The card stays in the data state of a multiple block command (CMD18 or CMD25) after a data
+    /// error, until it's sent a STOP_TRANSMISSION.
+    fn abort_multiple_blocks(&self, blocks: usize) {
+        // NOTE(unsafe) We have exclusive access to the peripheral
+        unsafe { self.on_drop() };
+        if blocks > 1 {
+            // The original error is returned, the card may already be stopped if the data path
+            // was still active.
+            let _ = self.cmd(Cmd::stop_transmission(), false); // CMD12
+        }
+    }
+
+    /// Wait idle on DOSNACT and CPSMACT
+    #[inline(always)]
+    fn wait_idle(&self) {
+        // NOTE(unsafe) Atomic read with no side-effects
+        unsafe { while self.cmd_active() || self.data_active() {} }
+    }
+
+    /// Prepares a transfer from the card into `buffer`, in blocks of `2^block_size` bytes.
+    ///
+    /// The data path state machine is started with the command, and the returned DMA transfer
+    /// completes once `buffer` is filled.
+    ///
+    /// # Safety
+    ///
+    /// The transfer must be waited for with [`Self::complete_datapath_transfer`], or aborted with
+    /// [`Self::on_drop`] before the returned future is dropped.
+    unsafe fn prepare_datapath_read<'a, T: Instance, Dma: SdmmcDma<T>>(
+        &self,
+        buffer: &'a mut [u32],
+        block_size: u8,
+        data_transfer_timeout: u32,
+        dma: &'a mut Dma,
+    ) -> impl Future<Output = ()> + 'a {
+        assert!(block_size <= 14, "Block size up to 2^14 bytes");
+        let regs = self.0;
+
+        // Command AND Data state machines must be idle
+        self.wait_idle();
+        self.clear_interrupt_flags();
+
+        // NOTE(unsafe) We have exclusive access to the regisers
+
+        regs.dtimer()
+            .write(|w| w.set_datatime(data_transfer_timeout));
+        regs.dlenr()
+            .write(|w| w.set_datalength(buffer.len() as u32 * 4));
+
+        self.start_read_dma::<T, Dma>(buffer, block_size, dma)
+    }
+
+    /// Prepares a transfer of `buffer` to the card, in blocks of `2^block_size` bytes.
+    ///
+    /// The data path state machine must be started with [`Self::start_data_write`] after the
+    /// response to the command.
+    ///
+    /// # Safety
+    ///
+    /// The transfer must be waited for with [`Self::complete_datapath_transfer`], or aborted with
+    /// [`Self::on_drop`] before the returned future is dropped.
+    unsafe fn prepare_datapath_write<'a, T: Instance, Dma: SdmmcDma<T>>(
+        &self,
+        buffer: &'a [u32],
+        block_size: u8,
+        data_transfer_timeout: u32,
+        dma: &'a mut Dma,
+    ) -> impl Future<Output = ()> + 'a {
+        assert!(block_size <= 14, "Block size up to 2^14 bytes");
+        let regs = self.0;
+
+        // Command AND Data state machines must be idle
+        self.wait_idle();
+        self.clear_interrupt_flags();
+
+        // NOTE(unsafe) We have exclusive access to the regisers
+
+        regs.dtimer()
+            .write(|w| w.set_datatime(data_transfer_timeout));
+        regs.dlenr()
+            .write(|w| w.set_datalength(buffer.len() as u32 * 4));
+
+        self.start_write_dma::<T, Dma>(buffer, block_size, dma)
+    }
+
+    /// Waits for the end of the data transfer started by the last command, and for its DMA
+    /// `transfer`.
+    async fn complete_datapath_transfer(
+        &self,
+        waker_reg: &AtomicWaker,
+        transfer: impl Future<Output = ()>,
+    ) -> Result<(), Error> {
+        let regs = self.0;
+
+        // NOTE(unsafe) We have exclusive access to the regisers
+        self.data_interrupts(true);
+
+        let res = poll_fn(|cx| {
+            waker_reg.register(cx.waker());
+            let status = unsafe { regs.star().read() };
+
+            if status.dcrcfail() {
+                return Poll::Ready(Err(Error::Crc));
+            } else if status.dtimeout() {
+                return Poll::Ready(Err(Error::Timeout));
+            } else if status.rxoverr() {
+                return Poll::Ready(Err(Error::RxOverFlow));
+            } else if status.txunderr() {
+                return Poll::Ready(Err(Error::TxUnderFlow));
+            } else if status.dataend() {
+                return Poll::Ready(Ok(()));
+            }
+            Poll::Pending
+        })
+        .await;
+        self.clear_interrupt_flags();
+
+        if res.is_ok() {
+            // On reads, the last words are moved out of the FIFO after the end of the data.
+            transfer.await;
+        }
+        res
+    }
+
+    /// Switch mode using CMD6.
+    ///
+    /// Attempt to set a new signalling mode. The selected
+    /// signalling mode is returned. Expects the current clock
+    /// frequency to be > 12.5MHz.
+    async fn switch_signalling_mode<T: Instance, Dma: SdmmcDma<T>>(
+        &self,
+        signalling: Signalling,
+        waker_reg: &AtomicWaker,
+        data_transfer_timeout: u32,
+        dma: &mut Dma,
+    ) -> Result<Signalling, Error> {
+        // NB PLSS v7_10 4.3.10.4: "the use of SET_BLK_LEN command is not
+        // necessary"
+
+        let set_function = 0x8000_0000
+            | match signalling {
+                // See PLSS v7_10 Table 4-11
+                Signalling::DDR50 => 0xFF_FF04,
+                Signalling::SDR104 => 0xFF_1F03,
+                Signalling::SDR50 => 0xFF_1F02,
+                Signalling::SDR25 => 0xFF_FF01,
+                Signalling::SDR12 => 0xFF_FF00,
+            };
+
+        let mut status = [0u32; 16];
+
+        let transfer = unsafe {
+            self.prepare_datapath_read::<T, Dma>(&mut status, 6, data_transfer_timeout, dma)
+        };
+        let on_drop = OnDrop::new(|| unsafe { self.on_drop() });
+
+        self.cmd(Cmd::cmd6(set_function), true)?; // CMD6
+
+        let res = self.complete_datapath_transfer(waker_reg, transfer).await;
+
+        // Host is allowed to use the new functions at least 8
+        // clocks after the end of the switch command
+        // transaction. We know the current clock period is < 80ns,
+        // so a total delay of 640ns is required here
+        for _ in 0..300 {
+            cortex_m::asm::nop();
+        }
+
+        match res {
+            Ok(_) => {
+                on_drop.defuse();
+                self.stop_datapath();
+
+                // Function Selection of Function Group 1
+                let selection = (u32::from_be(status[4]) >> 24) & 0xF;
+
+                match selection {
+                    0 => Ok(Signalling::SDR12),
+                    1 => Ok(Signalling::SDR25),
+                    2 => Ok(Signalling::SDR50),
+                    3 => Ok(Signalling::SDR104),
+                    4 => Ok(Signalling::DDR50),
+                    _ => Err(Error::UnsupportedCardType),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Query the card status (CMD13, returns R1)
+    ///
+    fn read_status(&self, card: &Card) -> Result<CardStatus, Error> {
+        let regs = self.0;
+        let rca = card.rca;
+
+        self.cmd(Cmd::card_status(rca << 16), false)?; // CMD13
+
+        // NOTE(unsafe) Atomic read with no side-effects
+        let r1 = unsafe { regs.respr(0).read().cardstatus1() };
+        Ok(r1.into())
+    }
+
+    /// Reads the SD Status (ACMD13)
+    async fn read_sd_status<T: Instance, Dma: SdmmcDma<T>>(
+        &self,
+        card: &mut Card,
+        waker_reg: &AtomicWaker,
+        data_transfer_timeout: u32,
+        dma: &mut Dma,
+    ) -> Result<(), Error> {
+        let rca = card.rca;
+        self.cmd(Cmd::set_block_length(64), false)?; // CMD16
+        self.cmd(Cmd::app_cmd(rca << 16), false)?; // APP
+
+        let mut status = [0u32; 16];
+
+        let transfer = unsafe {
+            self.prepare_datapath_read::<T, Dma>(&mut status, 6, data_transfer_timeout, dma)
+        };
+        let on_drop = OnDrop::new(|| unsafe { self.on_drop() });
+
+        self.cmd(Cmd::card_status(0), true)?;
+
+        let res = self.complete_datapath_transfer(waker_reg, transfer).await;
+
+        if res.is_ok() {
+            on_drop.defuse();
+            self.stop_datapath();
+            for byte in status.iter_mut() {
+                *byte = u32::from_be(*byte);
+            }
+            card.status = status.into();
+        }
+        res
+    }
+
+    /// Select one card and place it into the _Tranfer State_
+    ///
+    /// If `None` is specifed for `card`, all cards are put back into
+    /// _Stand-by State_
+    fn select_card(&self, card: Option<&Card>) -> Result<(), Error> {
+        // Determine Relative Card Address (RCA) of given card
+        let rca = card.map(|c| c.rca << 16).unwrap_or(0);
+
+        let r = self.cmd(Cmd::sel_desel_card(rca), false);
+        match (r, rca) {
+            (Err(Error::Timeout), 0) => Ok(()),
+            _ => r,
+        }
+    }
+
+    async fn get_scr<T: Instance, Dma: SdmmcDma<T>>(
+        &self,
+        card: &mut Card,
+        waker_reg: &AtomicWaker,
+        data_transfer_timeout: u32,
+        dma: &mut Dma,
+    ) -> Result<(), Error> {
+        // Read the the 64-bit SCR register
+        self.cmd(Cmd::set_block_length(8), false)?; // CMD16
+        self.cmd(Cmd::app_cmd(card.rca << 16), false)?;
+
+        let mut scr = [0u32; 2];
+
+        let transfer = unsafe {
+            self.prepare_datapath_read::<T, Dma>(&mut scr, 3, data_transfer_timeout, dma)
+        };
+        let on_drop = OnDrop::new(move || unsafe { self.on_drop() });
+
+        self.cmd(Cmd::cmd51(), true)?;
+
+        let res = self.complete_datapath_transfer(waker_reg, transfer).await;
+
+        if res.is_ok() {
+            on_drop.defuse();
+            self.stop_datapath();
+
+            unsafe {
+                let scr_bytes = &*(&scr as *const [u32; 2] as *const [u8; 8]);
+                card.scr = SCR(u64::from_be_bytes(*scr_bytes));
+            }
+        }
+        res
+    }
+
+    /// Send command to card
+    fn cmd(&self, cmd: Cmd, data: bool) -> Result<(), Error> {
+        let regs = self.0;
+
+        self.clear_interrupt_flags();
+        // NOTE(safety) Atomic operations
+        unsafe {
+            // CP state machine must be idle
+            self.wait_cmd_idle();
+
+            // Command arg
+            regs.argr().write(|w| w.set_cmdarg(cmd.arg));
+
+            // Command index and start CP State Machine
+            self.write_cmdr(cmd.cmd, cmd.resp, data);
+
+            let mut status;
+            if cmd.resp == Response::None {
+                // Wait for CMDSENT or a timeout
+                while {
+                    status = regs.star().read();
+                    !(status.ctimeout() || status.cmdsent())
+                } {}
+            } else {
+                // Wait for CMDREND or CCRCFAIL or a timeout
+                while {
+                    status = regs.star().read();
+                    !(status.ctimeout() || status.cmdrend() || status.ccrcfail())
+                } {}
+            }
+
+            if status.ctimeout() {
+                return Err(Error::Timeout);
+            } else if status.ccrcfail() {
+                return Err(Error::Crc);
+            }
+            Ok(())
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Ensure that `regs` has exclusive access to the regblocks
+    unsafe fn on_drop(&self) {
+        let regs = self.0;
+
+        if self.data_active() {
+            self.clear_interrupt_flags();
+            // Send abort
+            // CP state machine must be idle
+            self.wait_cmd_idle();
+
+            // Command arg
+            regs.argr().write(|w| w.set_cmdarg(0));
+
+            // Command index and start CP State Machine
+            self.write_cmdr(12, Response::Short, false);
+
+            // Wait for the abort, the data path state machine stops at the data timeout at most
+            while self.data_active() {}
+        }
+        self.data_interrupts(false);
+        self.clear_interrupt_flags();
+        self.stop_datapath();
+    }
+
+    /// Wait idle on CPSMACT
+    #[inline(always)]
+    unsafe fn wait_cmd_idle(&self) {
+        while self.cmd_active() {}
+    }
+}
+
+/// SD card Commands
+impl Cmd {
+    const fn new(cmd: u8, arg: u32, resp: Response) -> Cmd {
+        Cmd { cmd, arg, resp }
+    }
+
+    /// CMD0: Idle
+    const fn idle() -> Cmd {
+        Cmd::new(0, 0, Response::None)
+    }
+
+    /// CMD2: Send CID
+    const fn all_send_cid() -> Cmd {
+        Cmd::new(2, 0, Response::Long)
+    }
+
+    /// CMD3: Send Relative Address
+    const fn send_rel_addr() -> Cmd {
+        Cmd::new(3, 0, Response::Short)
+    }
+
+    /// CMD6: Switch Function Command
+    /// ACMD6: Bus Width
+    const fn cmd6(arg: u32) -> Cmd {
+        Cmd::new(6, arg, Response::Short)
+    }
+
+    /// CMD7: Select one card and put it into the _Tranfer State_
+    const fn sel_desel_card(rca: u32) -> Cmd {
+        Cmd::new(7, rca, Response::Short)
+    }
+
+    /// CMD8:
+    const fn hs_send_ext_csd(arg: u32) -> Cmd {
+        Cmd::new(8, arg, Response::Short)
+    }
+
+    /// CMD9:
+    const fn send_csd(rca: u32) -> Cmd {
+        Cmd::new(9, rca, Response::Long)
+    }
+
+    /// CMD12: Stop Transmission
+    const fn stop_transmission() -> Cmd {
+        Cmd::new(12, 0, Response::Short)
+    }
+
+    /// CMD13: Ask card to send status register
+    /// ACMD13: SD Status
+    const fn card_status(rca: u32) -> Cmd {
+        Cmd::new(13, rca, Response::Short)
+    }
+
+    /// CMD16:
+    const fn set_block_length(blocklen: u32) -> Cmd {
+        Cmd::new(16, blocklen, Response::Short)
+    }
+
+    /// CMD17: Block Read
+    const fn read_single_block(addr: u32) -> Cmd {
+        Cmd::new(17, addr, Response::Short)
+    }
+
+    /// CMD18: Multiple Block Read
+    const fn read_multiple_blocks(addr: u32) -> Cmd {
+        Cmd::new(18, addr, Response::Short)
+    }
+
+    /// CMD24: Block Write
+    const fn write_single_block(addr: u32) -> Cmd {
+        Cmd::new(24, addr, Response::Short)
+    }
+
+    /// CMD25: Multiple Block Write
+    const fn write_multiple_blocks(addr: u32) -> Cmd {
+        Cmd::new(25, addr, Response::Short)
+    }
+
+    const fn app_op_cmd(arg: u32) -> Cmd {
+        Cmd::new(41, arg, Response::Short)
+    }
+
+    const fn cmd51() -> Cmd {
+        Cmd::new(51, 0, Response::Short)
+    }
+
+    /// App Command. Indicates that next command will be a app command
+    const fn app_cmd(rca: u32) -> Cmd {
+        Cmd::new(55, rca, Response::Short)
+    }
+}
+
+//////////////////////////////////////////////////////
+
+pub(crate) mod sealed {
+    use super::*;
+    use crate::gpio::Pin as GpioPin;
+
+    pub trait Instance {
+        type Interrupt: Interrupt;
+
+        fn inner() -> SdmmcInner;
+        fn state() -> &'static AtomicWaker;
+    }
+    pub trait CkPin<T: Instance>: GpioPin {
+        const AF_NUM: u8;
+    }
+    pub trait CmdPin<T: Instance>: GpioPin {
+        const AF_NUM: u8;
+    }
+    pub trait D0Pin<T: Instance>: GpioPin {
+        const AF_NUM: u8;
+    }
+    pub trait D1Pin<T: Instance>: GpioPin {
+        const AF_NUM: u8;
+    }
+    pub trait D2Pin<T: Instance>: GpioPin {
+        const AF_NUM: u8;
+    }
+    pub trait D3Pin<T: Instance>: GpioPin {
+        const AF_NUM: u8;
+    }
+    pub trait D4Pin<T: Instance>: GpioPin {
+        const AF_NUM: u8;
+    }
+    pub trait D5Pin<T: Instance>: GpioPin {
+        const AF_NUM: u8;
+    }
+    pub trait D6Pin<T: Instance>: GpioPin {
+        const AF_NUM: u8;
+    }
+    pub trait D7Pin<T: Instance>: GpioPin {
+        const AF_NUM: u8;
+    }
+
+    pub trait Pins<T: Instance> {}
+}
+
+pub trait Instance: sealed::Instance + RccPeripheral + 'static {}
+pub trait CkPin<T: Instance>: sealed::CkPin<T> + 'static {}
+pub trait CmdPin<T: Instance>: sealed::CmdPin<T> + 'static {}
+pub trait D0Pin<T: Instance>: sealed::D0Pin<T> + 'static {}
+pub trait D1Pin<T: Instance>: sealed::D1Pin<T> + 'static {}
+pub trait D2Pin<T: Instance>: sealed::D2Pin<T> + 'static {}
+pub trait D3Pin<T: Instance>: sealed::D3Pin<T> + 'static {}
+pub trait D4Pin<T: Instance>: sealed::D4Pin<T> + 'static {}
+pub trait D5Pin<T: Instance>: sealed::D5Pin<T> + 'static {}
+pub trait D6Pin<T: Instance>: sealed::D6Pin<T> + 'static {}
+pub trait D7Pin<T: Instance>: sealed::D7Pin<T> + 'static {}
+
+pub trait Pins<T: Instance>: sealed::Pins<T> + 'static {
+    const BUSWIDTH: BusWidth;
+
+    fn configure(&mut self);
+    fn deconfigure(&mut self);
+}
+
+impl<T, CLK, CMD, D0, D1, D2, D3> sealed::Pins<T> for (CLK, CMD, D0, D1, D2, D3)
+where
+    T: Instance,
+    CLK: CkPin<T>,
+    CMD: CmdPin<T>,
+    D0: D0Pin<T>,
+    D1: D1Pin<T>,
+    D2: D2Pin<T>,
+    D3: D3Pin<T>,
+{
+}
+
+impl<T, CLK, CMD, D0> sealed::Pins<T> for (CLK, CMD, D0)
+where
+    T: Instance,
+    CLK: CkPin<T>,
+    CMD: CmdPin<T>,
+    D0: D0Pin<T>,
+{
+}
+
+/// # Safety
+///
+/// Access to the GPIO block of `pin` should be exclusive
+unsafe fn configure_pin(pin: &impl crate::gpio::sealed::Pin, af_num: u8, pup: bool) {
+    pin.set_as_af(af_num, AFType::OutputPushPull);
+
+    #[cfg(gpio_v2)]
+    {
+        use crate::pac::gpio::vals::Pupdr;
+
+        pin.set_speed(crate::gpio::Speed::VeryHigh);
+        if pup {
+            let n = pin._pin() as usize;
+            pin.block()
+                .pupdr()
+                .modify(|w| w.set_pupdr(n, Pupdr::PULLUP));
+        }
+    }
+    // The pull-ups of the card bus have to be external on the F1
+    #[cfg(gpio_v1)]
+    let _ = pup;
+}
+
+/// # Safety
+///
+/// Access to the GPIO block of `pin` should be exclusive
+unsafe fn deconfigure_pin(pin: &impl crate::gpio::sealed::Pin) {
+    pin.set_as_analog();
+
+    #[cfg(gpio_v2)]
+    {
+        use crate::pac::gpio::vals::Pupdr;
+
+        pin.set_speed(crate::gpio::Speed::Low);
+        let n = pin._pin() as usize;
+        pin.block()
+            .pupdr()
+            .modify(|w| w.set_pupdr(n, Pupdr::FLOATING));
+    }
+}
+
+impl<T, CLK, CMD, D0, D1, D2, D3> Pins<T> for (CLK, CMD, D0, D1, D2, D3)
+where
+    T: Instance,
+    CLK: CkPin<T>,
+    CMD: CmdPin<T>,
+    D0: D0Pin<T>,
+    D1: D1Pin<T>,
+    D2: D2Pin<T>,
+    D3: D3Pin<T>,
+{
+    const BUSWIDTH: BusWidth = BusWidth::Four;
+
+    fn configure(&mut self) {
+        let (clk_pin, cmd_pin, d0_pin, d1_pin, d2_pin, d3_pin) = self;
+
+        cortex_m::interrupt::free(|_| unsafe {
+            configure_pin(clk_pin, CLK::AF_NUM, false);
+            configure_pin(cmd_pin, CMD::AF_NUM, true);
+            configure_pin(d0_pin, D0::AF_NUM, true);
+            configure_pin(d1_pin, D1::AF_NUM, true);
+            configure_pin(d2_pin, D2::AF_NUM, true);
+            configure_pin(d3_pin, D3::AF_NUM, true);
+        });
+    }
+
+    fn deconfigure(&mut self) {
+        let (clk_pin, cmd_pin, d0_pin, d1_pin, d2_pin, d3_pin) = self;
+
+        cortex_m::interrupt::free(|_| unsafe {
+            deconfigure_pin(clk_pin);
+            deconfigure_pin(cmd_pin);
+            deconfigure_pin(d0_pin);
+            deconfigure_pin(d1_pin);
+            deconfigure_pin(d2_pin);
+            deconfigure_pin(d3_pin);
+        });
+    }
+}
+
+impl<T, CLK, CMD, D0> Pins<T> for (CLK, CMD, D0)
+where
+    T: Instance,
+    CLK: CkPin<T>,
+    CMD: CmdPin<T>,
+    D0: D0Pin<T>,
+{
+    const BUSWIDTH: BusWidth = BusWidth::One;
+
+    fn configure(&mut self) {
+        let (clk_pin, cmd_pin, d0_pin) = self;
+
+        cortex_m::interrupt::free(|_| unsafe {
+            configure_pin(clk_pin, CLK::AF_NUM, false);
+            configure_pin(cmd_pin, CMD::AF_NUM, true);
+            configure_pin(d0_pin, D0::AF_NUM, true);
+        });
+    }
+
+    fn deconfigure(&mut self) {
+        let (clk_pin, cmd_pin, d0_pin) = self;
+
+        cortex_m::interrupt::free(|_| unsafe {
+            deconfigure_pin(clk_pin);
+            deconfigure_pin(cmd_pin);
+            deconfigure_pin(d0_pin);
+        });
+    }
+}
+
+crate::pac::peripherals!(
+    (sdmmc, $inst:ident) => {
+        impl sealed::Instance for peripherals::$inst {
+            type Interrupt = crate::interrupt::$inst;
+
+            fn inner() -> SdmmcInner {
+                const INNER: SdmmcInner = SdmmcInner(crate::pac::$inst);
+                INNER
+            }
+
+            fn state() -> &'static ::embassy::waitqueue::AtomicWaker {
+                static WAKER: ::embassy::waitqueue::AtomicWaker = ::embassy::waitqueue::AtomicWaker::new();
+                &WAKER
+            }
+        }
+
+        impl Instance for peripherals::$inst {}
+    };
+);
+
+macro_rules! impl_pin {
+    ($inst:ident, $pin:ident, $signal:ident, $af:expr) => {
+        impl sealed::$signal<peripherals::$inst> for peripherals::$pin {
+            const AF_NUM: u8 = $af;
+        }
+
+        impl $signal<peripherals::$inst> for peripherals::$pin {}
+    };
+}
+
+#[cfg(not(rcc_f1))]
+crate::pac::peripheral_pins!(
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, CK, $af:expr) => {
+        impl_pin!($inst, $pin, CkPin, $af);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, CMD, $af:expr) => {
+        impl_pin!($inst, $pin, CmdPin, $af);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D0, $af:expr) => {
+        impl_pin!($inst, $pin, D0Pin, $af);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D1, $af:expr) => {
+        impl_pin!($inst, $pin, D1Pin, $af);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D2, $af:expr) => {
+        impl_pin!($inst, $pin, D2Pin, $af);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D3, $af:expr) => {
+        impl_pin!($inst, $pin, D3Pin, $af);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D4, $af:expr) => {
+        impl_pin!($inst, $pin, D4Pin, $af);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D5, $af:expr) => {
+        impl_pin!($inst, $pin, D5Pin, $af);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D6, $af:expr) => {
+        impl_pin!($inst, $pin, D6Pin, $af);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D7, $af:expr) => {
+        impl_pin!($inst, $pin, D7Pin, $af);
+    };
+);
+
+#[cfg(rcc_f1)]
+crate::pac::peripheral_pins!(
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, CK) => {
+        impl_pin!($inst, $pin, CkPin, 0);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, CMD) => {
+        impl_pin!($inst, $pin, CmdPin, 0);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D0) => {
+        impl_pin!($inst, $pin, D0Pin, 0);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D1) => {
+        impl_pin!($inst, $pin, D1Pin, 0);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D2) => {
+        impl_pin!($inst, $pin, D2Pin, 0);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D3) => {
+        impl_pin!($inst, $pin, D3Pin, 0);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D4) => {
+        impl_pin!($inst, $pin, D4Pin, 0);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D5) => {
+        impl_pin!($inst, $pin, D5Pin, 0);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D6) => {
+        impl_pin!($inst, $pin, D6Pin, 0);
+    };
+    ($inst:ident, sdmmc, SDMMC, $pin:ident, D7) => {
+        impl_pin!($inst, $pin, D7Pin, 0);
+    };
+);
+
+/// Maximum number of blocks of a single transfer, limited by the DMA transfer size
+const MAX_BLOCKS: usize = 0xFFFF / 128;
+
+impl<'d, T: Instance, P: Pins<T>, Dma: SdmmcDma<T>> embassy_traits::block_device::BlockDevice
+    for Sdmmc<'d, T, P, Dma>
+{
+    type Error = Error;
+    type ReadFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), Self::Error>> + 'a;
+    type WriteFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<(), Self::Error>> + 'a;
+
+    fn read<'a>(&'a mut self, block_idx: u32, buf: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move {
+            assert_eq!(buf.len() % 512, 0);
+            let mut block_idx = block_idx;
+
+            if buf.as_ptr() as usize % 4 == 0 {
+                // NOTE(unsafe) buf is aligned to 4 and its length is a multiple of 512
+                let blocks = unsafe {
+                    core::slice::from_raw_parts_mut(
+                        buf.as_mut_ptr() as *mut DataBlock,
+                        buf.len() / 512,
+                    )
+                };
+                for chunk in blocks.chunks_mut(MAX_BLOCKS) {
+                    self.read_blocks(block_idx, chunk).await?;
+                    block_idx += chunk.len() as u32;
+                }
+            } else {
+                let mut block = DataBlock::new();
+                for chunk in buf.chunks_mut(512) {
+                    self.read_block(block_idx, &mut block).await?;
+                    chunk.copy_from_slice(&block.0);
+                    block_idx += 1;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn write<'a>(&'a mut self, block_idx: u32, buf: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move {
+            assert_eq!(buf.len() % 512, 0);
+            let mut block_idx = block_idx;
+
+            if buf.as_ptr() as usize % 4 == 0 {
+                // NOTE(unsafe) buf is aligned to 4 and its length is a multiple of 512
+                let blocks = unsafe {
+                    core::slice::from_raw_parts(buf.as_ptr() as *const DataBlock, buf.len() / 512)
+                };
+                for chunk in blocks.chunks(MAX_BLOCKS) {
+                    self.write_blocks(block_idx, chunk).await?;
+                    block_idx += chunk.len() as u32;
+                }
+            } else {
+                let mut block = DataBlock::new();
+                for chunk in buf.chunks(512) {
+                    block.0.copy_from_slice(chunk);
+                    self.write_block(block_idx, &block).await?;
+                    block_idx += 1;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        512
+    }
+
+    /// Returns 0 until [`init_card`](Sdmmc::init_card) has succeeded.
+    fn block_count(&self) -> u32 {
+        self.card().map_or(0, |card| card.csd.block_count())
+    }
+}
+
+#[cfg(feature = "sdmmc-rs")]
+mod sdmmc_rs {
+    use super::*;
+    use core::future::Future;
+    use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx};
+
+    impl<'d, T: Instance, P: Pins<T>, Dma: SdmmcDma<T>> BlockDevice for Sdmmc<'d, T, P, Dma> {
+        type Error = Error;
+        type ReadFuture<'a>
+        where
+            Self: 'a,
+        = impl Future<Output = Result<(), Self::Error>> + 'a;
+        type WriteFuture<'a>
+        where
+            Self: 'a,
+        = impl Future<Output = Result<(), Self::Error>> + 'a;
+
+        fn read<'a>(
+            &'a mut self,
+            blocks: &'a mut [Block],
+            start_block_idx: BlockIdx,
+            _reason: &str,
+        ) -> Self::ReadFuture<'a> {
+            async move {
+                // NOTE(unsafe) Block and DataBlock are both 512 bytes aligned to 4
+                let blocks = unsafe {
+                    core::slice::from_raw_parts_mut(
+                        blocks.as_mut_ptr() as *mut DataBlock,
+                        blocks.len(),
+                    )
+                };
+                self.read_blocks(start_block_idx.0, blocks).await
+            }
+        }
+
+        fn write<'a>(
+            &'a mut self,
+            blocks: &'a [Block],
+            start_block_idx: BlockIdx,
+        ) -> Self::WriteFuture<'a> {
+            async move {
+                // NOTE(unsafe) Block and DataBlock are both 512 bytes aligned to 4
+                let blocks = unsafe {
+                    core::slice::from_raw_parts(blocks.as_ptr() as *const DataBlock, blocks.len())
+                };
+                self.write_blocks(start_block_idx.0, blocks).await
+            }
+        }
+
+        fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+            let card = self.card()?;
+            let count = card.csd.block_count();
+            Ok(BlockCount(count))
+        }
+    }
+}
@@ -1 +1,265 @@
+//! SDMMC v1 (SDIO): the data is moved by a DMA channel, and the data path state machine of a
+//! write is started after the response to the command.
 
+use core::future::Future;
+
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+use sdio_host::BusWidth;
+
+use super::{Config, Error, Instance, Pins, Response, Sdmmc, SdmmcInner};
+use crate::peripherals;
+use crate::time::Hertz;
+
+/// Default SDMMC kernel clock
+pub(super) const DEFAULT_KERNEL_CLK: Hertz = Hertz(48_000_000);
+
+/// Calculate clock divisor. Returns a SDMMC_CK less than or equal to
+/// `sdmmc_ck` in Hertz.
+///
+/// Returns `(bypass, clk_div, clk_f)`, where `bypass` enables clock divisor bypass,
+/// `clk_div` is the divisor register value and `clk_f` is the resulting new clock frequency.
+fn clk_div(ker_ck: Hertz, sdmmc_ck: u32) -> Result<(bool, u8, Hertz), Error> {
+    // SDMMC_CK = SDMMCCLK / (CLKDIV + 2)
+    match (ker_ck.0 + sdmmc_ck - 1) / sdmmc_ck {
+        0 | 1 => Ok((true, 0, ker_ck)),
+        x @ 2..=257 => {
+            let clk_div = (x - 2) as u8;
+            let clk = Hertz(ker_ck.0 / x);
+
+            Ok((false, clk_div, clk))
+        }
+        _ => Err(Error::BadClock),
+    }
+}
+
+impl<'d, T: Instance, P: Pins<T>, Dma: SdmmcDma<T>> Sdmmc<'d, T, P, Dma> {
+    /// # Safety
+    ///
+    /// Futures that borrow this type can't be leaked
+    #[inline(always)]
+    pub unsafe fn new(
+        _peripheral: impl Unborrow<Target = T> + 'd,
+        pins: impl Unborrow<Target = P> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt>,
+        config: Config,
+        dma: impl Unborrow<Target = Dma> + 'd,
+    ) -> Self {
+        unborrow!(irq, pins, dma);
+        Self::new_inner(pins, irq, config, dma)
+    }
+}
+
+impl SdmmcInner {
+    /// Sets up CLKCR for the identification mode, and returns the SDMMC_CK frequency.
+    ///
+    /// # Safety
+    ///
+    /// Access to `regs` registers should be exclusive
+    pub(super) unsafe fn init_clkcr(&self, kernel_clk: Hertz) -> Hertz {
+        // While the SD/SDIO card or eMMC is in identification mode,
+        // the SDMMC_CK frequency must be less than 400 kHz.
+        let (bypass, clkdiv, clock) = unwrap!(clk_div(kernel_clk, 400_000));
+
+        self.0.clkcr().write(|w| {
+            w.set_widbus(0);
+            w.set_clkdiv(clkdiv);
+            w.set_bypass(bypass);
+            w.set_pwrsav(false);
+            w.set_negedge(false);
+            // The hardware flow control of the SDIO is broken, see the errata of the F4 and F7
+            w.set_hwfc_en(false);
+            w.set_clken(true);
+        });
+
+        clock
+    }
+
+    /// Sets the CLKDIV field in CLKCR. Updates clock field in self
+    pub(super) fn clkcr_set_clkdiv(
+        &self,
+        freq: u32,
+        _width: BusWidth,
+        _hclk: Hertz,
+        ker_ck: Hertz,
+        clock: &mut Hertz,
+    ) -> Result<(), Error> {
+        let (bypass, clkdiv, new_clock) = clk_div(ker_ck, freq)?;
+        *clock = new_clock;
+
+        // NOTE(unsafe) We have exclusive access to the regblock
+        unsafe {
+            // CPSMACT and DPSMACT must be 0 to set CLKDIV
+            self.wait_idle();
+            self.0.clkcr().modify(|w| {
+                w.set_clkdiv(clkdiv);
+                w.set_bypass(bypass);
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the command path state machine is active
+    #[inline(always)]
+    pub(super) unsafe fn cmd_active(&self) -> bool {
+        self.0.star().read().cmdact()
+    }
+
+    /// Returns whether the data path state machine is active
+    #[inline(always)]
+    pub(super) unsafe fn data_active(&self) -> bool {
+        let status = self.0.star().read();
+        status.rxact() || status.txact()
+    }
+
+    /// Starts the command path state machine with command `cmd`.
+    pub(super) unsafe fn write_cmdr(&self, cmd: u8, resp: Response, _data: bool) {
+        self.0.cmdr().write(|w| {
+            w.set_waitint(false);
+            w.set_waitresp(resp as u8);
+            w.set_cmdindex(cmd);
+            w.set_cpsmen(true);
+        });
+    }
+
+    /// Starts the DMA transfer and the data path state machine of a read into `buffer`.
+    pub(super) unsafe fn start_read_dma<'a, T: Instance, Dma: SdmmcDma<T>>(
+        &self,
+        buffer: &'a mut [u32],
+        block_size: u8,
+        dma: &'a mut Dma,
+    ) -> impl Future<Output = ()> + 'a {
+        let regs = self.0;
+
+        let request = dma.request();
+        let transfer = crate::dma::read(dma, request, regs.fifor().ptr() as *mut u32, buffer);
+        regs.dctrl().modify(|w| {
+            w.set_dblocksize(block_size);
+            w.set_dtdir(true);
+            w.set_dmaen(true);
+            w.set_dten(true);
+        });
+        transfer
+    }
+
+    /// Starts the DMA transfer of a write of `buffer`. The data path state machine is started
+    /// by [`Self::start_data_write`].
+    pub(super) unsafe fn start_write_dma<'a, T: Instance, Dma: SdmmcDma<T>>(
+        &self,
+        buffer: &'a [u32],
+        block_size: u8,
+        dma: &'a mut Dma,
+    ) -> impl Future<Output = ()> + 'a {
+        let regs = self.0;
+
+        let request = dma.request();
+        let transfer = crate::dma::write(dma, request, buffer, regs.fifor().ptr() as *mut u32);
+        regs.dctrl().modify(|w| {
+            w.set_dblocksize(block_size);
+            w.set_dtdir(false);
+            w.set_dmaen(true);
+        });
+        transfer
+    }
+
+    /// Starts sending the data of a write, after the response to the command.
+    #[inline(always)]
+    pub(super) unsafe fn start_data_write(&self) {
+        self.0.dctrl().modify(|w| w.set_dten(true));
+    }
+
+    /// Releases the DMA after a successful transfer
+    #[inline(always)]
+    pub(super) fn stop_datapath(&self) {
+        // NOTE(unsafe) We have exclusive access to the regisers
+        unsafe { self.0.dctrl().modify(|w| w.set_dmaen(false)) }
+    }
+
+    /// Clear flags in interrupt clear register
+    #[inline(always)]
+    pub(super) fn clear_interrupt_flags(&self) {
+        // NOTE(unsafe) Atomic write
+        unsafe {
+            self.0.icr().write(|w| {
+                w.set_ccrcfailc(true);
+                w.set_dcrcfailc(true);
+                w.set_ctimeoutc(true);
+                w.set_dtimeoutc(true);
+                w.set_txunderrc(true);
+                w.set_rxoverrc(true);
+                w.set_cmdrendc(true);
+                w.set_cmdsentc(true);
+                w.set_dataendc(true);
+                w.set_dbckendc(true);
+                w.set_sdioitc(true);
+                w.set_stbiterrc(true);
+                w.set_ceataendc(true);
+            });
+        }
+    }
+
+    /// Enables the interrupts for data transfer
+    #[inline(always)]
+    pub(super) fn data_interrupts(&self, enable: bool) {
+        // NOTE(unsafe) Atomic write
+        unsafe {
+            self.0.maskr().write(|w| {
+                w.set_dcrcfailie(enable);
+                w.set_dtimeoutie(enable);
+                w.set_dataendie(enable);
+                w.set_rxoverrie(enable);
+                w.set_txunderrie(enable);
+            });
+        }
+    }
+}
+
+pub(crate) mod sealed {
+    use super::Instance;
+
+    pub trait SdmmcDma<T: Instance> {
+        fn request(&self) -> crate::dma::Request;
+    }
+}
+
+/// DMA channel used by the SDMMC v1 (SDIO) for data transfers
+pub trait SdmmcDma<T: Instance>: sealed::SdmmcDma<T> + crate::dma::Channel {}
+
+#[allow(unused)]
+macro_rules! impl_dma {
+    ($inst:ident, {dmamux: $dmamux:ident}, $request:expr) => {
+        impl<T> sealed::SdmmcDma<peripherals::$inst> for T
+        where
+            T: crate::dma::MuxChannel<Mux = crate::dma::$dmamux>,
+        {
+            fn request(&self) -> crate::dma::Request {
+                $request
+            }
+        }
+
+        impl<T> SdmmcDma<peripherals::$inst> for T where
+            T: crate::dma::MuxChannel<Mux = crate::dma::$dmamux>
+        {
+        }
+    };
+    ($inst:ident, {channel: $channel:ident}, $request:expr) => {
+        impl sealed::SdmmcDma<peripherals::$inst> for peripherals::$channel {
+            fn request(&self) -> crate::dma::Request {
+                $request
+            }
+        }
+
+        impl SdmmcDma<peripherals::$inst> for peripherals::$channel {}
+    };
+}
+
+// The SDIO transfers data in both directions on the same DMA channel.
+crate::pac::peripheral_dma_channels! {
+    ($peri:ident, sdmmc, $kind:ident, RX, $channel:tt, $request:expr) => {
+        impl_dma!($peri, $channel, $request);
+    };
+    ($peri:ident, sdmmc, $kind:ident, TX, $channel:tt, $request:expr) => {
+        impl_dma!($peri, $channel, $request);
+    };
+}
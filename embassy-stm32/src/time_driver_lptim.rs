@@ -0,0 +1,270 @@
+//! Time driver running off LPTIM1 clocked by the LSE, which keeps counting in STOP mode.
+//!
+//! The LPTIM has a single compare channel: it is set to the earliest alarm falling in the
+//! current overflow cycle, and re-armed at each overflow.
+
+use atomic_polyfill::{AtomicU32, AtomicU8};
+use core::cell::Cell;
+use core::sync::atomic::Ordering;
+use core::{mem, ptr};
+use embassy::interrupt::InterruptExt;
+use embassy::time::driver::{AlarmHandle, Driver};
+
+use crate::interrupt;
+use crate::interrupt::{CriticalSection, Mutex};
+use crate::pac::LPTIM1 as REGS;
+use crate::peripherals;
+use crate::rcc::sealed::RccPeripheral;
+
+#[cfg(not(any(rcc_g0, rcc_g4, rcc_l0, rcc_l4, rcc_wl5)))]
+compile_error!("the LPTIM1 time driver is only supported on the G0, G4, L0, L4 and WL families");
+
+const ALARM_COUNT: usize = 3;
+
+type T = peripherals::LPTIM1;
+
+#[interrupt]
+fn LPTIM1() {
+    DRIVER.on_interrupt()
+}
+
+/// Reads the counter, which is clocked asynchronously: two consecutive reads must match.
+unsafe fn counter() -> u16 {
+    loop {
+        let a = REGS.cnt().read().cnt();
+        let b = REGS.cnt().read().cnt();
+        if a == b {
+            return a;
+        }
+    }
+}
+
+// The counter runs at the tick rate, and `period` counts its overflows. The counter is 16 bits,
+// so a period is 2 seconds at 32768 Hz.
+//
+// ARRM is set one tick before the counter wraps. The interrupt handler waits for the wrap before
+// incrementing `period`, so `now()` only has to detect an overflow whose interrupt is still
+// pending: ARRM is set and the counter is low.
+fn calc_now(period: u32, counter: u16) -> u64 {
+    ((period as u64) << 16) + counter as u64
+}
+
+struct AlarmState {
+    timestamp: Cell<u64>,
+
+    // This is really a Option<(fn(*mut ()), *mut ())>
+    // but fn pointers aren't allowed in const yet
+    callback: Cell<*const ()>,
+    ctx: Cell<*mut ()>,
+}
+
+unsafe impl Send for AlarmState {}
+
+impl AlarmState {
+    const fn new() -> Self {
+        Self {
+            timestamp: Cell::new(u64::MAX),
+            callback: Cell::new(ptr::null()),
+            ctx: Cell::new(ptr::null_mut()),
+        }
+    }
+}
+
+struct LptimDriver {
+    /// Number of counter overflows since boot.
+    period: AtomicU32,
+    alarm_count: AtomicU8,
+    /// Timestamp at which to fire alarm. u64::MAX if no alarm is scheduled.
+    alarms: Mutex<[AlarmState; ALARM_COUNT]>,
+}
+
+const ALARM_STATE_NEW: AlarmState = AlarmState::new();
+
+embassy::time_driver_impl!(static DRIVER: LptimDriver = LptimDriver {
+    period: AtomicU32::new(0),
+    alarm_count: AtomicU8::new(0),
+    alarms: Mutex::new([ALARM_STATE_NEW; ALARM_COUNT]),
+});
+
+impl LptimDriver {
+    fn init(&'static self) {
+        T::enable();
+        T::reset();
+
+        // NOTE(unsafe) Critical section to use the unsafe methods
+        critical_section::with(|_| unsafe {
+            crate::rcc::enable_lse(false);
+            crate::pac::RCC
+                .ccipr()
+                .modify(|w| w.set_lptim1sel(crate::pac::rcc::vals::Lptimsel::LSE));
+
+            // IER can only be written while the timer is disabled, ARR and CMP only while it
+            // is enabled.
+            REGS.ier().write(|w| {
+                w.set_cmpmie(true);
+                w.set_arrmie(true);
+            });
+            REGS.cr().write(|w| w.set_enable(true));
+            REGS.arr().write(|w| w.set_arr(0xffff));
+            while !REGS.isr().read().arrok() {}
+            REGS.icr().write(|w| w.set_arrokcf(true));
+
+            let irq: interrupt::LPTIM1 = core::mem::transmute(());
+            irq.unpend();
+            irq.enable();
+
+            REGS.cr().write(|w| {
+                w.set_enable(true);
+                w.set_cntstrt(true);
+            });
+        })
+    }
+
+    fn on_interrupt(&self) {
+        critical_section::with(|cs| unsafe {
+            let isr = REGS.isr().read();
+
+            if isr.arrm() {
+                // Wait for the counter to wrap, see `calc_now`.
+                while counter() == 0xffff {}
+                REGS.icr().write(|w| w.set_arrmcf(true));
+                self.period.fetch_add(1, Ordering::Relaxed);
+            }
+            if isr.cmpm() {
+                REGS.icr().write(|w| w.set_cmpmcf(true));
+            }
+
+            self.check_alarms(cs);
+        })
+    }
+
+    fn now_cs(&self, _cs: CriticalSection) -> u64 {
+        // NOTE(unsafe) Reads with no side-effects, in a critical section
+        unsafe {
+            let period = self.period.load(Ordering::Relaxed);
+            let counter = counter();
+            let overflowed = REGS.isr().read().arrm() && counter < 0x8000;
+            calc_now(period + overflowed as u32, counter)
+        }
+    }
+
+    fn get_alarm<'a>(&'a self, cs: CriticalSection<'a>, alarm: AlarmHandle) -> &'a AlarmState {
+        // safety: we're allowed to assume the AlarmState is created by us, and
+        // we never create one that's out of bounds.
+        unsafe { self.alarms.borrow(cs).get_unchecked(alarm.id() as usize) }
+    }
+
+    /// Triggers the expired alarms, and sets the compare register to the next one if it falls in
+    /// the current overflow cycle.
+    fn check_alarms(&self, cs: CriticalSection) {
+        loop {
+            let t = self.now_cs(cs);
+
+            let mut next = u64::MAX;
+            let mut expired = None;
+            for (n, alarm) in self.alarms.borrow(cs).iter().enumerate() {
+                let at = alarm.timestamp.get();
+                if at <= t {
+                    expired = Some(n);
+                    break;
+                }
+                next = next.min(at);
+            }
+
+            if let Some(n) = expired {
+                // The callback can set alarms, check again afterwards.
+                self.trigger_alarm(n, cs);
+                continue;
+            }
+
+            // A compare write takes effect after a few ticks, later alarms fire a bit late.
+            let next = next.max(t + 4);
+            if next >> 16 == t >> 16 {
+                // NOTE(unsafe) We're in a critical section
+                unsafe {
+                    REGS.cmp().write(|w| w.set_cmp(next as u16));
+                    while !REGS.isr().read().cmpok() {}
+                    REGS.icr().write(|w| w.set_cmpokcf(true));
+                }
+            }
+            return;
+        }
+    }
+
+    fn trigger_alarm(&self, n: usize, cs: CriticalSection) {
+        let alarm = &self.alarms.borrow(cs)[n];
+        alarm.timestamp.set(u64::MAX);
+
+        // Call after clearing alarm, so the callback can set another alarm.
+
+        // safety:
+        // - we can ignore the possiblity of `f` being unset (null) because of the safety contract of `allocate_alarm`.
+        // - other than that we only store valid function pointers into alarm.callback
+        let f: fn(*mut ()) = unsafe { mem::transmute(alarm.callback.get()) };
+        f(alarm.ctx.get());
+    }
+
+    /// Returns the number of ticks until the next alarm, or `None` if no alarm is set.
+    #[cfg(feature = "low-power")]
+    fn time_until_next_alarm(&self) -> Option<u64> {
+        critical_section::with(|cs| {
+            let t = self.now_cs(cs);
+            self.alarms
+                .borrow(cs)
+                .iter()
+                .map(|a| a.timestamp.get())
+                .min()
+                .filter(|&at| at != u64::MAX)
+                .map(|at| at.saturating_sub(t))
+        })
+    }
+}
+
+impl Driver for LptimDriver {
+    fn now(&self) -> u64 {
+        critical_section::with(|cs| self.now_cs(cs))
+    }
+
+    unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
+        let id = self
+            .alarm_count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |x| {
+                if x < ALARM_COUNT as u8 {
+                    Some(x + 1)
+                } else {
+                    None
+                }
+            });
+
+        match id {
+            Ok(id) => Some(AlarmHandle::new(id)),
+            Err(_) => None,
+        }
+    }
+
+    fn set_alarm_callback(&self, alarm: AlarmHandle, callback: fn(*mut ()), ctx: *mut ()) {
+        critical_section::with(|cs| {
+            let alarm = self.get_alarm(cs, alarm);
+
+            alarm.callback.set(callback as *const ());
+            alarm.ctx.set(ctx);
+        })
+    }
+
+    fn set_alarm(&self, alarm: AlarmHandle, timestamp: u64) {
+        critical_section::with(|cs| {
+            self.get_alarm(cs, alarm).timestamp.set(timestamp);
+            self.check_alarms(cs);
+        })
+    }
+}
+
+pub(crate) fn init() {
+    DRIVER.init()
+}
+
+/// Returns the number of ticks until the next alarm, or `None` if no alarm is set.
+#[cfg(feature = "low-power")]
+pub(crate) fn time_until_next_alarm() -> Option<u64> {
+    DRIVER.time_until_next_alarm()
+}
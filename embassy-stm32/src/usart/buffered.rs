@@ -0,0 +1,467 @@
+use atomic_polyfill::{compiler_fence, Ordering};
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::Context;
+use core::task::Poll;
+use embassy::waitqueue::WakerRegistration;
+use embassy_hal_common::peripheral::{PeripheralMutex, PeripheralState, StateStorage};
+use embassy_hal_common::ring_buffer::RingBuffer;
+use futures::future::poll_fn;
+
+use super::*;
+use crate::gpio::sealed::Pin as _;
+use crate::gpio::{AnyPin, Level, Output, Pin as GpioPin, Speed};
+
+pub struct State<'d, T: Instance>(StateStorage<StateInner<'d, T>>);
+impl<'d, T: Instance> State<'d, T> {
+    pub fn new() -> Self {
+        Self(StateStorage::new())
+    }
+}
+
+struct StateInner<'d, T: Instance> {
+    uart: Uart<'d, T, NoDma, NoDma>,
+    phantom: PhantomData<&'d mut T>,
+    /// Driver enable of an RS-485 transceiver, high while transmitting.
+    de: Option<Output<'d, AnyPin>>,
+
+    rx_waker: WakerRegistration,
+    rx: RingBuffer<'d>,
+    /// Reception error, reported by the next read.
+    rx_error: Option<Error>,
+    /// The line has been idle since the last received byte.
+    rx_idle: bool,
+
+    tx_waker: WakerRegistration,
+    tx: RingBuffer<'d>,
+    /// Set when the first byte is written, until the last one is sent.
+    tx_active: bool,
+}
+
+unsafe impl<'d, T: Instance> Send for StateInner<'d, T> {}
+unsafe impl<'d, T: Instance> Sync for StateInner<'d, T> {}
+
+type Inner<'d, T> = RefCell<PeripheralMutex<'d, StateInner<'d, T>>>;
+
+/// UART with interrupt-driven reception and transmission through ring buffers.
+///
+/// Bytes are received in the background, as long as the RX buffer isn't full. Reception errors
+/// are returned by the next read.
+pub struct BufferedUart<'d, T: Instance> {
+    inner: Inner<'d, T>,
+}
+
+impl<'d, T: Instance> Unpin for BufferedUart<'d, T> {}
+
+/// Receiving half of a [`BufferedUart`], see [`BufferedUart::split`].
+pub struct BufferedUartRx<'a, 'd, T: Instance> {
+    inner: &'a Inner<'d, T>,
+}
+
+impl<'a, 'd, T: Instance> Unpin for BufferedUartRx<'a, 'd, T> {}
+
+/// Transmitting half of a [`BufferedUart`], see [`BufferedUart::split`].
+pub struct BufferedUartTx<'a, 'd, T: Instance> {
+    inner: &'a Inner<'d, T>,
+}
+
+impl<'a, 'd, T: Instance> Unpin for BufferedUartTx<'a, 'd, T> {}
+
+impl<'d, T: Instance> BufferedUart<'d, T> {
+    pub unsafe fn new(
+        state: &'d mut State<'d, T>,
+        uart: Uart<'d, T, NoDma, NoDma>,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        tx_buffer: &'d mut [u8],
+        rx_buffer: &'d mut [u8],
+    ) -> BufferedUart<'d, T> {
+        Self::new_inner(state, uart, irq, None, tx_buffer, rx_buffer)
+    }
+
+    /// Creates a buffered UART driving the DE (driver enable) input of an RS-485 transceiver.
+    ///
+    /// `de` is set high before transmitting, and low once the last byte is sent.
+    pub unsafe fn new_with_de(
+        state: &'d mut State<'d, T>,
+        uart: Uart<'d, T, NoDma, NoDma>,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        de: impl Unborrow<Target = impl GpioPin> + 'd,
+        tx_buffer: &'d mut [u8],
+        rx_buffer: &'d mut [u8],
+    ) -> BufferedUart<'d, T> {
+        unborrow!(de);
+        let de = Output::new(de.degrade(), Level::Low, Speed::Medium);
+        Self::new_inner(state, uart, irq, Some(de), tx_buffer, rx_buffer)
+    }
+
+    unsafe fn new_inner(
+        state: &'d mut State<'d, T>,
+        uart: Uart<'d, T, NoDma, NoDma>,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        de: Option<Output<'d, AnyPin>>,
+        tx_buffer: &'d mut [u8],
+        rx_buffer: &'d mut [u8],
+    ) -> BufferedUart<'d, T> {
+        unborrow!(irq);
+
+        let r = uart.inner.regs();
+        r.cr1().modify(|w| {
+            w.set_rxneie(true);
+            w.set_idleie(true);
+        });
+
+        Self {
+            inner: RefCell::new(PeripheralMutex::new_unchecked(
+                irq,
+                &mut state.0,
+                move || StateInner {
+                    uart,
+                    phantom: PhantomData,
+                    de,
+
+                    rx: RingBuffer::new(rx_buffer),
+                    rx_waker: WakerRegistration::new(),
+                    rx_error: None,
+                    rx_idle: false,
+
+                    tx: RingBuffer::new(tx_buffer),
+                    tx_waker: WakerRegistration::new(),
+                    tx_active: false,
+                },
+            )),
+        }
+    }
+
+    /// Splits the UART into halves, to receive and transmit concurrently.
+    pub fn split<'a>(&'a mut self) -> (BufferedUartRx<'a, 'd, T>, BufferedUartTx<'a, 'd, T>) {
+        (
+            BufferedUartRx { inner: &self.inner },
+            BufferedUartTx { inner: &self.inner },
+        )
+    }
+
+    /// Receives into `buf` until it's full, or the line goes idle after some bytes were received.
+    ///
+    /// Returns the number of bytes received.
+    pub async fn read_until_idle(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        read_until_idle(&self.inner, buf).await
+    }
+}
+
+impl<'a, 'd, T: Instance> BufferedUartRx<'a, 'd, T> {
+    /// Receives into `buf` until it's full, or the line goes idle after some bytes were received.
+    ///
+    /// Returns the number of bytes received.
+    pub async fn read_until_idle(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        read_until_idle(self.inner, buf).await
+    }
+}
+
+impl<'d, T: Instance> StateInner<'d, T>
+where
+    Self: 'd,
+{
+    fn on_rx(&mut self) {
+        let r = self.uart.inner.regs();
+        unsafe {
+            let sr = sr(r).read();
+            // TODO: do we want to handle interrupts the same way on v1 hardware?
+            if sr.pe() {
+                clear_interrupt_flag(r, InterruptFlag::PE);
+                self.set_rx_error(Error::Parity);
+            } else if sr.fe() {
+                clear_interrupt_flag(r, InterruptFlag::FE);
+                self.set_rx_error(Error::Framing);
+            } else if sr.ne() {
+                clear_interrupt_flag(r, InterruptFlag::NE);
+                self.set_rx_error(Error::Noise);
+            } else if sr.ore() {
+                clear_interrupt_flag(r, InterruptFlag::ORE);
+                self.set_rx_error(Error::Overrun);
+            } else if sr.rxne() {
+                self.rx_idle = false;
+                let buf = self.rx.push_buf();
+                if buf.is_empty() {
+                    self.rx_waker.wake();
+                } else {
+                    buf[0] = rdr(r).read_volatile();
+                    self.rx.push(1);
+                }
+            } else if sr.idle() {
+                clear_interrupt_flag(r, InterruptFlag::IDLE);
+                self.rx_idle = true;
+                self.rx_waker.wake();
+            };
+        }
+    }
+
+    fn set_rx_error(&mut self, error: Error) {
+        trace!("Reception error: {:?}", error);
+        self.rx_error = Some(error);
+        self.rx_waker.wake();
+    }
+
+    fn on_tx(&mut self) {
+        let r = self.uart.inner.regs();
+        unsafe {
+            let sr = sr(r).read();
+            if sr.txe() {
+                let buf = self.tx.pop_buf();
+                if !buf.is_empty() {
+                    if !self.tx_active {
+                        self.tx_active = true;
+                        if let Some(de) = &self.de {
+                            de.pin.set_high();
+                        }
+                    }
+                    r.cr1().modify(|w| {
+                        w.set_txeie(true);
+                        w.set_tcie(false);
+                    });
+                    // This also clears TC.
+                    tdr(r).write_volatile(buf[0].into());
+                    self.tx.pop(1);
+                    self.tx_waker.wake();
+                } else if self.tx_active && sr.tc() {
+                    // The last byte is sent.
+                    r.cr1().modify(|w| {
+                        w.set_txeie(false);
+                        w.set_tcie(false);
+                    });
+                    self.tx_active = false;
+                    if let Some(de) = &self.de {
+                        de.pin.set_low();
+                    }
+                    self.tx_waker.wake();
+                } else {
+                    // Disable interrupt until we have something to transmit again, or wait for
+                    // the last byte to be sent.
+                    r.cr1().modify(|w| {
+                        w.set_txeie(false);
+                        w.set_tcie(self.tx_active);
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl<'d, T: Instance> PeripheralState for StateInner<'d, T>
+where
+    Self: 'd,
+{
+    type Interrupt = T::Interrupt;
+    fn on_interrupt(&mut self) {
+        self.on_rx();
+        self.on_tx();
+    }
+}
+
+impl From<Error> for embassy::io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Overrun => embassy::io::Error::Other,
+            Error::Framing | Error::Noise | Error::Parity => embassy::io::Error::InvalidData,
+        }
+    }
+}
+
+fn poll_fill_buf<'a, 'd, T: Instance>(
+    inner: &'a Inner<'d, T>,
+    cx: &mut Context<'_>,
+) -> Poll<Result<&'a [u8], embassy::io::Error>> {
+    inner.borrow_mut().with(|state| {
+        compiler_fence(Ordering::SeqCst);
+
+        if let Some(error) = state.rx_error.take() {
+            return Poll::Ready(Err(error.into()));
+        }
+
+        // We have data ready in buffer? Return it.
+        let buf = state.rx.pop_buf();
+        if !buf.is_empty() {
+            let buf: &[u8] = buf;
+            // Safety: buffer lives as long as uart
+            let buf: &[u8] = unsafe { core::mem::transmute(buf) };
+            return Poll::Ready(Ok(buf));
+        }
+
+        state.rx_waker.register(cx.waker());
+        Poll::<Result<&[u8], embassy::io::Error>>::Pending
+    })
+}
+
+fn consume<'d, T: Instance>(inner: &Inner<'d, T>, amt: usize) {
+    let mut inner = inner.borrow_mut();
+    let signal = inner.with(|state| {
+        let full = state.rx.is_full();
+        state.rx.pop(amt);
+        full
+    });
+    if signal {
+        inner.pend();
+    }
+}
+
+fn read_until_idle<'a, 'd, T: Instance>(
+    inner: &'a Inner<'d, T>,
+    buf: &'a mut [u8],
+) -> impl Future<Output = Result<usize, Error>> + 'a {
+    let mut n = 0;
+    poll_fn(move |cx| {
+        let mut inner = inner.borrow_mut();
+        let (poll, signal) = inner.with(|state| {
+            if let Some(error) = state.rx_error.take() {
+                return (Poll::Ready(Err(error)), false);
+            }
+
+            let full = state.rx.is_full();
+            while n < buf.len() {
+                let data = state.rx.pop_buf();
+                if data.is_empty() {
+                    break;
+                }
+                let len = core::cmp::min(data.len(), buf.len() - n);
+                buf[n..n + len].copy_from_slice(&data[..len]);
+                state.rx.pop(len);
+                n += len;
+            }
+
+            if n == buf.len() || (n > 0 && state.rx_idle && state.rx.is_empty()) {
+                (Poll::Ready(Ok(n)), full)
+            } else {
+                state.rx_waker.register(cx.waker());
+                (Poll::Pending, full)
+            }
+        });
+        // Bytes could not be received while the buffer was full.
+        if signal {
+            inner.pend();
+        }
+        poll
+    })
+}
+
+fn poll_write<'d, T: Instance>(
+    inner: &Inner<'d, T>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+) -> Poll<Result<usize, embassy::io::Error>> {
+    let mut inner = inner.borrow_mut();
+    let (poll, empty) = inner.with(|state| {
+        let empty = state.tx.is_empty();
+        let tx_buf = state.tx.push_buf();
+        if tx_buf.is_empty() {
+            state.tx_waker.register(cx.waker());
+            return (Poll::Pending, empty);
+        }
+
+        let n = core::cmp::min(tx_buf.len(), buf.len());
+        tx_buf[..n].copy_from_slice(&buf[..n]);
+        state.tx.push(n);
+
+        (Poll::Ready(Ok(n)), empty)
+    });
+    if empty {
+        inner.pend();
+    }
+    poll
+}
+
+fn poll_flush<'d, T: Instance>(
+    inner: &Inner<'d, T>,
+    cx: &mut Context<'_>,
+) -> Poll<Result<(), embassy::io::Error>> {
+    inner.borrow_mut().with(|state| {
+        // Also wait for the last byte to be sent, so that the DE pin is low.
+        if !state.tx.is_empty() || state.tx_active {
+            state.tx_waker.register(cx.waker());
+            return Poll::Pending;
+        }
+
+        Poll::Ready(Ok(()))
+    })
+}
+
+impl<'d, T: Instance> embassy::io::AsyncBufRead for BufferedUart<'d, T> {
+    fn poll_fill_buf(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<&[u8], embassy::io::Error>> {
+        poll_fill_buf(&self.get_mut().inner, cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        consume(&self.inner, amt)
+    }
+}
+
+impl<'d, T: Instance> embassy::io::AsyncWrite for BufferedUart<'d, T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, embassy::io::Error>> {
+        poll_write(&self.inner, cx, buf)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), embassy::io::Error>> {
+        poll_flush(&self.inner, cx)
+    }
+}
+
+impl<'a, 'd, T: Instance> embassy::io::AsyncBufRead for BufferedUartRx<'a, 'd, T> {
+    fn poll_fill_buf(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<&[u8], embassy::io::Error>> {
+        poll_fill_buf(self.get_mut().inner, cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        consume(self.inner, amt)
+    }
+}
+
+impl<'a, 'd, T: Instance> embassy::io::AsyncWrite for BufferedUartTx<'a, 'd, T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, embassy::io::Error>> {
+        poll_write(self.inner, cx, buf)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), embassy::io::Error>> {
+        poll_flush(self.inner, cx)
+    }
+}
+
+impl<'d, T: Instance> embassy_traits::uart::ReadUntilIdle for BufferedUart<'d, T> {
+    type ReadUntilIdleFuture<'a>
+    where
+        Self: 'a,
+    = impl Future<Output = Result<usize, embassy_traits::uart::Error>> + 'a;
+
+    fn read_until_idle<'a>(&'a mut self, buf: &'a mut [u8]) -> Self::ReadUntilIdleFuture<'a> {
+        read_until_idle(&self.inner, buf).map_err(|_| embassy_traits::uart::Error::Other)
+    }
+}
+
+impl<'a, 'd, T: Instance> embassy_traits::uart::ReadUntilIdle for BufferedUartRx<'a, 'd, T> {
+    type ReadUntilIdleFuture<'b>
+    where
+        Self: 'b,
+    = impl Future<Output = Result<usize, embassy_traits::uart::Error>> + 'b;
+
+    fn read_until_idle<'b>(&'b mut self, buf: &'b mut [u8]) -> Self::ReadUntilIdleFuture<'b> {
+        read_until_idle(self.inner, buf).map_err(|_| embassy_traits::uart::Error::Other)
+    }
+}
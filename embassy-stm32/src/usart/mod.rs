@@ -4,6 +4,7 @@ use core::future::Future;
 use core::marker::PhantomData;
 use embassy::interrupt::Interrupt;
 use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
 use embassy_hal_common::unborrow;
 use futures::TryFutureExt;
 
@@ -272,219 +273,11 @@ where
     }
 }
 
+mod buffered;
 pub use buffered::*;
-mod buffered {
-    use atomic_polyfill::{compiler_fence, Ordering};
-    use core::pin::Pin;
-    use core::task::Context;
-    use core::task::Poll;
-    use embassy::waitqueue::WakerRegistration;
-    use embassy_hal_common::peripheral::{PeripheralMutex, PeripheralState, StateStorage};
-    use embassy_hal_common::ring_buffer::RingBuffer;
 
-    use super::*;
-
-    pub struct State<'d, T: Instance>(StateStorage<StateInner<'d, T>>);
-    impl<'d, T: Instance> State<'d, T> {
-        pub fn new() -> Self {
-            Self(StateStorage::new())
-        }
-    }
-
-    struct StateInner<'d, T: Instance> {
-        uart: Uart<'d, T, NoDma, NoDma>,
-        phantom: PhantomData<&'d mut T>,
-
-        rx_waker: WakerRegistration,
-        rx: RingBuffer<'d>,
-
-        tx_waker: WakerRegistration,
-        tx: RingBuffer<'d>,
-    }
-
-    unsafe impl<'d, T: Instance> Send for StateInner<'d, T> {}
-    unsafe impl<'d, T: Instance> Sync for StateInner<'d, T> {}
-
-    pub struct BufferedUart<'d, T: Instance> {
-        inner: PeripheralMutex<'d, StateInner<'d, T>>,
-    }
-
-    impl<'d, T: Instance> Unpin for BufferedUart<'d, T> {}
-
-    impl<'d, T: Instance> BufferedUart<'d, T> {
-        pub unsafe fn new(
-            state: &'d mut State<'d, T>,
-            uart: Uart<'d, T, NoDma, NoDma>,
-            irq: impl Unborrow<Target = T::Interrupt> + 'd,
-            tx_buffer: &'d mut [u8],
-            rx_buffer: &'d mut [u8],
-        ) -> BufferedUart<'d, T> {
-            unborrow!(irq);
-
-            let r = uart.inner.regs();
-            r.cr1().modify(|w| {
-                w.set_rxneie(true);
-                w.set_idleie(true);
-            });
-
-            Self {
-                inner: PeripheralMutex::new_unchecked(irq, &mut state.0, move || StateInner {
-                    uart,
-                    phantom: PhantomData,
-                    tx: RingBuffer::new(tx_buffer),
-                    tx_waker: WakerRegistration::new(),
-
-                    rx: RingBuffer::new(rx_buffer),
-                    rx_waker: WakerRegistration::new(),
-                }),
-            }
-        }
-    }
-
-    impl<'d, T: Instance> StateInner<'d, T>
-    where
-        Self: 'd,
-    {
-        fn on_rx(&mut self) {
-            let r = self.uart.inner.regs();
-            unsafe {
-                let sr = sr(r).read();
-                // TODO: do we want to handle interrupts the same way on v1 hardware?
-                if sr.pe() {
-                    clear_interrupt_flag(r, InterruptFlag::PE);
-                    trace!("Parity error");
-                } else if sr.fe() {
-                    clear_interrupt_flag(r, InterruptFlag::FE);
-                    trace!("Framing error");
-                } else if sr.ne() {
-                    clear_interrupt_flag(r, InterruptFlag::NE);
-                    trace!("Noise error");
-                } else if sr.ore() {
-                    clear_interrupt_flag(r, InterruptFlag::ORE);
-                    trace!("Overrun error");
-                } else if sr.rxne() {
-                    let buf = self.rx.push_buf();
-                    if buf.is_empty() {
-                        self.rx_waker.wake();
-                    } else {
-                        buf[0] = rdr(r).read_volatile();
-                        self.rx.push(1);
-                    }
-                } else if sr.idle() {
-                    clear_interrupt_flag(r, InterruptFlag::IDLE);
-                    self.rx_waker.wake();
-                };
-            }
-        }
-
-        fn on_tx(&mut self) {
-            let r = self.uart.inner.regs();
-            unsafe {
-                if sr(r).read().txe() {
-                    let buf = self.tx.pop_buf();
-                    if !buf.is_empty() {
-                        r.cr1().modify(|w| {
-                            w.set_txeie(true);
-                        });
-                        tdr(r).write_volatile(buf[0].into());
-                        self.tx.pop(1);
-                        self.tx_waker.wake();
-                    } else {
-                        // Disable interrupt until we have something to transmit again
-                        r.cr1().modify(|w| {
-                            w.set_txeie(false);
-                        });
-                    }
-                }
-            }
-        }
-    }
-
-    impl<'d, T: Instance> PeripheralState for StateInner<'d, T>
-    where
-        Self: 'd,
-    {
-        type Interrupt = T::Interrupt;
-        fn on_interrupt(&mut self) {
-            self.on_rx();
-            self.on_tx();
-        }
-    }
-
-    impl<'d, T: Instance> embassy::io::AsyncBufRead for BufferedUart<'d, T> {
-        fn poll_fill_buf(
-            mut self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-        ) -> Poll<Result<&[u8], embassy::io::Error>> {
-            self.inner.with(|state| {
-                compiler_fence(Ordering::SeqCst);
-
-                // We have data ready in buffer? Return it.
-                let buf = state.rx.pop_buf();
-                if !buf.is_empty() {
-                    let buf: &[u8] = buf;
-                    // Safety: buffer lives as long as uart
-                    let buf: &[u8] = unsafe { core::mem::transmute(buf) };
-                    return Poll::Ready(Ok(buf));
-                }
-
-                state.rx_waker.register(cx.waker());
-                Poll::<Result<&[u8], embassy::io::Error>>::Pending
-            })
-        }
-        fn consume(mut self: Pin<&mut Self>, amt: usize) {
-            let signal = self.inner.with(|state| {
-                let full = state.rx.is_full();
-                state.rx.pop(amt);
-                full
-            });
-            if signal {
-                self.inner.pend();
-            }
-        }
-    }
-
-    impl<'d, T: Instance> embassy::io::AsyncWrite for BufferedUart<'d, T> {
-        fn poll_write(
-            mut self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-            buf: &[u8],
-        ) -> Poll<Result<usize, embassy::io::Error>> {
-            let (poll, empty) = self.inner.with(|state| {
-                let empty = state.tx.is_empty();
-                let tx_buf = state.tx.push_buf();
-                if tx_buf.is_empty() {
-                    state.tx_waker.register(cx.waker());
-                    return (Poll::Pending, empty);
-                }
-
-                let n = core::cmp::min(tx_buf.len(), buf.len());
-                tx_buf[..n].copy_from_slice(&buf[..n]);
-                state.tx.push(n);
-
-                (Poll::Ready(Ok(n)), empty)
-            });
-            if empty {
-                self.inner.pend();
-            }
-            poll
-        }
-
-        fn poll_flush(
-            mut self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-        ) -> Poll<Result<(), embassy::io::Error>> {
-            self.inner.with(|state| {
-                if !state.tx.is_empty() {
-                    state.tx_waker.register(cx.waker());
-                    return Poll::Pending;
-                }
-
-                Poll::Ready(Ok(()))
-            })
-        }
-    }
-}
+mod ringbuffered;
+pub use ringbuffered::*;
 
 #[cfg(usart_v1)]
 fn tdr(r: crate::pac::usart::Usart) -> *mut u8 {
@@ -563,6 +356,7 @@ pub(crate) mod sealed {
 
     pub trait Instance {
         fn regs(&self) -> crate::pac::usart::Usart;
+        fn rx_waker() -> &'static AtomicWaker;
     }
     pub trait RxPin<T: Instance>: Pin {
         fn af_num(&self) -> u8;
@@ -606,6 +400,11 @@ crate::pac::interrupts!(
             fn regs(&self) -> crate::pac::usart::Usart {
                 crate::pac::$inst
             }
+
+            fn rx_waker() -> &'static AtomicWaker {
+                static WAKER: AtomicWaker = AtomicWaker::new();
+                &WAKER
+            }
         }
 
         impl Instance for peripherals::$inst {
@@ -0,0 +1,170 @@
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use embassy::interrupt::InterruptExt;
+use futures::future::poll_fn;
+
+use super::*;
+use crate::dma::DmaRingBuffer;
+
+/// UART receiving continuously into a buffer with a DMA channel in circular mode.
+///
+/// Unlike [`Uart`]'s reads, no byte is lost between two reads, as long as the buffer doesn't
+/// fill up. Otherwise the next read returns [`Error::Overrun`] and the unread bytes are dropped.
+///
+/// Reads return as soon as some bytes were received: the UART interrupt wakes the reader when the
+/// line goes idle, and the DMA interrupt when half of the buffer is filled.
+pub struct RingBufferedUartRx<'d, T: Instance, TxDma, RxDma>
+where
+    RxDma: crate::usart::RxDma<T>,
+{
+    uart: Uart<'d, T, TxDma, RxDma>,
+    irq: T::Interrupt,
+    ring_buf: DmaRingBuffer<'d, u8>,
+    /// Bytes returned by `poll_fill_buf` were overwritten before being consumed.
+    overrun: bool,
+    /// The DMA stops in STOP mode.
+    #[cfg(feature = "low-power")]
+    _stop_veto: crate::low_power::StopVeto,
+}
+
+impl<'d, T: Instance, TxDma, RxDma> Unpin for RingBufferedUartRx<'d, T, TxDma, RxDma> where
+    RxDma: crate::usart::RxDma<T>
+{
+}
+
+impl<'d, T: Instance, TxDma, RxDma> RingBufferedUartRx<'d, T, TxDma, RxDma>
+where
+    RxDma: crate::usart::RxDma<T>,
+{
+    /// Starts receiving into `dma_buf`.
+    ///
+    /// The buffer must be large enough to hold the bytes received while the reader is busy.
+    pub fn new(
+        mut uart: Uart<'d, T, TxDma, RxDma>,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        dma_buf: &'d mut [u8],
+    ) -> Self {
+        unborrow!(irq);
+        assert!(dma_buf.len() <= 0xFFFF);
+
+        let r = uart.inner.regs();
+        let mut ring_buf = DmaRingBuffer::new(dma_buf);
+
+        irq.disable();
+        irq.set_handler(on_interrupt::<T>);
+        irq.set_handler_context(r.0 as *mut ());
+        irq.unpend();
+        irq.enable();
+
+        let ch = &mut uart.rx_dma;
+        let request = ch.request();
+        unsafe {
+            ch.start_circular_read(request, rdr(r), ring_buf.dma_buf());
+            r.cr3().modify(|w| w.set_dmar(true));
+            r.cr1().modify(|w| w.set_idleie(true));
+        }
+
+        Self {
+            uart,
+            irq,
+            ring_buf,
+            overrun: false,
+            #[cfg(feature = "low-power")]
+            _stop_veto: crate::low_power::StopVeto::new(),
+        }
+    }
+
+    /// Reads the received bytes into `buf`, waiting until at least one is available.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        poll_fn(|cx| {
+            self.register_waker(cx);
+
+            match self.ring_buf.read(&mut self.uart.rx_dma, buf) {
+                Ok(0) => Poll::Pending,
+                Ok(n) => Poll::Ready(Ok(n)),
+                Err(_) => {
+                    self.ring_buf.clear(&mut self.uart.rx_dma);
+                    Poll::Ready(Err(Error::Overrun))
+                }
+            }
+        })
+        .await
+    }
+
+    fn register_waker(&mut self, cx: &mut Context<'_>) {
+        T::rx_waker().register(cx.waker());
+        self.uart.rx_dma.set_waker(cx.waker());
+    }
+}
+
+impl<'d, T: Instance, TxDma, RxDma> Drop for RingBufferedUartRx<'d, T, TxDma, RxDma>
+where
+    RxDma: crate::usart::RxDma<T>,
+{
+    fn drop(&mut self) {
+        self.irq.disable();
+        let r = self.uart.inner.regs();
+        unsafe {
+            r.cr1().modify(|w| w.set_idleie(false));
+            r.cr3().modify(|w| w.set_dmar(false));
+        }
+
+        let ch = &mut self.uart.rx_dma;
+        ch.request_stop();
+        while ch.is_running() {}
+    }
+}
+
+unsafe fn on_interrupt<T: Instance>(ctx: *mut ()) {
+    let r = crate::pac::usart::Usart(ctx as _);
+    if sr(r).read().idle() {
+        clear_interrupt_flag(r, InterruptFlag::IDLE);
+        T::rx_waker().wake();
+    }
+}
+
+impl<'d, T: Instance, TxDma, RxDma> embassy::io::AsyncBufRead
+    for RingBufferedUartRx<'d, T, TxDma, RxDma>
+where
+    RxDma: crate::usart::RxDma<T>,
+{
+    fn poll_fill_buf(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<&[u8], embassy::io::Error>> {
+        let this = self.get_mut();
+
+        if this.overrun {
+            this.overrun = false;
+            this.ring_buf.clear(&mut this.uart.rx_dma);
+            return Poll::Ready(Err(embassy::io::Error::Other));
+        }
+
+        this.register_waker(cx);
+
+        match this.ring_buf.len(&mut this.uart.rx_dma) {
+            Ok(0) => Poll::Pending,
+            Ok(_) => match this.ring_buf.fill_buf(&mut this.uart.rx_dma) {
+                Ok(buf) => Poll::Ready(Ok(buf)),
+                Err(_) => Poll::Ready(Err(embassy::io::Error::Other)),
+            },
+            Err(_) => {
+                this.ring_buf.clear(&mut this.uart.rx_dma);
+                Poll::Ready(Err(embassy::io::Error::Other))
+            }
+        }
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        // The bytes were overwritten while the caller used them: report it on the next fill.
+        if this.ring_buf.consume(&mut this.uart.rx_dma, amt).is_err() {
+            this.overrun = true;
+        }
+    }
+}
@@ -0,0 +1,154 @@
+#![macro_use]
+
+use core::marker::PhantomData;
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+use stm32_usbd::UsbPeripheral;
+use usb_device::bus::UsbBusAllocator;
+
+pub use embassy_hal_common::usb::*;
+
+#[cfg(not(rcc_f1))]
+use crate::gpio::sealed::AFType;
+use crate::peripherals;
+use crate::rcc::RccPeripheral;
+
+cfg_if::cfg_if! {
+    if #[cfg(any(rcc_f1, rcc_f3, rcc_l1))] {
+        const EP_MEMORY: *const () = 0x4000_6000 as _;
+        const EP_MEMORY_SIZE: usize = 512;
+        const EP_MEMORY_ACCESS_2X16: bool = false;
+    } else if #[cfg(any(rcc_l4, rcc_wb))] {
+        const EP_MEMORY: *const () = 0x4000_6c00 as _;
+        const EP_MEMORY_SIZE: usize = 1024;
+        const EP_MEMORY_ACCESS_2X16: bool = true;
+    } else {
+        const EP_MEMORY: *const () = 0x4000_6000 as _;
+        const EP_MEMORY_SIZE: usize = 1024;
+        const EP_MEMORY_ACCESS_2X16: bool = true;
+    }
+}
+
+/// The `usb` full-speed device peripheral.
+///
+/// The 48 MHz USB clock has to be set up in the RCC config. On F1, F3 and L1, the pull-up on
+/// D+ is external to the chip.
+pub struct UsbBus<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> UsbBus<'d, T> {
+    pub fn new(
+        _peri: impl Unborrow<Target = T> + 'd,
+        dp: impl Unborrow<Target = impl DpPin<T>> + 'd,
+        dm: impl Unborrow<Target = impl DmPin<T>> + 'd,
+    ) -> UsbBusAllocator<stm32_usbd::UsbBus<Self>> {
+        unborrow!(dp, dm);
+
+        // F1 connects the pins to the peripheral when it's enabled.
+        #[cfg(not(rcc_f1))]
+        critical_section::with(|_| unsafe {
+            dp.set_as_af(dp.af_num(), AFType::OutputPushPull);
+            dm.set_as_af(dm.af_num(), AFType::OutputPushPull);
+        });
+        #[cfg(rcc_f1)]
+        let _ = (dp, dm);
+
+        stm32_usbd::UsbBus::new(Self {
+            phantom: PhantomData,
+        })
+    }
+}
+
+// The peripheral is only accessed through `stm32_usbd`, which synchronizes itself.
+unsafe impl<'d, T: Instance> Send for UsbBus<'d, T> {}
+unsafe impl<'d, T: Instance> Sync for UsbBus<'d, T> {}
+
+unsafe impl<'d, T: Instance> UsbPeripheral for UsbBus<'d, T> {
+    const REGISTERS: *const () = T::REGISTERS;
+    const DP_PULL_UP_FEATURE: bool = cfg!(not(any(rcc_f1, rcc_f3, rcc_l1)));
+    const EP_MEMORY: *const () = EP_MEMORY;
+    const EP_MEMORY_SIZE: usize = EP_MEMORY_SIZE;
+    const EP_MEMORY_ACCESS_2X16: bool = EP_MEMORY_ACCESS_2X16;
+
+    fn enable() {
+        <T as crate::rcc::sealed::RccPeripheral>::enable();
+        <T as crate::rcc::sealed::RccPeripheral>::reset();
+    }
+
+    fn startup_delay() {
+        // tSTARTUP is 1 µs, at most 170 cycles with the fastest clocks.
+        cortex_m::asm::delay(170);
+    }
+}
+
+pub(crate) mod sealed {
+    use crate::gpio::Pin as GpioPin;
+
+    pub trait Instance {
+        const REGISTERS: *const ();
+    }
+
+    pub trait DpPin<T: Instance>: GpioPin {
+        fn af_num(&self) -> u8;
+    }
+
+    pub trait DmPin<T: Instance>: GpioPin {
+        fn af_num(&self) -> u8;
+    }
+}
+
+pub trait Instance: sealed::Instance + RccPeripheral {}
+pub trait DpPin<T: Instance>: sealed::DpPin<T> + 'static {}
+pub trait DmPin<T: Instance>: sealed::DmPin<T> + 'static {}
+
+crate::pac::peripherals!(
+    (usb, $inst:ident) => {
+        impl sealed::Instance for peripherals::$inst {
+            const REGISTERS: *const () = crate::pac::$inst.0 as *const ();
+        }
+
+        impl Instance for peripherals::$inst {}
+    };
+);
+
+crate::pac::interrupts!(
+    ($inst:ident, usb, $block:ident, GLOBAL, $irq:ident) => {
+        unsafe impl USBInterrupt for crate::interrupt::$irq {}
+    };
+    ($inst:ident, usb, $block:ident, LP, $irq:ident) => {
+        unsafe impl USBInterrupt for crate::interrupt::$irq {}
+    };
+);
+
+macro_rules! impl_pin {
+    ($inst:ident, $pin:ident, $signal:ident, $af:expr) => {
+        impl sealed::$signal<peripherals::$inst> for peripherals::$pin {
+            fn af_num(&self) -> u8 {
+                $af
+            }
+        }
+
+        impl $signal<peripherals::$inst> for peripherals::$pin {}
+    };
+}
+
+#[cfg(not(rcc_f1))]
+crate::pac::peripheral_pins!(
+    ($inst:ident, usb, USB, $pin:ident, DP, $af:expr) => {
+        impl_pin!($inst, $pin, DpPin, $af);
+    };
+    ($inst:ident, usb, USB, $pin:ident, DM, $af:expr) => {
+        impl_pin!($inst, $pin, DmPin, $af);
+    };
+);
+
+#[cfg(rcc_f1)]
+crate::pac::peripheral_pins!(
+    ($inst:ident, usb, USB, $pin:ident, DP) => {
+        impl_pin!($inst, $pin, DpPin, 0);
+    };
+    ($inst:ident, usb, USB, $pin:ident, DM) => {
+        impl_pin!($inst, $pin, DmPin, 0);
+    };
+);
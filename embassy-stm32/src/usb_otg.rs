@@ -0,0 +1,228 @@
+#![macro_use]
+
+use core::marker::PhantomData;
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+use synopsys_usb_otg::{PhyType, UsbPeripheral};
+use usb_device::bus::UsbBusAllocator;
+
+pub use embassy_hal_common::usb::*;
+
+use crate::gpio::sealed::AFType;
+use crate::peripherals;
+use crate::rcc::RccPeripheral;
+
+#[cfg(all(feature = "usb-otg-fs", feature = "usb-otg-hs"))]
+compile_error!("the `usb-otg-fs` and `usb-otg-hs` features can't be enabled together");
+
+/// An OTG_FS or OTG_HS peripheral in device mode, using the internal full-speed PHY.
+///
+/// The 48 MHz USB clock has to be set up in the RCC config, e.g. with `pll48` on F4. On H7, it's
+/// PLL1 Q or PLL3 Q when configured at 48 MHz, else HSI48.
+pub struct UsbOtg<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> UsbOtg<'d, T> {
+    /// Creates the USB bus, `ep_memory` being used to buffer the OUT endpoints.
+    pub fn new(
+        _peri: impl Unborrow<Target = T> + 'd,
+        dp: impl Unborrow<Target = impl DpPin<T>> + 'd,
+        dm: impl Unborrow<Target = impl DmPin<T>> + 'd,
+        ep_memory: &'static mut [u32],
+    ) -> UsbBusAllocator<synopsys_usb_otg::UsbBus<Self>> {
+        unborrow!(dp, dm);
+
+        critical_section::with(|_| unsafe {
+            dp.set_as_af(dp.af_num(), AFType::OutputPushPull);
+            dm.set_as_af(dm.af_num(), AFType::OutputPushPull);
+        });
+
+        synopsys_usb_otg::UsbBus::new(
+            Self {
+                phantom: PhantomData,
+            },
+            ep_memory,
+        )
+    }
+}
+
+// The peripheral is only accessed through `synopsys_usb_otg`, which synchronizes itself.
+unsafe impl<'d, T: Instance> Send for UsbOtg<'d, T> {}
+unsafe impl<'d, T: Instance> Sync for UsbOtg<'d, T> {}
+
+unsafe impl<'d, T: Instance> UsbPeripheral for UsbOtg<'d, T> {
+    const REGISTERS: *const () = T::REGISTERS;
+    const HIGH_SPEED: bool = T::HIGH_SPEED;
+    const FIFO_DEPTH_WORDS: usize = T::FIFO_DEPTH_WORDS;
+    const ENDPOINT_COUNT: usize = T::ENDPOINT_COUNT;
+
+    fn enable() {
+        #[cfg(rcc_h7)]
+        unsafe {
+            enable_h7_usb_supply_and_clock();
+        }
+
+        <T as crate::rcc::sealed::RccPeripheral>::enable();
+        <T as crate::rcc::sealed::RccPeripheral>::reset();
+    }
+
+    fn phy_type(&self) -> PhyType {
+        PhyType::InternalFullSpeed
+    }
+
+    fn ahb_frequency_hz(&self) -> u32 {
+        <T as crate::rcc::sealed::RccPeripheral>::frequency().0
+    }
+}
+
+/// Turns the USB regulator on, and selects the 48 MHz USB clock.
+#[cfg(rcc_h7)]
+unsafe fn enable_h7_usb_supply_and_clock() {
+    use crate::pac::rcc::vals::Usbsel;
+    use crate::pac::{PWR, RCC};
+    use crate::time::Hertz;
+
+    critical_section::with(|_| {
+        PWR.cr3().modify(|w| w.set_usb33den(true));
+        while !PWR.cr3().read().usb33rdy() {}
+
+        let freqs = crate::rcc::get_freqs();
+        let usbsel = if freqs.pll1_q == Some(Hertz(48_000_000)) {
+            Usbsel::PLL1_Q
+        } else if freqs.pll3_q == Some(Hertz(48_000_000)) {
+            Usbsel::PLL3_Q
+        } else {
+            // Always on, see `rcc::init`.
+            Usbsel::HSI48
+        };
+        RCC.d2ccip2r().modify(|w| w.set_usbsel(usbsel));
+    });
+}
+
+pub(crate) mod sealed {
+    use crate::gpio::Pin as GpioPin;
+
+    pub trait Instance {
+        const REGISTERS: *const ();
+        const HIGH_SPEED: bool;
+        const FIFO_DEPTH_WORDS: usize;
+        const ENDPOINT_COUNT: usize;
+    }
+
+    pub trait DpPin<T: Instance>: GpioPin {
+        fn af_num(&self) -> u8;
+    }
+
+    pub trait DmPin<T: Instance>: GpioPin {
+        fn af_num(&self) -> u8;
+    }
+}
+
+pub trait Instance: sealed::Instance + RccPeripheral {}
+pub trait DpPin<T: Instance>: sealed::DpPin<T> + 'static {}
+pub trait DmPin<T: Instance>: sealed::DmPin<T> + 'static {}
+
+#[cfg(feature = "usb-otg-fs")]
+crate::pac::peripherals!(
+    (otgfs, $inst:ident) => {
+        impl sealed::Instance for peripherals::$inst {
+            const REGISTERS: *const () = crate::pac::$inst.0 as *const ();
+            const HIGH_SPEED: bool = false;
+
+            cfg_if::cfg_if! {
+                if #[cfg(rcc_h7)] {
+                    const FIFO_DEPTH_WORDS: usize = 1024;
+                    const ENDPOINT_COUNT: usize = 9;
+                } else if #[cfg(any(
+                    stm32f412, stm32f413, stm32f423, stm32f446, stm32f469, stm32f479, rcc_f7, rcc_l4
+                ))] {
+                    const FIFO_DEPTH_WORDS: usize = 320;
+                    const ENDPOINT_COUNT: usize = 6;
+                } else {
+                    const FIFO_DEPTH_WORDS: usize = 320;
+                    const ENDPOINT_COUNT: usize = 4;
+                }
+            }
+        }
+
+        impl Instance for peripherals::$inst {}
+    };
+);
+
+#[cfg(feature = "usb-otg-hs")]
+crate::pac::peripherals!(
+    (otghs, $inst:ident) => {
+        impl sealed::Instance for peripherals::$inst {
+            const REGISTERS: *const () = crate::pac::$inst.0 as *const ();
+            const HIGH_SPEED: bool = true;
+            const FIFO_DEPTH_WORDS: usize = 1024;
+
+            cfg_if::cfg_if! {
+                if #[cfg(any(rcc_f7, rcc_h7))] {
+                    const ENDPOINT_COUNT: usize = 9;
+                } else {
+                    const ENDPOINT_COUNT: usize = 6;
+                }
+            }
+        }
+
+        impl Instance for peripherals::$inst {}
+    };
+);
+
+#[cfg(feature = "usb-otg-fs")]
+crate::pac::interrupts!(
+    ($inst:ident, otgfs, $block:ident, GLOBAL, $irq:ident) => {
+        unsafe impl USBInterrupt for crate::interrupt::$irq {}
+    };
+);
+
+#[cfg(feature = "usb-otg-hs")]
+crate::pac::interrupts!(
+    ($inst:ident, otghs, $block:ident, GLOBAL, $irq:ident) => {
+        unsafe impl USBInterrupt for crate::interrupt::$irq {}
+    };
+);
+
+macro_rules! impl_pin {
+    ($inst:ident, $pin:ident, $signal:ident, $af:expr) => {
+        impl sealed::$signal<peripherals::$inst> for peripherals::$pin {
+            fn af_num(&self) -> u8 {
+                $af
+            }
+        }
+
+        impl $signal<peripherals::$inst> for peripherals::$pin {}
+    };
+}
+
+#[cfg(all(feature = "usb-otg-fs", not(rcc_f1)))]
+crate::pac::peripheral_pins!(
+    ($inst:ident, otgfs, $kind:ident, $pin:ident, DP, $af:expr) => {
+        impl_pin!($inst, $pin, DpPin, $af);
+    };
+    ($inst:ident, otgfs, $kind:ident, $pin:ident, DM, $af:expr) => {
+        impl_pin!($inst, $pin, DmPin, $af);
+    };
+);
+
+#[cfg(all(feature = "usb-otg-fs", rcc_f1))]
+crate::pac::peripheral_pins!(
+    ($inst:ident, otgfs, $kind:ident, $pin:ident, DP) => {
+        impl_pin!($inst, $pin, DpPin, 0);
+    };
+    ($inst:ident, otgfs, $kind:ident, $pin:ident, DM) => {
+        impl_pin!($inst, $pin, DmPin, 0);
+    };
+);
+
+#[cfg(feature = "usb-otg-hs")]
+crate::pac::peripheral_pins!(
+    ($inst:ident, otghs, $kind:ident, $pin:ident, DP, $af:expr) => {
+        impl_pin!($inst, $pin, DpPin, $af);
+    };
+    ($inst:ident, otghs, $kind:ident, $pin:ident, DM, $af:expr) => {
+        impl_pin!($inst, $pin, DmPin, $af);
+    };
+);
@@ -0,0 +1,164 @@
+//! Window watchdog.
+//!
+//! The WWDG is clocked by the APB clock, and resets the chip if it is petted too late (after the
+//! timeout) or too early (before the window). Its early wakeup interrupt fires one tick before
+//! the timeout, see [`WindowWatchdog::wait_early_wakeup`].
+
+use core::future::Future;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use atomic_polyfill::{AtomicBool, Ordering};
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::interrupt;
+use crate::pac::wwdg::vals::{Wdga, Wdgtb};
+use crate::pac::WWDG;
+use crate::peripherals;
+use crate::rcc::sealed::RccPeripheral;
+
+#[cfg(any(rcc_g0, rcc_g4, rcc_u5, rcc_wb, rcc_wl5))]
+const MAX_WDGTB: u8 = 7;
+#[cfg(not(any(rcc_g0, rcc_g4, rcc_u5, rcc_wb, rcc_wl5)))]
+const MAX_WDGTB: u8 = 3;
+
+/// The counter resets the chip when it goes from 0x40 to 0x3f.
+const MIN_COUNTER: u8 = 0x40;
+const MAX_COUNTER: u8 = 0x7f;
+
+static EARLY_WAKEUP: AtomicBool = AtomicBool::new(false);
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+#[non_exhaustive]
+pub struct Config {
+    /// Time after a pet at which the chip is reset, in microseconds.
+    ///
+    /// The watchdog counts 64 ticks of 4096 APB clock periods at most, divided by up to 8 (128
+    /// on G0, G4, U5, WB and WL).
+    pub timeout_us: u32,
+    /// Time after a pet during which petting again resets the chip, in microseconds. 0 allows
+    /// petting at any time.
+    pub window_us: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timeout_us: 10_000,
+            window_us: 0,
+        }
+    }
+}
+
+pub struct WindowWatchdog<'d> {
+    counter: u8,
+    _p: PhantomData<&'d mut peripherals::WWDG>,
+}
+
+impl<'d> WindowWatchdog<'d> {
+    /// Starts the watchdog. It can't be stopped, except by a reset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.timeout_us` is out of range for the APB clock, or if the window isn't
+    /// shorter than the timeout.
+    pub fn new(
+        _p: impl Unborrow<Target = peripherals::WWDG> + 'd,
+        irq: impl Unborrow<Target = interrupt::WWDG> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(_p, irq);
+
+        peripherals::WWDG::enable();
+        let clock = peripherals::WWDG::frequency().0;
+
+        let (wdgtb, ticks) = unwrap!(calculate(clock, config.timeout_us));
+        let tick_us = (4096u64 << wdgtb) * 1_000_000 / clock as u64;
+        let window_ticks = (config.window_us as u64 + tick_us - 1) / tick_us;
+        assert!(window_ticks < ticks as u64);
+
+        let counter = MIN_COUNTER - 1 + ticks;
+        let window = counter - window_ticks as u8;
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        unsafe {
+            WWDG.cfr().write(|w| {
+                w.set_wdgtb(Wdgtb(wdgtb));
+                w.set_w(window);
+            });
+            WWDG.cr().write(|w| {
+                w.set_wdga(Wdga::ENABLED);
+                w.set_t(counter);
+            });
+        }
+
+        Self {
+            counter,
+            _p: PhantomData,
+        }
+    }
+
+    unsafe fn on_interrupt(_: *mut ()) {
+        // EWIF is cleared by writing 0.
+        WWDG.sr().write(|w| w.set_ewif(false));
+        EARLY_WAKEUP.store(true, Ordering::Relaxed);
+        WAKER.wake();
+    }
+
+    /// Pets the watchdog, restarting its timeout. Petting it during the window resets the chip.
+    pub fn pet(&mut self) {
+        unsafe {
+            WWDG.cr().write(|w| {
+                w.set_wdga(Wdga::ENABLED);
+                w.set_t(self.counter);
+            })
+        }
+    }
+
+    /// Waits for the early wakeup interrupt, fired one watchdog tick before the timeout.
+    ///
+    /// This is the last chance to pet the watchdog, or to save state before the reset. Once
+    /// enabled by the first call, the interrupt can only be disabled by a reset.
+    pub fn wait_early_wakeup(&mut self) -> impl Future<Output = ()> + '_ {
+        EARLY_WAKEUP.store(false, Ordering::Relaxed);
+        unsafe {
+            WWDG.sr().write(|w| w.set_ewif(false));
+            WWDG.cfr().modify(|w| w.set_ewi(true));
+        }
+
+        poll_fn(|cx| {
+            WAKER.register(cx.waker());
+            if EARLY_WAKEUP.swap(false, Ordering::Relaxed) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+    }
+}
+
+/// Returns the prescaler (WDGTB) and the number of ticks for a timeout of `timeout_us`
+/// microseconds, or `None` if it is out of range.
+///
+/// The smallest possible prescaler is used, for the best resolution.
+fn calculate(clock: u32, timeout_us: u32) -> Option<(u8, u8)> {
+    let max_ticks = (MAX_COUNTER - MIN_COUNTER + 1) as u64;
+    for wdgtb in 0..=MAX_WDGTB {
+        let div = 4096u64 << wdgtb;
+        let ticks = (timeout_us as u64 * clock as u64 + div * 500_000) / (div * 1_000_000);
+        if ticks == 0 {
+            return None;
+        }
+        if ticks <= max_ticks {
+            return Some((wdgtb, ticks as u8));
+        }
+    }
+    None
+}
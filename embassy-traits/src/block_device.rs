@@ -0,0 +1,33 @@
+//! Async block device API
+
+use core::future::Future;
+
+/// A storage device accessed in fixed-size blocks, like an SD card.
+pub trait BlockDevice {
+    type Error;
+
+    type ReadFuture<'a>: Future<Output = Result<(), Self::Error>> + 'a
+    where
+        Self: 'a;
+
+    type WriteFuture<'a>: Future<Output = Result<(), Self::Error>> + 'a
+    where
+        Self: 'a;
+
+    /// Reads consecutive blocks, starting at `block_idx`, until `buf` is full.
+    ///
+    /// buf.len() must be a multiple of self.block_size().
+    fn read<'a>(&'a mut self, block_idx: u32, buf: &'a mut [u8]) -> Self::ReadFuture<'a>;
+
+    /// Writes consecutive blocks, starting at `block_idx`, with the contents of `buf`.
+    ///
+    /// buf.len() must be a multiple of self.block_size().
+    fn write<'a>(&'a mut self, block_idx: u32, buf: &'a [u8]) -> Self::WriteFuture<'a>;
+
+    /// Returns the block size in bytes.
+    /// This is guaranteed to be a power of 2.
+    fn block_size(&self) -> usize;
+
+    /// Returns the total number of blocks.
+    fn block_count(&self) -> u32;
+}
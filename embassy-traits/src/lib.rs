@@ -3,6 +3,7 @@
 #![feature(type_alias_impl_trait)]
 
 pub mod adapter;
+pub mod block_device;
 pub mod delay;
 pub mod flash;
 pub mod gpio;
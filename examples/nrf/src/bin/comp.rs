@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use embassy::executor::Spawner;
+use embassy_nrf::comp::{self, Comp};
+use embassy_nrf::{interrupt, Peripherals};
+use example_common::*;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    // Compare AIN2 against VDD/2, with a small hysteresis.
+    let mut config = comp::Config::default();
+    config.reference = comp::Reference::Vdd;
+    config.threshold_down = 29;
+    config.threshold_up = 33;
+    let mut comp = Comp::new(p.COMP, interrupt::take!(COMP_LPCOMP), p.P0_04, config);
+
+    loop {
+        if comp.sample() {
+            info!("above");
+            comp.wait_down().await;
+        } else {
+            info!("below");
+            comp.wait_up().await;
+        }
+    }
+}
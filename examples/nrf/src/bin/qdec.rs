@@ -0,0 +1,28 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use example_common::*;
+
+use embassy::executor::Spawner;
+use embassy_nrf::{
+    interrupt,
+    qdec::{self, Qdec},
+    Peripherals,
+};
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    let irq = interrupt::take!(QDEC);
+    let config = qdec::Config::default();
+    let mut rotary_enc = Qdec::new(p.QDEC, irq, p.P0_31, p.P0_30, config);
+
+    info!("Turn rotary encoder!");
+    let mut value = 0;
+    loop {
+        value += rotary_enc.read().await;
+        info!("Value: {}", value);
+    }
+}
@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use example_common::*;
+
+use embassy::executor::Spawner;
+use embassy::time::{Duration, Timer};
+use embassy_nrf::radio::ble::{self, Radio};
+use embassy_nrf::radio::pdu::{AdvPduWriter, PduType};
+use embassy_nrf::{interrupt, Peripherals};
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    let irq = interrupt::take!(RADIO);
+    let mut radio = unwrap!(Radio::new(p.RADIO, irq, ble::Config::default()));
+
+    let mut pdu = [0; 39];
+    let len = unwrap!(unwrap!(unwrap!(AdvPduWriter::new(
+        &mut pdu,
+        PduType::AdvNonconnInd,
+        [0x01, 0x02, 0x03, 0x04, 0x05, 0xC6],
+        true
+    ))
+    .ad(0x01, &[0x06]))
+    .ad(0x09, b"embassy"))
+    .finish();
+
+    loop {
+        for channel in [37, 38, 39] {
+            unwrap!(radio.set_channel(channel));
+            unwrap!(radio.transmit(&pdu[..len]).await);
+        }
+        info!("advertised");
+        Timer::after(Duration::from_millis(100)).await;
+    }
+}
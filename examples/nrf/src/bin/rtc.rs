@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use example_common::*;
+
+use embassy::executor::Spawner;
+use embassy_nrf::{
+    interrupt,
+    rtc::{self, Rtc},
+    Peripherals,
+};
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    let irq = interrupt::take!(RTC2);
+    // 8 Hz counter
+    let mut rtc = Rtc::new(p.RTC2, irq, rtc::Config::with_frequency(8));
+    rtc.start();
+
+    loop {
+        let target = rtc.counter() + 8;
+        let mut cc = rtc.cc(0);
+        cc.write(target);
+        cc.wait().await;
+        info!("tick: {}", rtc.now());
+    }
+}
@@ -15,6 +15,7 @@ async fn main(_spawner: Spawner, mut p: Peripherals) {
     let config = Config::default();
     let channel_config = ChannelConfig::single_ended(&mut p.P0_02);
     let mut saadc = Saadc::new(p.SAADC, interrupt::take!(SAADC), config, [channel_config]);
+    saadc.calibrate().await;
 
     loop {
         let mut buf = [0; 1];
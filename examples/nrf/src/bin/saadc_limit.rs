@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use embassy::executor::Spawner;
+use embassy_nrf::ppi::Ppi;
+use embassy_nrf::saadc::{ChannelConfig, Config, Limit, Limits, Oversample, Saadc, VddInput};
+use embassy_nrf::timer::{Frequency, Timer};
+use embassy_nrf::{interrupt, Peripherals};
+use example_common::*;
+
+// Monitors the supply voltage and reports when it goes below 2.5V or above 3.3V.
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    let mut config = Config::default();
+    config.oversample = Oversample::OVER8X;
+
+    // With the default 0.6V reference and 1/6 gain, the 12-bit full scale is 3.6V.
+    let mut channel_config = ChannelConfig::single_ended(VddInput);
+    channel_config.limits = Some(Limits {
+        low: (2500 * 4096 / 3600) as i16,
+        high: (3300 * 4096 / 3600) as i16,
+    });
+    let mut saadc = Saadc::new(p.SAADC, interrupt::take!(SAADC), config, [channel_config]);
+    saadc.calibrate().await;
+
+    let mut timer = Timer::new(p.TIMER0);
+    timer.set_frequency(Frequency::F1MHz);
+    timer.cc(0).write(100_000); // Sample every 100ms
+    timer.cc(0).short_compare_clear();
+
+    let mut ppi = Ppi::new_one_to_one(p.PPI_CH0, timer.cc(0).event_compare(), saadc.task_sample());
+    ppi.enable();
+
+    timer.start();
+
+    loop {
+        match saadc.wait_limit(0).await {
+            Limit::Low => info!("supply voltage low"),
+            Limit::High => info!("supply voltage high"),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+#![feature(type_alias_impl_trait)]
+
+use embassy::executor::Spawner;
+use embassy_fat::{FileBlockDevice, Mode, Volume};
+use log::*;
+
+#[embassy::task]
+async fn run(path: String) {
+    let device = FileBlockDevice::open(&path).unwrap();
+    let mut volume = Volume::open(device).await.unwrap();
+    info!("{:?} volume opened", volume.fat_type());
+
+    let root = volume.root_dir();
+    volume
+        .read_dir(&root, |entry| {
+            if entry.is_dir() {
+                info!("{}/", entry.name());
+            } else {
+                info!("{} ({} bytes)", entry.name(), entry.size());
+            }
+        })
+        .await
+        .unwrap();
+
+    let mut file = volume
+        .open_file(&root, "hello.txt", Mode::Truncate)
+        .await
+        .unwrap();
+    volume
+        .write(&mut file, b"Hello from embassy!\n")
+        .await
+        .unwrap();
+    volume.close_file(file).await.unwrap();
+
+    let mut file = volume
+        .open_file(&root, "hello.txt", Mode::Read)
+        .await
+        .unwrap();
+    let mut buf = [0; 64];
+    let n = volume.read(&mut file, &mut buf).await.unwrap();
+    info!("read {:?}", core::str::from_utf8(&buf[..n]).unwrap());
+
+    volume.close().await.unwrap();
+}
+
+#[embassy::main]
+async fn main(spawner: Spawner) {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Debug)
+        .format_timestamp_nanos()
+        .init();
+
+    // Create an image with e.g. `mkfs.fat -C disk.img 8192`
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "disk.img".to_string());
+    spawner.spawn(run(path)).unwrap();
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+
+use embassy::executor::Spawner;
+use embassy::time::{Delay, Duration, Timer};
+use embassy_stm32::adc::{Adc, SampleTime};
+use embassy_stm32::{interrupt, Peripherals};
+use example_common::*;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    let mut adc = Adc::new_with_irq(p.ADC1, interrupt::take!(ADC), &mut Delay);
+    adc.set_sample_time(SampleTime::Cycles480);
+    let mut vref = adc.enable_vref(&mut Delay);
+    let mut pa0 = p.PA0;
+    let mut pa1 = p.PA1;
+    let mut dma = p.DMA2_CH0;
+
+    loop {
+        let v = adc.read_async(&mut pa0).await;
+        info!("PA0: {} mV", adc.to_millivolts(v));
+
+        // 4 passes over PA0, PA1 and Vref
+        let mut buf = [0u16; 12];
+        adc.read_sequence(&mut [&mut pa0, &mut pa1, &mut vref], &mut dma, &mut buf)
+            .await;
+        info!("sequence: {}", buf);
+
+        let mut injected = [0u16; 2];
+        adc.read_injected(&mut [&mut pa1, &mut vref], &mut injected)
+            .await;
+        info!("injected: {}", injected);
+
+        Timer::after(Duration::from_secs(1)).await;
+    }
+}
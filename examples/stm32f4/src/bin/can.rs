@@ -5,18 +5,16 @@
 #[path = "../example_common.rs"]
 mod example_common;
 
-use cortex_m_rt::entry;
-use embassy_stm32::can::filter::Mask32;
+use embassy::executor::Spawner;
 use embassy_stm32::can::{Can, Frame, StandardId};
 use embassy_stm32::gpio::{Input, Pull};
+use embassy_stm32::{interrupt, Peripherals};
 use example_common::*;
 
-#[entry]
-fn main() -> ! {
+#[embassy::main]
+async fn main(_spawner: Spawner, mut p: Peripherals) {
     info!("Hello World!");
 
-    let mut p = embassy_stm32::init(Default::default());
-
     // The next two lines are a workaround for testing without transceiver.
     // To synchronise to the bus the RX input needs to see a high level.
     // Use `mem::forget()` to release the borrow on the pin but keep the
@@ -24,9 +22,17 @@ fn main() -> ! {
     let rx_pin = Input::new(&mut p.PA11, Pull::Up);
     core::mem::forget(rx_pin);
 
-    let mut can = Can::new(p.CAN1, p.PA11, p.PA12);
+    let mut can = Can::new(
+        p.CAN1,
+        p.PA11,
+        p.PA12,
+        interrupt::take!(CAN1_TX),
+        interrupt::take!(CAN1_RX0),
+        interrupt::take!(CAN1_RX1),
+        interrupt::take!(CAN1_SCE),
+    );
 
-    can.modify_filters().enable_bank(0, Mask32::accept_all());
+    can.accept_all();
 
     can.modify_config()
         .set_bit_timing(0x001c0003) // http://www.bittiming.can-wiki.info/
@@ -37,10 +43,13 @@ fn main() -> ! {
     let mut i: u8 = 0;
     loop {
         let tx_frame = Frame::new_data(unwrap!(StandardId::new(i as _)), [i]);
-        unwrap!(nb::block!(can.transmit(&tx_frame)));
-        while !can.is_transmitter_idle() {}
-        let rx_frame = unwrap!(nb::block!(can.receive()));
-        info!("loopback frame {=u8}", unwrap!(rx_frame.data())[0]);
+        unwrap!(can.write(&tx_frame).await);
+        let envelope = unwrap!(can.read().await);
+        info!(
+            "loopback frame {=u8} at {=u64}",
+            unwrap!(envelope.frame.data())[0],
+            envelope.ts.as_ticks()
+        );
         i += 1;
     }
 }
@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+
+use embassy::executor::Spawner;
+use embassy_stm32::dac::{Ch1Trigger, Ch2Trigger, Channel, Dac, ValueArray, Wave};
+use embassy_stm32::pac;
+use embassy_stm32::pac::timer::vals::Mms;
+use embassy_stm32::Peripherals;
+use example_common::*;
+
+/// One period of a sine wave, as 12 bit samples.
+const SINE: [u16; 32] = [
+    2047, 2446, 2830, 3184, 3494, 3749, 3938, 4055, 4094, 4055, 3938, 3749, 3494, 3184, 2830, 2446,
+    2047, 1648, 1264, 910, 600, 345, 156, 39, 0, 39, 156, 345, 600, 910, 1264, 1648,
+];
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    // Update events of TIM6, clocked at 16 MHz, on its TRGO output at 32 kHz.
+    unsafe {
+        pac::RCC.apb1enr().modify(|w| w.set_tim6en(true));
+        pac::TIM6
+            .arr()
+            .write(|w| w.set_arr(16_000_000 / 32_000 - 1));
+        pac::TIM6.cr2().modify(|w| w.set_mms(Mms::UPDATE));
+        pac::TIM6.cr1().modify(|w| w.set_cen(true));
+    }
+
+    let mut dac = Dac::new(p.DAC, p.PA4, p.PA5);
+
+    // 1 kHz sine on PA4.
+    unwrap!(dac.select_trigger_ch1(Ch1Trigger::Tim6));
+    unwrap!(dac.enable_channel(Channel::Ch1));
+
+    // Triangle of 1023 steps up and 1023 down on PA5, around 15.6 Hz.
+    unwrap!(dac.select_trigger_ch2(Ch2Trigger::Tim6));
+    unwrap!(dac.set_wave(Channel::Ch2, Wave::Triangle(10)));
+    unwrap!(dac.enable_channel(Channel::Ch2));
+
+    info!("Playing");
+    unwrap!(
+        dac.write_ch1(p.DMA1_CH5, ValueArray::Bit12Right(&SINE), true)
+            .await
+    );
+}
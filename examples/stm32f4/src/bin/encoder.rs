@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use embassy::executor::Spawner;
+use embassy_stm32::pwm::{Encoder, EncoderMode};
+use embassy_stm32::{interrupt, Peripherals};
+use example_common::*;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    // Quadrature encoder on PA6 (A) and PA7 (B).
+    let irq = interrupt::take!(TIM3);
+    let mut encoder = Encoder::new(p.TIM3, p.PA6, p.PA7, irq, EncoderMode::BothEdges);
+
+    let mut count = encoder.count();
+    loop {
+        let new = encoder.wait_count_changed(count).await;
+        let steps = new.wrapping_sub(count) as i16;
+        info!("{} steps {:?}, count {}", steps, encoder.direction(), new);
+        count = new;
+    }
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+
+use embassy::executor::Spawner;
+use embassy_stm32::flash::Flash;
+use embassy_stm32::Peripherals;
+use example_common::*;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello Flash!");
+
+    // Sector 5, 128 KiB at offset 128 KiB, well after the program.
+    const ADDR: u32 = 0x20000;
+
+    let mut f = Flash::new(p.FLASH);
+    let sector = unwrap!(f.sector(ADDR));
+    info!("Sector {} of bank {}, {} bytes", sector.index, sector.bank, sector.size);
+
+    info!("Reading...");
+    let mut buf = [0u8; 8];
+    unwrap!(f.blocking_read(ADDR, &mut buf));
+    info!("Read: {:x}", buf);
+
+    info!("Erasing...");
+    unwrap!(f.blocking_erase(sector.start, sector.start + sector.size));
+
+    info!("Reading...");
+    let mut buf = [0u8; 8];
+    unwrap!(f.blocking_read(ADDR, &mut buf));
+    info!("Read after erase: {:x}", buf);
+
+    info!("Writing...");
+    unwrap!(f.blocking_write(ADDR, &[1, 2, 3, 4, 5, 6, 7, 8]));
+
+    info!("Reading...");
+    let mut buf = [0u8; 8];
+    unwrap!(f.blocking_read(ADDR, &mut buf));
+    info!("Read: {:x}", buf);
+    assert_eq!(&buf[..], &[1, 2, 3, 4, 5, 6, 7, 8]);
+}
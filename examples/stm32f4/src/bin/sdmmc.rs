@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use embassy::executor::Spawner;
+use embassy_stm32::sdmmc::{self, DataBlock, Sdmmc};
+use embassy_stm32::time::{Hertz, U32Ext};
+use embassy_stm32::{interrupt, Config, Peripherals};
+use example_common::*;
+
+fn config() -> Config {
+    let mut config = Config::default();
+    config.rcc.sys_ck = Some(Hertz(48_000_000));
+    config.rcc.pll48 = true;
+    config
+}
+
+#[embassy::main(config = "config()")]
+async fn main(_spawner: Spawner, p: Peripherals) -> ! {
+    info!("Hello World!");
+
+    let irq = interrupt::take!(SDIO);
+
+    let mut sdmmc = unsafe {
+        Sdmmc::new(
+            p.SDIO,
+            (p.PC12, p.PD2, p.PC8, p.PC9, p.PC10, p.PC11),
+            irq,
+            sdmmc::Config::default(),
+            p.DMA2_CH3,
+        )
+    };
+
+    // Should print 400kHz for initialization
+    info!("Configured clock: {}", sdmmc.clock().0);
+
+    unwrap!(sdmmc.init_card(24.mhz()).await);
+
+    let card = unwrap!(sdmmc.card());
+
+    info!("Card: {:#?}", Debug2Format(card));
+    info!("Clock: {}", sdmmc.clock().0);
+
+    let mut blocks = [DataBlock::new(), DataBlock::new()];
+    unwrap!(sdmmc.read_blocks(0, &mut blocks).await);
+    info!("First bytes: {:02x}", &blocks[0].0[..16]);
+
+    loop {}
+}
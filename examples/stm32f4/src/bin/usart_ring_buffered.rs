@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use embassy::executor::Spawner;
+use embassy::time::{Duration, Timer};
+use embassy_stm32::dma::NoDma;
+use embassy_stm32::usart::{Config, RingBufferedUartRx, Uart};
+use embassy_stm32::{interrupt, Peripherals};
+use example_common::*;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    let config = Config::default();
+    let usart = Uart::new(p.USART3, p.PD9, p.PD8, NoDma, p.DMA1_CH1, config);
+
+    let mut dma_buf = [0u8; 64];
+    let mut rx = RingBufferedUartRx::new(usart, interrupt::take!(USART3), &mut dma_buf);
+
+    let mut buf = [0u8; 16];
+    loop {
+        match rx.read(&mut buf).await {
+            Ok(n) => info!("read {:x}", &buf[..n]),
+            Err(e) => warn!("read error: {:?}", e),
+        }
+
+        // The bytes received meanwhile wait in the DMA buffer.
+        Timer::after(Duration::from_millis(100)).await;
+    }
+}
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use embassy::executor::Spawner;
+use embassy::io::AsyncWriteExt;
+use embassy::time::{Duration, Timer};
+use embassy_stm32::dma::NoDma;
+use embassy_stm32::usart::{BufferedUart, Config, State, Uart};
+use embassy_stm32::{interrupt, Peripherals};
+use example_common::*;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    let mut tx_buffer = [0u8; 32];
+    let mut rx_buffer = [0u8; 64];
+
+    let config = Config::default();
+    let usart = Uart::new(p.USART3, p.PD9, p.PD8, NoDma, NoDma, config);
+    let mut state = State::new();
+    // PD12 drives the DE input of the RS-485 transceiver.
+    let mut usart = unsafe {
+        BufferedUart::new_with_de(
+            &mut state,
+            usart,
+            interrupt::take!(USART3),
+            p.PD12,
+            &mut tx_buffer,
+            &mut rx_buffer,
+        )
+    };
+    let (mut rx, mut tx) = usart.split();
+
+    let receive = async {
+        let mut buf = [0u8; 32];
+        loop {
+            match rx.read_until_idle(&mut buf).await {
+                Ok(n) => info!("received frame {:x}", &buf[..n]),
+                Err(e) => warn!("reception error: {:?}", e),
+            }
+        }
+    };
+
+    let transmit = async {
+        loop {
+            unwrap!(tx.write_all(b"ping\r\n").await);
+            // Releases the bus once the last byte is sent.
+            unwrap!(tx.flush().await);
+            Timer::after(Duration::from_secs(1)).await;
+        }
+    };
+
+    futures::join!(receive, transmit);
+}
@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use embassy::executor::Spawner;
+use embassy::time::{Duration, Timer};
+use embassy_stm32::iwdg::IndependentWatchdog;
+use embassy_stm32::rcc::{clear_reset_cause, reset_cause};
+use embassy_stm32::Peripherals;
+use example_common::*;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    info!("reset cause: {:?}", reset_cause());
+    clear_reset_cause();
+
+    let mut wdg = IndependentWatchdog::new(p.IWDG, 1_000_000);
+    info!("watchdog timeout: {=u32} us", wdg.timeout_us());
+
+    // Stop petting after 5 seconds, the watchdog then resets the chip.
+    for _ in 0..10 {
+        Timer::after(Duration::from_millis(500)).await;
+        wdg.pet();
+        info!("petted");
+    }
+    info!("waiting for the reset");
+}
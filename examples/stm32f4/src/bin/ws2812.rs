@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use embassy::executor::Spawner;
+use embassy::time::{Duration, Timer};
+use embassy_stm32::gpio::NoPin;
+use embassy_stm32::pwm::{Channel, Pwm};
+use embassy_stm32::time::U32Ext;
+use embassy_stm32::Peripherals;
+use example_common::*;
+
+const LEDS: usize = 8;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    // WS2812 data line on PB4. Each bit is an 800 kHz period, whose pulse width sets its value.
+    let mut pwm = Pwm::new(p.TIM3, p.PB4, NoPin, NoPin, NoPin, 800.khz());
+    let max = pwm.get_max_duty() as u16;
+    let (zero, one) = (max / 3, max * 2 / 3);
+    pwm.set_duty(Channel::Ch1, 0);
+    pwm.enable(Channel::Ch1);
+
+    // 24 bits per LED, then a low period ending the waveform.
+    let mut dma = p.DMA1_CH2;
+    let mut duty = [0u16; LEDS * 24 + 1];
+    let mut step = 0u8;
+    loop {
+        for (i, led) in duty[..LEDS * 24].chunks_mut(24).enumerate() {
+            // Green, red, blue, most significant bit first.
+            let grb = [step.wrapping_add(i as u8 * 32), 0, 32];
+            for (bits, color) in led.chunks_mut(8).zip(grb.iter()) {
+                for (j, bit) in bits.iter_mut().enumerate() {
+                    *bit = if color & (0x80 >> j) != 0 { one } else { zero };
+                }
+            }
+        }
+
+        pwm.waveform_dma(&mut dma, Channel::Ch1, &duty).await;
+        step = step.wrapping_add(4);
+        Timer::after(Duration::from_millis(20)).await;
+    }
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+
+use embassy::executor::Spawner;
+use embassy_stm32::interrupt;
+use embassy_stm32::qspi::{Config, Qspi, QspiFlash};
+use embassy_stm32::Peripherals;
+use example_common::*;
+
+const ADDRESS: u32 = 0x1000;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    // A quad SPI NOR flash, like a W25Q128, wired to QUADSPI bank 1.
+    let qspi = Qspi::new(
+        p.QUADSPI,
+        interrupt::take!(QUADSPI),
+        p.PB2,
+        p.PB6,
+        p.PD11,
+        p.PD12,
+        p.PE2,
+        p.PD13,
+        p.DMA2_CH7,
+        Config::default(),
+    );
+    let mut flash = unwrap!(QspiFlash::new(qspi).await);
+    let params = flash.parameters();
+    info!(
+        "Flash of {} bytes, pages of {} bytes",
+        params.size, params.page_size
+    );
+
+    unwrap!(flash.erase(ADDRESS).await);
+    unwrap!(flash.write(ADDRESS, b"Hello QSPI!").await);
+
+    let mut buf = [0; 11];
+    unwrap!(flash.read(ADDRESS, &mut buf).await);
+    info!("Read: {=[u8]:a}", buf);
+
+    let mapped = flash.memory_mapped();
+    let bytes = &mapped.as_slice()[ADDRESS as usize..][..buf.len()];
+    info!("Memory-mapped: {=[u8]:a}", bytes);
+}
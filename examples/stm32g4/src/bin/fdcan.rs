@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use embassy::executor::Spawner;
+use embassy_stm32::fdcan::{Config, Fdcan, Frame, Mode, StandardId};
+use embassy_stm32::{interrupt, Peripherals};
+use example_common::*;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    let mut config = Config::default();
+    config.bitrate = 500_000;
+    config.fd = true;
+    config.data_bitrate = Some(2_000_000);
+    // Receive own frames, without a transceiver.
+    config.mode = Mode::InternalLoopback;
+
+    let irq = interrupt::take!(FDCAN1_IT0);
+    let mut can = Fdcan::new(p.FDCAN1, p.PA11, p.PA12, irq, config);
+    can.accept_all();
+
+    let mut i: u8 = 0;
+    loop {
+        let data = [i; 32];
+        let tx_frame = unwrap!(Frame::new_fd(
+            unwrap!(StandardId::new(i as _)),
+            &data,
+            true
+        ));
+        unwrap!(can.write(&tx_frame).await);
+        let envelope = unwrap!(can.read().await);
+        info!(
+            "loopback frame {=u8}, {=usize} bytes at {=u64}",
+            envelope.frame.data()[0],
+            envelope.frame.data().len(),
+            envelope.ts.as_ticks()
+        );
+        i = i.wrapping_add(1);
+    }
+}
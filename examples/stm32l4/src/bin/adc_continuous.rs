@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+
+use embassy::executor::Spawner;
+use embassy::time::Delay;
+use embassy_stm32::adc::{Adc, SampleTime};
+use embassy_stm32::Peripherals;
+use example_common::*;
+
+fn config() -> embassy_stm32::Config {
+    unsafe {
+        embassy_stm32::pac::RCC.ccipr().modify(|w| {
+            w.set_adcsel(0b11);
+        });
+    }
+    Default::default()
+}
+
+#[embassy::main(config = "config()")]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    let mut adc = Adc::new(p.ADC1, &mut Delay);
+    adc.set_sample_time(SampleTime::Cycles640_5);
+    let mut channel = p.PC0;
+
+    let mut dma_buf = [0u16; 256];
+    let mut samples = adc.start_continuous(&mut channel, p.DMA1_CH1, &mut dma_buf);
+
+    let mut buf = [0u16; 64];
+    loop {
+        match samples.read(&mut buf).await {
+            Ok(n) => {
+                let sum: u32 = buf[..n].iter().map(|&s| s as u32).sum();
+                info!("{} samples, average {}", n, sum / n as u32);
+            }
+            Err(_) => warn!("samples overwritten"),
+        }
+    }
+}
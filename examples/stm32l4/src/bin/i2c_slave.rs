@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+
+use embassy::executor::Spawner;
+use embassy_stm32::i2c::{Address, Command, I2cSlave, SlaveConfig};
+use embassy_stm32::interrupt;
+use embassy_stm32::Peripherals;
+use example_common::{info, unwrap, warn};
+
+const ADDRESS: u8 = 0x42;
+
+/// Registers exposed to the controller, which writes the index of the first one it accesses,
+/// then the values to write, or reads them after a repeated START.
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) -> ! {
+    let irq = interrupt::take!(I2C2_EV);
+    let err_irq = interrupt::take!(I2C2_ER);
+    let mut config = SlaveConfig::new(Address::SevenBit(ADDRESS));
+    config.general_call = true;
+    let mut i2c = I2cSlave::new(
+        p.I2C2, p.PB10, p.PB11, irq, err_irq, p.DMA1_CH4, p.DMA1_CH5, config,
+    );
+
+    let mut registers = [0u8; 16];
+    let mut index = 0;
+    let mut buf = [0u8; 17];
+
+    loop {
+        let request = unwrap!(i2c.listen().await);
+        match request.command {
+            Command::Write => match i2c.respond_to_write_dma(&mut buf).await {
+                Ok(0) => {}
+                Ok(n) if request.address == Address::SevenBit(0) => {
+                    info!("General call: {}", &buf[..n]);
+                }
+                Ok(n) => {
+                    index = buf[0] as usize % registers.len();
+                    for &value in &buf[1..n] {
+                        registers[index] = value;
+                        index = (index + 1) % registers.len();
+                    }
+                }
+                Err(_) => warn!("Write failed"),
+            },
+            Command::Read => match i2c.respond_to_read_dma(&registers[index..]).await {
+                Ok(n) => info!("Sent {} registers from {}", n, index),
+                Err(_) => warn!("Read failed"),
+            },
+        }
+    }
+}
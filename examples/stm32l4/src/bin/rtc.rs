@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+#[path = "../example_common.rs"]
+mod example_common;
+use embassy::executor::Spawner;
+use embassy_stm32::rcc::RtcClockSource;
+use embassy_stm32::rtc::{Alarm, AlarmId, Config, DateTime, Rtc, WakeupClock};
+use embassy_stm32::{interrupt, Peripherals};
+use example_common::*;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    let mut config = Config::default();
+    config.clock_source = RtcClockSource::LSE;
+
+    let irq = interrupt::take!(RTC_ALARM);
+    let mut rtc = Rtc::new(p.RTC, irq, config);
+    rtc.enable_wakeup_interrupt(interrupt::take!(RTC_WKUP));
+
+    // Count the resets in a backup register.
+    let boots = unwrap!(rtc.read_backup_register(0)) + 1;
+    rtc.write_backup_register(0, boots);
+    info!("boot {}", boots);
+
+    if rtc.now().is_none() {
+        rtc.set_datetime(unwrap!(DateTime::new(2021, 12, 6, 12, 0, 0)));
+    }
+
+    // Every minute, at 30 seconds.
+    let alarm = Alarm {
+        second: Some(30),
+        ..Default::default()
+    };
+    unwrap!(rtc.set_alarm(AlarmId::A, alarm));
+
+    loop {
+        rtc.wait_alarm(AlarmId::A).await;
+        let now = unwrap!(rtc.now());
+        info!(
+            "alarm at {}-{}-{} {}:{}:{}",
+            now.year(),
+            now.month(),
+            now.day(),
+            now.hour(),
+            now.minute(),
+            now.second()
+        );
+
+        rtc.start_wakeup_timer(WakeupClock::Seconds, 5);
+        rtc.wait_wakeup().await;
+        rtc.stop_wakeup_timer();
+        info!("5 seconds later");
+    }
+}
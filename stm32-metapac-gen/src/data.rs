@@ -22,6 +22,13 @@ pub struct Memory {
 pub struct MemoryRegion {
     pub base: u32,
     pub bytes: Option<u32>,
+    #[serde(default)]
+    pub settings: Option<MemorySettings>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Deserialize)]
+pub struct MemorySettings {
+    pub erase_size: u32,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Deserialize)]
@@ -74,6 +74,111 @@ macro_rules! {} {{
     .unwrap();
 }
 
+fn make_flash(out: &mut String, chip_name: &str, flash: &Memory) {
+    let bank1 = flash.regions.get("BANK_1").unwrap();
+    write!(
+        out,
+        "pub const FLASH_BASE: usize = 0x{:x};
+pub const FLASH_SIZE: usize = {};
+",
+        bank1.base, flash.bytes
+    )
+    .unwrap();
+
+    let bank_count = flash
+        .regions
+        .keys()
+        .filter(|name| name.starts_with("BANK_"))
+        .count();
+    let erase_size = match &bank1.settings {
+        Some(settings) => Some(settings.erase_size),
+        None => flash_erase_size(chip_name, flash.bytes, bank_count),
+    };
+    if let Some(erase_size) = erase_size {
+        write!(out, "pub const FLASH_ERASE_SIZE: usize = {};\n", erase_size).unwrap();
+    }
+
+    // Only regions with a known size, BANK_1 defaulting to the whole flash.
+    let mut regions: Vec<(&String, &MemoryRegion)> = flash.regions.iter().collect();
+    regions.sort_by_key(|(_, r)| r.base);
+    let table = regions
+        .into_iter()
+        .filter_map(|(name, r)| {
+            let bytes = match (name.as_str(), r.bytes) {
+                (_, Some(bytes)) => bytes,
+                ("BANK_1", None) => flash.bytes,
+                _ => return None,
+            };
+            Some(vec![
+                name.clone(),
+                format!("0x{:x}", r.base),
+                bytes.to_string(),
+            ])
+        })
+        .collect();
+    make_table(out, "flash_regions", &table);
+}
+
+/// Returns the page size of the chips with uniform flash pages, when the chip data doesn't
+/// give it.
+///
+/// The page size of some lines depends on the bank configuration, `bank_count` being the
+/// number of banks of the default configuration.
+fn flash_erase_size(chip_name: &str, flash_bytes: u32, bank_count: usize) -> Option<u32> {
+    const K: u32 = 1024;
+
+    let name = chip_name.to_ascii_uppercase();
+    let line = |prefixes: &[&str]| prefixes.iter().any(|p| name.starts_with(p));
+
+    let size = if line(&["STM32F0"]) {
+        if flash_bytes >= 128 * K {
+            2 * K
+        } else {
+            K
+        }
+    } else if line(&["STM32F105", "STM32F107"]) {
+        2 * K
+    } else if line(&["STM32F1"]) {
+        if flash_bytes >= 256 * K {
+            2 * K
+        } else {
+            K
+        }
+    } else if line(&["STM32F3", "STM32G0", "STM32WL"]) {
+        2 * K
+    } else if line(&["STM32G47", "STM32G48"]) {
+        if bank_count == 1 {
+            4 * K
+        } else {
+            2 * K
+        }
+    } else if line(&["STM32G4"]) {
+        2 * K
+    } else if line(&["STM32L4R", "STM32L4S", "STM32L4P", "STM32L4Q"]) {
+        if bank_count == 1 {
+            8 * K
+        } else {
+            4 * K
+        }
+    } else if line(&["STM32L4"]) {
+        2 * K
+    } else if line(&["STM32WB"]) {
+        4 * K
+    } else if line(&["STM32L0"]) {
+        128
+    } else if line(&["STM32L1"]) {
+        256
+    } else if line(&["STM32H7A", "STM32H7B"]) {
+        8 * K
+    } else if line(&["STM32H7"]) {
+        128 * K
+    } else {
+        // F2/F4/F7 sectors have different sizes.
+        return None;
+    };
+    Some(size)
+}
+
 pub struct Options {
     pub chips: Vec<String>,
     pub out_dir: PathBuf,
@@ -420,6 +525,7 @@ pub fn gen_chip(
     make_table(&mut data, "dbgmcu", &dbgmcu_table);
     make_peripheral_counts(&mut data, &peripheral_counts);
     make_dma_channel_counts(&mut data, &dma_channel_counts);
+    make_flash(&mut data, &chip.name, &chip.flash);
 
     let mut file = File::create(chip_dir.join("mod.rs")).unwrap();
     file.write_all(data.as_bytes()).unwrap();
@@ -621,3 +727,30 @@ fn gen_memory_x(out_dir: &PathBuf, chip: &Chip) {
     let mut file = File::create(out_dir.join("memory_x").join("memory.x")).unwrap();
     file.write_all(memory_x.as_bytes()).unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const K: u32 = 1024;
+
+    #[test]
+    fn flash_erase_sizes() {
+        assert_eq!(flash_erase_size("STM32F030F4", 16 * K, 1), Some(K));
+        assert_eq!(flash_erase_size("STM32F072RB", 128 * K, 1), Some(2 * K));
+        assert_eq!(flash_erase_size("STM32F103C8", 64 * K, 1), Some(K));
+        assert_eq!(flash_erase_size("STM32F103ZE", 512 * K, 1), Some(2 * K));
+        assert_eq!(flash_erase_size("STM32F107VC", 256 * K, 1), Some(2 * K));
+        assert_eq!(flash_erase_size("STM32G071RB", 128 * K, 1), Some(2 * K));
+        assert_eq!(flash_erase_size("STM32G474RE", 512 * K, 2), Some(2 * K));
+        assert_eq!(flash_erase_size("STM32G474RE", 512 * K, 1), Some(4 * K));
+        assert_eq!(flash_erase_size("STM32G431KB", 128 * K, 1), Some(2 * K));
+        assert_eq!(flash_erase_size("STM32L476RG", 1024 * K, 2), Some(2 * K));
+        assert_eq!(flash_erase_size("STM32L4R5ZI", 2048 * K, 2), Some(4 * K));
+        assert_eq!(flash_erase_size("STM32L4R5ZI", 2048 * K, 1), Some(8 * K));
+        assert_eq!(flash_erase_size("STM32WB55RG", 1024 * K, 1), Some(4 * K));
+        assert_eq!(flash_erase_size("STM32L072CZ", 192 * K, 2), Some(128));
+        assert_eq!(flash_erase_size("STM32H743ZI", 2048 * K, 2), Some(128 * K));
+        assert_eq!(flash_erase_size("STM32F429ZI", 2048 * K, 2), None);
+    }
+}